@@ -130,6 +130,13 @@ impl Buffer {
             *this += that;
         }
     }
+    pub fn accumulate_scaled(&mut self, that: &Buffer, scale: BufferType) {
+        debug_assert!(self.len() == that.len(), "buffer length mismatch");
+
+        for (this, that) in self.iter_mut().zip(that.iter()) {
+            *this += that * scale;
+        }
+    }
 }
 impl Default for &mut Buffer {
     fn default() -> Self {
@@ -2,13 +2,15 @@ use std::collections::BTreeSet;
 
 use meminterval::IntervalTree;
 
-use crate::{Id, IdMap, Note, Range};
+use crate::{Id, IdMap, Note, Range, Sample};
 
 #[derive(Clone, Debug)]
 /// A clip on a track, independent of a start position
 pub struct Clip {
     pub name: String,
     pub length: u64,
+    /// If this is an audio clip, what it plays. Audio clips don't have notes.
+    pub audio: Option<AudioClip>,
 
     note_map: IdMap<Note, (i64, Note)>,
     notes_range: IntervalTree<i64, Id<Note>>,
@@ -21,6 +23,7 @@ impl Clip {
         Self {
             name,
             length,
+            audio: None,
 
             note_map: IdMap::new(),
             notes_range: IntervalTree::new(),
@@ -29,6 +32,13 @@ impl Clip {
         }
     }
 
+    pub fn audio(name: String, length: u64, audio: AudioClip) -> Self {
+        Self {
+            audio: Some(audio),
+            ..Self::empty(name, length)
+        }
+    }
+
     pub fn insert_note(&mut self, start_pos: i64, note_id: Id<Note>, note: Note) {
         self.notes_range.insert(note.range_with(start_pos), note_id);
         self.notes_start_position.insert((start_pos, note_id));
@@ -113,3 +123,61 @@ impl Clip {
             })
     }
 }
+
+/// Audio played by a clip. Audio plays at its original speed unless it follows the song's tempo, so the clip can be
+/// longer or shorter than the sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioClip {
+    pub sample_id: Id<Sample>,
+    /// The frame of the sample that plays at the start of the clip.
+    pub start_offset: u64,
+    pub gain: f32,
+    /// Length of the linear fade in at the start of the clip, in song units.
+    pub fade_in: u64,
+    /// Length of the linear fade out at the end of the clip, in song units.
+    pub fade_out: u64,
+    /// The tempo the audio was recorded at. If this is set, the audio is time-stretched to follow the song's tempo.
+    pub source_bpm: Option<f32>,
+    /// How far the audio is pitch-shifted, in semitones. This doesn't change its speed.
+    pub pitch: f32,
+}
+
+impl AudioClip {
+    pub fn new(sample_id: Id<Sample>) -> Self {
+        Self {
+            sample_id,
+            start_offset: 0,
+            gain: 1.0,
+            fade_in: 0,
+            fade_out: 0,
+            source_bpm: None,
+            pitch: 0.0,
+        }
+    }
+
+    /// How fast the audio plays when the song is at `bpm`. 2 is twice as fast as it was recorded.
+    pub fn speed(&self, bpm: f32) -> f64 {
+        self.source_bpm
+            .map_or(1.0, |source_bpm| bpm as f64 / source_bpm as f64)
+    }
+
+    /// The pitch shift as a frequency ratio.
+    pub fn pitch_ratio(&self) -> f64 {
+        2.0f64.powf(self.pitch as f64 / 12.0)
+    }
+
+    /// The gain at `pos` units into a clip of length `length`, including the fades.
+    pub fn gain_at(&self, pos: f64, length: u64) -> f32 {
+        let fade_in = if self.fade_in > 0 {
+            (pos / self.fade_in as f64).min(1.0)
+        } else {
+            1.0
+        };
+        let fade_out = if self.fade_out > 0 {
+            ((length as f64 - pos) / self.fade_out as f64).min(1.0)
+        } else {
+            1.0
+        };
+        self.gain * (fade_in * fade_out).max(0.0) as f32
+    }
+}
@@ -245,9 +245,20 @@ impl<T, V> IdMap<T, V> {
         self.remove(id)
             .unwrap_or_else(|| panic!("nonexistent id: {id:?}"))
     }
+    /// Reserves capacity for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
     pub fn clear(&mut self) {
         self.map.clear();
     }
+    pub fn retain(&mut self, mut f: impl FnMut(Id<T>, &mut V) -> bool) {
+        self.map.retain(|id, val| f(id.cast(), val));
+    }
+    /// Removes and returns every entry, keeping the allocated capacity.
+    pub fn drain(&mut self) -> impl Iterator<Item = (Id<T>, V)> + '_ {
+        self.map.drain().map(|(id, val)| (id.cast(), val))
+    }
 
     // TODO make these functions give Id<T> instead of &Id<T>
     pub fn keys(&self) -> impl Iterator<Item = Id<T>> + '_ {
@@ -1,7 +1,9 @@
 mod note;
 pub use note::Note;
 mod clip;
-pub use clip::Clip;
+pub use clip::{AudioClip, Clip};
+mod sample;
+pub use sample::Sample;
 mod range;
 pub use range::Range;
 pub mod id;
@@ -12,7 +14,7 @@ mod track;
 pub use resourcekey;
 #[deprecated(note = "use resourcekey directly")]
 pub use resourcekey::ResourceKey;
-pub use track::Track;
+pub use track::{Track, TrackSend};
 mod patch;
 pub use patch::{
     Cable, CableConnection, CableTag, Node, NodeData, NodeInput, NodeOutput, NodeTag, Patch,
@@ -53,6 +53,7 @@ impl Patch {
                     .into_iter()
                     .map(|bias| NodeInput {
                         bias,
+                        smoothing_ms: None,
                         connections: Vec::new(),
                     })
                     .collect(),
@@ -350,6 +351,8 @@ impl ops::IndexMut<Id<Node>> for Patch {
 #[derive(Debug, Clone, Default)]
 pub struct NodeInput {
     pub bias: f32,
+    /// How long changes to `bias` take to glide to their new value, in milliseconds. `None` uses the default smoothing time.
+    pub smoothing_ms: Option<f32>,
     // connections are additive to the value
     pub connections: Vec<(Id<Cable>, CableConnection)>,
 }
@@ -531,6 +534,7 @@ impl Node {
                 for _ in 0..num_inputs {
                     vec.push(NodeInput {
                         bias: 1.0,
+                        smoothing_ms: None,
                         connections: Vec::new(),
                     });
                 }
@@ -590,6 +594,7 @@ impl Node {
     pub fn push_input(&mut self, bias: f32) {
         self.inputs.push(NodeInput {
             bias,
+            smoothing_ms: None,
             connections: Vec::new(),
         });
 
@@ -1,6 +1,6 @@
 use std::ops;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Default)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Default)]
 /// cubedaw range. Describes an inclusive start position and an exclusive end position.
 pub struct Range {
     pub start: i64,
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+/// Mono audio data owned by the project, like an imported audio file. Samples can't be edited after they're created,
+/// so the data is shared instead of copied.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub name: String,
+    sample_rate: u32,
+    data: Arc<[f32]>,
+    /// The minimum and maximum of every [`Self::PEAK_BLOCK_SIZE`] frames, for drawing waveforms.
+    peaks: Arc<[(f32, f32)]>,
+}
+
+impl Sample {
+    pub const PEAK_BLOCK_SIZE: usize = 256;
+
+    pub fn new(name: String, sample_rate: u32, data: Arc<[f32]>) -> Self {
+        assert!(sample_rate > 0, "sample rate can't be 0");
+        let peaks = data
+            .chunks(Self::PEAK_BLOCK_SIZE)
+            .map(|block| {
+                block
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &x| {
+                        (min.min(x), max.max(x))
+                    })
+            })
+            .collect();
+        Self {
+            name,
+            sample_rate,
+            data,
+            peaks,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    pub fn data(&self) -> &Arc<[f32]> {
+        &self.data
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn duration_seconds(&self) -> f64 {
+        self.data.len() as f64 / self.sample_rate as f64
+    }
+
+    /// The minimum and maximum values of the frames in `range`. Blocks of [`Self::PEAK_BLOCK_SIZE`] frames are
+    /// summarized, so long ranges are cheap but the result might include a few frames outside of the range.
+    /// Returns `None` if the range doesn't contain any frames.
+    pub fn peak(&self, range: std::ops::Range<usize>) -> Option<(f32, f32)> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        if range.is_empty() {
+            return None;
+        }
+        let fold =
+            |(min, max): (f32, f32), (x_min, x_max): (f32, f32)| (min.min(x_min), max.max(x_max));
+        let init = (f32::INFINITY, f32::NEG_INFINITY);
+        Some(if range.len() < Self::PEAK_BLOCK_SIZE {
+            self.data[range].iter().map(|&x| (x, x)).fold(init, fold)
+        } else {
+            self.peaks
+                [range.start / Self::PEAK_BLOCK_SIZE..range.end.div_ceil(Self::PEAK_BLOCK_SIZE)]
+                .iter()
+                .copied()
+                .fold(init, fold)
+        })
+    }
+}
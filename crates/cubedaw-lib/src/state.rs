@@ -1,13 +1,23 @@
-use crate::{id::IdMap, track::Track, Id, Range};
+use ahash::HashSetExt;
+
+use crate::{Id, IdSet, Range, Sample, id::IdMap, track::Track};
 
 #[derive(Debug, Clone)]
 pub struct State {
     // TODO implement bpm automation (after non-bpm automation is done ofc)
     pub bpm: f32,
+    /// The top number of the time signature. Every beat is a quarter note.
+    pub beats_per_bar: u32,
 
     pub tracks: IdMap<Track>,
+    /// Audio data used by audio clips. Samples stay in the project when nothing uses them.
+    pub samples: IdMap<Sample>,
     pub root_track: Id<Track>,
     pub song_boundary: Range,
+
+    /// The part of the song that playback loops around when [`Self::looping`] is on.
+    pub loop_range: Range,
+    pub looping: bool,
 }
 
 const _: () = {
@@ -24,7 +34,116 @@ impl State {
     ) -> crate::PreciseSongPos {
         // when bpm automation exists this will have to be changed
         let units = duration.as_secs_f64() / 60.0 * self.bpm as f64 * Range::UNITS_PER_BEAT as f64;
-        pos + crate::PreciseSongPos::from_song_pos_f64(units)
+        self.wrap_position(pos, pos + crate::PreciseSongPos::from_song_pos_f64(units))
+    }
+
+    /// Identifies what a track and all its descendants sound like: this changes whenever any of them are edited (see
+    /// [`Track::version`]), a descendant's fader or sends change, a child is added or removed, or the tempo or song
+    /// boundary changes. Used to tell when prerendered audio of the track is out of date. The track's own fader and
+    /// sends don't count, since they're applied after its output.
+    pub fn track_fingerprint(&self, track_id: Id<Track>) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let track = self.tracks.force_get(track_id);
+        let mut hasher = std::hash::DefaultHasher::new();
+        track_id.hash(&mut hasher);
+        track.version().hash(&mut hasher);
+        self.bpm.to_bits().hash(&mut hasher);
+        // freezing renders the whole song boundary
+        self.song_boundary.hash(&mut hasher);
+        // children aren't in any particular order, so combine them in a way that doesn't depend on the order
+        track
+            .children
+            .iter()
+            .fold(0u64, |sum, &child_id| {
+                sum.wrapping_add(self.routed_track_fingerprint(child_id))
+            })
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+    /// [`Self::track_fingerprint`], plus the track's fader and sends. These change what the track adds to its parent.
+    fn routed_track_fingerprint(&self, track_id: Id<Track>) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let track = self.tracks.force_get(track_id);
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.track_fingerprint(track_id).hash(&mut hasher);
+        track.gain().to_bits().hash(&mut hasher);
+        // same as with children
+        track
+            .sends()
+            .iter()
+            .fold(0u64, |sum, (target_id, send)| {
+                let mut hasher = std::hash::DefaultHasher::new();
+                target_id.hash(&mut hasher);
+                send.amount.to_bits().hash(&mut hasher);
+                send.pre_fader.hash(&mut hasher);
+                sum.wrapping_add(hasher.finish())
+            })
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The track that `track_id` is a child of, or `None` if it's the root track (or doesn't exist).
+    pub fn parent_of(&self, track_id: Id<Track>) -> Option<Id<Track>> {
+        self.tracks
+            .iter()
+            .find(|(_, track)| track.children.contains(&track_id))
+            .map(|(parent_id, _)| parent_id)
+    }
+
+    /// Whether the output of `track_id` depends on the output of `other_id`, either because `other_id` is one of its
+    /// descendants or through sends and sidechains. Routing `track_id` into `other_id` would create a cycle if it does.
+    /// Every track depends on itself.
+    pub fn track_depends_on(&self, track_id: Id<Track>, other_id: Id<Track>) -> bool {
+        let mut visited = IdSet::new();
+        let mut stack = vec![track_id];
+        while let Some(track_id) = stack.pop() {
+            if track_id == other_id {
+                return true;
+            }
+            if !visited.insert(track_id) {
+                continue;
+            }
+            let Some(track) = self.tracks.get(track_id) else {
+                continue;
+            };
+            stack.extend(track.children.iter().copied());
+            stack.extend(track.sidechain_sources());
+            stack.extend(
+                self.tracks
+                    .iter()
+                    .filter(|(_, sender)| sender.sends().has(track_id))
+                    .map(|(sender_id, _)| sender_id),
+            );
+        }
+        false
+    }
+
+    /// The loop range, if looping is on and the range isn't empty.
+    pub fn active_loop(&self) -> Option<Range> {
+        (self.looping && self.loop_range.length() > 0).then_some(self.loop_range)
+    }
+    /// If playing from `from` to `to` passes the end of the loop, returns where playback actually ends up after
+    /// looping back. Otherwise returns `to`. Playback only loops if it starts inside the loop.
+    pub fn wrap_position(
+        &self,
+        from: crate::PreciseSongPos,
+        to: crate::PreciseSongPos,
+    ) -> crate::PreciseSongPos {
+        let Some(loop_range) = self.active_loop() else {
+            return to;
+        };
+        if !loop_range.contains(from.song_pos) {
+            return to;
+        }
+        // whole units, so this is exact
+        let loop_length = crate::PreciseSongPos::from_song_pos(loop_range.length());
+        let mut to = to;
+        while to.song_pos >= loop_range.end {
+            to = to - loop_length;
+        }
+        to
     }
 }
 
@@ -32,10 +151,117 @@ impl Default for State {
     fn default() -> Self {
         Self {
             bpm: 120.0,
+            beats_per_bar: 4,
 
             tracks: IdMap::new(),
+            samples: IdMap::new(),
             root_track: Id::invalid(),
             song_boundary: Range::new(0, 16 * Range::UNITS_PER_BEAT as i64 * 4),
+
+            loop_range: Range::from_beats(0, 16),
+            looping: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PreciseSongPos;
+
+    #[test]
+    fn wrap_position() {
+        let mut state = State {
+            loop_range: Range::new(100, 200),
+            looping: true,
+            ..Default::default()
+        };
+        let pos = |song_pos, fraction| PreciseSongPos::new(song_pos, fraction);
+
+        assert_eq!(state.wrap_position(pos(150, 0), pos(180, 7)), pos(180, 7));
+        assert_eq!(state.wrap_position(pos(150, 0), pos(200, 7)), pos(100, 7));
+        assert_eq!(state.wrap_position(pos(150, 0), pos(431, 7)), pos(131, 7));
+        // playback that starts outside the loop doesn't loop
+        assert_eq!(state.wrap_position(pos(50, 0), pos(250, 0)), pos(250, 0));
+        assert_eq!(state.wrap_position(pos(200, 0), pos(250, 0)), pos(250, 0));
+
+        state.looping = false;
+        assert_eq!(state.wrap_position(pos(150, 0), pos(250, 0)), pos(250, 0));
+    }
+
+    #[test]
+    fn track_fingerprint() {
+        let mut state = State::default();
+        let (root, child) = (Id::arbitrary(), Id::arbitrary());
+        state.tracks.insert(root, Track::new(Default::default()));
+        state.tracks.insert(child, Track::new(Default::default()));
+        state.tracks.force_get_mut(root).children.insert(child);
+
+        let fingerprint = state.track_fingerprint(root);
+        assert_eq!(state.clone().track_fingerprint(root), fingerprint);
+        // reading doesn't count as an edit
+        let _ = state.tracks.force_get(child).patch();
+        assert_eq!(state.track_fingerprint(root), fingerprint);
+
+        // the track's own fader and sends are applied after the frozen audio, but its children's aren't
+        state.tracks.force_get_mut(root).set_gain(0.5);
+        state
+            .tracks
+            .force_get_mut(root)
+            .set_send(child, Some(Default::default()));
+        assert_eq!(state.track_fingerprint(root), fingerprint);
+        state.tracks.force_get_mut(child).set_gain(0.5);
+        let child_gain_changed = state.track_fingerprint(root);
+        assert_ne!(child_gain_changed, fingerprint);
+        state
+            .tracks
+            .force_get_mut(child)
+            .set_send(root, Some(Default::default()));
+        let child_send_added = state.track_fingerprint(root);
+        assert_ne!(child_send_added, child_gain_changed);
+
+        state.tracks.force_get_mut(child).patch_mut();
+        let edited = state.track_fingerprint(root);
+        assert_ne!(edited, child_send_added);
+        state.bpm = 100.0;
+        let tempo_changed = state.track_fingerprint(root);
+        assert_ne!(tempo_changed, edited);
+        state.song_boundary.end += 1;
+        assert_ne!(state.track_fingerprint(root), tempo_changed);
+    }
+    #[test]
+    fn track_depends_on() {
+        let mut state = State::default();
+        let [root, a, b] = [(); 3].map(|_| Id::arbitrary());
+        for track_id in [root, a, b] {
+            state
+                .tracks
+                .insert(track_id, Track::new(Default::default()));
+        }
+        state.tracks.force_get_mut(root).children.extend([a, b]);
+        // `b` reads `a` through a sidechain
+        state.tracks.force_get_mut(b).patch_mut().insert_node(
+            Id::arbitrary(),
+            crate::NodeData::new_disconnected(
+                resourcekey::literal!("builtin:sidechain"),
+                Track::sidechain_args(Some(a)),
+            ),
+            vec![],
+            1,
+        );
+
+        assert!(state.track_depends_on(root, a));
+        assert!(state.track_depends_on(b, a));
+        assert!(!state.track_depends_on(a, b));
+        assert!(!state.track_depends_on(a, root));
+        assert!(state.track_depends_on(a, a));
+
+        // a send from `b` makes `a` depend on `b` too. this is a cycle, so it couldn't be made in the app
+        state
+            .tracks
+            .force_get_mut(b)
+            .set_send(a, Some(Default::default()));
+        assert!(state.track_depends_on(a, b));
+        assert!(state.track_depends_on(b, a));
+    }
+}
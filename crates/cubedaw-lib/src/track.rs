@@ -2,13 +2,19 @@ use std::collections::BTreeMap;
 
 use ahash::HashSetExt;
 
-use crate::{Clip, Id, IdMap, IdSet, Patch, Range};
+use crate::{Buffer, Clip, Id, IdMap, IdSet, Patch, Range};
 
+/// A track in the song. There's no separate kind of group track: any track can have clips and child tracks, and its
+/// children's output is mixed into its patch's input.
 #[derive(Debug, Clone)]
 pub struct Track {
-    pub patch: Patch,
+    patch: Patch,
 
     polyphony: u32,
+    gain: f32,
+    sends: IdMap<Track, TrackSend>,
+    /// Goes up every time the patch, clips or polyphony might've changed. See [`Self::version`].
+    version: u64,
 
     // these two fields are kept synchronized with one another
     clip_map: IdMap<Clip>,
@@ -22,6 +28,9 @@ impl Track {
         Self {
             patch,
             polyphony: 32,
+            gain: 1.0,
+            sends: IdMap::new(),
+            version: 0,
 
             clip_map: Default::default(),
             clips: Default::default(),
@@ -66,7 +75,41 @@ impl Track {
         }
     }
 
+    pub fn patch(&self) -> &Patch {
+        &self.patch
+    }
+    /// The tracks that this track's `builtin:sidechain` nodes read from. See [`crate::State::track_depends_on`].
+    pub fn sidechain_sources(&self) -> impl Iterator<Item = Id<Track>> + '_ {
+        self.patch
+            .nodes()
+            .filter(|(_, node)| node.data.key == resourcekey::literal!("builtin:sidechain"))
+            .filter_map(|(_, node)| Self::sidechain_source(&node.data.inner))
+    }
+    /// Reads the track that a `builtin:sidechain` node reads from out of its args.
+    pub fn sidechain_source(args: &Buffer) -> Option<Id<Track>> {
+        let bytes = args.as_bytes().get(..8)?;
+        let raw = u64::from_ne_bytes(bytes.try_into().expect("unreachable"));
+        std::num::NonZero::new(raw).map(Id::from_raw)
+    }
+    /// The args of a `builtin:sidechain` node that reads from `source`. See [`Self::sidechain_source`].
+    pub fn sidechain_args(source: Option<Id<Track>>) -> Box<Buffer> {
+        let raw = source.map_or(0, |source| source.raw().get());
+        raw.to_ne_bytes().as_slice().into()
+    }
+
+    pub fn patch_mut(&mut self) -> &mut Patch {
+        self.version += 1;
+        &mut self.patch
+    }
+
+    /// A counter that changes whenever the track is mutably accessed in a way that could change how it sounds
+    /// (not counting its children). Identical edits made to two clones of a track keep their versions equal.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     pub fn add_clip(&mut self, clip_id: Id<Clip>, start_pos: i64, clip: Clip) -> &mut Clip {
+        self.version += 1;
         let clip_range = Range::from_start_length(start_pos, clip.length);
         self.check_overlap_with(clip_range);
 
@@ -76,6 +119,7 @@ impl Track {
     }
 
     pub fn remove_clip(&mut self, clip_id: Id<Clip>, start_pos: i64) -> Clip {
+        self.version += 1;
         let clip = self.clip_map.take(clip_id);
         let removed = self
             .clips
@@ -88,6 +132,7 @@ impl Track {
         clip
     }
     pub fn remove_clip_from_range(&mut self, clip_range: Range) -> (Id<Clip>, Clip) {
+        self.version += 1;
         let clip_id = self
             .clips
             .remove(&clip_range)
@@ -97,6 +142,7 @@ impl Track {
     }
 
     pub fn move_clip(&mut self, clip_range: Range, new_start_pos: i64) {
+        self.version += 1;
         let Some(clip_id) = self.clips.remove(&clip_range) else {
             panic!("Track::move_clip was given nonexistent Clip: {clip_range:?}");
         };
@@ -128,8 +174,15 @@ impl Track {
         self.clip_map.get(id)
     }
     pub fn clip_mut(&mut self, id: Id<Clip>) -> Option<&mut Clip> {
+        self.version += 1;
         self.clip_map.get_mut(id)
     }
+    /// Gets the range of a clip. This is O(n) in the number of clips.
+    pub fn clip_range(&self, id: Id<Clip>) -> Option<Range> {
+        self.clips
+            .iter()
+            .find_map(|(&range, &clip_id)| (clip_id == id).then_some(range))
+    }
 
     pub fn clips(&self) -> impl Iterator<Item = (Range, Id<Clip>, &Clip)> {
         self.clips.iter().map(|(&range, &id)| {
@@ -145,6 +198,47 @@ impl Track {
         self.polyphony
     }
     pub fn set_polyphony(&mut self, polyphony: u32) {
+        self.version += 1;
         self.polyphony = polyphony;
     }
+
+    /// The track's fader. Its output is multiplied by this before going to its parent.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+    /// Doesn't change [`Self::version`], since the fader is applied after the track's output.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// The tracks this track's output is sent to, besides its parent.
+    pub fn sends(&self) -> &IdMap<Track, TrackSend> {
+        &self.sends
+    }
+    /// Adds, changes or removes (if `send` is `None`) the send to `target_id`, returning the old one.
+    /// Sends can't make cycles; check [`crate::State::track_depends_on`] first. Like [`Self::set_gain`], this doesn't
+    /// change [`Self::version`].
+    pub fn set_send(&mut self, target_id: Id<Track>, send: Option<TrackSend>) -> Option<TrackSend> {
+        match send {
+            Some(send) => self.sends.replace(target_id, send),
+            None => self.sends.remove(target_id),
+        }
+    }
+}
+
+/// Part of a track's output that's sent to another track's input, like an aux send to a reverb bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackSend {
+    /// How much of the output is sent.
+    pub amount: f32,
+    /// Whether the output is sent before the track's fader (see [`Track::gain`]) is applied.
+    pub pre_fader: bool,
+}
+impl Default for TrackSend {
+    fn default() -> Self {
+        Self {
+            amount: 1.0,
+            pre_fader: false,
+        }
+    }
 }
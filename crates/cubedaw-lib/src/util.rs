@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 /// A precise position in the song. Mainly used for rendering.
 pub struct PreciseSongPos {
     /// Song position, rounded down.
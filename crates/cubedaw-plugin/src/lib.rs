@@ -16,7 +16,7 @@ use resourcekey::{Namespace, ResourceKey};
 
 // TODO: currently we use wasm_encoder types, is it worth revealing implementation details
 // about the crate?
-pub use wasm_encoder::Instruction;
+pub use wasm_encoder::{BlockType, Instruction, MemArg};
 
 pub struct Plugin {
     hash: u64,
@@ -27,6 +27,8 @@ pub struct Plugin {
 
     import_indices: [Option<u32>; CubedawPluginImport::SIZE],
     exported_nodes: ahash::HashMap<ResourceKey, u32>,
+    /// Latencies of the nodes that declared one, in samples.
+    node_latencies: ahash::HashMap<ResourceKey, u32>,
     funcs: Box<[PreparedFunction]>,
     /// The index that the module's functions start at.
     /// This is equal to the number of `Some(_)`s in `cubedaw_imports`.
@@ -109,6 +111,7 @@ impl Plugin {
 
         let mut func_exports: HashMap<&str, u32> = HashMap::new();
         let mut function_names_of_exported_modules: HashMap<ResourceKey, &str> = HashMap::new();
+        let mut node_latencies: HashMap<ResourceKey, u32> = HashMap::new();
 
         let mut start_function: Option<u32> = None;
 
@@ -354,6 +357,19 @@ impl Plugin {
                                 function_names_of_exported_modules.insert(key, export_name);
                             }
                         }
+                        Some(CubedawSectionType::NodeLatency) => {
+                            let mut bytes = section.data();
+                            while !bytes.is_empty() {
+                                let (key, latency): (ResourceKey, u32) =
+                                    postcard_deserialize(&mut bytes).with_context(|| {
+                                        format!(
+                                            "can't deserialize node latency from {:#?}",
+                                            util::ByteString(bytes)
+                                        )
+                                    })?;
+                                node_latencies.insert(key, latency);
+                            }
+                        }
                         Some(CubedawSectionType::PluginMeta) => {
                             let mut bytes = section.data();
                             let mut id: Option<Namespace> = None;
@@ -418,6 +434,13 @@ impl Plugin {
             }
         }
 
+        if let Some(key) = node_latencies
+            .keys()
+            .find(|key| !exported_nodes.contains_key(*key))
+        {
+            anyhow::bail!("plugin declares latency for nonexistent node {key:?}");
+        }
+
         let start_function = start_function.map(|function| funcs[function as usize].clone());
 
         Ok(Self {
@@ -429,6 +452,7 @@ impl Plugin {
 
             import_indices: cubedaw_imports,
             exported_nodes,
+            node_latencies,
             funcs: funcs.into_boxed_slice(),
             func_offset: cubedaw_imports.iter().filter(|i| i.is_some()).count() as u32,
             tables,
@@ -452,6 +476,11 @@ impl Plugin {
         self.exported_nodes.keys()
     }
 
+    /// The latency a node adds to its outputs, in samples. Nodes that don't declare a latency have none.
+    pub fn node_latency(&self, node: &ResourceKey) -> u32 {
+        self.node_latencies.get(node).copied().unwrap_or(0)
+    }
+
     pub fn memory(&self) -> wasm_encoder::MemoryType {
         self.memory
     }
@@ -502,22 +531,28 @@ impl Plugin {
                 .global(global.ty.encode(), &global.init.encode(&info));
         }
         for elem in &self.elems {
+            // element items are function indices, which have to be offset like everything else
+            let items: Box<[u32]> = elem
+                .items
+                .iter()
+                .map(|&func_idx| {
+                    func_idx
+                        .checked_add_signed(info.func_offset)
+                        .expect("element segment references an import")
+                })
+                .collect();
             match elem.kind {
                 misc::ElementKind::Active {
                     table_index,
                     ref offset,
                 } => module.elems.active(
-                    Some(table_index),
+                    Some(table_index + info.table_offset),
                     &offset.encode(&info),
-                    wasm_encoder::Elements::Functions(Cow::Borrowed(&elem.items)),
+                    wasm_encoder::Elements::Functions(Cow::Borrowed(&items)),
                 ),
-                misc::ElementKind::Passive => {
-                    module
-                        .elems
-                        .passive(wasm_encoder::Elements::Functions(Cow::Borrowed(
-                            &elem.items,
-                        )))
-                }
+                misc::ElementKind::Passive => module
+                    .elems
+                    .passive(wasm_encoder::Elements::Functions(Cow::Borrowed(&items))),
             };
         }
         for data in &self.datas {
@@ -530,7 +565,7 @@ impl Plugin {
                     ref offset,
                 } => {
                     module.datas.active(
-                        memory_index,
+                        memory_index + info.memory_offset,
                         &offset.encode(&info),
                         data.data.iter().cloned(),
                     );
@@ -597,12 +632,18 @@ pub enum CubedawPluginImport {
     Input = 1,
     Output = 2,
     Attribute = 3,
+    Sample = 4,
 }
 
 impl CubedawPluginImport {
-    pub const SIZE: usize = 4;
-    pub const ALL: [Self; Self::SIZE] =
-        [Self::SampleRate, Self::Input, Self::Output, Self::Attribute];
+    pub const SIZE: usize = 5;
+    pub const ALL: [Self; Self::SIZE] = [
+        Self::SampleRate,
+        Self::Input,
+        Self::Output,
+        Self::Attribute,
+        Self::Sample,
+    ];
 
     pub fn new(name: &str) -> Option<Self> {
         Some(match name {
@@ -610,6 +651,7 @@ impl CubedawPluginImport {
             "input" => Self::Input,
             "output" => Self::Output,
             "attribute" => Self::Attribute,
+            "sample" => Self::Sample,
             _ => return None,
         })
     }
@@ -620,6 +662,7 @@ impl CubedawPluginImport {
             Self::Input => "input",
             Self::Output => "output",
             Self::Attribute => "attribute",
+            Self::Sample => "sample",
         }
     }
 
@@ -654,6 +697,23 @@ impl CubedawPluginImport {
                     wasm_encoder::ValType::V128,
                 ],
             ),
+            // (sample id, base position, offsets from the base position) -> frames
+            Self::Sample => wasm_encoder::FuncType::new(
+                [
+                    wasm_encoder::ValType::I64,
+                    wasm_encoder::ValType::I32,
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                ],
+                [
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                    wasm_encoder::ValType::V128,
+                ],
+            ),
         }
     }
 }
@@ -662,6 +722,7 @@ enum CubedawSectionType {
     PluginVersion,
     PluginMeta,
     NodeList,
+    NodeLatency,
 }
 
 impl CubedawSectionType {
@@ -670,6 +731,7 @@ impl CubedawSectionType {
             "cubedaw:plugin_version" => Self::PluginVersion,
             "cubedaw:plugin_meta" => Self::PluginMeta,
             "cubedaw:node_list" => Self::NodeList,
+            "cubedaw:node_latency" => Self::NodeLatency,
             _ => return None,
         })
     }
@@ -105,6 +105,31 @@ impl ModuleStitch {
         self.exports
             .export(name, wasm_encoder::ExportKind::Memory, memory_idx);
     }
+    /// Adds a 32-bit memory with at least `min_pages` 64 KiB pages and returns its index.
+    pub fn add_memory(&mut self, min_pages: u64) -> u32 {
+        let memory_idx = self.memories.len();
+        self.memories.memory(wasm_encoder::MemoryType {
+            minimum: min_pages,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        memory_idx
+    }
+    /// Adds a mutable `i32` global and returns its index.
+    pub fn add_global_i32(&mut self, init: i32) -> u32 {
+        let global_idx = self.globals.len();
+        self.globals.global(
+            wasm_encoder::GlobalType {
+                val_type: wasm_encoder::ValType::I32,
+                mutable: true,
+                shared: false,
+            },
+            &wasm_encoder::ConstExpr::i32_const(init),
+        );
+        global_idx
+    }
     pub fn finish(mut self) -> Vec<u8> {
         let mut encoder = wasm_encoder::Module::new();
 
@@ -301,10 +326,13 @@ impl FunctionStitch {
             info.memory_offset
         };
         let d = |data: u32| data + info.data_index;
+        let g = |global: u32| global + info.global_offset;
 
         let modified_inst = match *inst {
             // Call instructions.
             I::Call(func_idx) => I::Call(f(func_idx)),
+            I::ReturnCall(func_idx) => I::ReturnCall(f(func_idx)),
+            I::RefFunc(func_idx) => I::RefFunc(f(func_idx)),
             I::CallIndirect {
                 type_index,
                 table_index,
@@ -430,6 +458,8 @@ impl FunctionStitch {
             I::LocalGet(idx) => I::LocalGet(l(idx)),
             I::LocalSet(idx) => I::LocalSet(l(idx)),
             I::LocalTee(idx) => I::LocalTee(l(idx)),
+            I::GlobalGet(idx) => I::GlobalGet(g(idx)),
+            I::GlobalSet(idx) => I::GlobalSet(g(idx)),
 
             // there are a bunch of atomic/gc/whatever instructions but those aren't allowed in plugins
             // so we don't need to handle them
@@ -0,0 +1,178 @@
+//! Debug-mode detection of heap allocations on the audio threads.
+//!
+//! Install [`GuardedAllocator`] as the global allocator, then every allocation made on a thread while an [`AllocGuard`]
+//! is alive gets counted and reported when the guard is dropped. Without `debug_assertions` the guards do nothing.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    /// How many guards are alive on this thread.
+    static GUARD_DEPTH: Cell<u32> = const { Cell::new(0) };
+    /// Allocations made on this thread while a guard was alive.
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    // `try_with` because this can be called during thread teardown
+    let _ = GUARD_DEPTH.try_with(|depth| {
+        if depth.get() > 0 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        }
+    });
+}
+
+/// The system allocator, but it counts allocations made while an [`AllocGuard`] is alive.
+pub struct GuardedAllocator;
+
+// SAFETY: everything is forwarded to `System`
+unsafe impl GlobalAlloc for GuardedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Reports heap allocations made on the current thread until it's dropped. Guards can be nested; only the outermost one reports.
+#[must_use = "the guard only checks allocations while it's alive"]
+pub struct AllocGuard {
+    name: &'static str,
+}
+
+impl AllocGuard {
+    pub fn new(name: &'static str) -> Self {
+        if cfg!(debug_assertions) {
+            GUARD_DEPTH.with(|depth| {
+                if depth.get() == 0 {
+                    ALLOCATIONS.with(|allocations| allocations.set(0));
+                }
+                depth.set(depth.get() + 1);
+            });
+        }
+        Self { name }
+    }
+
+    /// Allocations made on this thread since the outermost guard was created.
+    pub fn allocations(&self) -> u64 {
+        ALLOCATIONS.with(Cell::get)
+    }
+}
+
+impl Drop for AllocGuard {
+    fn drop(&mut self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let depth = GUARD_DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get()
+        });
+        let allocations = self.allocations();
+        // the guard has to be off before logging, since logging allocates
+        if depth == 0 && allocations > 0 {
+            tracing::warn!(
+                "{allocations} heap allocation(s) during {} on the audio thread",
+                self.name
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cubedaw_lib::{Buffer, Id, Note, Patch, PreciseSongPos, State, Track};
+
+    use super::AllocGuard;
+    use crate::{WorkerHost, WorkerOptions, WorkerState, host::WorkerTrackState};
+
+    #[test]
+    fn counts_allocations() {
+        let guard = AllocGuard::new("test");
+        drop(std::hint::black_box(vec![1u8; 16]));
+        assert_eq!(guard.allocations(), 1);
+    }
+
+    #[test]
+    fn steady_state_processing_doesnt_allocate() {
+        let mut state = State::default();
+        let track_id = Id::arbitrary();
+        state.tracks.insert(track_id, Track::new(Patch::new()));
+        state.root_track = track_id;
+
+        let options = WorkerOptions::new(Default::default());
+        let mut output = Buffer::new_box_zeroed(options.buffer_size);
+        let mut host = WorkerHost::new(state, options);
+        let pos = PreciseSongPos::default();
+        // so it's the stitched graph that gets processed
+        host.options().stitch_cache.wait();
+
+        // the first buffers set up the voice pool and scratch space
+        for _ in 0..4 {
+            host = host.process(None, pos, &mut output);
+        }
+
+        let guard = AllocGuard::new("test");
+        for i in 0..64 {
+            let note_id = Id::new(i);
+            host.start_live_note(track_id, note_id, Note::new(100, 0));
+            host = host.process(None, pos, &mut output);
+            host.stop_live_note(track_id, note_id);
+            host = host.process(None, pos, &mut output);
+        }
+        assert_eq!(guard.allocations(), 0);
+
+        drop(guard);
+        host.join();
+    }
+
+    #[test]
+    fn stitching_while_processing_doesnt_allocate() {
+        let options = WorkerOptions::new(Default::default());
+        let mut state = WorkerState::new(&options);
+        let mut track_state = WorkerTrackState::empty(&options);
+        let key = track_state.track_nodes.stitch_key().unwrap().clone();
+        let silence = Buffer::new_box_zeroed(options.buffer_size);
+        let mut process = |state: &mut WorkerState| {
+            track_state
+                .track_nodes
+                .process(
+                    &options,
+                    state,
+                    &Default::default(),
+                    &silence,
+                    &|_| None,
+                    &silence,
+                )
+                .unwrap();
+        };
+        process(&mut state);
+
+        let guard = AllocGuard::new("test");
+        // the graph finishes stitching in the middle of processing
+        options.stitch_cache.wait();
+        for _ in 0..4 {
+            process(&mut state);
+        }
+        assert_eq!(guard.allocations(), 0);
+        drop(guard);
+        assert!(state.stitched_graph(&key).is_none());
+
+        // and is only instantiated between buffers
+        state.update_stitched_graphs(&options);
+        assert!(state.stitched_graph(&key).is_some());
+        process(&mut state);
+    }
+}
@@ -4,7 +4,12 @@ pub enum HostToWorkerEvent {
     StartProcessing {
         state: &'static State,
         start_pos: PreciseSongPos,
+        /// The job queue for this buffer. It's resized (and thus replaced) when there are more jobs than it can hold.
+        work_tx: crossbeam_channel::Sender<crate::WorkerJob>,
+        work_rx: crossbeam_channel::Receiver<crate::WorkerJob>,
     },
+    /// Throw away and recreate the worker's plugin instances. Sent after an error, since the instances might be in a bad state.
+    RebuildState,
 }
 
 pub enum WorkerToHostEvent {
@@ -14,18 +19,19 @@ pub enum WorkerToHostEvent {
     /// Workers must guarantee that they have dropped all references to the state/worker state before sending `WorkerToHostEvent::Idle`.
     Idle,
 
-    /// The worker encountered an error while processing.
+    /// The worker encountered an error while processing a job on a track. The job's output is left silent and processing continues.
     /// This (unless there are bugs) always means that something has gone wrong that the app can't control; e.g. corrupted project files, abnormal plugin behavior, etc.
     ///
-    /// Since errors may leave worker state in a poisoned state, this means that the worker host has to be reloaded.
-    Error(anyhow::Error),
+    /// Since errors may leave worker state in a poisoned state, the worker host silences the offending node (if there is one; see [`crate::node_graph::NodeError`])
+    /// and tells the workers to rebuild their state.
+    Error {
+        track_id: Id<cubedaw_lib::Track>,
+        error: anyhow::Error,
+    },
 }
 pub enum JobDescriptor {
     NoteProcess {
         track_id: Id<cubedaw_lib::Track>,
         note_descriptor: crate::NoteDescriptor,
     },
-    TrackProcess {
-        track_id: Id<cubedaw_lib::Track>,
-    },
 }
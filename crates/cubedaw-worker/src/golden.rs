@@ -0,0 +1,934 @@
+//! Golden-audio tests: songs are built in code, rendered offline and compared against reference renders in `golden/`.
+//!
+//! After an intentional change to how things sound, run the tests with `CUBEDAW_UPDATE_GOLDEN=1` to rewrite the references,
+//! then listen to the changed files before committing them.
+//!
+//! Most songs only use the builtin nodes. The ones that use the default nodes plugin load the build of it checked in
+//! to `golden/`, so they don't depend on `plugin/` having been built. After changing the plugin, rebuild it in
+//! `plugin/` and copy `cubedaw_default_nodes.wasm` over before updating the references.
+
+use std::{path::PathBuf, sync::Arc};
+
+use ahash::{HashMap, HashMapExt};
+
+use cubedaw_lib::{
+    AudioClip, Buffer, Cable, CableConnection, Clip, Id, IdMap, Node, NodeData, Note, Patch, Range,
+    ResourceKey, Sample, State, Track, TrackSend,
+};
+
+use crate::{
+    DynNodeFactory, Metronome, NodeRegistry, WorkerHost, WorkerOptions, offline,
+    resample::ResampleQuality, wav,
+};
+
+const SAMPLE_RATE: u32 = 8000;
+const BEAT: i64 = Range::UNITS_PER_BEAT as i64;
+/// How far a sample can be from the reference before it counts as a regression.
+const TOLERANCE: f32 = 1e-5;
+const UPDATE_VAR: &str = "CUBEDAW_UPDATE_GOLDEN";
+
+/// A song built out of the builtin nodes. Every note outputs a constant level, so the render shows exactly when
+/// (and how loudly) each note plays.
+struct Song {
+    state: State,
+    clips: IdMap<Track, Id<Clip>>,
+    metronome: Metronome,
+    registry: Arc<NodeRegistry>,
+}
+
+impl Song {
+    /// A song with a root track that outputs `note_level` for each of its notes, multiplied by `gain`.
+    fn new(note_level: f32, gain: f32) -> (Self, Id<Track>) {
+        let mut song = Self {
+            state: State::default(),
+            clips: IdMap::new(),
+            metronome: Metronome::default(),
+            registry: Default::default(),
+        };
+        let root = song.add_track(None, note_level, gain);
+        song.state.root_track = root;
+        (song, root)
+    }
+
+    /// Adds a track that outputs `note_level` for each of its notes plus whatever its children output, multiplied by `gain`.
+    fn add_track(&mut self, parent: Option<Id<Track>>, note_level: f32, gain: f32) -> Id<Track> {
+        let mut patch = Patch::new();
+        let note_output: Id<Node> = Id::arbitrary();
+        patch.insert_node(
+            note_output,
+            NodeData::new_disconnected(resourcekey::literal!("builtin:output"), Default::default()),
+            vec![note_level],
+            1,
+        );
+        let track_output: Id<Node> = Id::arbitrary();
+        patch.insert_node(
+            track_output,
+            NodeData::new_disconnected(
+                resourcekey::literal!("builtin:track_output"),
+                Default::default(),
+            ),
+            vec![0.0],
+            0,
+        );
+        patch.insert_cable(
+            Id::arbitrary(),
+            Cable::one(note_output, track_output),
+            CableConnection { multiplier: gain },
+        );
+
+        let track_id = Id::arbitrary();
+        self.state.tracks.insert(track_id, Track::new(patch));
+        if let Some(parent) = parent {
+            self.state
+                .tracks
+                .force_get_mut(parent)
+                .children
+                .insert(track_id);
+        }
+        track_id
+    }
+
+    /// Adds a note to the track's note clip, which starts at 0 and is created by the first note.
+    fn add_note(&mut self, track_id: Id<Track>, start: i64, length: i64) {
+        let track = self.state.tracks.force_get_mut(track_id);
+        let clip_id = *self.clips.get_mut_or_insert(track_id, || {
+            let clip_id = Id::arbitrary();
+            track.add_clip(
+                clip_id,
+                0,
+                Clip::empty("golden".into(), 64 * Range::UNITS_PER_BEAT),
+            );
+            clip_id
+        });
+        track.clip_mut(clip_id).expect("unreachable").insert_note(
+            start,
+            Id::arbitrary(),
+            Note::new(length as u64, 0),
+        );
+    }
+    fn add_sample(&mut self, sample_rate: u32, data: Vec<f32>) -> Id<Sample> {
+        let sample_id = Id::arbitrary();
+        self.state.samples.insert(
+            sample_id,
+            Sample::new("golden".into(), sample_rate, data.into()),
+        );
+        sample_id
+    }
+    fn add_audio_clip(&mut self, track_id: Id<Track>, start: i64, length: i64, audio: AudioClip) {
+        self.state.tracks.force_get_mut(track_id).add_clip(
+            Id::arbitrary(),
+            start,
+            Clip::audio("golden".into(), length as u64, audio),
+        );
+    }
+    /// Adds a `builtin:sidechain` node that reads `source`, multiplied by `gain`, into the track's output.
+    fn add_sidechain(&mut self, track_id: Id<Track>, source: Id<Track>, gain: f32) {
+        let patch = self.state.tracks.force_get_mut(track_id).patch_mut();
+        let track_output = patch
+            .get_active_node(&resourcekey::literal!("builtin:track_output"))
+            .unwrap();
+        let sidechain: Id<Node> = Id::arbitrary();
+        patch.insert_node(
+            sidechain,
+            NodeData::new_disconnected(
+                resourcekey::literal!("builtin:sidechain"),
+                Track::sidechain_args(Some(source)),
+            ),
+            vec![],
+            1,
+        );
+        patch.insert_cable(
+            Id::arbitrary(),
+            Cable::one(sidechain, track_output),
+            CableConnection { multiplier: gain },
+        );
+    }
+    /// Puts a node between the track's note output and its track output, so every note goes through it.
+    /// The node's first input is connected and the rest are set to `inputs`.
+    fn insert_note_node(
+        &mut self,
+        track_id: Id<Track>,
+        key: ResourceKey,
+        args: Box<Buffer>,
+        inputs: Vec<f32>,
+    ) {
+        let patch = self.state.tracks.force_get_mut(track_id).patch_mut();
+        let note_output = patch
+            .get_active_node(&resourcekey::literal!("builtin:output"))
+            .unwrap();
+        let track_output = patch
+            .get_active_node(&resourcekey::literal!("builtin:track_output"))
+            .unwrap();
+        let (cable_id, _) = patch
+            .cables()
+            .find(|(_, cable)| cable.input_node == note_output)
+            .unwrap();
+        let (_, connection) = patch.take_cable(cable_id);
+
+        let node: Id<Node> = Id::arbitrary();
+        patch.insert_node(
+            node,
+            NodeData::new_disconnected(key, args),
+            [0.0].into_iter().chain(inputs).collect(),
+            1,
+        );
+        patch.insert_cable(
+            Id::arbitrary(),
+            Cable::one(note_output, node),
+            CableConnection { multiplier: 1.0 },
+        );
+        patch.insert_cable(Id::arbitrary(), Cable::one(node, track_output), connection);
+    }
+    /// Puts a node in front of the track's note output, so every note plays its own copy of it.
+    /// The node's inputs are set to `inputs`.
+    fn insert_voice_node(
+        &mut self,
+        track_id: Id<Track>,
+        key: ResourceKey,
+        args: Box<Buffer>,
+        inputs: Vec<f32>,
+    ) -> Id<Node> {
+        let patch = self.state.tracks.force_get_mut(track_id).patch_mut();
+        let note_output = patch
+            .get_active_node(&resourcekey::literal!("builtin:output"))
+            .unwrap();
+
+        let node: Id<Node> = Id::arbitrary();
+        patch.insert_node(node, NodeData::new_disconnected(key, args), inputs, 1);
+        patch.insert_cable(
+            Id::arbitrary(),
+            Cable::one(node, note_output),
+            CableConnection { multiplier: 1.0 },
+        );
+        node
+    }
+    /// Sets how long changes to a node's input take to fade in, like the app does when it creates the node.
+    fn set_input_smoothing(
+        &mut self,
+        track_id: Id<Track>,
+        node_id: Id<Node>,
+        input_index: usize,
+        smoothing_ms: f32,
+    ) {
+        let patch = self.state.tracks.force_get_mut(track_id).patch_mut();
+        let node = patch.node_entry_mut(node_id).unwrap();
+        node.inputs_mut()[input_index].smoothing_ms = Some(smoothing_ms);
+    }
+    fn add_send(&mut self, track_id: Id<Track>, target_id: Id<Track>, send: TrackSend) {
+        self.state
+            .tracks
+            .force_get_mut(track_id)
+            .set_send(target_id, Some(send));
+    }
+    fn set_polyphony(&mut self, track_id: Id<Track>, polyphony: u32) {
+        self.state
+            .tracks
+            .force_get_mut(track_id)
+            .set_polyphony(polyphony);
+    }
+
+    fn set_loop(&mut self, loop_range: Range) {
+        self.state.loop_range = loop_range;
+        self.state.looping = true;
+    }
+
+    fn options(&self, num_workers: u32) -> WorkerOptions {
+        let mut options = WorkerOptions::with_audio(
+            self.registry.clone(),
+            SAMPLE_RATE,
+            WorkerOptions::DEFAULT_BUFFER_SIZE,
+        );
+        options.num_workers = num_workers;
+        // keeps the references easy to check by hand; the sinc kernels are tested in `resample`
+        options.resample_quality = ResampleQuality::Linear;
+        options
+    }
+    fn num_samples(&self, beats: u32) -> usize {
+        let samples_per_beat = SAMPLE_RATE as f32 * 60.0 / self.state.bpm;
+        (beats as f32 * samples_per_beat) as usize
+    }
+
+    fn render(&self, beats: u32, num_workers: u32) -> Vec<f32> {
+        let options = self.options(num_workers);
+        let num_samples = self.num_samples(beats);
+        offline::render_with_metronome(self.state.clone(), options, self.metronome, 0, num_samples)
+            .expect("render failed")
+    }
+
+    /// Renders `beats` beats of the song and compares it to the reference named `name`.
+    /// The song is rendered with different numbers of workers, since that shouldn't change the result.
+    #[track_caller]
+    fn check(&self, name: &str, beats: u32) {
+        let rendered = self.render(beats, 1);
+        check_golden(name, &rendered);
+        for num_workers in [2, 4] {
+            compare(
+                &format!("{name} with {num_workers} workers"),
+                &rendered,
+                &self.render(beats, num_workers),
+            );
+        }
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "golden", &format!("{name}.wav")]
+        .iter()
+        .collect()
+}
+
+#[track_caller]
+fn check_golden(name: &str, rendered: &[f32]) {
+    let path = golden_path(name);
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::write(&path, wav::encode(rendered, SAMPLE_RATE)).expect("failed to write golden");
+        return;
+    }
+
+    let Ok(bytes) = std::fs::read(&path) else {
+        panic!(
+            "no reference render at {}; rerun with {UPDATE_VAR}=1 to create it",
+            path.display()
+        );
+    };
+    let reference = wav::decode(name.into(), &bytes).expect("reference render is corrupted");
+    assert_eq!(
+        reference.sample_rate(),
+        SAMPLE_RATE,
+        "reference render {name} has the wrong sample rate"
+    );
+
+    if let Err(message) = try_compare(reference.data(), rendered) {
+        // save what we got so it can be listened to/diffed
+        let actual_path = std::env::temp_dir().join(format!("cubedaw-golden-{name}.wav"));
+        let _ = std::fs::write(&actual_path, wav::encode(rendered, SAMPLE_RATE));
+        panic!(
+            "{name} doesn't match its reference render: {message}\nthe render was saved to {}; if the change is intentional, rerun with {UPDATE_VAR}=1",
+            actual_path.display()
+        );
+    }
+}
+
+#[track_caller]
+fn compare(name: &str, expected: &[f32], actual: &[f32]) {
+    if let Err(message) = try_compare(expected, actual) {
+        panic!("{name}: {message}");
+    }
+}
+
+fn try_compare(expected: &[f32], actual: &[f32]) -> Result<(), String> {
+    if expected.len() != actual.len() {
+        return Err(format!(
+            "expected {} samples, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    let mismatches: Vec<_> = expected
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|&(_, (a, b))| (a - b).abs() > TOLERANCE || a.is_nan() != b.is_nan())
+        .collect();
+    match mismatches.first() {
+        None => Ok(()),
+        Some(&(index, (expected, actual))) => Err(format!(
+            "{} samples differ, starting at sample {index} (expected {expected}, got {actual})",
+            mismatches.len()
+        )),
+    }
+}
+
+#[test]
+fn single_note() {
+    let (mut song, root) = Song::new(0.5, 1.0);
+    // starts in the middle of a buffer and between two samples
+    song.add_note(root, BEAT / 3, BEAT);
+    song.check("single_note", 2);
+}
+
+#[test]
+fn overlapping_notes() {
+    let (mut song, root) = Song::new(0.25, 0.5);
+    song.add_note(root, 0, 2 * BEAT);
+    song.add_note(root, BEAT / 2, BEAT);
+    song.add_note(root, BEAT, BEAT / 4);
+    song.check("overlapping_notes", 3);
+}
+
+#[test]
+fn polyphony_limit() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    song.set_polyphony(root, 2);
+    song.add_note(root, 0, 2 * BEAT);
+    song.add_note(root, BEAT / 4, BEAT);
+    // dropped, since the other two notes are still playing
+    song.add_note(root, BEAT / 2, BEAT);
+    // plays, since the second note ended
+    song.add_note(root, 3 * BEAT / 2, BEAT);
+    song.check("polyphony_limit", 3);
+}
+
+#[test]
+fn group_tracks() {
+    let (mut song, root) = Song::new(1.0, 0.5);
+    let a = song.add_track(Some(root), 0.25, 1.0);
+    let b = song.add_track(Some(root), 0.5, 1.0);
+    let nested = song.add_track(Some(b), 0.125, 2.0);
+    song.add_note(root, 0, BEAT / 2);
+    song.add_note(a, BEAT / 4, BEAT);
+    song.add_note(b, BEAT / 2, BEAT);
+    song.add_note(nested, BEAT, BEAT);
+    song.check("group_tracks", 3);
+}
+
+#[test]
+fn looping() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    // the loop's ends don't line up with samples or buffers
+    song.set_loop(Range::new(BEAT / 3, 2 * BEAT + BEAT / 3));
+    // starts before the loop, so it only plays the first time
+    song.add_note(root, 0, BEAT);
+    // starts at the start of the loop, so it plays every time
+    song.add_note(root, BEAT / 3, BEAT / 2);
+    // cut off by the end of the loop every time
+    song.add_note(root, BEAT, 2 * BEAT);
+    song.check("looping", 6);
+}
+
+#[test]
+fn metronome() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    song.state.beats_per_bar = 3;
+    song.metronome = Metronome {
+        enabled: true,
+        level: 0.5,
+    };
+    // the loop starts between beats, so the first beat after looping back is the second beat of the bar
+    song.set_loop(Range::new(BEAT / 2, 4 * BEAT));
+    song.add_note(root, BEAT / 2, BEAT);
+    song.check("metronome", 8);
+}
+
+#[test]
+fn audio_clips() {
+    let (mut song, root) = Song::new(0.0, 0.5);
+    // half the output sample rate, so every other output sample is interpolated
+    let sample_id = song.add_sample(
+        SAMPLE_RATE / 2,
+        (0..4000).map(|i| (i as f32 * 0.05).sin()).collect(),
+    );
+    song.set_loop(Range::new(BEAT / 2, 2 * BEAT + BEAT / 2));
+    // starts before the loop, so playback starts partway through it after looping back
+    song.add_audio_clip(
+        root,
+        BEAT / 3,
+        BEAT,
+        AudioClip {
+            start_offset: 100,
+            gain: 0.8,
+            fade_in: BEAT as u64 / 4,
+            fade_out: BEAT as u64 / 4,
+            ..AudioClip::new(sample_id)
+        },
+    );
+    // cut off by the end of the loop
+    song.add_audio_clip(root, 3 * BEAT / 2, BEAT * 2, AudioClip::new(sample_id));
+    song.check("audio_clips", 5);
+}
+
+/// A registry with the builtin nodes and the checked-in build of the default nodes plugin.
+fn default_nodes_registry() -> Arc<NodeRegistry> {
+    let plugin = cubedaw_plugin::Plugin::new(include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/golden/cubedaw_default_nodes.wasm"
+    )))
+    .expect("the default nodes plugin isn't valid");
+
+    // the real factories are part of the app's node UIs; zeroed state is enough for every default node
+    let mut dyn_node_factories = HashMap::new();
+    for key in plugin.exported_nodes() {
+        dyn_node_factories.insert(key.clone(), DynNodeFactory::new(|_| Box::new([0; 16])));
+    }
+    let mut registry = NodeRegistry::default();
+    registry.register_plugin(plugin, &mut dyn_node_factories);
+    Arc::new(registry)
+}
+
+#[test]
+fn default_nodes() {
+    let (mut song, root) = Song::new(0.5, 1.0);
+    song.registry = default_nodes_registry();
+    // `cubedaw:math`'s args start with the operation, and 2 is multiply
+    song.insert_note_node(
+        root,
+        resourcekey::literal!("cubedaw:math"),
+        [2u8, 0, 0, 0].as_slice().into(),
+        vec![0.5],
+    );
+    song.add_note(root, BEAT / 3, BEAT);
+
+    let (mut expected, root) = Song::new(0.25, 1.0);
+    expected.add_note(root, BEAT / 3, BEAT);
+    let expected = expected.render(3, 1);
+    for num_workers in [1, 2, 4] {
+        compare(
+            &format!("default nodes with {num_workers} workers"),
+            &expected,
+            &song.render(3, num_workers),
+        );
+    }
+}
+
+#[test]
+fn oscillator() {
+    let (mut song, root) = Song::new(0.0, 1.0);
+    song.registry = default_nodes_registry();
+    // a saw (1) with relative pitch (0), half an octave above the note
+    let node_id = song.insert_voice_node(
+        root,
+        resourcekey::literal!("cubedaw:oscillator"),
+        [1u8, 0, 0, 0].as_slice().into(),
+        vec![0.5],
+    );
+    song.set_input_smoothing(root, node_id, 0, 0.0);
+    song.add_note(root, BEAT / 3, BEAT);
+    song.check("oscillator", 2);
+}
+
+#[test]
+fn sampler() {
+    let (mut song, root) = Song::new(0.0, 1.0);
+    song.registry = default_nodes_registry();
+    let sample_id = song.add_sample(SAMPLE_RATE, (0..400).map(|i| i as f32 / 400.0).collect());
+
+    // a root pitch above the note's plays the sample back slower than it was recorded, between its samples
+    let mut args = Vec::new();
+    args.extend(sample_id.raw().get().to_le_bytes());
+    args.extend(0.5f32.to_le_bytes());
+    // loops between 10ms and 30ms in
+    args.extend(0.01f32.to_le_bytes());
+    args.extend(0.03f32.to_le_bytes());
+    // relative pitch, then padding
+    args.extend([0, 0, 0, 0]);
+    let node_id = song.insert_voice_node(
+        root,
+        resourcekey::literal!("cubedaw:sampler"),
+        args.as_slice().into(),
+        vec![0.0],
+    );
+    song.set_input_smoothing(root, node_id, 0, 0.0);
+    song.add_note(root, BEAT / 3, BEAT);
+    song.check("sampler", 2);
+}
+
+#[test]
+fn render_resampled() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    song.add_note(root, 0, 2 * BEAT);
+    let direct = song.render(3, 1);
+
+    let mut options = song.options(1);
+    options.resample_quality = ResampleQuality::High;
+    let resampled = offline::render_resampled(
+        song.state.clone(),
+        &options,
+        2 * SAMPLE_RATE,
+        0,
+        direct.len(),
+    )
+    .expect("render failed");
+    assert_eq!(resampled.len(), direct.len());
+    // the kernel rings around the start and end of the note, so only compare the middle
+    let middle = 1000..7000;
+    compare(
+        "resampled render",
+        &direct[middle.clone()],
+        &resampled[middle],
+    );
+}
+
+#[test]
+fn frozen_track() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    let child = song.add_track(Some(root), 0.5, 0.5);
+    let grandchild = song.add_track(Some(child), 0.125, 1.0);
+    song.set_loop(Range::new(BEAT / 3, 2 * BEAT + BEAT / 3));
+    song.add_note(root, 0, BEAT);
+    song.add_note(child, BEAT / 3, BEAT / 2);
+    song.add_note(grandchild, BEAT, BEAT / 2);
+
+    let frozen = offline::freeze(&song.state, song.options(1), child).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(child, Some(frozen));
+    assert!(host.is_frozen(child));
+    let rendered = offline::render_with_host(host, 0, song.num_samples(6)).expect("render failed");
+    compare("frozen track", &song.render(6, 1), &rendered);
+}
+
+#[test]
+fn editing_unfreezes() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    let child = song.add_track(Some(root), 0.5, 0.5);
+    let grandchild = song.add_track(Some(child), 0.125, 1.0);
+
+    let frozen = offline::freeze(&song.state, song.options(1), child).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(child, Some(frozen.clone()));
+    host.state_mut()
+        .tracks
+        .force_get_mut(grandchild)
+        .set_polyphony(4);
+    let mut buffer = cubedaw_lib::Buffer::new_box_zeroed(host.options().buffer_size);
+    host = host.process(None, Default::default(), &mut buffer);
+    assert!(!host.is_frozen(child));
+
+    // out of date, so this does nothing
+    host.set_frozen(child, Some(frozen));
+    assert!(!host.is_frozen(child));
+    host.join();
+}
+
+#[test]
+fn moving_the_fader_keeps_frozen() {
+    let (mut song, root) = Song::new(0.25, 1.0);
+    let child = song.add_track(Some(root), 0.5, 1.0);
+    let grandchild = song.add_track(Some(child), 0.125, 1.0);
+    song.add_note(child, 0, BEAT);
+    song.add_note(grandchild, BEAT / 2, BEAT);
+    let frozen = offline::freeze(&song.state, song.options(1), child).expect("freeze failed");
+
+    // the fader is applied when the frozen audio is played back
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(child, Some(frozen.clone()));
+    for state in [&mut song.state, host.state_mut()] {
+        state.tracks.force_get_mut(child).set_gain(0.5);
+    }
+    let mut buffer = cubedaw_lib::Buffer::new_box_zeroed(host.options().buffer_size);
+    host = host.process(None, Default::default(), &mut buffer);
+    assert!(host.is_frozen(child));
+    let rendered = offline::render_with_host(host, 0, song.num_samples(2)).expect("render failed");
+    compare(
+        "frozen track with its fader moved",
+        &song.render(2, 1),
+        &rendered,
+    );
+
+    // but the grandchild's fader isn't
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(child, Some(frozen));
+    host.state_mut()
+        .tracks
+        .force_get_mut(grandchild)
+        .set_gain(0.5);
+    host = host.process(None, Default::default(), &mut buffer);
+    assert!(!host.is_frozen(child));
+    host.join();
+}
+
+#[test]
+fn live_input() {
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let patch = song.state.tracks.force_get_mut(root).patch_mut();
+    let track_output = patch
+        .get_active_node(&resourcekey::literal!("builtin:track_output"))
+        .unwrap();
+    let audio_input: Id<Node> = Id::arbitrary();
+    patch.insert_node(
+        audio_input,
+        NodeData::new_disconnected(
+            resourcekey::literal!("builtin:audio_input"),
+            Default::default(),
+        ),
+        vec![],
+        1,
+    );
+    patch.insert_cable(
+        Id::arbitrary(),
+        Cable::one(audio_input, track_output),
+        CableConnection { multiplier: 0.5 },
+    );
+
+    for stitch in [false, true] {
+        let mut options = song.options(1);
+        options.stitch_node_graphs = stitch;
+        let mut host = WorkerHost::new(song.state.clone(), options);
+        let mut buffer = cubedaw_lib::Buffer::new_box_zeroed(host.options().buffer_size);
+        for (i, sample) in host.live_input_mut().iter_mut().enumerate() {
+            *sample = i as f32 / 256.0;
+        }
+        host = host.process(None, Default::default(), &mut buffer);
+        for (i, &sample) in buffer.iter().enumerate() {
+            assert_eq!(sample, i as f32 / 512.0, "stitched: {stitch}");
+        }
+
+        // the input isn't held over to the next buffer
+        host = host.process(None, Default::default(), &mut buffer);
+        assert!(buffer.iter().all(|&sample| sample == 0.0));
+        host.join();
+    }
+}
+
+#[test]
+fn sidechain() {
+    // `b` cancels out `a`, so only `b`'s own notes are left
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let a = song.add_track(Some(root), 0.5, 1.0);
+    let b = song.add_track(Some(root), 0.25, 1.0);
+    song.add_sidechain(b, a, -1.0);
+    song.add_note(a, 0, 2 * BEAT);
+    song.add_note(b, BEAT / 3, BEAT);
+
+    let (mut expected, root) = Song::new(0.0, 1.0);
+    let b = expected.add_track(Some(root), 0.25, 1.0);
+    expected.add_note(b, BEAT / 3, BEAT);
+    let expected = expected.render(3, 1);
+    for num_workers in [1, 2, 4] {
+        compare(
+            &format!("sidechain with {num_workers} workers"),
+            &expected,
+            &song.render(3, num_workers),
+        );
+    }
+}
+
+#[test]
+fn broken_tracks_are_silenced() {
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let good = song.add_track(Some(root), 0.5, 1.0);
+    let broken = song.add_track(Some(root), 0.25, 1.0);
+    song.add_note(good, 0, BEAT);
+    song.add_note(broken, 0, BEAT);
+    song.insert_note_node(
+        broken,
+        resourcekey::literal!("test:missing"),
+        Default::default(),
+        vec![],
+    );
+
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    let errors = host.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].track_id, Some(broken));
+    assert!(errors[0].node_id.is_some());
+
+    // tracks added later are reported the same way, and don't stop the others from syncing
+    let added = song.add_track(Some(root), 0.125, 1.0);
+    song.insert_note_node(
+        added,
+        resourcekey::literal!("test:missing"),
+        Default::default(),
+        vec![],
+    );
+    song.state.tracks.force_get_mut(good).set_polyphony(4);
+    *host.state_mut() = song.state.clone();
+    let mut buffer = Buffer::new_box_zeroed(host.options().buffer_size);
+    host = host.process(None, Default::default(), &mut buffer);
+    let errors = host.take_errors();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|error| error.track_id == Some(added)));
+
+    let (mut expected, root) = Song::new(0.0, 1.0);
+    let good = expected.add_track(Some(root), 0.5, 1.0);
+    expected.add_note(good, 0, BEAT);
+    expected.set_polyphony(good, 4);
+    compare(
+        "song with broken tracks",
+        &expected.render(2, 1),
+        &offline::render_with_host(host, 0, song.num_samples(2)).expect("render failed"),
+    );
+}
+
+#[test]
+fn freezing_with_external_sidechains() {
+    // the bass ducks under the kick, like in `sidechain`
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let drums = song.add_track(Some(root), 0.0, 1.0);
+    let kick = song.add_track(Some(drums), 0.5, 1.0);
+    let bass_group = song.add_track(Some(root), 0.0, 1.0);
+    let bass = song.add_track(Some(bass_group), 0.25, 1.0);
+    song.add_sidechain(bass, kick, -1.0);
+    song.add_note(kick, 0, 2 * BEAT);
+    song.add_note(bass, BEAT / 3, BEAT);
+    let expected = song.render(3, 1);
+
+    // the kick's sidechain output comes from its frozen audio
+    let frozen = offline::freeze(&song.state, song.options(1), kick).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(kick, Some(frozen));
+    assert!(host.is_frozen(kick));
+    let rendered = offline::render_with_host(host, 0, song.num_samples(3)).expect("render failed");
+    compare("frozen sidechain source", &expected, &rendered);
+
+    // but the kick isn't processed while the drums are frozen
+    assert_eq!(
+        offline::freeze_blocker(&song.state, drums),
+        Some(offline::FreezeBlocker::SidechainOut {
+            source: kick,
+            consumer: bass
+        })
+    );
+    // and the kick wouldn't be in the bass group's frozen audio
+    for track_id in [bass, bass_group] {
+        assert_eq!(
+            offline::freeze_blocker(&song.state, track_id),
+            Some(offline::FreezeBlocker::SidechainIn {
+                source: kick,
+                consumer: bass
+            })
+        );
+        assert!(offline::freeze(&song.state, song.options(1), track_id).is_err());
+    }
+
+    // sidechains inside the frozen track are fine
+    let frozen = offline::freeze(&song.state, song.options(1), root).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(root, Some(frozen));
+    assert!(host.is_frozen(root));
+    let rendered = offline::render_with_host(host, 0, song.num_samples(3)).expect("render failed");
+    compare("frozen sidechain", &expected, &rendered);
+}
+
+#[test]
+fn sidechain_cycle() {
+    // neither sidechain can be satisfied, so both are silent instead of deadlocking
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let a = song.add_track(Some(root), 0.5, 1.0);
+    let b = song.add_track(Some(root), 0.25, 1.0);
+    song.add_sidechain(a, b, 1.0);
+    song.add_sidechain(b, a, 1.0);
+    song.add_note(a, 0, BEAT);
+    song.add_note(b, 0, BEAT);
+
+    let rendered = song.render(2, 2);
+    let beat = song.num_samples(1);
+    assert!(rendered[..beat].iter().all(|&sample| sample == 0.75));
+}
+
+#[test]
+fn sends() {
+    for pre_fader in [false, true] {
+        let (mut song, root) = Song::new(0.0, 1.0);
+        let track = song.add_track(Some(root), 0.5, 1.0);
+        let bus = song.add_track(Some(root), 0.0, 1.0);
+        song.state.tracks.force_get_mut(track).set_gain(0.5);
+        song.add_send(
+            track,
+            bus,
+            TrackSend {
+                amount: 0.5,
+                pre_fader,
+            },
+        );
+        song.add_note(track, BEAT / 3, BEAT);
+
+        // the same as the track playing directly, plus what it sends
+        let sent = if pre_fader {
+            0.5 * 0.5
+        } else {
+            0.5 * 0.5 * 0.5
+        };
+        let (mut expected, root) = Song::new(0.0, 1.0);
+        let direct = expected.add_track(Some(root), 0.5 * 0.5 + sent, 1.0);
+        expected.add_note(direct, BEAT / 3, BEAT);
+        let expected = expected.render(3, 1);
+        for num_workers in [1, 2, 4] {
+            compare(
+                &format!("sends (pre-fader: {pre_fader}) with {num_workers} workers"),
+                &expected,
+                &song.render(3, num_workers),
+            );
+        }
+
+        // frozen audio doesn't have the fader in it, so pre-fader sends still work
+        let frozen = offline::freeze(&song.state, song.options(1), track).expect("freeze failed");
+        let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+        host.set_frozen(track, Some(frozen));
+        let rendered =
+            offline::render_with_host(host, 0, song.num_samples(3)).expect("render failed");
+        compare(
+            &format!("frozen sends (pre-fader: {pre_fader})"),
+            &expected,
+            &rendered,
+        );
+    }
+}
+
+#[test]
+fn send_cycle() {
+    // each send would make a cycle, so both are ignored instead of deadlocking
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let a = song.add_track(Some(root), 0.5, 1.0);
+    let b = song.add_track(Some(root), 0.25, 1.0);
+    song.add_send(a, b, TrackSend::default());
+    song.add_send(b, a, TrackSend::default());
+    // the root can't send to its own child either
+    song.add_send(root, a, TrackSend::default());
+    song.add_note(a, 0, BEAT);
+    song.add_note(b, 0, BEAT);
+
+    let rendered = song.render(2, 2);
+    let beat = song.num_samples(1);
+    assert!(rendered[..beat].iter().all(|&sample| sample == 0.75));
+}
+
+#[test]
+fn freezing_with_external_sends() {
+    let (mut song, root) = Song::new(0.0, 1.0);
+    let group = song.add_track(Some(root), 0.0, 1.0);
+    let child = song.add_track(Some(group), 0.5, 1.0);
+    let bus = song.add_track(Some(root), 0.0, 1.0);
+    song.add_note(child, 0, BEAT);
+
+    // the group's own sends are played from the frozen audio, so it can be frozen
+    song.add_send(group, bus, TrackSend::default());
+    let frozen = offline::freeze(&song.state, song.options(1), group).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(group, Some(frozen.clone()));
+    assert!(host.is_frozen(group));
+    let rendered = offline::render_with_host(host, 0, song.num_samples(2)).expect("render failed");
+    compare("frozen group with a send", &song.render(2, 1), &rendered);
+
+    // but its child isn't processed while it's frozen, so the child's sends would go missing
+    song.add_send(child, bus, TrackSend::default());
+    assert_eq!(
+        offline::freeze_blocker(&song.state, group),
+        Some(offline::FreezeBlocker::SendOut {
+            from: child,
+            to: bus
+        })
+    );
+    assert!(offline::freeze(&song.state, song.options(1), group).is_err());
+    song.state.tracks.force_get_mut(child).set_send(bus, None);
+    // (the bus can't send back while the group sends to it)
+    song.state.tracks.force_get_mut(group).set_send(bus, None);
+
+    // and it doesn't use its input, so sends to it or its child would too
+    song.add_send(bus, child, TrackSend::default());
+    assert_eq!(
+        offline::freeze_blocker(&song.state, group),
+        Some(offline::FreezeBlocker::SendIn {
+            from: bus,
+            to: child
+        })
+    );
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(group, Some(frozen));
+    assert!(!host.is_frozen(group));
+    host.join();
+
+    // which also unfreezes it if it's added after the group was frozen
+    song.state.tracks.force_get_mut(bus).set_send(child, None);
+    let frozen = offline::freeze(&song.state, song.options(1), group).expect("freeze failed");
+    let mut host = WorkerHost::new(song.state.clone(), song.options(1));
+    host.set_frozen(group, Some(frozen));
+    assert!(host.is_frozen(group));
+    host.state_mut()
+        .tracks
+        .force_get_mut(bus)
+        .set_send(child, Some(TrackSend::default()));
+    let mut buffer = cubedaw_lib::Buffer::new_box_zeroed(host.options().buffer_size);
+    host = host.process(None, Default::default(), &mut buffer);
+    assert!(!host.is_frozen(group));
+    host.join();
+}
@@ -1,22 +1,47 @@
 use std::{fmt::Debug, sync::Arc, thread};
 
-use anyhow::Context;
-use cubedaw_lib::{Buffer, IdMap, InternalBufferType, PreciseSongPos, Range, State};
+use cubedaw_lib::{
+    Buffer, Clip, Id, IdMap, InternalBufferType, Node, Note, PreciseSongPos, Range, State, Track,
+};
 
 use crate::{
-    WorkerJob, WorkerOptions,
+    Level, Metronome, NoteDescriptor, Probe, WorkerJob, WorkerOptions,
+    alloc_guard::AllocGuard,
     common::{HostToWorkerEvent, JobDescriptor, WorkerToHostEvent},
+    metronome::MetronomeState,
+    node_graph::NodeError,
+    offline::{self, FrozenTrack},
+    stretch::TimeStretch,
     sync::SyncBuffer,
     worker,
 };
 mod state;
-pub use state::{WorkerHostState, WorkerLiveNoteState, WorkerNoteState, WorkerTrackState};
+pub use state::{
+    VoicePool, WorkerHostState, WorkerLiveNoteState, WorkerNoteState, WorkerTrackState,
+};
+
+/// An error that happened while processing. See [`WorkerHost::take_errors`].
+#[derive(Debug)]
+pub struct ProcessingError {
+    /// The track the error happened on, if any.
+    pub track_id: Option<Id<Track>>,
+    /// The node that caused the error, if any. This node is silenced until the worker host is recreated.
+    pub node_id: Option<Id<Node>>,
+    pub error: anyhow::Error,
+}
 
 /// An interface for controlling the audio worker threads. This won't run on the ui thread. Please do not run this on the ui thread.
 #[derive(Debug)]
 pub struct WorkerHost {
     state: State,
     worker_state: WorkerHostState,
+    errors: Vec<ProcessingError>,
+    /// The level of the master output during the last buffer.
+    master_level: Level,
+    metronome: MetronomeState,
+
+    /// Whether `state` might've changed since `worker_state` was last synced with it.
+    needs_sync: bool,
 
     worker_handles: Box<[WorkerHandle]>,
     worker_options: Arc<WorkerOptions>,
@@ -25,30 +50,74 @@ pub struct WorkerHost {
 
     work_tx: crossbeam_channel::Sender<WorkerJob>,
     work_rx: crossbeam_channel::Receiver<WorkerJob>,
+
+    /// Audio coming into the `builtin:audio_input` nodes during the next buffer. See [`Self::live_input_mut`].
+    live_input: Box<Buffer>,
+
+    /// Arena for per-buffer allocations. This is reset (not freed) after every buffer.
+    allocator: Box<bumpalo::Bump>,
+    scratch: ProcessScratch,
+}
+
+/// How many events can be queued from the host to a worker or from the workers to the host. Neither side gets far ahead of the other.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Collections that are reused across calls to [`WorkerHost::process`] so processing doesn't allocate.
+/// The references in here only live for one buffer; everything is cleared before the arena is reset.
+#[derive(Default)]
+struct ProcessScratch {
+    track_data: IdMap<Track, &'static mut WorkerTrackState>,
+    track_stack: Vec<(Id<Track>, &'static WorkerJobSyncBuffer)>,
+    track_jobs: Vec<(&'static WorkerJobSyncBuffer, WorkerJob)>,
+    /// The inputs of the tracks that get processed this buffer, and whether their nodes run (they don't if they're frozen).
+    track_inputs: IdMap<Track, (&'static WorkerJobSyncBuffer, bool)>,
+    /// The outputs of the tracks that `builtin:sidechain` nodes read from.
+    sidechain_outputs: IdMap<Track, &'static SidechainSyncBuffer>,
+    processed_stack: Vec<Id<Track>>,
+    deleted_notes: Vec<(Id<Track>, NoteDescriptor)>,
+}
+
+impl ProcessScratch {
+    fn reserve(&mut self, num_tracks: usize, num_voices: usize) {
+        self.track_data.reserve(num_tracks);
+        self.track_stack.reserve(num_tracks);
+        self.track_jobs.reserve(num_tracks);
+        self.track_inputs.reserve(num_tracks);
+        self.sidechain_outputs.reserve(num_tracks);
+        self.processed_stack.reserve(num_tracks);
+        self.deleted_notes.reserve(num_voices);
+    }
+}
+
+impl Debug for ProcessScratch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessScratch").finish_non_exhaustive()
+    }
 }
 
 impl WorkerHost {
     pub fn new(state: State, worker_options: WorkerOptions) -> Self {
         let worker_options = Arc::new(worker_options);
 
-        let (worker_tx, rx) = crossbeam_channel::unbounded();
-        let (work_tx, work_rx) = crossbeam_channel::unbounded();
+        let (worker_tx, rx) = crossbeam_channel::bounded(EVENT_CHANNEL_CAPACITY);
+        // resized to fit all the jobs before each buffer; see `Self::reserve_for_processing`
+        let (work_tx, work_rx) = crossbeam_channel::bounded(0);
 
         let mut worker_handles = Vec::with_capacity(worker_options.num_workers as usize);
         for i in 0..worker_options.num_workers {
-            let handle = WorkerHandle::new(
-                i,
-                worker_tx.clone(),
-                worker_options.clone(),
-                work_tx.clone(),
-                work_rx.clone(),
-            );
+            let handle = WorkerHandle::new(i, worker_tx.clone(), worker_options.clone());
             worker_handles.push(handle);
         }
 
+        let buffer_size = worker_options.buffer_size;
+        let mut errors = Vec::new();
         Self {
-            worker_state: WorkerHostState::new(&state, &worker_options),
+            worker_state: WorkerHostState::new(&state, &worker_options, &mut errors),
             state,
+            errors,
+            master_level: Level::default(),
+            metronome: MetronomeState::default(),
+            needs_sync: false,
             worker_handles: worker_handles.into_boxed_slice(),
             worker_options,
 
@@ -57,29 +126,145 @@ impl WorkerHost {
 
             work_rx,
             work_tx,
+
+            live_input: Buffer::new_box_zeroed(buffer_size),
+
+            allocator: Box::new(bumpalo::Bump::new()),
+            scratch: ProcessScratch::default(),
         }
     }
 
     fn sync_with_state(&mut self) {
-        let Self {
-            state,
-            worker_options,
-            ..
-        } = self;
+        if !self.needs_sync {
+            return;
+        }
+        self.needs_sync = false;
 
         self.worker_state
-            .sync_with(state, worker_options)
-            .with_context(|| format!("state is {state:?}"))
-            .expect("todo!()");
+            .sync_with(&self.state, &self.worker_options, &mut self.errors);
+    }
+
+    /// Makes sure everything `process` needs is allocated up front, so the processing itself doesn't allocate.
+    fn reserve_for_processing(&mut self) {
+        let num_tracks = self.worker_state.tracks.len();
+        let num_voices: usize = self
+            .worker_state
+            .tracks
+            .values()
+            .map(WorkerTrackState::num_voices)
+            .sum();
+        self.scratch.reserve(num_tracks, num_voices);
+
+        // every track and voice is at most one job, plus one finalization signal per worker
+        let num_jobs = num_tracks + num_voices + self.worker_options.num_workers as usize;
+        if self
+            .work_tx
+            .capacity()
+            .is_none_or(|capacity| capacity < num_jobs)
+        {
+            let (work_tx, work_rx) = crossbeam_channel::bounded(num_jobs.next_power_of_two());
+            self.work_tx = work_tx;
+            self.work_rx = work_rx;
+        }
     }
 
     /// Delete all currently processing jobs. This will result in silence
     pub fn stop_all_processing(&mut self) {
         for track_state in self.worker_state.tracks.values_mut() {
-            track_state.live_notes.clear();
-            track_state.notes.clear();
+            track_state.stop_all_notes();
             track_state.track_nodes.reset();
         }
+        self.metronome.stop();
+    }
+
+    /// Starts a live note on a track. Live notes aren't attached to the `State` and play until
+    /// [`Self::stop_live_note`] is called, regardless of whether the song is being played.
+    pub fn start_live_note(&mut self, track_id: Id<Track>, note_id: Id<Note>, note: Note) {
+        let Some(track_state) = self.worker_state.tracks.get_mut(track_id) else {
+            tracing::warn!("tried to start live note on nonexistent track {track_id:?}");
+            return;
+        };
+        if let Some(old_note) = track_state.live_notes.remove(note_id) {
+            track_state.voices.give_back(old_note.nodes);
+        }
+        let Some(nodes) = track_state.voices.take() else {
+            tracing::warn!("track {track_id:?} is at its polyphony limit; dropping live note");
+            return;
+        };
+        track_state.live_notes.insert(
+            note_id,
+            WorkerLiveNoteState {
+                start_pos: 0,
+                note,
+                nodes,
+                samples_elapsed: 0,
+                released: false,
+                samples_since_release: 0,
+            },
+        );
+    }
+    /// Stops a live note started with [`Self::start_live_note`].
+    pub fn stop_live_note(&mut self, track_id: Id<Track>, note_id: Id<Note>) {
+        if let Some(note_state) = self
+            .worker_state
+            .tracks
+            .get_mut(track_id)
+            .and_then(|track_state| track_state.live_notes.get_mut(note_id))
+        {
+            note_state.released = true;
+        }
+    }
+
+    /// Freezes a track, playing back `frozen` instead of processing the track and its descendants, or unfreezes it.
+    /// The track is unfrozen automatically once it or any of its descendants are edited, or once it's routed in a way
+    /// that means it can't be frozen (see [`offline::freeze_blocker`]), so this does nothing if `frozen` is already
+    /// out of date.
+    pub fn set_frozen(&mut self, track_id: Id<Track>, frozen: Option<FrozenTrack>) {
+        let Some(track_state) = self.worker_state.tracks.get_mut(track_id) else {
+            tracing::warn!("tried to freeze nonexistent track {track_id:?}");
+            return;
+        };
+        if let Some(ref frozen) = frozen {
+            if frozen.sample_rate != self.worker_options.sample_rate {
+                tracing::warn!("frozen track {track_id:?} was rendered at a different sample rate");
+                return;
+            }
+            if frozen.fingerprint != self.state.track_fingerprint(track_id) {
+                tracing::info!("track {track_id:?} was edited while it was being frozen");
+                return;
+            }
+            if let Some(blocker) = offline::freeze_blocker(&self.state, track_id) {
+                tracing::info!("track {track_id:?} can't be frozen: {blocker}");
+                return;
+            }
+        }
+        track_state.frozen = frozen;
+    }
+    pub fn is_frozen(&self, track_id: Id<Track>) -> bool {
+        self.worker_state
+            .tracks
+            .get(track_id)
+            .is_some_and(|track_state| track_state.frozen.is_some())
+    }
+
+    /// Sets or removes the probe on a node. Each node has at most one probe.
+    pub fn set_probe(&mut self, track_id: Id<Track>, node_id: Id<Node>, probe: Option<Probe>) {
+        let Some(track_state) = self.worker_state.tracks.get_mut(track_id) else {
+            tracing::warn!("tried to probe node on nonexistent track {track_id:?}");
+            return;
+        };
+        track_state.probes.retain(|probe| probe.node_id != node_id);
+        if let Some(probe) = probe {
+            assert_eq!(probe.node_id, node_id, "probe is for a different node");
+            track_state.probes.push(probe);
+        }
+    }
+
+    pub fn metronome(&self) -> Metronome {
+        self.metronome.settings
+    }
+    pub fn set_metronome(&mut self, metronome: Metronome) {
+        self.metronome.settings = metronome;
     }
 
     pub fn options(&self) -> &WorkerOptions {
@@ -94,12 +279,20 @@ impl WorkerHost {
         output: &mut Buffer,
     ) -> Self {
         self.sync_with_state();
+        self.reserve_for_processing();
+
+        // everything from here on shouldn't allocate
+        let guard = AllocGuard::new("WorkerHost::process");
 
         use std::{cell::UnsafeCell, mem::ManuallyDrop};
 
         let Self {
             state,
             worker_state,
+            errors: self_errors,
+            master_level: _,
+            mut metronome,
+            needs_sync,
 
             mut worker_handles,
             worker_options,
@@ -109,9 +302,18 @@ impl WorkerHost {
 
             work_rx,
             work_tx,
+
+            mut live_input,
+            allocator,
+            mut scratch,
         } = self;
 
-        let allocator_cell = UnsafeCell::new(Box::leak(Box::new(bumpalo::Bump::new())));
+        // the same ranges `add_jobs` processes, for the metronome
+        let buffer_ranges = start_pos
+            .as_deref()
+            .map(|&start_pos| BufferRanges::new(&state, &worker_options, start_pos));
+
+        let allocator_cell = UnsafeCell::new(Box::leak(allocator));
         // SAFETY: allocator_cell isn't touched until after all the workers are finished processing.
         let allocator = unsafe { &**allocator_cell.get() };
         let state = allocator.alloc(ManuallyDrop::new(UnsafeCell::new(state)));
@@ -123,12 +325,14 @@ impl WorkerHost {
 
             add_jobs(
                 allocator,
+                &mut scratch,
                 &work_tx,
                 state,
                 worker_state,
                 &worker_options,
                 start_pos.as_deref_mut(),
                 live_pos,
+                &live_input,
             )
         };
 
@@ -143,11 +347,13 @@ impl WorkerHost {
                         Some(ref start_pos) => **start_pos,
                         None => Default::default(),
                     },
+                    work_tx: work_tx.clone(),
+                    work_rx: work_rx.clone(),
                 })
                 .expect("worker closed channel??");
         }
 
-        let mut deleted_notes = Vec::new();
+        let mut errors = Vec::new();
 
         // aaaaand we're off! :DDD
         let mut remaining_processing_workers = worker_options.num_workers;
@@ -165,15 +371,12 @@ impl WorkerHost {
                         track_id,
                         note_descriptor,
                     } => {
-                        deleted_notes.push((track_id, note_descriptor));
-                    }
-                    JobDescriptor::TrackProcess { track_id } => {
-                        todo!("{track_id:?}");
+                        scratch.deleted_notes.push((track_id, note_descriptor));
                     }
                 },
-                WorkerToHostEvent::Error(err) => {
-                    // TODO
-                    panic!("error encountered in worker host: {err}");
+                WorkerToHostEvent::Error { track_id, error } => {
+                    tracing::error!("error while processing track {track_id:?}: {error:#}");
+                    errors.push((track_id, error));
                 }
             }
         }
@@ -189,33 +392,42 @@ impl WorkerHost {
         //   - This makes `ManuallyDrop::take(<state/worker_state>)` safe.
         //   - This makes `allocator_cell.into_inner()` safe.
         // - Of course, `Box::from_raw(allocator)` is safe because the allocator was properly leaked from a `Box<bumpalo::Bump>`.
-        let (state, mut worker_state) = unsafe {
-            let ret = (
+        //   Nothing in `scratch` references the allocator either, since it was all cleared in `add_jobs`.
+        let (state, mut worker_state, mut allocator) = unsafe {
+            (
                 ManuallyDrop::take(state).into_inner(),
                 ManuallyDrop::take(worker_state).into_inner(),
-            );
+                Box::<bumpalo::Bump>::from_raw(allocator_cell.into_inner()),
+            )
+        };
+        allocator.reset();
 
-            let allocator: Box<bumpalo::Bump> = Box::from_raw(allocator_cell.into_inner());
-            drop(allocator);
+        // input that isn't rewritten before the next buffer is silence, not a repeat of this one
+        live_input.fill(0.0);
 
-            ret
-        };
+        // after the root track, so no track's processing affects it
+        metronome.process(&state, &worker_options, buffer_ranges.as_ref(), output);
+        let master_level = Level::of(output);
 
-        for (track_id, note_descriptor) in deleted_notes {
+        for (track_id, note_descriptor) in scratch.deleted_notes.drain(..) {
             let track = worker_state.tracks.force_get_mut(track_id);
-            match note_descriptor {
-                crate::NoteDescriptor::Live { note_id, .. } => {
-                    track.live_notes.take(note_id);
-                }
-                crate::NoteDescriptor::State { note_id, .. } => {
-                    track.notes.take(note_id);
-                }
-            }
+            let nodes = match note_descriptor {
+                NoteDescriptor::Live { note_id, .. } => track.live_notes.take(note_id).nodes,
+                NoteDescriptor::State { note_id, .. } => track.notes.take(note_id).nodes,
+                NoteDescriptor::Looped { note_id, .. } => track.looped_notes.take(note_id).nodes,
+            };
+            track.voices.give_back(nodes);
         }
 
-        Self {
+        drop(guard);
+
+        let mut this = Self {
             state,
             worker_state,
+            errors: self_errors,
+            master_level,
+            metronome,
+            needs_sync,
 
             worker_handles,
             worker_options,
@@ -225,7 +437,45 @@ impl WorkerHost {
 
             work_rx,
             work_tx,
+
+            live_input,
+            allocator,
+            scratch,
+        };
+
+        if !errors.is_empty() {
+            for (track_id, error) in errors {
+                let node_id = error
+                    .downcast_ref::<NodeError>()
+                    .map(|node_error| node_error.node_id);
+                if let Some(node_id) = node_id
+                    && let Some(track_state) = this.worker_state.tracks.get_mut(track_id)
+                {
+                    track_state.silence_node(node_id);
+                }
+                this.errors.push(ProcessingError {
+                    track_id: Some(track_id),
+                    node_id,
+                    error,
+                });
+            }
+
+            // plugin instances might've been left in a bad state (e.g. after a trap), so recreate them
+            for handle in this.worker_handles.iter() {
+                handle
+                    .tx
+                    .send(HostToWorkerEvent::RebuildState)
+                    .expect("worker closed channel??");
+            }
         }
+
+        this
+    }
+
+    /// Takes all the errors that happened since the last call to this function.
+    /// Errors don't stop processing: nodes that cause errors are silenced instead.
+    pub fn take_errors(&mut self) -> Vec<ProcessingError> {
+        std::mem::take(&mut self.errors)
     }
 
     pub fn join(self) {
@@ -245,10 +495,29 @@ impl WorkerHost {
         }
     }
 
+    /// The audio going into every `builtin:audio_input` node during the next call to [`Self::process`]. It's silenced
+    /// after every buffer, so it has to be written before each call.
+    pub fn live_input_mut(&mut self) -> &mut Buffer {
+        &mut self.live_input
+    }
+
+    /// The level of the master output during the last call to [`Self::process`].
+    pub fn master_level(&self) -> Level {
+        self.master_level
+    }
+    /// The levels of each track's output during the last call to [`Self::process`].
+    pub fn track_levels(&self) -> impl Iterator<Item = (Id<Track>, Level)> + '_ {
+        self.worker_state
+            .tracks
+            .iter()
+            .map(|(track_id, track_state)| (track_id, track_state.level))
+    }
+
     pub fn state(&self) -> &State {
         &self.state
     }
     pub fn state_mut(&mut self) -> &mut State {
+        self.needs_sync = true;
         &mut self.state
     }
 }
@@ -264,115 +533,305 @@ impl WorkerHandle {
 
         worker_tx: crossbeam_channel::Sender<WorkerToHostEvent>,
         worker_options: Arc<WorkerOptions>,
-
-        work_tx: crossbeam_channel::Sender<WorkerJob>,
-        work_rx: crossbeam_channel::Receiver<WorkerJob>,
     ) -> Self {
-        let (tx, worker_rx) = crossbeam_channel::unbounded();
+        let (tx, worker_rx) = crossbeam_channel::bounded(EVENT_CHANNEL_CAPACITY);
         Self {
             tx,
             join_handle: thread::Builder::new()
                 .name(format!("Audio Worker #{index}"))
-                .spawn(move || {
-                    worker::run_forever(worker_tx, worker_rx, work_tx, work_rx, &worker_options)
-                })
+                .spawn(move || worker::run_forever(worker_tx, worker_rx, &worker_options))
                 .expect("failed to spawn thread"),
         }
     }
 }
 
+/// The song positions that one buffer plays.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BufferRanges {
+    pub start_pos: PreciseSongPos,
+    /// Where the next buffer starts, after looping back if needed.
+    pub end_pos: PreciseSongPos,
+    pub range: Range,
+    /// If playback loops back partway through the buffer, there's a second range starting at the start of the loop.
+    /// Positions in that range are `loop_shift` (the second value) units before where they'd be without looping.
+    pub looped: Option<(Range, i64)>,
+    pub units_per_sample: f64,
+}
+
+impl BufferRanges {
+    pub fn new(state: &State, worker_options: &WorkerOptions, start_pos: PreciseSongPos) -> Self {
+        // (samples/second) / (60 seconds/minute) * beats/minute * units/beat
+        let units_per_sample = 1.0 / worker_options.sample_rate as f64 / 60.0
+            * state.bpm as f64
+            * Range::UNITS_PER_BEAT as f64;
+
+        let end_pos = start_pos
+            + PreciseSongPos::from_song_pos_f64(
+                worker_options.buffer_size as f64 * units_per_sample,
+            );
+        let wrapped_end_pos = state.wrap_position(start_pos, end_pos);
+
+        // each consecutive range of start_pos to end_pos must result in consecutive song ranges
+        // so don't use end_pos.ceil_to_song_pos() or whatever since that could result in overlap
+        // which is very very bad and will cause very very bad things
+        let (range, looped) = if wrapped_end_pos == end_pos {
+            (Range::new(start_pos.song_pos, end_pos.song_pos), None)
+        } else {
+            // wrap_position only subtracts whole loop lengths, so this is exact.
+            // (loops shorter than a buffer go around more than once; only the last time around gets played)
+            let loop_shift = (end_pos - wrapped_end_pos).song_pos;
+            (
+                Range::new(start_pos.song_pos, state.loop_range.end),
+                Some((
+                    Range::new(state.loop_range.start, wrapped_end_pos.song_pos),
+                    loop_shift,
+                )),
+            )
+        };
+
+        Self {
+            start_pos,
+            end_pos: wrapped_end_pos,
+            range,
+            looped,
+            units_per_sample,
+        }
+    }
+
+    /// The sample offset into this buffer that `pos` corresponds to, unclamped. Positions in the looped range
+    /// have to be shifted by the loop shift first.
+    pub fn samples_until(&self, pos: i64) -> i64 {
+        let units_into_buffer =
+            (PreciseSongPos::from_song_pos(pos) - self.start_pos).to_song_pos_f64();
+        (units_into_buffer / self.units_per_sample).round() as i64
+    }
+}
+
+/// Stops all notes of the descendants of a frozen track and takes them out of `track_data`, since they're not processed.
+fn discard_descendants(
+    state: &State,
+    track: &Track,
+    track_data: &mut IdMap<Track, &'static mut WorkerTrackState>,
+) {
+    for &child_id in &track.children {
+        if let Some(child_data) = track_data.remove(child_id) {
+            child_data.stop_all_notes();
+        }
+        discard_descendants(state, state.tracks.force_get(child_id), track_data);
+    }
+}
+
 type WorkerJobSyncBuffer = SyncBuffer<&'static mut cubedaw_lib::Buffer, WorkerJob>;
+type SidechainSyncBuffer = SyncBuffer<&'static mut cubedaw_lib::Buffer>;
 
 #[must_use = "you should do something with the master output returned from this function"]
 fn add_jobs(
     allocator: &'static bumpalo::Bump,
+    scratch: &mut ProcessScratch,
     work_tx: &crossbeam_channel::Sender<WorkerJob>,
     state: &'static State,
     worker_state: &'static mut WorkerHostState,
     worker_options: &WorkerOptions,
     start_pos_ref: Option<&mut PreciseSongPos>,
-    _live_pos: PreciseSongPos, // TODO
+    live_pos: PreciseSongPos,
+    live_input: &Buffer,
 ) -> crate::sync::SyncAccessibleReadHandle<'static, &'static mut cubedaw_lib::Buffer, WorkerJob> {
-    let allocate_sync_buffer = |alloc: &'static bumpalo::Bump| -> &'static WorkerJobSyncBuffer {
+    let allocate_buffer = |alloc: &'static bumpalo::Bump| -> &'static mut Buffer {
         let slice = alloc.alloc_slice_fill_copy(
             worker_options.buffer_size as usize / InternalBufferType::N,
             InternalBufferType::ZERO,
         );
-        alloc.alloc(SyncBuffer::new(cubedaw_lib::Buffer::new_mut(slice)))
+        cubedaw_lib::Buffer::new_mut(slice)
+    };
+    let allocate_sync_buffer = |alloc: &'static bumpalo::Bump| -> &'static WorkerJobSyncBuffer {
+        alloc.alloc(SyncBuffer::new(allocate_buffer(alloc)))
     };
 
     let master_output = allocate_sync_buffer(allocator);
-
-    struct TrackTempData {
-        sync_buffer: &'static WorkerJobSyncBuffer,
-        job: WorkerJob,
-    }
-    let mut track_temp_map: IdMap<cubedaw_lib::Track, TrackTempData> = IdMap::new();
-
-    // required due to borrowing rules
-    let mut track_id_to_mutable_reference_to_track_data: IdMap<_, &'static mut _> = IdMap::new();
-
-    let song_range_that_we_will_process = start_pos_ref.map(|start_pos_ref| {
-        let start_pos = *start_pos_ref;
-        let end_pos = start_pos
-            + PreciseSongPos::from_song_pos_f32({
-                // samples / (samples/second) / (60 seconds/minute) * beats/minute * units/beat
-                worker_options.buffer_size as f32 / worker_options.sample_rate as f32 / 60.0
-                    * state.bpm
-                    * Range::UNITS_PER_BEAT as f32
-            });
-        // each consecutive range of start_pos to end_pos must result in consecutive song ranges
-        // so don't use end_pos.ceil_to_song_pos() or whatever since that could result in overlap
-        // which is very very bad and will cause very very bad things
-        let song_range_that_we_will_process = Range::new(start_pos.song_pos, end_pos.song_pos);
-
-        *start_pos_ref = end_pos;
-
-        song_range_that_we_will_process
+    let live_input: &'static Buffer =
+        Buffer::new_mut(allocator.alloc_slice_copy(live_input.as_internal()));
+
+    let ProcessScratch {
+        // required due to borrowing rules
+        track_data: track_id_to_mutable_reference_to_track_data,
+        track_stack,
+        track_jobs,
+        track_inputs,
+        sidechain_outputs,
+        processed_stack,
+        ..
+    } = scratch;
+
+    let buffer_ranges = start_pos_ref.map(|start_pos_ref| {
+        let buffer_ranges = BufferRanges::new(state, worker_options, *start_pos_ref);
+        *start_pos_ref = buffer_ranges.end_pos;
+        buffer_ranges
     });
+    let song_range_that_we_will_process = buffer_ranges.map(|ranges| ranges.range);
+    let looped_range = buffer_ranges.and_then(|ranges| ranges.looped);
+    let loop_shift = looped_range.map_or(0, |(_, loop_shift)| loop_shift);
+
+    // the sample offset into this buffer that `pos` corresponds to, unclamped
+    let samples_until =
+        |pos: i64| -> i64 { buffer_ranges.map_or(0, |ranges| ranges.samples_until(pos)) };
+    // the sample offset into this buffer that `pos` corresponds to, clamped to the buffer
+    let sample_offset_of =
+        |pos: i64| -> u32 { samples_until(pos).clamp(0, worker_options.buffer_size as i64) as u32 };
+    // notes are delayed by their track's note compensation, so they end that much later too.
+    // returns `None` if the note doesn't end in this buffer.
+    let end_offset_of = |samples_until_end: i64, compensation: u32| -> Option<u32> {
+        let end = samples_until_end + compensation as i64;
+        (end < worker_options.buffer_size as i64).then(|| end.max(0) as u32)
+    };
 
     for (track_id, track_data) in &mut worker_state.tracks {
         track_id_to_mutable_reference_to_track_data.insert(track_id, track_data);
     }
 
-    let mut track_stack = Vec::new();
+    // sidechains can make a track wait on one that comes later in the track tree, so every input has to exist up front.
+    // descendants of frozen tracks aren't processed, so they don't get one
+    if state.tracks.has(state.root_track) {
+        processed_stack.push(state.root_track);
+    }
+    while let Some(track_id) = processed_stack.pop() {
+        let runs_nodes = track_id_to_mutable_reference_to_track_data
+            .get(track_id)
+            .is_some_and(|track_data| track_data.frozen.is_none());
+        track_inputs.insert(track_id, (allocate_sync_buffer(allocator), runs_nodes));
+        if runs_nodes {
+            processed_stack.extend(state.tracks.force_get(track_id).children.iter().copied());
+        }
+    }
+    let mut sidechain_output_of = |track_id: Id<Track>| -> &'static SidechainSyncBuffer {
+        sidechain_outputs.get_mut_or_insert(track_id, || {
+            allocator.alloc(SyncBuffer::new(allocate_buffer(allocator)))
+        })
+    };
+
     if state.tracks.has(state.root_track) {
         track_stack.push((state.root_track, master_output));
     }
     while let Some((track_id, group_input)) = track_stack.pop() {
-        let sync_buffer = allocate_sync_buffer(allocator);
+        let (sync_buffer, _) = *track_inputs.get(track_id).expect("unreachable");
 
         let track = state.tracks.force_get(track_id);
-        // match track.inner {
-        //     cubedaw_lib::TrackInner::Group(ref track_data) => {
-        //         let worker_track_data = group_track_id_to_mutable_reference_to_group_track_data
-        //             .remove(track_id)
-        //             .unwrap();
-
-        //         let job = WorkerJob::TrackGroup {
-        //             track_id,
-        //             nodes: &mut worker_track_data.nodes,
-        //             input: sync_buffer.get_read_handle(),
-        //             output: group_input.get_write_handle(),
-        //         };
-
-        //         track_temp_map.insert(track_id, TrackTempData { sync_buffer, job });
-
-        //     }
-        //     cubedaw_lib::TrackInner::Clip(ref track_data) => {
         let worker_track_data = track_id_to_mutable_reference_to_track_data
             .remove(track_id)
             .unwrap();
 
+        if worker_track_data.frozen.is_some() {
+            // the frozen audio already has the track's notes and descendants in it
+            worker_track_data.stop_all_notes();
+        }
+
+        // frozen tracks don't use their input, so nothing is sent to them and they don't read sidechains
+        let processed_input = |track_id: Id<Track>| {
+            track_inputs
+                .get(track_id)
+                .filter(|&&(_, runs_nodes)| runs_nodes)
+                .map(|&(input, _)| input)
+        };
+        let consumers = &worker_track_data.sidechain_consumers;
+        let output = crate::job::TrackOutput {
+            gain: track.gain(),
+            parent: group_input.get_write_handle(),
+            sends: allocator.alloc_slice_fill_iter(worker_track_data.sends.iter().map(
+                |&(target_id, send)| {
+                    processed_input(target_id).map(|input| crate::job::SendOutput {
+                        amount: send.amount,
+                        pre_fader: send.pre_fader,
+                        output: input.get_write_handle(),
+                    })
+                },
+            )),
+            sidechain: consumers
+                .iter()
+                .any(|&consumer_id| processed_input(consumer_id).is_some())
+                .then(|| crate::job::SidechainOutput {
+                    buffer: sidechain_output_of(track_id).get_write_handle(),
+                    consumers: allocator.alloc_slice_fill_iter(consumers.iter().map(
+                        |&consumer_id| {
+                            processed_input(consumer_id).map(|input| input.get_write_handle())
+                        },
+                    )),
+                }),
+        };
+        if let Some(ref frozen) = worker_track_data.frozen {
+            discard_descendants(state, track, track_id_to_mutable_reference_to_track_data);
+
+            // the audio has the track's own latency in it, but it still has to be delayed to line up with its siblings
+            let delay = worker_track_data.track_nodes.output_delay() as i64;
+            let index_at_buffer_start =
+                |shift: i64| -samples_until(frozen.start_pos + shift) - delay;
+            let job = WorkerJob::FrozenTrackProcess {
+                audio: &frozen.audio,
+                start_index: song_range_that_we_will_process.map(|_| index_at_buffer_start(0)),
+                loop_jump: looped_range.map(|(_, loop_shift)| {
+                    (
+                        sample_offset_of(state.loop_range.end),
+                        index_at_buffer_start(loop_shift),
+                    )
+                }),
+                level: &mut worker_track_data.level,
+                output,
+            };
+            track_jobs.push((sync_buffer, job));
+            continue;
+        }
+
+        for probe in &mut worker_track_data.probes {
+            probe.choose_voice(&worker_track_data.notes, &worker_track_data.live_notes);
+        }
+        let probes: &'static [Probe] = &worker_track_data.probes;
+
+        // child tracks are summed into this track's input
+        for &child_id in &track.children {
+            track_stack.push((child_id, sync_buffer));
+        }
+
+        // sources that aren't processed are silent
+        let sidechains =
+            allocator.alloc_slice_fill_iter(worker_track_data.sidechain_sources.iter().map(
+                |&source_id| {
+                    (
+                        source_id,
+                        track_inputs
+                            .has(source_id)
+                            .then(|| sidechain_output_of(source_id).get_read_handle()),
+                    )
+                },
+            ));
+
         let job = WorkerJob::TrackProcess {
             track_id,
             nodes: &mut worker_track_data.track_nodes,
+            level: &mut worker_track_data.level,
+            probes,
+            live_input,
+            sidechains,
             input: sync_buffer.get_read_handle(),
-            output: group_input.get_write_handle(),
+            output,
         };
 
+        let note_compensation = worker_track_data.note_compensation;
+
         // live notes
         for (live_note_id, note_state) in &mut worker_track_data.live_notes {
+            if note_state.samples_elapsed == 0 {
+                note_state.start_pos = live_pos.song_pos;
+            }
+            let samples_elapsed = note_state.samples_elapsed;
+            note_state.samples_elapsed += worker_options.buffer_size as u64;
+
+            // live notes are started and stopped in between buffers
+            let end_offset = if note_state.released {
+                let samples_since_release = note_state.samples_since_release;
+                note_state.samples_since_release += worker_options.buffer_size as u64;
+                end_offset_of(-(samples_since_release as i64), note_compensation)
+            } else {
+                None
+            };
+
             work_tx
                 .send(WorkerJob::NoteProcess {
                     track_id,
@@ -380,9 +839,12 @@ fn add_jobs(
                         note_id: live_note_id,
                         note: &note_state.note,
                         start_pos: note_state.start_pos,
-                        samples_elapsed: note_state.samples_elapsed,
+                        samples_elapsed,
                     },
                     nodes: &mut note_state.nodes,
+                    end_offset,
+                    probes,
+                    live_input,
                     output: sync_buffer.get_write_handle(),
                 })
                 .unwrap();
@@ -390,33 +852,126 @@ fn add_jobs(
 
         // non-live notes
         {
-            // add the notes that started in this range to the worker state...
-            if let Some(song_range_that_we_will_process) = song_range_that_we_will_process {
-                for (clip_range, clip_id) in
-                    track.clips_intersecting(song_range_that_we_will_process)
-                {
+            // the start position, note and end position of a note in the state
+            let note_of = |note_id: Id<Note>, clip_id: Id<Clip>| {
+                let clip_start = track
+                    .clip_range(clip_id)
+                    .expect("note state desynced with track")
+                    .start;
+                let (start_pos, note) = track.clip(clip_id).unwrap().note(note_id).unwrap();
+                (start_pos, note, note.range_with(clip_start + start_pos).end)
+            };
+            // adds the notes that start in `range` to the worker state. `shift` is added to positions before they're turned into sample offsets.
+            // (this takes the fields separately since the track's node graph is already borrowed by the track job)
+            let start_notes = |notes: &mut IdMap<Note, WorkerNoteState>,
+                               looped_notes: &mut IdMap<Note, WorkerNoteState>,
+                               voices: &mut VoicePool,
+                               range: Range,
+                               shift: i64| {
+                for (clip_range, clip_id) in track.clips_intersecting(range) {
                     let clip = track.clip(clip_id).unwrap();
-                    for (_start_pos, note_id, _note) in clip.note_start_positions_in(
-                        clip_range.intersect(song_range_that_we_will_process) - clip_range.start,
-                    ) {
-                        worker_track_data.notes.insert(
+                    for (start_pos, note_id, _note) in
+                        clip.note_start_positions_in(clip_range.intersect(range) - clip_range.start)
+                    {
+                        // notes cut off by a loop are only finishing, so they give up their voices first
+                        let looped_note_id = looped_notes.keys().next();
+                        if voices.is_empty()
+                            && let Some(looped_note_id) = looped_note_id
+                        {
+                            voices.give_back(looped_notes.take(looped_note_id).nodes);
+                        }
+                        let Some(mut nodes) = voices.take() else {
+                            tracing::warn!(
+                                "track {track_id:?} is at its polyphony limit; dropping note"
+                            );
+                            continue;
+                        };
+                        nodes.set_start_offset(sample_offset_of(
+                            clip_range.start + start_pos + shift,
+                        ));
+                        notes.insert(
                             note_id,
                             WorkerNoteState {
                                 clip_id,
-                                nodes: worker_track_data.note_nodes.clone(),
+                                nodes,
+                                cut_offset: None,
                             },
                         );
                     }
                 }
+            };
+
+            // add the notes that started in this range to the worker state...
+            if let Some(song_range_that_we_will_process) = song_range_that_we_will_process {
+                start_notes(
+                    &mut worker_track_data.notes,
+                    &mut worker_track_data.looped_notes,
+                    &mut worker_track_data.voices,
+                    song_range_that_we_will_process,
+                    0,
+                );
+            }
+            if let Some((looped_range, loop_shift)) = looped_range {
+                // ...cut off every playing note where playback loops back...
+                for (_note_id, note_state) in worker_track_data.looped_notes.drain() {
+                    worker_track_data.voices.give_back(note_state.nodes);
+                }
+                let loop_end_offset = samples_until(state.loop_range.end);
+                for (note_id, mut note_state) in worker_track_data.notes.drain() {
+                    let (_, _, end_pos) = note_of(note_id, note_state.clip_id);
+                    note_state.cut_offset = Some(samples_until(end_pos).min(loop_end_offset));
+                    worker_track_data.looped_notes.insert(note_id, note_state);
+                }
+                // ...and add the notes at the start of the loop
+                start_notes(
+                    &mut worker_track_data.notes,
+                    &mut worker_track_data.looped_notes,
+                    &mut worker_track_data.voices,
+                    looped_range,
+                    loop_shift,
+                );
             }
 
-            // ...then process all notes
-            for (note_id, note_state) in &mut worker_track_data.notes {
-                let (start_pos, note) = track
-                    .clip(note_state.clip_id)
-                    .unwrap()
-                    .note(note_id)
+            // then process all notes
+            for (note_id, note_state) in &mut worker_track_data.looped_notes {
+                let (start_pos, note, _) = note_of(note_id, note_state.clip_id);
+                let cut_offset = note_state
+                    .cut_offset
+                    .as_mut()
+                    .expect("looped notes are always cut off");
+                let end_offset = match song_range_that_we_will_process {
+                    Some(_) => end_offset_of(*cut_offset, note_compensation),
+                    None => Some(0),
+                };
+                *cut_offset -= worker_options.buffer_size as i64;
+
+                work_tx
+                    .send(WorkerJob::NoteProcess {
+                        track_id,
+                        note_descriptor: crate::NoteDescriptor::Looped {
+                            note_id,
+                            start_pos,
+                            note,
+                        },
+                        nodes: &mut note_state.nodes,
+                        end_offset,
+                        probes,
+                        live_input,
+                        output: sync_buffer.get_write_handle(),
+                    })
                     .unwrap();
+            }
+            for (note_id, note_state) in &mut worker_track_data.notes {
+                let (start_pos, note, end_pos) = note_of(note_id, note_state.clip_id);
+                let end_offset = match song_range_that_we_will_process {
+                    // if playback looped back, all these notes are in the looped range
+                    Some(_) => {
+                        end_offset_of(samples_until(end_pos + loop_shift), note_compensation)
+                    }
+                    // if we aren't playing, state notes can't progress so they end immediately
+                    None => Some(0),
+                };
+
                 work_tx
                     .send(WorkerJob::NoteProcess {
                         track_id,
@@ -426,18 +981,73 @@ fn add_jobs(
                             note,
                         },
                         nodes: &mut note_state.nodes,
+                        end_offset,
+                        probes,
+                        live_input,
                         output: sync_buffer.get_write_handle(),
                     })
                     .unwrap();
             }
         }
 
-        track_temp_map.insert(track_id, TrackTempData { sync_buffer, job });
+        // audio clips
+        if let Some(buffer_ranges) = buffer_ranges {
+            // audio doesn't go through the note nodes, so it's delayed to line up with the notes and child tracks.
+            // clips that just ended can still have some of their audio in this buffer.
+            let input_latency = note_compensation + worker_track_data.note_nodes.latency();
+            let latency_units =
+                (input_latency as f64 * buffer_ranges.units_per_sample).ceil() as i64;
+            let buffer_size = worker_options.buffer_size;
+            let loop_offset =
+                looped_range.map_or(buffer_size, |_| sample_offset_of(state.loop_range.end));
+            let segments =
+                core::iter::once((buffer_ranges.range, 0, (0, loop_offset)))
+                    .chain(looped_range.map(|(range, loop_shift)| {
+                        (range, loop_shift, (loop_offset, buffer_size))
+                    }));
+            let grain_caches = &worker_track_data.grain_caches;
+            for (segment, (range, shift, offsets)) in segments.enumerate() {
+                let range = Range::new(range.start - latency_units, range.end);
+                for (clip_range, clip_id) in track.clips_intersecting(range) {
+                    let clip = track.clip(clip_id).unwrap();
+                    let Some(ref audio) = clip.audio else {
+                        continue;
+                    };
+                    let Some(sample) = state.samples.get(audio.sample_id) else {
+                        continue;
+                    };
+                    work_tx
+                        .send(WorkerJob::AudioClipProcess {
+                            clip: audio,
+                            length: clip.length,
+                            data: sample.data(),
+                            rate: sample.sample_rate() as f64 / worker_options.sample_rate as f64,
+                            stretch: TimeStretch {
+                                speed: audio.speed(state.bpm),
+                                pitch: audio.pitch_ratio(),
+                            },
+                            grain_cache: grain_caches.get(clip_id).map(|caches| &caches[segment]),
+                            clip_start: samples_until(clip_range.start + shift)
+                                + input_latency as i64,
+                            offsets,
+                            units_per_sample: buffer_ranges.units_per_sample,
+                            output: sync_buffer.get_write_handle(),
+                        })
+                        .unwrap();
+                }
+            }
+        }
+
+        track_jobs.push((sync_buffer, job));
     }
     assert!(track_id_to_mutable_reference_to_track_data.is_empty());
+    track_inputs.clear();
 
-    // prime the SyncBuffers
-    for (_, TrackTempData { sync_buffer, job }) in track_temp_map {
+    // prime the SyncBuffers. sidechain outputs go first since track jobs can start as soon as their inputs are primed
+    for (_, sync_buffer) in sidechain_outputs.drain() {
+        sync_buffer.prime(());
+    }
+    for (sync_buffer, job) in track_jobs.drain(..) {
         if let Some(job) = sync_buffer.prime(job) {
             // sync_buffer.prime() returns Some(extra) when there are no writers
             // so just add the job to the queue
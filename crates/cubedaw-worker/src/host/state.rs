@@ -1,9 +1,16 @@
+use std::sync::Mutex;
+
+use ahash::HashSet;
 use anyhow::Result;
-use cubedaw_lib::{Buffer, Clip, Id, IdMap, Node, Note, Patch, State, Track};
+use cubedaw_lib::{Buffer, Clip, Id, IdMap, Node, Note, Patch, State, Track, TrackSend};
 
+use super::ProcessingError;
 use crate::{
-    WorkerOptions,
-    node_graph::{NoteNodeGraph, TrackNodeGraph},
+    Level, Probe, WorkerOptions,
+    node_graph::{NodeError, NoteNodeGraph, TrackNodeGraph},
+    offline::FrozenTrack,
+    plugin::stitched::StitchKey,
+    stretch::GrainCache,
 };
 
 #[derive(Debug)]
@@ -15,34 +22,23 @@ pub struct WorkerHostState {
 }
 
 impl WorkerHostState {
-    pub fn new(state: &State, options: &WorkerOptions) -> Self {
-        let mut tracks = IdMap::new();
-        for (track_id, track) in &state.tracks {
-            let mut node_map = IdMap::new();
-            for (node_id, node) in track.patch.nodes() {
-                let entry = options.registry.get(&node.data.key).unwrap_or_else(|| {
-                    panic!(
-                        "key {:?} doesn't exist in registry {:?}",
-                        &node.data.key, &options.registry
-                    )
-                });
-                node_map.insert(node_id, (entry.node_factory)(node.data.inner.as_bytes()));
-            }
-            tracks.insert(
-                track_id,
-                WorkerTrackState::from_track(track, options)
-                    .unwrap_or_else(|_| WorkerTrackState::empty(options)),
-            );
-        }
-
-        Self { tracks }
+    /// Creates the state for every track in `state`. Errors are handled like in [`Self::sync_with`].
+    pub fn new(state: &State, options: &WorkerOptions, errors: &mut Vec<ProcessingError>) -> Self {
+        let mut this = Self {
+            tracks: IdMap::new(),
+        };
+        this.sync_with(state, options, errors);
+        this
     }
 
+    /// Brings the state of every track up to date with `state`. A track that fails to sync is reported in `errors` and
+    /// stays silent until it's fixed, and the other tracks are synced anyway.
     pub fn sync_with(
         &mut self,
         state: &State,
         worker_options: &WorkerOptions,
-    ) -> anyhow::Result<()> {
+        errors: &mut Vec<ProcessingError>,
+    ) {
         let mut tracks_to_delete = Vec::new();
         let mut notes_to_delete = Vec::new();
         for (track_id, worker_track_data) in &mut self.tracks {
@@ -58,8 +54,18 @@ impl WorkerHostState {
                         }
                     }
                     for note_id in notes_to_delete.drain(..) {
-                        worker_track_data.notes.remove(note_id);
+                        let note_state = worker_track_data.notes.take(note_id);
+                        worker_track_data.voices.give_back(note_state.nodes);
                     }
+                    // looped notes are only finishing, so they can just stop
+                    worker_track_data
+                        .looped_notes
+                        .retain(|note_id, note_state| {
+                            track
+                                .clip(note_state.clip_id)
+                                .and_then(|clip| clip.note(note_id))
+                                .is_some()
+                        });
                 }
                 None => {
                     tracks_to_delete.push(track_id);
@@ -71,33 +77,172 @@ impl WorkerHostState {
         }
 
         for (track_id, track) in &state.tracks {
-            // match &track.inner {
-            //     cubedaw_lib::TrackInner::Group(inner) => {
-            //         if let Some(worker_track) = self.group_tracks.get_mut(track_id) {
-            //             // TODO only do this when the patch is mutated
-            //             worker_track.sync_with(track, worker_options)?;
-            //         } else {
-            //             dbg!(&track.patch);
-            //             self.group_tracks.insert(
-            //                 track_id,
-            //                 WorkerGroupTrackState::from_patch(track, worker_options)
-            //                     .unwrap(),
-            //             )
-            //         }
-            //     }
-            if let Some(worker_track) = self.tracks.get_mut(track_id) {
+            let result = match self.tracks.get_mut(track_id) {
                 // TODO only do this when the patch is mutated
-                worker_track.sync_with(track, worker_options)?;
-            } else {
-                dbg!(&track.patch);
-                self.tracks.insert(
-                    track_id,
-                    WorkerTrackState::from_track(track, worker_options).unwrap(),
-                )
+                Some(worker_track) => worker_track.sync_with(track, worker_options),
+                None => WorkerTrackState::from_track(track, worker_options)
+                    .map(|worker_track| self.tracks.insert(track_id, worker_track)),
+            };
+            if let Err(error) = result {
+                tracing::error!("failed to sync track {track_id:?}: {error:#}");
+                errors.push(ProcessingError {
+                    track_id: Some(track_id),
+                    node_id: error
+                        .downcast_ref::<NodeError>()
+                        .map(|node_error| node_error.node_id),
+                    error,
+                });
+                self.tracks
+                    .replace(track_id, WorkerTrackState::empty(worker_options));
             }
         }
 
-        Ok(())
+        for (track_id, worker_track) in &mut self.tracks {
+            if let Some(ref frozen) = worker_track.frozen
+                && (frozen.fingerprint != state.track_fingerprint(track_id)
+                    || crate::offline::freeze_blocker(state, track_id).is_some())
+            {
+                tracing::info!("track {track_id:?} was edited; unfreezing it");
+                worker_track.frozen = None;
+            }
+        }
+
+        if state.tracks.has(state.root_track) {
+            self.compensate_latency(state, state.root_track, worker_options);
+        }
+        self.sync_routing(state);
+
+        // forget the stitched graphs nothing uses anymore
+        let stitch_keys: HashSet<&StitchKey> = self
+            .tracks
+            .values()
+            .flat_map(|track| {
+                [
+                    track.track_nodes.stitch_key(),
+                    track.note_nodes.stitch_key(),
+                ]
+            })
+            .flatten()
+            .map(|key| &**key)
+            .collect();
+        worker_options
+            .stitch_cache
+            .retain(|key| stitch_keys.contains(key));
+    }
+
+    /// Finds where each track sends its output and which tracks its sidechain nodes read from. Routes that would
+    /// make a track wait on itself are left out, so their sends and sidechain nodes stay silent.
+    fn sync_routing(&mut self, state: &State) {
+        for (track_id, track) in &state.tracks {
+            let Some(track_state) = self.tracks.get_mut(track_id) else {
+                continue;
+            };
+            track_state.sends.clear();
+            for (target_id, &send) in track.sends() {
+                if !state.tracks.has(target_id) {
+                    continue;
+                }
+                if state.track_depends_on(track_id, target_id) {
+                    tracing::warn!(
+                        "send from {track_id:?} to {target_id:?} would create a cycle; ignoring it"
+                    );
+                    continue;
+                }
+                track_state.sends.push((target_id, send));
+            }
+        }
+
+        let mut routes = Vec::new();
+        for (track_id, track) in &state.tracks {
+            for source_id in track.sidechain_sources() {
+                if !state.tracks.has(source_id) {
+                    continue;
+                }
+                if state.track_depends_on(source_id, track_id) {
+                    tracing::warn!(
+                        "sidechain from {source_id:?} to {track_id:?} would create a cycle; ignoring it"
+                    );
+                    continue;
+                }
+                routes.push((source_id, track_id));
+            }
+        }
+        routes.sort_unstable();
+        routes.dedup();
+
+        for track_state in self.tracks.values_mut() {
+            track_state.sidechain_sources.clear();
+            track_state.sidechain_consumers.clear();
+        }
+        for (source_id, consumer_id) in routes {
+            self.tracks
+                .force_get_mut(source_id)
+                .sidechain_consumers
+                .push(consumer_id);
+            self.tracks
+                .force_get_mut(consumer_id)
+                .sidechain_sources
+                .push(source_id);
+        }
+    }
+
+    /// Delays the notes and child tracks of a track so they all line up, then returns the latency of the track's output.
+    fn compensate_latency(
+        &mut self,
+        state: &State,
+        track_id: Id<Track>,
+        options: &WorkerOptions,
+    ) -> u32 {
+        let child_latencies: Vec<_> = state
+            .tracks
+            .force_get(track_id)
+            .children
+            .iter()
+            .map(|&child_id| (child_id, self.compensate_latency(state, child_id, options)))
+            .collect();
+
+        let track_state = self.tracks.force_get_mut(track_id);
+        let note_latency = track_state.note_nodes.latency();
+        let input_latency = child_latencies
+            .iter()
+            .map(|&(_, latency)| latency)
+            .fold(note_latency, u32::max);
+        track_state.set_note_compensation(input_latency - note_latency, options);
+        let output_latency = input_latency + track_state.track_nodes.latency();
+
+        for (child_id, child_latency) in child_latencies {
+            self.tracks
+                .force_get_mut(child_id)
+                .track_nodes
+                .set_output_delay(input_latency - child_latency);
+        }
+
+        output_latency
+    }
+}
+
+/// The note node graphs of a track that aren't playing a note.
+#[derive(Debug, Default)]
+pub struct VoicePool {
+    free: Vec<NoteNodeGraph>,
+}
+
+impl VoicePool {
+    /// Takes a free voice to play a new note with, or `None` if the track's polyphony has been reached.
+    pub fn take(&mut self) -> Option<NoteNodeGraph> {
+        let mut voice = self.free.pop()?;
+        voice.reset();
+        Some(voice)
+    }
+    /// Returns the voice of a note that stopped playing.
+    pub fn give_back(&mut self, voice: NoteNodeGraph) {
+        self.free.push(voice);
+    }
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
     }
 }
 
@@ -109,6 +254,30 @@ pub struct WorkerTrackState {
     // TODO: switch these to vec for optimization purposes (when necessary)
     pub notes: IdMap<Note, WorkerNoteState>,
     pub live_notes: IdMap<Note, WorkerLiveNoteState>,
+    /// Notes that were cut off by playback looping back but are still finishing, since their output is delayed by
+    /// [`Self::note_compensation`]. They're kept apart from `notes` since the same note can start again right away.
+    pub looped_notes: IdMap<Note, WorkerNoteState>,
+
+    /// Note node graphs that aren't playing a note. There are enough of these for the track's polyphony, so starting a note doesn't allocate.
+    pub voices: VoicePool,
+    /// How many samples notes are delayed by to line up with the child tracks. Notes end this much later too.
+    pub note_compensation: u32,
+    /// The level of the track's output during the last buffer.
+    pub level: Level,
+    /// Probes on nodes in the track's patch. There's at most one per node.
+    pub probes: Vec<Probe>,
+    /// The track's prerendered output. While this is set, it's played back instead of processing the track and its
+    /// descendants.
+    pub frozen: Option<FrozenTrack>,
+    /// The tracks this track's sidechain nodes read from. The track is processed after them.
+    pub sidechain_sources: Vec<Id<Track>>,
+    /// The tracks whose sidechain nodes read from this track.
+    pub sidechain_consumers: Vec<Id<Track>>,
+    /// The tracks this track sends its output to, besides its parent.
+    pub sends: Vec<(Id<Track>, TrackSend)>,
+    /// Where the time-stretching of each audio clip left off, for the part of the buffer before playback loops back
+    /// and the part after. Clip jobs share these, so they're behind mutexes, but each one is only used by one job.
+    pub grain_caches: IdMap<Clip, [Mutex<GrainCache>; 2]>,
 }
 impl WorkerTrackState {
     pub fn from_track(track: &Track, options: &WorkerOptions) -> anyhow::Result<Self> {
@@ -118,15 +287,29 @@ impl WorkerTrackState {
 
             notes: Default::default(),
             live_notes: Default::default(),
+            looped_notes: Default::default(),
+
+            voices: VoicePool::default(),
+            note_compensation: 0,
+            level: Level::default(),
+            probes: Vec::new(),
+            frozen: None,
+            sidechain_sources: Vec::new(),
+            sidechain_consumers: Vec::new(),
+            sends: Vec::new(),
+            grain_caches: IdMap::new(),
         };
         this.sync_with(track, options)?;
         Ok(this)
     }
 
     pub fn sync_with(&mut self, track: &Track, options: &WorkerOptions) -> anyhow::Result<()> {
-        let patch = &track.patch;
+        let patch = track.patch();
         patch.debug_assert_valid();
 
+        self.probes
+            .retain(|probe| patch.node_entry(probe.node_id).is_some());
+
         self.track_nodes.sync_with(patch, options)?;
         self.note_nodes.sync_with(patch, options)?;
 
@@ -134,13 +317,103 @@ impl WorkerTrackState {
         for (_note_id, note_state) in &mut self.notes {
             note_state.sync_with(track, options)?;
         }
+        for (_note_id, note_state) in &mut self.looped_notes {
+            note_state.sync_with(track, options)?;
+        }
         for (_note_id, note_state) in &mut self.live_notes {
             note_state.sync_with(track, options)?;
         }
+        for voice in &mut self.voices.free {
+            voice.sync_with(patch, options)?;
+        }
+
+        // keep exactly enough voices around for the track's polyphony
+        let polyphony = track.polyphony() as usize;
+        let num_playing = self.notes.len() + self.live_notes.len() + self.looped_notes.len();
+        let num_free = polyphony.saturating_sub(num_playing);
+        let free = &mut self.voices.free;
+        free.truncate(num_free);
+        free.reserve(polyphony.saturating_sub(free.len()));
+        while free.len() < num_free {
+            free.push(self.note_nodes.clone());
+        }
+        self.notes
+            .reserve(polyphony.saturating_sub(self.notes.len()));
+        self.live_notes
+            .reserve(polyphony.saturating_sub(self.live_notes.len()));
+        self.looped_notes
+            .reserve(polyphony.saturating_sub(self.looped_notes.len()));
+
+        self.grain_caches
+            .retain(|clip_id, _| track.clip(clip_id).is_some_and(|clip| clip.audio.is_some()));
+        for (_, clip_id, clip) in track.clips() {
+            if clip.audio.is_some() && !self.grain_caches.has(clip_id) {
+                self.grain_caches.insert(clip_id, Default::default());
+            }
+        }
 
         Ok(())
     }
 
+    fn set_note_compensation(&mut self, compensation: u32, options: &WorkerOptions) {
+        self.note_compensation = compensation;
+        self.note_nodes.set_compensation(compensation, options);
+        for voice in &mut self.voices.free {
+            voice.set_compensation(compensation, options);
+        }
+        for note_state in self
+            .notes
+            .values_mut()
+            .chain(self.looped_notes.values_mut())
+        {
+            note_state.nodes.set_compensation(compensation, options);
+        }
+        for note_state in self.live_notes.values_mut() {
+            note_state.nodes.set_compensation(compensation, options);
+        }
+    }
+
+    /// The number of voices this track has, playing or not.
+    pub fn num_voices(&self) -> usize {
+        self.voices.len() + self.notes.len() + self.live_notes.len() + self.looped_notes.len()
+    }
+    /// Stops all notes and returns their voices to the pool.
+    pub fn stop_all_notes(&mut self) {
+        for (_note_id, note_state) in self.notes.drain() {
+            self.voices.give_back(note_state.nodes);
+        }
+        for (_note_id, note_state) in self.live_notes.drain() {
+            self.voices.give_back(note_state.nodes);
+        }
+        self.stop_looped_notes();
+    }
+    /// Stops the notes that are finishing after being cut off by a loop.
+    pub fn stop_looped_notes(&mut self) {
+        for (_note_id, note_state) in self.looped_notes.drain() {
+            self.voices.give_back(note_state.nodes);
+        }
+    }
+
+    /// Silences a node in the track's node graph and in all of its notes.
+    pub fn silence_node(&mut self, node_id: Id<Node>) {
+        self.track_nodes.silence_node(node_id);
+        self.note_nodes.silence_node(node_id);
+        for (_note_id, note_state) in &mut self.notes {
+            note_state.nodes.silence_node(node_id);
+        }
+        for (_note_id, note_state) in &mut self.live_notes {
+            note_state.nodes.silence_node(node_id);
+        }
+        for (_note_id, note_state) in &mut self.looped_notes {
+            note_state.nodes.silence_node(node_id);
+        }
+        for voice in &mut self.voices.free {
+            voice.silence_node(node_id);
+        }
+    }
+
+    /// The state of a track that ignores its notes and passes its input through. Tracks that fail to sync fall back to
+    /// this, so they're silent apart from their children.
     pub fn empty(options: &WorkerOptions) -> Self {
         use cubedaw_lib::{NodeData, ResourceKey};
 
@@ -151,7 +424,7 @@ impl WorkerTrackState {
                 fake_patch.insert_node(
                     id,
                     NodeData::new_disconnected(key, inner),
-                    vec![1.0; num_inputs as usize],
+                    vec![0.0; num_inputs as usize],
                     num_outputs,
                 );
                 id
@@ -185,30 +458,42 @@ impl WorkerTrackState {
 pub struct WorkerNoteState {
     pub clip_id: Id<Clip>,
     pub nodes: NoteNodeGraph,
+    /// For looped notes, the sample offset into the current buffer where the note was cut off. Negative once that's in the past.
+    pub cut_offset: Option<i64>,
 }
 impl WorkerNoteState {
     pub fn sync_with(&mut self, track: &Track, options: &WorkerOptions) -> Result<()> {
-        self.nodes.sync_with(&track.patch, options)
+        self.nodes.sync_with(track.patch(), options)
     }
 }
 
 #[derive(Debug)]
 pub struct WorkerLiveNoteState {
+    /// The song position the note started at. This is set when the note is first processed.
     pub start_pos: i64,
     pub note: Note,
     pub nodes: NoteNodeGraph,
     pub samples_elapsed: u64,
+    /// Whether the note has been stopped. Released notes end at the start of the next buffer, plus the track's note compensation.
+    pub released: bool,
+    /// How many samples have been processed since the note was released.
+    pub samples_since_release: u64,
 }
 impl WorkerLiveNoteState {
     pub fn sync_with(&mut self, track: &Track, options: &WorkerOptions) -> Result<()> {
-        self.nodes.sync_with(&track.patch, options)
+        self.nodes.sync_with(track.patch(), options)
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::WorkerOptions;
+    use cubedaw_lib::{
+        Buffer, Cable, CableConnection, Id, IdMap, InternalBufferType, NodeData, Patch, Sample,
+        Track,
+    };
+
+    use crate::{WorkerOptions, WorkerState, resample::ResampleQuality, test_plugin};
 
     use super::WorkerTrackState;
 
@@ -218,6 +503,186 @@ mod tests {
 
         WorkerTrackState::empty(&options);
     }
+
+    #[test]
+    fn stitched_matches_unstitched() {
+        let mut options = WorkerOptions::new(Default::default());
+        let mut input = Buffer::new_box_zeroed(options.buffer_size);
+        for (i, sample) in input.iter_mut().enumerate() {
+            *sample = i as f32 * 0.01;
+        }
+        let silence = Buffer::new_box_zeroed(options.buffer_size);
+
+        let mut render = |stitch_node_graphs| {
+            options.stitch_node_graphs = stitch_node_graphs;
+            let mut state = WorkerState::new(&options);
+            let mut track_state = WorkerTrackState::empty(&options);
+            options.stitch_cache.wait();
+            state.update_stitched_graphs(&options);
+            track_state
+                .track_nodes
+                .process(
+                    &options,
+                    &mut state,
+                    &Default::default(),
+                    &silence,
+                    &|_| None,
+                    &input,
+                )
+                .unwrap()
+                .to_vec()
+        };
+
+        let stitched = render(true);
+        assert_eq!(stitched, render(false));
+        // the input is passed through
+        assert_eq!(stitched[100], 1.0);
+    }
+
+    #[test]
+    fn stitched_plugin_matches_unstitched() {
+        let mut patch = Patch::new();
+        let mut insert_node = |key, args, num_inputs: usize, num_outputs| {
+            let id = Id::arbitrary();
+            patch.insert_node(
+                id,
+                NodeData::new_disconnected(key, args),
+                vec![0.0; num_inputs],
+                num_outputs,
+            );
+            id
+        };
+        let input = insert_node(
+            resourcekey::literal!("builtin:output"),
+            Default::default(),
+            1,
+            1,
+        );
+        let scale = insert_node(test_plugin::SCALE, test_plugin::scale_args(0.5), 1, 2);
+        let output = insert_node(
+            resourcekey::literal!("builtin:track_output"),
+            Default::default(),
+            1,
+            0,
+        );
+        for cable in [
+            Cable::one(input, scale),
+            Cable::new(scale, 0, output, 0, 0),
+            Cable::new(scale, 1, output, 0, 1),
+        ] {
+            patch.insert_cable(Id::arbitrary(), cable, CableConnection { multiplier: 1.0 });
+        }
+        let track = Track::new(patch);
+
+        let mut options = WorkerOptions::new(test_plugin::registry());
+        let mut input = Buffer::new_box_zeroed(options.buffer_size);
+        for (i, sample) in input.iter_mut().enumerate() {
+            *sample = i as f32;
+        }
+        let mut render = |stitch_node_graphs| {
+            render_plugin_track(
+                &track,
+                &mut options,
+                &Default::default(),
+                &input,
+                stitch_node_graphs,
+            )
+        };
+
+        let stitched = render(true);
+        assert_eq!(stitched, render(false));
+        // half the input, plus the number of chunks processed so far
+        let chunks_per_buffer = options.buffer_size as usize / InternalBufferType::N;
+        let second_buffer = options.buffer_size as usize;
+        assert_eq!(
+            stitched[second_buffer + 20],
+            10.0 + (chunks_per_buffer + 2) as f32
+        );
+    }
+
+    #[test]
+    fn stitched_plugin_reads_samples() {
+        let mut patch = Patch::new();
+        let sample_id = Id::arbitrary();
+        // the notes' input is added to the sampler's position
+        let input = Id::arbitrary();
+        patch.insert_node(
+            input,
+            NodeData::new_disconnected(resourcekey::literal!("builtin:output"), Default::default()),
+            vec![0.0],
+            1,
+        );
+        let sampler = Id::arbitrary();
+        patch.insert_node(
+            sampler,
+            NodeData::new_disconnected(test_plugin::SAMPLER, test_plugin::sampler_args(sample_id)),
+            vec![0.0],
+            1,
+        );
+        let output = Id::arbitrary();
+        patch.insert_node(
+            output,
+            NodeData::new_disconnected(
+                resourcekey::literal!("builtin:track_output"),
+                Default::default(),
+            ),
+            vec![0.0],
+            0,
+        );
+        for cable in [Cable::one(input, sampler), Cable::one(sampler, output)] {
+            patch.insert_cable(Id::arbitrary(), cable, CableConnection { multiplier: 1.0 });
+        }
+        let track = Track::new(patch);
+
+        let mut options = WorkerOptions::new(test_plugin::registry());
+        // so reading at whole positions gives back exactly what's in the sample
+        options.resample_quality = ResampleQuality::Linear;
+        // runs out partway through the second buffer
+        let len = options.buffer_size as usize * 3 / 2;
+        let data: Vec<f32> = (0..len).map(|i| i as f32 * 0.5).collect();
+        let mut samples = IdMap::new();
+        samples.insert(
+            sample_id,
+            Sample::new("a".into(), options.sample_rate, data.clone().into()),
+        );
+        let silence = Buffer::new_box_zeroed(options.buffer_size);
+
+        let unstitched = render_plugin_track(&track, &mut options, &samples, &silence, false);
+        let stitched = render_plugin_track(&track, &mut options, &samples, &silence, true);
+        assert_eq!(&unstitched[..len], &data[..]);
+        assert!(unstitched[len..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(stitched, unstitched);
+    }
+
+    /// Renders three buffers of a track that uses the test plugin, with `input` as the input of every note.
+    fn render_plugin_track(
+        track: &Track,
+        options: &mut WorkerOptions,
+        samples: &IdMap<Sample>,
+        input: &Buffer,
+        stitch_node_graphs: bool,
+    ) -> Vec<f32> {
+        options.stitch_node_graphs = stitch_node_graphs;
+        let mut state = WorkerState::new(options);
+        let mut track_state = WorkerTrackState::from_track(track, options).unwrap();
+        options.stitch_cache.wait();
+        state.update_stitched_graphs(options);
+        if stitch_node_graphs {
+            let key = track_state.track_nodes.stitch_key().unwrap();
+            assert!(state.stitched_graph(key).is_some());
+        }
+
+        let silence = Buffer::new_box_zeroed(options.buffer_size);
+        let mut rendered = Vec::new();
+        for _ in 0..3 {
+            let output = track_state
+                .track_nodes
+                .process(options, &mut state, samples, &silence, &|_| None, input)
+                .unwrap();
+            rendered.extend_from_slice(output);
+        }
+        rendered
+    }
 }
 
 // TODO
@@ -1,10 +1,10 @@
-use anyhow::Result;
-use cubedaw_lib::{Buffer, Id, Note, PreciseSongPos, Track};
+use cubedaw_lib::{AudioClip, Buffer, Id, Note, PreciseSongPos, Track};
 
 use crate::{
-    WorkerState,
+    Level, Probe, ProbeSource, WorkerState,
     common::JobDescriptor,
     node_graph::{NoteNodeGraph, TrackNodeGraph},
+    stretch::{GrainCache, TimeStretch},
     sync,
     worker::WorkerScratch,
 };
@@ -17,20 +17,165 @@ pub enum WorkerJob {
         track_id: Id<Track>,
         note_descriptor: NoteDescriptor,
         nodes: &'static mut NoteNodeGraph,
+        /// The sample offset into this buffer at which the note ends, if it ends in this buffer.
+        end_offset: Option<u32>,
+        /// The probes on the note's track.
+        probes: &'static [Probe],
+        /// What the `builtin:audio_input` nodes output.
+        live_input: &'static Buffer,
         output: sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, WorkerJob>,
     },
     /// Process a track.
     TrackProcess {
         track_id: Id<Track>,
         nodes: &'static mut TrackNodeGraph,
+        /// Where to put the level of the track's output.
+        level: &'static mut Level,
+        probes: &'static [Probe],
+        /// What the `builtin:audio_input` nodes output.
+        live_input: &'static Buffer,
+        /// The outputs of the tracks the `builtin:sidechain` nodes read from. They're finished before this job starts.
+        /// Tracks that aren't processed (because an ancestor is frozen) have no output.
+        sidechains: &'static [(Id<Track>, Option<SidechainReadHandle>)],
         input: sync::SyncAccessibleReadHandle<'static, &'static mut Buffer, WorkerJob>,
+        output: TrackOutput,
+    },
+    /// Play back a frozen track's prerendered output instead of processing the track.
+    FrozenTrackProcess {
+        audio: &'static [f32],
+        /// The index into `audio` that lines up with the start of this buffer, or `None` if the song isn't playing.
+        /// Indices before the start of `audio` are silent.
+        start_index: Option<i64>,
+        /// If playback loops back partway through this buffer: the offset into the buffer where it does, and the
+        /// index into `audio` that would line up with the start of the buffer from then on.
+        loop_jump: Option<(u32, i64)>,
+        level: &'static mut Level,
+        output: TrackOutput,
+    },
+    /// Play part of an audio clip into its track's input.
+    AudioClipProcess {
+        clip: &'static AudioClip,
+        /// The length of the clip, in song units.
+        length: u64,
+        data: &'static [f32],
+        /// How many frames of the sample play per output sample.
+        rate: f64,
+        stretch: TimeStretch,
+        /// Where the clip's time-stretching left off, if the track has one for it.
+        grain_cache: Option<&'static std::sync::Mutex<GrainCache>>,
+        /// The sample offset into this buffer where the clip starts. This can be outside of the buffer.
+        clip_start: i64,
+        /// The part of the buffer to play into. The rest is left alone.
+        offsets: (u32, u32),
+        units_per_sample: f64,
         output: sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, WorkerJob>,
     },
     /// Not actually a job. This is a signal to the worker that they should drop all resources and send the `Idle` event.
     Finalize,
 }
+/// Everywhere a track's output goes.
+#[derive(Debug)]
+pub struct TrackOutput {
+    /// The track's fader.
+    pub gain: f32,
+    /// The input of the track's parent, or the master output.
+    pub parent: sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, WorkerJob>,
+    /// The track's sends. Sends to tracks that aren't processed are `None`.
+    pub sends: &'static mut [Option<SendOutput>],
+    pub sidechain: Option<SidechainOutput>,
+}
+
+impl TrackOutput {
+    /// Applies the fader to `buffer` (or silence, if the track failed to process) and writes it everywhere it goes.
+    /// Returns the parent's job if this was the last thing it was waiting on.
+    fn write(
+        self,
+        mut buffer: Option<&mut Buffer>,
+        level: &mut Level,
+        work_tx: &crossbeam_channel::Sender<WorkerJob>,
+    ) -> Option<WorkerJob> {
+        for send in self.sends.iter_mut() {
+            if let Some(send) = send.take_if(|send| send.pre_fader) {
+                send.write(buffer.as_deref(), work_tx);
+            }
+        }
+        if let Some(ref mut buffer) = buffer {
+            for sample in buffer.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+        let buffer = buffer.as_deref();
+
+        *level = buffer.map_or_else(Level::default, |buffer| Level::of(buffer));
+        let job_to_add = self.parent.lock(|output_buf| {
+            if let Some(buffer) = buffer {
+                output_buf.accumulate(buffer);
+            }
+        });
+        for send in self.sends.iter_mut().filter_map(Option::take) {
+            send.write(buffer, work_tx);
+        }
+        if let Some(sidechain) = self.sidechain {
+            sidechain.write(buffer, work_tx);
+        }
+        job_to_add
+    }
+}
+
+/// A track's send to another track's input.
+#[derive(Debug)]
+pub struct SendOutput {
+    pub amount: f32,
+    pub pre_fader: bool,
+    pub output: sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, WorkerJob>,
+}
+
+impl SendOutput {
+    fn write(self, buffer: Option<&Buffer>, work_tx: &crossbeam_channel::Sender<WorkerJob>) {
+        let job = self.output.lock(|output_buf| {
+            if let Some(buffer) = buffer {
+                output_buf.accumulate_scaled(buffer, self.amount);
+            }
+        });
+        // like with sidechains, this is a track input and never the master output
+        if let Some(job) = job {
+            work_tx.send(job).unwrap();
+        }
+    }
+}
+
+pub type SidechainReadHandle = sync::SyncAccessibleReadHandle<'static, &'static mut Buffer, ()>;
+
+/// Where a track's output goes for the `builtin:sidechain` nodes of other tracks.
+#[derive(Debug)]
+pub struct SidechainOutput {
+    pub buffer: sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, ()>,
+    /// Write handles to the inputs of the tracks that read `buffer`. Nothing's written to them; they're only there so
+    /// those tracks wait until `buffer` is done.
+    pub consumers: &'static mut [Option<
+        sync::SyncAccessibleWriteHandle<'static, &'static mut Buffer, WorkerJob>,
+    >],
+}
+
+impl SidechainOutput {
+    /// Writes the track's output (or silence, if there isn't any) and lets the tracks reading it start.
+    fn write(self, buffer: Option<&Buffer>, work_tx: &crossbeam_channel::Sender<WorkerJob>) {
+        self.buffer.lock(|output_buf| match buffer {
+            Some(buffer) => output_buf.copy_from(buffer),
+            None => output_buf.fill(0.0),
+        });
+        for consumer in self.consumers.iter_mut() {
+            // a track's input is never the master output, so this is never `WorkerJob::Finalize`
+            if let Some(job) = consumer.take().and_then(|consumer| consumer.lock(|_| ())) {
+                work_tx.send(job).unwrap();
+            }
+        }
+    }
+}
+
 impl WorkerJob {
-    /// Processes the job.
+    /// Processes the job. If processing fails, the job's output is left silent and the error is
+    /// returned in [`WorkerJobResult::error`].
     pub fn process(
         self,
         state: &cubedaw_lib::State,
@@ -38,56 +183,204 @@ impl WorkerJob {
         worker_options: &crate::WorkerOptions,
         worker_state: &mut WorkerState,
         scratch: &mut WorkerScratch,
-    ) -> Result<WorkerJobResult> {
-        let _ = (state, start_pos, scratch);
-        Ok(match self {
+        work_tx: &crossbeam_channel::Sender<WorkerJob>,
+    ) -> WorkerJobResult {
+        let _ = start_pos;
+        match self {
             Self::NoteProcess {
                 track_id,
                 note_descriptor,
                 nodes,
+                end_offset,
+                probes,
+                live_input,
                 output,
             } => {
-                let note = match note_descriptor {
-                    NoteDescriptor::State { note, .. } => note,
-                    NoteDescriptor::Live { note, .. } => note,
+                let (note_id, note) = match note_descriptor {
+                    NoteDescriptor::State { note_id, note, .. }
+                    | NoteDescriptor::Looped { note_id, note, .. } => (note_id, note),
+                    NoteDescriptor::Live { note_id, note, .. } => (note_id, note),
                 };
 
-                // TODO: tail detection and note autoremoval
-                let buffer = nodes.process(worker_options, worker_state, note)?;
+                // TODO: tail detection
+                let result = nodes.process(
+                    worker_options,
+                    worker_state,
+                    &state.samples,
+                    live_input,
+                    note,
+                    end_offset,
+                );
 
+                // the output has to be locked even on error, otherwise whatever's waiting on it would never run
                 let job_to_add = output.lock(|output_buf| {
-                    output_buf.accumulate(buffer);
+                    if let Ok(buffer) = result {
+                        output_buf.accumulate(buffer);
+                    }
                 });
 
+                let error = result.err();
+
+                for probe in probes
+                    .iter()
+                    .filter(|probe| probe.listens_to_voice(note_id))
+                {
+                    if let Some(buffer) = nodes.node_output(probe.node_id, probe.output_index) {
+                        probe.ring.write(buffer);
+                    }
+                }
+
                 WorkerJobResult {
-                    finished_job_descriptor: Some(JobDescriptor::NoteProcess {
-                        track_id,
-                        note_descriptor,
-                    }),
+                    // notes that errored are removed
+                    finished_job_descriptor: (end_offset.is_some() || error.is_some()).then_some(
+                        JobDescriptor::NoteProcess {
+                            track_id,
+                            note_descriptor,
+                        },
+                    ),
                     job_to_add,
+                    error: error.map(|error| (track_id, error)),
                 }
             }
             Self::TrackProcess {
                 track_id,
                 nodes,
+                level,
+                probes,
+                live_input,
+                sidechains,
                 input,
                 output,
             } => {
-                let buffer = nodes.process(worker_options, worker_state, input.wait())?;
-
-                // dbg!(buffer);
+                let sidechain = |source_id: Id<Track>| -> Option<&Buffer> {
+                    let (_, handle) = sidechains.iter().find(|&&(id, _)| id == source_id)?;
+                    handle.as_ref().map(|handle| &**handle.wait())
+                };
+                let result = nodes.process(
+                    worker_options,
+                    worker_state,
+                    &state.samples,
+                    live_input,
+                    &sidechain,
+                    input.wait(),
+                );
 
-                let job_to_add = output.lock(|output_buf| {
-                    output_buf.accumulate(buffer);
+                // the fader is applied in place, so the output can't stay in the node graph
+                let buffer = result.as_ref().ok().map(|&buffer| {
+                    scratch.0.copy_from(buffer);
+                    &mut *scratch.0
                 });
+                let job_to_add = output.write(buffer, level, work_tx);
+
+                let error = result.err();
+
+                for probe in probes
+                    .iter()
+                    .filter(|probe| probe.source == ProbeSource::Track)
+                {
+                    if let Some(buffer) = nodes.node_output(probe.node_id, probe.output_index) {
+                        probe.ring.write(buffer);
+                    }
+                }
+
+                WorkerJobResult {
+                    finished_job_descriptor: None,
+                    job_to_add,
+                    error: error.map(|error| (track_id, error)),
+                }
+            }
+            Self::FrozenTrackProcess {
+                audio,
+                start_index,
+                loop_jump,
+                level,
+                output,
+            } => {
+                let buffer = &mut *scratch.0;
+                match start_index {
+                    Some(start_index) => {
+                        for (offset, sample) in buffer.iter_mut().enumerate() {
+                            let index_at_buffer_start = match loop_jump {
+                                Some((jump_offset, index)) if offset >= jump_offset as usize => {
+                                    index
+                                }
+                                _ => start_index,
+                            };
+                            *sample = usize::try_from(index_at_buffer_start + offset as i64)
+                                .ok()
+                                .and_then(|index| audio.get(index))
+                                .copied()
+                                .unwrap_or(0.0);
+                        }
+                    }
+                    None => buffer.fill(0.0),
+                }
+
+                let job_to_add = output.write(Some(buffer), level, work_tx);
 
                 WorkerJobResult {
-                    finished_job_descriptor: Some(JobDescriptor::TrackProcess { track_id }),
+                    finished_job_descriptor: None,
                     job_to_add,
+                    error: None,
+                }
+            }
+            Self::AudioClipProcess {
+                clip,
+                length,
+                data,
+                rate,
+                stretch,
+                grain_cache,
+                clip_start,
+                offsets: (start, end),
+                units_per_sample,
+                output,
+            } => {
+                let buffer = &mut *scratch.0;
+                buffer.fill(0.0);
+                let kernel = worker_options.resample_quality.kernel();
+                if !stretch.is_identity() {
+                    let mut fallback = GrainCache::default();
+                    let mut lock = grain_cache.map(|cache| {
+                        cache
+                            .lock()
+                            .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    });
+                    stretch.render(
+                        kernel,
+                        data,
+                        clip.start_offset as f64,
+                        rate,
+                        worker_options.sample_rate,
+                        start as i64 - clip_start,
+                        lock.as_deref_mut().unwrap_or(&mut fallback),
+                        &mut (**buffer)[start as usize..end as usize],
+                    );
+                }
+                for offset in start..end {
+                    let samples_into_clip = (offset as i64 - clip_start) as f64;
+                    let pos = samples_into_clip * units_per_sample;
+                    if !(0.0..length as f64).contains(&pos) {
+                        buffer[offset] = 0.0;
+                        continue;
+                    }
+                    if stretch.is_identity() {
+                        let frame = clip.start_offset as f64 + samples_into_clip * rate;
+                        buffer[offset] = kernel.interpolate(data, frame, rate);
+                    }
+                    buffer[offset] *= clip.gain_at(pos, length);
+                }
+
+                let job_to_add = output.lock(|output_buf| output_buf.accumulate(buffer));
+
+                WorkerJobResult {
+                    finished_job_descriptor: None,
+                    job_to_add,
+                    error: None,
                 }
             }
             Self::Finalize => unimplemented!("can't call process() on WorkerJob::Finalize"),
-        })
+        }
     }
     // pub fn track_id(&self) -> Id<Track> {
     //     match *self {
@@ -102,6 +395,8 @@ pub struct WorkerJobResult {
     /// If the associated job can no longer produce audio, this is `Some(job_descriptor)`. Otherwise, it's `None`.
     pub finished_job_descriptor: Option<crate::common::JobDescriptor>,
     pub job_to_add: Option<WorkerJob>,
+    /// The error that occurred during processing, if any, along with the track it occurred on.
+    pub error: Option<(Id<Track>, anyhow::Error)>,
 }
 
 /// A descriptor for a [`cubedaw_lib::Note`]. Either a path to a note in the State, or a "live"
@@ -116,6 +411,13 @@ pub enum NoteDescriptor {
         start_pos: i64,
         note: &'static Note,
     },
+    /// A note in a `State` that was cut off by playback looping back. See [`crate::host::WorkerTrackState::looped_notes`].
+    Looped {
+        note_id: Id<Note>,
+
+        start_pos: i64,
+        note: &'static Note,
+    },
     Live {
         note_id: Id<Note>,
 
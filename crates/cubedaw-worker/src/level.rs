@@ -0,0 +1,49 @@
+/// Peak and RMS levels of a buffer of audio, for metering.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Level {
+    pub peak: f32,
+    pub rms: f32,
+    /// Whether any sample was outside of `-1.0..=1.0`.
+    pub clipped: bool,
+}
+
+impl Level {
+    pub fn of(buf: &[f32]) -> Self {
+        if buf.is_empty() {
+            return Self::default();
+        }
+        let mut peak = 0.0f32;
+        let mut sum_of_squares = 0.0f32;
+        for &sample in buf {
+            peak = peak.max(sample.abs());
+            sum_of_squares += sample * sample;
+        }
+        Self {
+            peak,
+            rms: (sum_of_squares / buf.len() as f32).sqrt(),
+            clipped: peak > 1.0,
+        }
+    }
+    /// The louder of two levels, e.g. to combine the levels of several buffers.
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            peak: self.peak.max(other.peak),
+            rms: self.rms.max(other.rms),
+            clipped: self.clipped || other.clipped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Level;
+
+    #[test]
+    fn square_wave() {
+        let level = Level::of(&[0.5, -0.5, 0.5, -0.5]);
+        assert_eq!(level.peak, 0.5);
+        assert_eq!(level.rms, 0.5);
+        assert!(!level.clipped);
+        assert!(Level::of(&[0.0, -1.5]).clipped);
+    }
+}
@@ -2,21 +2,40 @@
 #![feature(gen_blocks)]
 #![feature(non_null_from_ref)]
 
+pub mod alloc_guard;
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: alloc_guard::GuardedAllocator = alloc_guard::GuardedAllocator;
 mod common;
+#[cfg(test)]
+mod golden;
 pub mod host;
+pub mod offline;
+pub use offline::FrozenTrack;
 mod plugin;
 mod registry;
 pub use registry::{DynNodeFactory, NodeRegistry, NodeRegistryEntry, PluginData};
+mod level;
+mod metronome;
+mod probe;
 mod worker;
-pub use host::WorkerHost;
+pub use host::{ProcessingError, WorkerHost};
+pub use level::Level;
+pub use metronome::Metronome;
+pub use probe::{Probe, ProbeRing, ProbeSource};
 pub use worker::WorkerOptions;
 pub mod command;
 mod state;
 pub(crate) use state::WorkerState;
+#[cfg(test)]
+mod test_plugin;
 
 pub mod sync;
 
+pub mod resample;
+pub mod stretch;
 mod util;
+pub mod wav;
 
 mod job;
 pub(crate) use job::{NoteDescriptor, WorkerJob};
@@ -0,0 +1,121 @@
+//! The click track. It's generated by the worker host and mixed into the master output, so it doesn't go through any track.
+
+use cubedaw_lib::{Range, State};
+
+use crate::{WorkerOptions, host::BufferRanges};
+
+/// Metronome settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metronome {
+    pub enabled: bool,
+    /// Gain of the clicks, separate from the song's volume.
+    pub level: f32,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            level: 0.5,
+        }
+    }
+}
+
+/// How long each click lasts.
+const CLICK_SECONDS: f32 = 0.04;
+/// Time constant of the clicks' decay.
+const CLICK_DECAY_SECONDS: f32 = 0.006;
+/// The first beat of each bar.
+const ACCENT_FREQUENCY: f32 = 1760.0;
+const FREQUENCY: f32 = 1320.0;
+/// How loud the other beats are compared to the first beat of the bar.
+const UNACCENTED_GAIN: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy)]
+struct Click {
+    /// How many samples the click has played for.
+    age: u32,
+    accent: bool,
+}
+
+/// Generates the clicks. Clicks can continue into the next buffer, so this has to stick around between buffers.
+#[derive(Debug, Default)]
+pub(crate) struct MetronomeState {
+    pub settings: Metronome,
+    click: Option<Click>,
+}
+
+impl MetronomeState {
+    /// Adds the clicks for the beats in `ranges` to `output`. `ranges` is `None` when the song isn't playing.
+    pub fn process(
+        &mut self,
+        state: &State,
+        options: &WorkerOptions,
+        ranges: Option<&BufferRanges>,
+        output: &mut [f32],
+    ) {
+        if !self.settings.enabled {
+            self.stop();
+            return;
+        }
+
+        const UNITS_PER_BEAT: i64 = Range::UNITS_PER_BEAT as i64;
+        let beats_per_bar = state.beats_per_bar.max(1) as i64;
+        let buffer_size = output.len() as i64;
+
+        let mut pos = 0;
+        if let Some(ranges) = ranges {
+            let range_iter = core::iter::once((ranges.range, 0)).chain(ranges.looped);
+            for (range, shift) in range_iter {
+                // beats in range.start..range.end, rounded up
+                let first_beat = (range.start + UNITS_PER_BEAT - 1).div_euclid(UNITS_PER_BEAT);
+                let end_beat = (range.end + UNITS_PER_BEAT - 1).div_euclid(UNITS_PER_BEAT);
+                for beat in first_beat..end_beat {
+                    let offset = ranges
+                        .samples_until(beat * UNITS_PER_BEAT + shift)
+                        .clamp(pos, buffer_size);
+                    self.render(options, &mut output[pos as usize..offset as usize]);
+                    pos = offset;
+                    self.click = Some(Click {
+                        age: 0,
+                        accent: beat.rem_euclid(beats_per_bar) == 0,
+                    });
+                }
+            }
+        }
+        self.render(options, &mut output[pos as usize..]);
+    }
+
+    /// Cuts off the current click.
+    pub fn stop(&mut self) {
+        self.click = None;
+    }
+
+    /// Continues the current click, if any.
+    fn render(&mut self, options: &WorkerOptions, output: &mut [f32]) {
+        let Some(mut click) = self.click else {
+            return;
+        };
+        let sample_rate = options.sample_rate as f32;
+        let length = (CLICK_SECONDS * sample_rate) as u32;
+        let (frequency, gain) = if click.accent {
+            (ACCENT_FREQUENCY, 1.0)
+        } else {
+            (FREQUENCY, UNACCENTED_GAIN)
+        };
+        let gain = gain * self.settings.level;
+
+        for sample in output {
+            if click.age >= length {
+                self.click = None;
+                return;
+            }
+            let t = click.age as f32 / sample_rate;
+            *sample += gain
+                * (core::f32::consts::TAU * frequency * t).sin()
+                * (-t / CLICK_DECAY_SECONDS).exp();
+            click.age += 1;
+        }
+        self.click = Some(click);
+    }
+}
@@ -0,0 +1,79 @@
+/// A delay of a whole number of samples, used for latency compensation.
+///
+/// The delay can be changed without allocating as long as it stays within the capacity.
+#[derive(Clone, Debug, Default)]
+pub struct DelayLine {
+    buffer: Box<[f32]>,
+    delay: u32,
+    pos: u32,
+}
+
+impl DelayLine {
+    /// Makes sure delays of up to `capacity` samples can be set without allocating.
+    pub fn reserve(&mut self, capacity: u32) {
+        if self.buffer.len() < capacity as usize {
+            self.buffer = vec![0.0; capacity as usize].into_boxed_slice();
+            self.pos = 0;
+        }
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+    /// Sets the delay and clears out whatever was in the delay line. Panics if there isn't enough capacity; see [`Self::reserve`].
+    pub fn set_delay(&mut self, delay: u32) {
+        assert!(
+            delay as usize <= self.buffer.len(),
+            "delay of {delay} samples exceeds capacity of {}",
+            self.buffer.len()
+        );
+        self.delay = delay;
+        self.reset();
+    }
+
+    /// Clears out whatever was in the delay line.
+    pub fn reset(&mut self) {
+        self.buffer[..self.delay as usize].fill(0.0);
+        self.pos = 0;
+    }
+
+    /// Delays `buf` in place, carrying samples over from and into the previous and next calls.
+    pub fn process(&mut self, buf: &mut [f32]) {
+        if self.delay == 0 {
+            return;
+        }
+        let delayed = &mut self.buffer[..self.delay as usize];
+        let mut pos = self.pos as usize;
+        for sample in buf {
+            std::mem::swap(sample, &mut delayed[pos]);
+            pos += 1;
+            if pos == delayed.len() {
+                pos = 0;
+            }
+        }
+        self.pos = pos as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DelayLine;
+
+    #[test]
+    fn delays_across_calls() {
+        let mut delay = DelayLine::default();
+        delay.reserve(5);
+        delay.set_delay(5);
+
+        let mut a: Vec<f32> = (1..=4).map(|x| x as f32).collect();
+        let mut b: Vec<f32> = (5..=8).map(|x| x as f32).collect();
+        delay.process(&mut a);
+        delay.process(&mut b);
+        assert_eq!(a, [0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(b, [0.0, 1.0, 2.0, 3.0]);
+
+        delay.set_delay(0);
+        delay.process(&mut b);
+        assert_eq!(b, [0.0, 1.0, 2.0, 3.0]);
+    }
+}
@@ -1,19 +1,31 @@
-// mod group_track;
 // mod note;
-// mod clip_track;
+
+use std::sync::Arc;
 
 use ahash::HashSetExt;
 use anyhow::Context;
-use cubedaw_lib::{Buffer, Id, IdMap, IdSet, InternalBufferType, Node, Patch};
+use cubedaw_lib::{Buffer, Id, IdMap, IdSet, Node, Patch, Sample, Track};
 use resourcekey::ResourceKey;
 
-use crate::{WorkerOptions, WorkerState, plugin::AttributeMap, util};
-
+use crate::{
+    WorkerOptions, WorkerState,
+    plugin::{
+        AttributeMap,
+        stitched::{StitchKey, StitchedNode, StitchedNodeGraph},
+    },
+    util,
+};
+
+mod delay;
+pub use delay::DelayLine;
 mod synth_note;
 pub use synth_note::NoteNodeGraph;
 mod synth_track;
 pub use synth_track::TrackNodeGraph;
 
+/// Finds the output of the track a `builtin:sidechain` node reads from, if it has one.
+pub type SidechainLookup<'a> = dyn Fn(Id<Track>) -> Option<&'a Buffer> + 'a;
+
 #[derive(Clone, Debug)]
 /// A node graph. This is designed for fast updates and interactivity instead of performance.
 pub struct PreparedNodeGraph {
@@ -24,6 +36,16 @@ pub struct PreparedNodeGraph {
 
     id_to_index: IdMap<Node, u32>,
     nodes: Vec<NodeGraphEntry>,
+
+    /// Scratch buffer for cable multipliers when processing a stitched graph, or for delayed cables otherwise.
+    scratch: Box<Buffer>,
+    /// Whether any cables are delayed to compensate for latency.
+    has_delays: bool,
+    /// The graph's structure, if it gets stitched. See [`WorkerOptions::stitch_cache`].
+    ///
+    /// Stitched modules can't delay cables, so graphs with latency compensation delays (i.e. with a lookahead node
+    /// on one path but not another) are always processed node by node.
+    stitch_key: Option<Arc<StitchKey>>,
 }
 
 impl PreparedNodeGraph {
@@ -49,9 +71,10 @@ impl PreparedNodeGraph {
         options: &WorkerOptions,
         input_node: Option<Id<Node>>,
         output_node: Id<Node>,
-    ) {
+    ) -> anyhow::Result<()> {
         self.input_node = input_node;
         self.output_node = output_node;
+        self.has_delays = false;
 
         let mut node_id_to_vec_index_map: IdMap<Node, u32> = IdMap::new();
 
@@ -111,6 +134,8 @@ impl PreparedNodeGraph {
                         args: Default::default(),
                         state: Default::default(),
                         original_state: Default::default(),
+                        latency: 0,
+                        silenced: false,
                     });
                 }
             }
@@ -118,10 +143,18 @@ impl PreparedNodeGraph {
 
         while let Some(node_id) = zero_indegree_node_stack.pop() {
             let node = patch.node_entry(node_id).expect("unreachable");
+            let Some(registry_entry) = options.registry.get(&node.data.key) else {
+                return Err(NodeError {
+                    node_id,
+                    key: node.data.key.clone(),
+                    plugin: None,
+                    error: anyhow::anyhow!("no node with this key is registered"),
+                }
+                .into());
+            };
 
             let mut entry = prev_entries.remove(node_id).unwrap_or_else(|| {
-                let entry = options.registry.get(&node.data.key).expect("unreachable");
-                let state: Box<Buffer> = (entry.node_factory)(node.data.inner.as_bytes())
+                let state: Box<Buffer> = (registry_entry.node_factory)(node.data.inner.as_bytes())
                     .as_ref()
                     .into();
                 NodeGraphEntry {
@@ -135,6 +168,9 @@ impl PreparedNodeGraph {
                     args: Default::default(),
                     inputs: Default::default(),
                     outputs: Default::default(),
+                    latency: 0,
+
+                    silenced: false,
                 }
             });
 
@@ -158,6 +194,7 @@ impl PreparedNodeGraph {
                         connection: u32::MAX,
                         output_index: u32::MAX,
                         multiplier: InterpolatedValue::default(),
+                        delay: DelayLine::default(),
                     },
                 );
                 for (cable, graph_connection) in node_input
@@ -169,11 +206,40 @@ impl PreparedNodeGraph {
                     graph_connection
                         .multiplier
                         .set_raw(cable.node_input_connection(patch).multiplier);
+                    graph_connection
+                        .multiplier
+                        .set_smoothing(options.smoothing_ms, options.sample_rate);
                     graph_connection.output_index = cable.input_output_index;
                 }
 
                 graph_input.bias.set_raw(node_input.bias);
+                graph_input.bias.set_smoothing(
+                    node_input.smoothing_ms.unwrap_or(options.smoothing_ms),
+                    options.sample_rate,
+                );
+            }
+
+            // delay the cables coming from lower-latency paths so everything arrives at the same time
+            let input_latency = entry
+                .inputs
+                .iter()
+                .flat_map(|input| &input.connections)
+                .map(|conn| self.nodes[conn.connection as usize].latency)
+                .max()
+                .unwrap_or(0);
+            for conn in entry
+                .inputs
+                .iter_mut()
+                .flat_map(|input| &mut input.connections)
+            {
+                let delay = input_latency - self.nodes[conn.connection as usize].latency;
+                if conn.delay.delay() != delay {
+                    conn.delay.reserve(delay);
+                    conn.delay.set_delay(delay);
+                }
+                self.has_delays |= delay > 0;
             }
+            entry.latency = input_latency + registry_entry.latency();
 
             entry
                 .outputs
@@ -215,6 +281,28 @@ impl PreparedNodeGraph {
         );
 
         self.id_to_index = node_id_to_vec_index_map;
+
+        // the graph's output is the output node's first output, so there has to be one
+        if let Some(output_node) = self.get_node_mut(output_node)
+            && output_node.outputs.is_empty()
+        {
+            output_node.add_dummy_output(options);
+        }
+
+        if self.scratch.len() != options.buffer_size as usize {
+            self.scratch = Buffer::new_box_zeroed(options.buffer_size);
+        }
+
+        self.stitch_key = (options.stitch_node_graphs && !self.has_delays).then(|| {
+            let key = StitchKey::new(
+                self.stitched_nodes(options).collect(),
+                options.sample_rate,
+                options.buffer_size,
+            );
+            options.stitch_cache.request(key, options)
+        });
+
+        Ok(())
     }
     pub fn empty(input_node: Option<Id<Node>>, output_node: Id<Node>) -> Self {
         Self {
@@ -223,6 +311,10 @@ impl PreparedNodeGraph {
 
             id_to_index: IdMap::new(),
             nodes: Vec::new(),
+
+            scratch: Default::default(),
+            has_delays: false,
+            stitch_key: None,
         }
     }
 
@@ -244,12 +336,55 @@ impl PreparedNodeGraph {
             .map(|&index| &mut self.nodes[index as usize])
     }
 
+    /// The buffer a node wrote to one of its outputs during the last call to [`Self::process`].
+    pub fn node_output(&self, node_id: Id<Node>, output_index: u32) -> Option<&Buffer> {
+        self.get_node(node_id)?
+            .outputs
+            .get(output_index as usize)
+            .map(|output| &*output.buffer)
+    }
+
+    /// The graph's structure if it gets stitched, so the host knows which stitched graphs are in use.
+    pub fn stitch_key(&self) -> Option<&Arc<StitchKey>> {
+        self.stitch_key.as_ref()
+    }
+
+    /// How many samples the graph delays its input by, i.e. the latency at the output node.
+    pub fn latency(&self) -> u32 {
+        self.get_node(self.output_node)
+            .map_or(0, |output_node| output_node.latency)
+    }
+
     pub fn process(
         &mut self,
         options: &WorkerOptions,
         state: &mut WorkerState,
+        samples: &IdMap<Sample>,
+        live_input: &Buffer,
+        sidechain: &SidechainLookup,
         attribute_map: &mut dyn AttributeMap,
     ) -> anyhow::Result<()> {
+        for node in &mut self.nodes {
+            let Some(output) = node.outputs.first_mut() else {
+                continue;
+            };
+            if node.key == resourcekey::literal!("builtin:audio_input") {
+                output.buffer.copy_from(live_input);
+            } else if node.key == resourcekey::literal!("builtin:sidechain") {
+                match Track::sidechain_source(&node.args).and_then(sidechain) {
+                    Some(buffer) => output.buffer.copy_from(buffer),
+                    None => output.buffer.fill(0.0),
+                }
+            }
+        }
+
+        // cloning the key doesn't allocate, and lets `self` be borrowed mutably below
+        if let Some(key) = self.stitch_key.clone()
+            && let Some(stitched) = state.stitched_graph(&key)
+        {
+            return self.process_stitched(options, stitched, samples, attribute_map);
+        }
+
         // self.nodes has been topologically sorted so the all dependencies of a node appear before it in the vec
         for index in 0..self.nodes.len() {
             let (previous_nodes, [node, ..]) = self.nodes.split_at_mut(index) else {
@@ -258,8 +393,12 @@ impl PreparedNodeGraph {
                 );
             };
 
-            if Some(node.node_id) == self.input_node {
-                // skip input node if it exists
+            if node.is_external(self.input_node) {
+                // skip input nodes, their outputs are already written
+                continue;
+            }
+            if node.silenced {
+                // the outputs were already zeroed when the node was silenced
                 continue;
             }
 
@@ -269,13 +408,19 @@ impl PreparedNodeGraph {
                     connection,
                     output_index,
                     ref mut multiplier,
+                    ref mut delay,
                 } in &mut input.connections
                 {
                     let connected_node = &previous_nodes[connection as usize];
+                    let mut conn_buffer: &Buffer =
+                        &connected_node.outputs[output_index as usize].buffer;
+                    if delay.delay() > 0 {
+                        self.scratch.copy_from(conn_buffer);
+                        delay.process(&mut self.scratch);
+                        conn_buffer = &self.scratch;
+                    }
 
-                    for ((conn_val, buf_val), multiplier) in connected_node.outputs
-                        [output_index as usize]
-                        .buffer
+                    for ((conn_val, buf_val), multiplier) in conn_buffer
                         .iter()
                         .zip(input.buffer.iter_mut())
                         .zip(multiplier.iter())
@@ -290,37 +435,17 @@ impl PreparedNodeGraph {
                 .get(&node.key)
                 .expect("desynced node graph");
             match registry_entry.plugin_data {
-                Some(ref _plugin_data) => {
-                    let mut plugin = state
-                        .standalone_instances
-                        .get(&node.key)
-                        .expect("desynced node graph")
-                        .borrow_mut();
-                    let data = plugin.store_mut().data_mut();
-
-                    data.inputs.resize_with(node.inputs.len(), Default::default);
-                    data.outputs
-                        .resize_with(node.outputs.len(), Default::default);
-
-                    for sample_idx in 0..options.buffer_size as usize / InternalBufferType::N {
-                        let data = plugin.store_mut().data_mut();
-
-                        for (input_idx, input) in node.inputs.iter().enumerate() {
-                            data.inputs[input_idx] = input.buffer.as_internal()[sample_idx];
-                        }
-
-                        plugin.run(
-                            &node.key,
-                            node.args.as_bytes(),
-                            node.state.as_bytes_mut(),
-                            attribute_map,
-                        )?;
-
-                        let data = plugin.store_mut().data_mut();
-
-                        for (output_idx, output) in node.outputs.iter_mut().enumerate() {
-                            output.buffer.as_internal_mut()[sample_idx] = data.outputs[output_idx];
+                Some(ref plugin_data) => {
+                    if let Err(error) = node.run_plugin(options, state, samples, attribute_map) {
+                        // don't let one bad node take down the whole graph; silence it and keep going
+                        node.silence();
+                        return Err(NodeError {
+                            node_id: node.node_id,
+                            key: node.key.clone(),
+                            plugin: Some(plugin_data.plugin.manifest().name.clone()),
+                            error,
                         }
+                        .into());
                     }
                 }
                 None => {
@@ -334,12 +459,137 @@ impl PreparedNodeGraph {
         Ok(())
     }
 
+    fn stitched_nodes(&self, options: &WorkerOptions) -> impl Iterator<Item = StitchedNode> {
+        self.nodes.iter().map(|node| StitchedNode {
+            key: node.key.clone(),
+            plugin: options
+                .registry
+                .get(&node.key)
+                .expect("desynced node graph")
+                .plugin_data
+                .clone(),
+            is_input: node.is_external(self.input_node),
+            inputs: node
+                .inputs
+                .iter()
+                .map(|input| {
+                    input
+                        .connections
+                        .iter()
+                        .map(|conn| (conn.connection, conn.output_index))
+                        .collect()
+                })
+                .collect(),
+            num_outputs: node.outputs.len() as u32,
+            args_len: node.args.as_bytes().len() as u32,
+            state_len: node.state.as_bytes().len() as u32,
+        })
+    }
+    fn process_stitched(
+        &mut self,
+        options: &WorkerOptions,
+        stitched: &mut StitchedNodeGraph,
+        samples: &IdMap<Sample>,
+        attribute_map: &mut dyn AttributeMap,
+    ) -> anyhow::Result<()> {
+        for index in 0..self.nodes.len() {
+            let is_input = self.nodes[index].is_external(self.input_node);
+            let node = &mut self.nodes[index];
+            stitched.set_enabled(index, !node.silenced && !is_input);
+            if node.silenced || is_input {
+                // these aren't touched by the module, so copy over their outputs instead
+                for (output_index, output) in node.outputs.iter().enumerate() {
+                    stitched.write_output(index, output_index, &output.buffer);
+                }
+                continue;
+            }
+
+            for (input_index, input) in node.inputs.iter_mut().enumerate() {
+                input.bias.fill_buffer(&mut input.buffer);
+                stitched.write_bias(index, input_index, &input.buffer);
+                for (conn_index, conn) in input.connections.iter_mut().enumerate() {
+                    conn.multiplier.fill_buffer(&mut self.scratch);
+                    stitched.write_multiplier(index, input_index, conn_index, &self.scratch);
+                }
+            }
+            stitched.write_args_and_state(index, node.args.as_bytes(), node.state.as_bytes());
+        }
+
+        let result = stitched.process(samples, attribute_map);
+
+        for index in 0..self.nodes.len() {
+            let is_input = self.nodes[index].is_external(self.input_node);
+            let node = &mut self.nodes[index];
+            if node.silenced || is_input {
+                continue;
+            }
+            stitched.read_state(index, node.state.as_bytes_mut());
+            for (output_index, output) in node.outputs.iter_mut().enumerate() {
+                stitched.read_output(index, output_index, &mut output.buffer);
+            }
+        }
+
+        match result {
+            Ok(()) => Ok(()),
+            Err((Some(index), error)) => {
+                let node = &mut self.nodes[index];
+                node.silence();
+                Err(NodeError {
+                    node_id: node.node_id,
+                    key: node.key.clone(),
+                    plugin: options
+                        .registry
+                        .get(&node.key)
+                        .and_then(|entry| entry.plugin_data.as_ref())
+                        .map(|plugin_data| plugin_data.plugin.manifest().name.clone()),
+                    error,
+                }
+                .into())
+            }
+            Err((None, error)) => Err(error),
+        }
+    }
+
     pub fn reset(&mut self) {
         for node in &mut self.nodes {
             node.reset();
+            for input in &mut node.inputs {
+                input.bias.snap();
+                for conn in &mut input.connections {
+                    conn.multiplier.snap();
+                    conn.delay.reset();
+                }
+            }
+        }
+    }
+
+    /// Silences a node so it only outputs zeroes. The node stays silenced until it's removed from the graph.
+    pub fn silence_node(&mut self, node_id: Id<Node>) {
+        if let Some(node) = self.get_node_mut(node_id) {
+            node.silence();
+        }
+    }
+}
+
+/// An error caused by a specific node in a node graph.
+#[derive(Debug)]
+pub struct NodeError {
+    pub node_id: Id<Node>,
+    pub key: ResourceKey,
+    /// The name of the plugin the node is from.
+    pub plugin: Option<String>,
+    pub error: anyhow::Error,
+}
+impl std::fmt::Display for NodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {}", self.key)?;
+        if let Some(ref plugin) = self.plugin {
+            write!(f, " (from plugin {plugin:?})")?;
         }
+        write!(f, " failed: {:#}", self.error)
     }
 }
+impl std::error::Error for NodeError {}
 
 #[derive(Clone, Debug)]
 pub struct NodeGraphEntry {
@@ -351,13 +601,57 @@ pub struct NodeGraphEntry {
     node_id: Id<Node>,
     args: Box<Buffer>,
     original_state: Box<Buffer>,
+    /// How many samples this node's outputs lag behind the graph's input.
+    latency: u32,
+
+    silenced: bool,
 }
 impl NodeGraphEntry {
+    /// Whether the node's outputs are written by the host instead of being processed: the graph's input node and any
+    /// `builtin:audio_input` or `builtin:sidechain` nodes.
+    fn is_external(&self, input_node: Option<Id<Node>>) -> bool {
+        Some(self.node_id) == input_node
+            || self.key == resourcekey::literal!("builtin:audio_input")
+            || self.key == resourcekey::literal!("builtin:sidechain")
+    }
+
     fn reset(&mut self) {
         self.state.copy_from_slice(&self.original_state);
     }
 
-    pub fn add_dummy_output(&mut self, options: &WorkerOptions) {
+    fn silence(&mut self) {
+        self.silenced = true;
+        self.reset();
+        for output in &mut self.outputs {
+            output.buffer.fill(0.0);
+        }
+    }
+
+    fn run_plugin(
+        &mut self,
+        options: &WorkerOptions,
+        state: &mut WorkerState,
+        samples: &IdMap<Sample>,
+        attribute_map: &mut dyn AttributeMap,
+    ) -> anyhow::Result<()> {
+        let mut plugin = state
+            .standalone_instances
+            .get(&self.key)
+            .context("plugin wasn't instantiated")?
+            .borrow_mut();
+        plugin.run(
+            &self.key,
+            self.args.as_bytes(),
+            self.state.as_bytes_mut(),
+            self.inputs.iter().map(|input| &*input.buffer),
+            self.outputs.iter_mut().map(|output| &mut *output.buffer),
+            options.buffer_size,
+            samples,
+            attribute_map,
+        )
+    }
+
+    fn add_dummy_output(&mut self, options: &WorkerOptions) {
         self.outputs = vec![NodeGraphOutput {
             buffer: Buffer::new_box_zeroed(options.buffer_size),
         }];
@@ -368,12 +662,15 @@ impl NodeGraphEntry {
 struct InterpolatedValue {
     raw_value: f32,
     interpolated_value: f32,
+    /// How far `interpolated_value` moves towards `raw_value` every sample.
+    factor: f32,
 }
 impl Default for InterpolatedValue {
     fn default() -> Self {
         Self {
             raw_value: f32::NAN,
             interpolated_value: f32::NAN,
+            factor: 1.0,
         }
     }
 }
@@ -384,13 +681,29 @@ impl InterpolatedValue {
             self.interpolated_value = val;
         }
     }
+    /// Jumps straight to the target value.
+    pub fn snap(&mut self) {
+        self.interpolated_value = self.raw_value;
+    }
+    /// Sets the time constant of the smoothing: after `time_ms` milliseconds the value will have moved ~63% of the way to the target.
+    /// A time of 0 makes the value jump immediately.
+    pub fn set_smoothing(&mut self, time_ms: f32, sample_rate: u32) {
+        let time_samples = time_ms * 0.001 * sample_rate as f32;
+        self.factor = if time_samples > 0.0 {
+            1.0 - (-1.0 / time_samples).exp()
+        } else {
+            1.0
+        };
+    }
     pub fn fill_buffer(&mut self, buf: &mut [f32]) {
         for (val, dst) in self.iter().zip(buf) {
             *dst = val;
         }
     }
     pub fn iter(&mut self) -> impl Iterator<Item = f32> {
-        let is_raw = if (self.raw_value - self.interpolated_value).abs() < f32::EPSILON {
+        let is_raw = if self.factor >= 1.0
+            || (self.raw_value - self.interpolated_value).abs() < f32::EPSILON
+        {
             self.interpolated_value = self.raw_value;
             true
         } else {
@@ -402,9 +715,8 @@ impl InterpolatedValue {
                     yield self.raw_value;
                 } else {
                     yield self.interpolated_value;
-                    // TODO: not hardcode this. this should also be dependent on the sample rate
                     self.interpolated_value =
-                        util::lerp(self.interpolated_value, self.raw_value, 0.005);
+                        util::lerp(self.interpolated_value, self.raw_value, self.factor);
                 }
             }
         }
@@ -427,4 +739,36 @@ struct NodeGraphCableConnection {
     connection: u32,
     output_index: u32,
     multiplier: InterpolatedValue,
+    /// Latency compensation for when the connected node has less latency than the other inputs of this node.
+    delay: DelayLine,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InterpolatedValue;
+
+    fn value_after(time_ms: f32, sample_rate: u32, elapsed_ms: f32) -> f32 {
+        let mut value = InterpolatedValue::default();
+        value.set_raw(0.0);
+        value.set_smoothing(time_ms, sample_rate);
+        value.set_raw(1.0);
+
+        let num_samples = (elapsed_ms * 0.001 * sample_rate as f32) as usize;
+        let mut buf = vec![0.0; num_samples + 1];
+        value.fill_buffer(&mut buf);
+        buf[num_samples]
+    }
+
+    #[test]
+    fn smoothing_is_sample_rate_independent() {
+        let a = value_after(10.0, 44100, 10.0);
+        let b = value_after(10.0, 96000, 10.0);
+        assert!((a - (1.0 - (-1.0f32).exp())).abs() < 0.01, "{a}");
+        assert!((a - b).abs() < 0.01, "{a} != {b}");
+    }
+
+    #[test]
+    fn zero_smoothing_jumps() {
+        assert_eq!(value_after(0.0, 44100, 0.0), 1.0);
+    }
 }
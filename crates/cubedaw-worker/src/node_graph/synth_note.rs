@@ -1,36 +1,88 @@
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
-use cubedaw_lib::{Buffer, Id, InternalBufferType, Note, Patch};
+use cubedaw_lib::{Buffer, Id, IdMap, InternalBufferType, Node, Note, Patch, Sample};
 
 use crate::{
     WorkerOptions,
-    plugin::{Attribute, AttributeMap},
+    plugin::{Attribute, AttributeMap, stitched::StitchKey},
 };
 
-use super::{PreparedNodeGraph, WorkerState};
+use super::{DelayLine, PreparedNodeGraph, WorkerState};
 
 #[derive(Debug, Clone)]
-pub struct NoteNodeGraph(PreparedNodeGraph);
+pub struct NoteNodeGraph {
+    graph: PreparedNodeGraph,
+
+    /// Extra delay so the note lines up with higher-latency paths into the track. See [`Self::set_compensation`].
+    compensation: u32,
+    /// Delays the note's audio by how many samples into its first buffer it started (so it starts on the exact sample)
+    /// plus the compensation.
+    delay: DelayLine,
+    output: Box<Buffer>,
+}
 
 impl NoteNodeGraph {
     pub fn empty() -> Self {
         // TODO: set this to a basic node graph so input_node() and friends can't panic
-        Self(PreparedNodeGraph::empty(None, Id::invalid()))
+        Self {
+            graph: PreparedNodeGraph::empty(None, Id::invalid()),
+
+            compensation: 0,
+            delay: DelayLine::default(),
+            output: Default::default(),
+        }
     }
     pub fn sync_with(&mut self, patch: &Patch, options: &WorkerOptions) -> anyhow::Result<()> {
         let note_output = patch
             .get_active_node(&resourcekey::literal!("builtin:output"))
             .context("no note output exists")?;
 
-        self.0.sync_with(patch, options, None, note_output);
+        self.graph.sync_with(patch, options, None, note_output)?;
+
+        if self.output.len() != options.buffer_size as usize {
+            self.output = Buffer::new_box_zeroed(options.buffer_size);
+        }
+        self.delay.reserve(options.buffer_size + self.compensation);
 
         Ok(())
     }
 
+    /// How many samples the note's node graph delays its audio by.
+    pub fn latency(&self) -> u32 {
+        self.graph.latency()
+    }
+    /// Sets an extra delay for the note's audio, for when other things feeding into the track have more latency than the note.
+    pub fn set_compensation(&mut self, compensation: u32, options: &WorkerOptions) {
+        if self.compensation == compensation {
+            return;
+        }
+        self.compensation = compensation;
+        self.delay.reserve(options.buffer_size + compensation);
+        self.delay.set_delay(compensation);
+    }
+
+    /// Resets the graph so it can be reused for a new note.
+    pub fn reset(&mut self) {
+        self.graph.reset();
+        self.delay.set_delay(self.compensation);
+    }
+
+    /// Sets the sample offset the note starts at in the first buffer it's processed in.
+    pub fn set_start_offset(&mut self, start_offset: u32) {
+        self.delay.set_delay(self.compensation + start_offset);
+    }
+
+    /// Processes one buffer of the note. If `end_offset` is `Some(_)`, the note ends that many samples
+    /// into the buffer and everything after is silenced.
     pub fn process(
         &mut self,
         options: &WorkerOptions,
         state: &mut WorkerState,
+        samples: &IdMap<Sample>,
+        live_input: &Buffer,
         note: &Note,
+        end_offset: Option<u32>,
     ) -> Result<&Buffer> {
         struct NoteAttributeMap<'a> {
             note: &'a Note,
@@ -43,13 +95,47 @@ impl NoteNodeGraph {
             }
         }
 
-        self.0
-            .process(options, state, &mut NoteAttributeMap { note })?;
+        self.graph.process(
+            options,
+            state,
+            samples,
+            live_input,
+            // notes can't wait for other tracks
+            &|_| None,
+            &mut NoteAttributeMap { note },
+        )?;
+
+        let rendered = &self
+            .graph
+            .get_node(self.graph.output_node())
+            .expect("unreachable")
+            .outputs[0]
+            .buffer;
+
+        // the node graph always renders whole buffers, so shift everything over to where the note actually starts
+        self.output.copy_from(rendered);
+        self.delay.process(&mut self.output);
+
+        if let Some(end_offset) = end_offset {
+            let output: &mut [f32] = &mut self.output;
+            let len = output.len();
+            output[(end_offset as usize).min(len)..].fill(0.0);
+        }
+
+        Ok(&self.output)
+    }
+
+    pub fn silence_node(&mut self, node_id: Id<Node>) {
+        self.graph.silence_node(node_id);
+    }
+
+    /// See [`PreparedNodeGraph::stitch_key`].
+    pub fn stitch_key(&self) -> Option<&Arc<StitchKey>> {
+        self.graph.stitch_key()
+    }
 
-        let output_node = self
-            .0
-            .get_node_mut(self.0.output_node())
-            .expect("unreachable");
-        Ok(&output_node.outputs[0].buffer)
+    /// See [`PreparedNodeGraph::node_output`].
+    pub fn node_output(&self, node_id: Id<Node>, output_index: u32) -> Option<&Buffer> {
+        self.graph.node_output(node_id, output_index)
     }
 }
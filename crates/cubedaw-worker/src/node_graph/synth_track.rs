@@ -1,17 +1,22 @@
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
-use cubedaw_lib::{Buffer, Id, Patch};
+use cubedaw_lib::{Buffer, Id, IdMap, Node, Patch, Sample};
 
-use crate::WorkerOptions;
+use crate::{WorkerOptions, plugin::stitched::StitchKey};
 
-use super::{PreparedNodeGraph, WorkerState};
+use super::{DelayLine, PreparedNodeGraph, SidechainLookup, WorkerState};
 
 #[derive(Debug, Clone)]
 /// Node graph for the non-per-note clip
-pub struct TrackNodeGraph(PreparedNodeGraph);
+pub struct TrackNodeGraph(PreparedNodeGraph, DelayLine);
 
 impl TrackNodeGraph {
     pub fn empty() -> Self {
-        Self(PreparedNodeGraph::empty(None, Id::invalid()))
+        Self(
+            PreparedNodeGraph::empty(None, Id::invalid()),
+            DelayLine::default(),
+        )
     }
     pub fn sync_with(&mut self, patch: &Patch, options: &WorkerOptions) -> anyhow::Result<()> {
         let track_output = patch
@@ -22,20 +27,16 @@ impl TrackNodeGraph {
             .context("no note output exists")?;
 
         self.0
-            .sync_with(patch, options, Some(note_output), track_output);
-
-        self.0
-            .get_node_mut(track_output)
-            .expect("unreachable")
-            .add_dummy_output(options);
-
-        Ok(())
+            .sync_with(patch, options, Some(note_output), track_output)
     }
 
     pub fn process(
         &mut self,
         options: &WorkerOptions,
         state: &mut WorkerState,
+        samples: &IdMap<Sample>,
+        live_input: &Buffer,
+        sidechain: &SidechainLookup,
         input: &Buffer,
     ) -> Result<&Buffer> {
         let input_node = self
@@ -49,15 +50,55 @@ impl TrackNodeGraph {
         self.0.process(
             options,
             state,
+            samples,
+            live_input,
+            sidechain,
             // TODO
             &mut crate::plugin::NoopAttributeMap,
         )?;
 
-        let output_node = self.0.get_node(self.0.output_node()).expect("unreachable");
-        Ok(&output_node.outputs[0].buffer)
+        let output_node = self
+            .0
+            .get_node_mut(self.0.output_node())
+            .expect("unreachable");
+        let output = &mut output_node.outputs[0].buffer;
+        self.1.process(output);
+        Ok(output)
+    }
+
+    /// How many samples the track's node graph delays its input by.
+    pub fn latency(&self) -> u32 {
+        self.0.latency()
+    }
+    /// Sets how much the track's output is delayed by, so it lines up with higher-latency tracks in the same group.
+    pub fn set_output_delay(&mut self, delay: u32) {
+        if self.1.delay() != delay {
+            self.1.reserve(delay);
+            self.1.set_delay(delay);
+        }
+    }
+
+    /// See [`Self::set_output_delay`].
+    pub fn output_delay(&self) -> u32 {
+        self.1.delay()
     }
 
     pub fn reset(&mut self) {
         self.0.reset();
+        self.1.reset();
+    }
+
+    pub fn silence_node(&mut self, node_id: Id<Node>) {
+        self.0.silence_node(node_id);
+    }
+
+    /// See [`PreparedNodeGraph::stitch_key`].
+    pub fn stitch_key(&self) -> Option<&Arc<StitchKey>> {
+        self.0.stitch_key()
+    }
+
+    /// See [`PreparedNodeGraph::node_output`].
+    pub fn node_output(&self, node_id: Id<Node>, output_index: u32) -> Option<&Buffer> {
+        self.0.node_output(node_id, output_index)
     }
 }
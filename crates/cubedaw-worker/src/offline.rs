@@ -0,0 +1,227 @@
+//! Rendering without an audio device, as fast as possible.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use cubedaw_lib::{Buffer, Id, IdSet, PreciseSongPos, Range, State, Track};
+
+use crate::{Metronome, WorkerHost, WorkerOptions, resample};
+
+/// Renders `num_samples` samples of the song, starting at the song position `from`.
+///
+/// Unlike realtime processing, this stops at the first error instead of silencing the node that caused it.
+pub fn render(
+    state: State,
+    options: WorkerOptions,
+    from: i64,
+    num_samples: usize,
+) -> anyhow::Result<Vec<f32>> {
+    render_with_metronome(state, options, Metronome::default(), from, num_samples)
+}
+
+/// Like [`render`], but with the metronome mixed in if it's enabled.
+pub fn render_with_metronome(
+    state: State,
+    options: WorkerOptions,
+    metronome: Metronome,
+    from: i64,
+    num_samples: usize,
+) -> anyhow::Result<Vec<f32>> {
+    let mut host = WorkerHost::new(state, options);
+    host.set_metronome(metronome);
+    render_with_host(host, from, num_samples)
+}
+
+/// Like [`render`], but with a worker host that's already set up.
+pub fn render_with_host(
+    mut host: WorkerHost,
+    from: i64,
+    num_samples: usize,
+) -> anyhow::Result<Vec<f32>> {
+    // there's no deadline to meet here, so don't bother processing node by node while graphs are being stitched
+    host.options().stitch_cache.wait();
+
+    let buffer_size = host.options().buffer_size as usize;
+    let mut buffer = Buffer::new_box_zeroed(buffer_size as u32);
+    let mut pos = PreciseSongPos::from_song_pos(from);
+
+    let mut rendered = Vec::with_capacity(num_samples.next_multiple_of(buffer_size));
+    let mut result = Ok(());
+    while rendered.len() < num_samples {
+        let live_pos = pos;
+        host = host.process(Some(&mut pos), live_pos, &mut buffer);
+        rendered.extend_from_slice(&buffer);
+
+        if let Some(error) = host.take_errors().into_iter().next() {
+            result = Err(match error.track_id {
+                Some(track_id) => anyhow!("error on track {track_id:?}: {:#}", error.error),
+                None => error.error,
+            });
+            break;
+        }
+    }
+    host.join();
+
+    result?;
+    rendered.truncate(num_samples);
+    Ok(rendered)
+}
+
+/// Like [`render`], but the song is rendered at `render_rate` and then resampled to `options.sample_rate`, e.g. to
+/// render at a higher rate than the live engine runs at. `num_samples` is at `options.sample_rate`.
+pub fn render_resampled(
+    state: State,
+    options: &WorkerOptions,
+    render_rate: u32,
+    from: i64,
+    num_samples: usize,
+) -> anyhow::Result<Vec<f32>> {
+    let mut render_options =
+        WorkerOptions::with_audio(options.registry.clone(), render_rate, options.buffer_size);
+    render_options.num_workers = options.num_workers;
+    render_options.smoothing_ms = options.smoothing_ms;
+    render_options.stitch_node_graphs = options.stitch_node_graphs;
+    render_options.resample_quality = options.resample_quality;
+
+    let render_samples =
+        (num_samples as f64 * render_rate as f64 / options.sample_rate as f64).ceil() as usize;
+    let rendered = render(state, render_options, from, render_samples)?;
+    let mut resampled = resample::resample(
+        &rendered,
+        render_rate,
+        options.sample_rate,
+        options.resample_quality,
+    );
+    resampled.resize(num_samples, 0.0);
+    Ok(resampled)
+}
+
+/// How long to keep rendering after the end of the song when freezing a track, so reverb tails and such aren't cut off.
+const FREEZE_TAIL_SECONDS: f32 = 2.0;
+
+/// A track's output (including its descendants), rendered ahead of time. See [`WorkerHost::set_frozen`].
+#[derive(Debug, Clone)]
+pub struct FrozenTrack {
+    /// The track's output before its fader, starting at [`Self::start_pos`].
+    pub audio: Arc<[f32]>,
+    pub start_pos: i64,
+    pub sample_rate: u32,
+    /// The [`State::track_fingerprint`] of the track when it was rendered.
+    pub fingerprint: u64,
+}
+
+/// Why a track can't be frozen. While a track is frozen, it doesn't use its input and its descendants aren't
+/// processed, so the frozen audio can only stand in for them if nothing outside the track is routed into or out of
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreezeBlocker {
+    /// A track outside the frozen track sends to it or one of its descendants.
+    SendIn { from: Id<Track>, to: Id<Track> },
+    /// One of the frozen track's descendants sends to a track outside it.
+    SendOut { from: Id<Track>, to: Id<Track> },
+    /// The frozen track or one of its descendants sidechains a track outside it, which wouldn't be in the frozen audio.
+    SidechainIn {
+        source: Id<Track>,
+        consumer: Id<Track>,
+    },
+    /// A track outside the frozen track sidechains one of its descendants.
+    SidechainOut {
+        source: Id<Track>,
+        consumer: Id<Track>,
+    },
+}
+
+impl std::fmt::Display for FreezeBlocker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::SendIn { .. } => "a track outside it sends to a track inside it",
+            Self::SendOut { .. } => "a track inside it sends to a track outside it",
+            Self::SidechainIn { .. } => "a track inside it sidechains a track outside it",
+            Self::SidechainOut { .. } => "a track outside it sidechains a track inside it",
+        })
+    }
+}
+
+/// The track and all its descendants.
+fn subtree(state: &State, track_id: Id<Track>) -> IdSet<Track> {
+    let mut subtree = IdSet::default();
+    let mut stack = vec![track_id];
+    while let Some(track_id) = stack.pop() {
+        subtree.insert(track_id);
+        stack.extend(state.tracks.force_get(track_id).children.iter().copied());
+    }
+    subtree
+}
+
+/// Returns why `track_id` can't be frozen, or `None` if it can. The track's own sends, and sidechains that read it, are
+/// fine, since they're played from the frozen audio.
+pub fn freeze_blocker(state: &State, track_id: Id<Track>) -> Option<FreezeBlocker> {
+    let subtree = subtree(state, track_id);
+    // routes the worker host ignores (to tracks that don't exist, or that would make cycles) don't count
+    for (other_id, other) in &state.tracks {
+        for (target_id, _) in other.sends() {
+            if !state.tracks.has(target_id) || state.track_depends_on(other_id, target_id) {
+                continue;
+            }
+            let (from, to) = (other_id, target_id);
+            match (subtree.contains(&from), subtree.contains(&to)) {
+                (false, true) => return Some(FreezeBlocker::SendIn { from, to }),
+                (true, false) if from != track_id => {
+                    return Some(FreezeBlocker::SendOut { from, to });
+                }
+                _ => (),
+            }
+        }
+        for source_id in other.sidechain_sources() {
+            if !state.tracks.has(source_id) || state.track_depends_on(source_id, other_id) {
+                continue;
+            }
+            let (source, consumer) = (source_id, other_id);
+            match (subtree.contains(&source), subtree.contains(&consumer)) {
+                (false, true) => return Some(FreezeBlocker::SidechainIn { source, consumer }),
+                (true, false) if source != track_id => {
+                    return Some(FreezeBlocker::SidechainOut { source, consumer });
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// Renders a track's output over the whole song. Fails if the track can't be frozen; see [`freeze_blocker`].
+pub fn freeze(
+    state: &State,
+    options: WorkerOptions,
+    track_id: Id<Track>,
+) -> anyhow::Result<FrozenTrack> {
+    if let Some(blocker) = freeze_blocker(state, track_id) {
+        anyhow::bail!("{blocker}");
+    }
+    let fingerprint = state.track_fingerprint(track_id);
+    let sample_rate = options.sample_rate;
+
+    // render the track as if it were the whole song
+    let mut state = state.clone();
+    let subtree = subtree(&state, track_id);
+    state
+        .tracks
+        .retain(|track_id, _| subtree.contains(&track_id));
+    state.root_track = track_id;
+    state.looping = false;
+    // the fader is applied when the frozen audio is played back, so pre-fader sends still work
+    state.tracks.force_get_mut(track_id).set_gain(1.0);
+
+    let song_boundary = state.song_boundary;
+    let seconds = song_boundary.length() as f32 / Range::UNITS_PER_BEAT as f32 / state.bpm * 60.0
+        + FREEZE_TAIL_SECONDS;
+    let num_samples = (seconds * sample_rate as f32) as usize;
+
+    let audio = render(state, options, song_boundary.start, num_samples)?;
+    Ok(FrozenTrack {
+        audio: audio.into(),
+        start_pos: song_boundary.start,
+        sample_rate,
+        fingerprint,
+    })
+}
@@ -1,9 +1,9 @@
-use cubedaw_lib::InternalBufferType;
+use cubedaw_lib::{InternalBufferType, Sample};
 
-pub mod standalone;
+use crate::resample::SincKernel;
 
-// TODO JIT-compile plugins together
-// pub mod stitched;
+pub mod standalone;
+pub mod stitched;
 
 /// Alignment for pointers passed to plugins, in bytes.
 const PLUGIN_ALIGN: u32 = 64;
@@ -39,3 +39,47 @@ impl AttributeMap for NoopAttributeMap {
         InternalBufferType::ZERO
     }
 }
+
+/// Reads frames from a sample for the `sample` import, at the positions `base + offsets[i]`. Positions are in samples
+/// at the project's sample rate, so a position that goes up by 1 every sample plays the sample back at its original
+/// speed. Everything outside of the sample is silent.
+pub fn read_sample(
+    sample: &Sample,
+    sample_rate: u32,
+    kernel: &SincKernel,
+    base: i32,
+    offsets: &[f32; InternalBufferType::N],
+) -> InternalBufferType {
+    let ratio = sample.sample_rate() as f64 / sample_rate as f64;
+    let mut result = [0.0f32; InternalBufferType::N];
+    for (i, (dst, &offset)) in result.iter_mut().zip(offsets).enumerate() {
+        let pos = (base as f64 + offset as f64) * ratio;
+        if !pos.is_finite() {
+            continue;
+        }
+        // the playback speed is however far the position moves until the next sample
+        let step = match offsets.get(i + 1) {
+            Some(&next) => next - offset,
+            None => offset - offsets[i.saturating_sub(1)],
+        };
+        *dst = kernel.interpolate(sample.data(), pos, step as f64 * ratio);
+    }
+    bytemuck::must_cast(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_sample_interpolates() {
+        let sample = Sample::new("a".into(), 2, [0.0, 1.0, 0.5].into());
+        let mut offsets = [100.0; InternalBufferType::N];
+        offsets[..4].copy_from_slice(&[-1.0, 0.0, 0.25, 0.75]);
+        // the sample is at half the project's sample rate so it's stretched out by 2
+        let kernel = crate::resample::ResampleQuality::Linear.kernel();
+        let frames = read_sample(&sample, 4, kernel, 1, &offsets);
+        assert_eq!(frames.as_array()[..4], [0.0, 0.5, 0.625, 0.875]);
+        assert!(frames.as_array()[4..].iter().all(|&x| x == 0.0));
+    }
+}
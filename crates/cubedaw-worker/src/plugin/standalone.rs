@@ -1,13 +1,13 @@
-use std::{mem, ptr::NonNull};
+use std::{mem, num::NonZero, ptr::NonNull};
 
 use ahash::HashMap;
-use anyhow::Result;
-use cubedaw_lib::InternalBufferType;
+use anyhow::{Context, Result};
+use cubedaw_lib::{Buffer, Id, IdMap, InternalBufferType, Sample};
 use cubedaw_plugin::{CubedawPluginImport, Instruction};
 use cubedaw_wasm::{ValType, Value};
 use resourcekey::ResourceKey;
 
-use crate::{WorkerOptions, plugin::Attribute};
+use crate::{WorkerOptions, plugin::Attribute, resample::ResampleQuality};
 
 use super::{AttributeMap, PLUGIN_ALIGN};
 
@@ -18,56 +18,101 @@ pub struct StandalonePluginFactory {
     exported_nodes: HashMap<ResourceKey, cubedaw_wasm::ExportLocation>,
 }
 
+// function indices. the io helpers are added before any plugin code so their indices are known while stitching
+const ATTRIBUTE_FUNC: u32 = 0;
+const SAMPLE_FUNC: u32 = 1;
+const INPUT_FUNC: u32 = 2;
+const OUTPUT_FUNC: u32 = 3;
+
+// global indices, set at the start of every block
+/// Address of the first input buffer.
+const INPUTS_GLOBAL: u32 = 0;
+const NUM_INPUTS_GLOBAL: u32 = 1;
+/// Address of the first output buffer.
+const OUTPUTS_GLOBAL: u32 = 2;
+const NUM_OUTPUTS_GLOBAL: u32 = 3;
+/// Size of every input and output buffer, in bytes.
+const STRIDE_GLOBAL: u32 = 4;
+/// Byte offset of the current chunk into every buffer.
+const CHUNK_GLOBAL: u32 = 5;
+/// Address of a zeroed chunk that out of range inputs read from. Out of range outputs write to the chunk after it.
+const SCRATCH_GLOBAL: u32 = 6;
+
+const CHUNK_BYTES: u32 = InternalBufferType::BYTES as u32;
+/// Size of the scratch chunks before the input buffers.
+const SCRATCH_BYTES: u32 = 2 * CHUNK_BYTES;
+
 impl StandalonePluginFactory {
     pub fn new(plugin: &cubedaw_plugin::Plugin, options: &WorkerOptions) -> Result<Self> {
         let WorkerOptions { sample_rate, .. } = *options;
         let mut module = cubedaw_plugin::ModuleStitch::with_imports(
-            cubedaw_plugin::ShimInfo::new(move |mut ctx| {
-                use cubedaw_plugin::CubedawPluginImport;
-                use cubedaw_plugin::Instruction;
-                match ctx.import() {
-                    CubedawPluginImport::SampleRate => {
-                        ctx.replace_only_current([Instruction::I32Const(sample_rate as i32)]);
-                    }
-                    CubedawPluginImport::Input => {
-                        ctx.replace_only_current([]);
-                        ctx.add_instruction_raw(Instruction::Call(0));
-                    }
-                    CubedawPluginImport::Output => {
-                        ctx.replace_only_current([]);
-                        ctx.add_instruction_raw(Instruction::Call(1));
-                    }
-                    CubedawPluginImport::Attribute => {
-                        ctx.replace_only_current([]);
-                        ctx.add_instruction_raw(Instruction::Call(2));
-                    }
+            cubedaw_plugin::ShimInfo::new(move |mut ctx| match ctx.import() {
+                CubedawPluginImport::SampleRate => {
+                    ctx.replace_only_current([Instruction::I32Const(sample_rate as i32)]);
+                }
+                CubedawPluginImport::Input => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(INPUT_FUNC));
+                }
+                CubedawPluginImport::Output => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(OUTPUT_FUNC));
+                }
+                CubedawPluginImport::Attribute => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(ATTRIBUTE_FUNC));
+                }
+                CubedawPluginImport::Sample => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(SAMPLE_FUNC));
                 }
             }),
             [
-                ("input", CubedawPluginImport::Input.ty()),
-                ("output", CubedawPluginImport::Output.ty()),
                 ("attribute", CubedawPluginImport::Attribute.ty()),
+                ("sample", CubedawPluginImport::Sample.ty()),
             ],
         );
+
+        for global in [
+            INPUTS_GLOBAL,
+            NUM_INPUTS_GLOBAL,
+            OUTPUTS_GLOBAL,
+            NUM_OUTPUTS_GLOBAL,
+            STRIDE_GLOBAL,
+            CHUNK_GLOBAL,
+            SCRATCH_GLOBAL,
+        ] {
+            let global_idx = module.add_global_i32(0);
+            debug_assert_eq!(global_idx, global);
+        }
+        let input_func = module.add_function(io_helper(false));
+        debug_assert_eq!(input_func, INPUT_FUNC);
+        let output_func = module.add_function(io_helper(true));
+        debug_assert_eq!(output_func, OUTPUT_FUNC);
+
         for node in plugin.exported_nodes() {
+            // processes one chunk
             let mut func = cubedaw_plugin::FunctionStitch::new(cubedaw_wasm::FuncType::new(
                 [ValType::I32, ValType::I32],
                 [],
             ));
             func.add_instruction_raw(&Instruction::LocalGet(0));
             func.add_instruction_raw(&Instruction::LocalGet(1));
-            // TODO
-            plugin.stitch_node(node, &mut func, &mut module)?;
+            plugin
+                .stitch_node(node, &mut func, &mut module)
+                .with_context(|| format!("failed to stitch node {node}"))?;
+            let chunk_func_idx = module.add_function(func);
 
-            let func_idx = module.add_function(func);
+            let func_idx = module.add_function(block_function(chunk_func_idx));
             module.export_function(node.as_str(), func_idx);
         }
 
+        // the plugin's memory is the only one in the module
         module.export_memory("mem", 0);
         let wasm_module = module.finish();
 
-        let module =
-            cubedaw_wasm::Module::new(options.registry.engine(), &wasm_module).expect("todo!()");
+        let module = cubedaw_wasm::Module::new(options.registry.engine(), &wasm_module)
+            .context("failed to compile plugin")?;
 
         Ok(Self {
             memory_location: module
@@ -76,52 +121,184 @@ impl StandalonePluginFactory {
             exported_nodes: plugin
                 .exported_nodes()
                 .map(|key| {
-                    (
+                    Ok((
                         key.clone(),
                         module
                             .get_export(key.as_str())
-                            .expect("exported node isn't exported???"),
-                    )
+                            .with_context(|| format!("node {key} isn't exported"))?,
+                    ))
                 })
-                .collect(),
+                .collect::<Result<_>>()?,
             module,
         })
     }
 
-    pub fn create(&self, options: &WorkerOptions) -> StandalonePlugin {
+    pub fn create(&self, options: &WorkerOptions) -> Result<StandalonePlugin> {
         let mut store = StandalonePluginStore::new(
             options.registry.engine(),
-            StandalonePluginParameters::default(),
+            StandalonePluginParameters::new(options),
         );
         let instance = options
             .registry
             .standalone_linker()
             .instantiate(&mut store, &self.module)
-            .expect("failed to instantiate module");
+            .context("failed to instantiate plugin")?;
         let memory = instance
             .get_memory(&mut store, &self.memory_location)
-            .expect("no memory in module or not exported");
+            .context("no memory in plugin or not exported")?;
 
-        StandalonePlugin {
-            byte_start: memory.size(&store),
+        Ok(StandalonePlugin {
+            byte_start: (memory.size(&store) << memory.page_size_log2(&store))
+                .next_multiple_of(PLUGIN_ALIGN),
             exported_nodes: self
                 .exported_nodes
                 .iter()
                 .map(|(key, export_location)| {
-                    (
+                    Ok((
                         key.clone(),
                         instance
                             .get_func(&mut store, export_location)
-                            .expect("????????"),
-                    )
+                            .with_context(|| format!("node {key} isn't a function"))?,
+                    ))
                 })
-                .collect(),
+                .collect::<Result<_>>()?,
 
             store,
             _instance: instance,
             memory,
+        })
+    }
+}
+
+/// `input(idx) -> (v128, v128, v128, v128)` or `output(v128, v128, v128, v128, idx)`, reading from or writing to the current chunk of the io buffers.
+fn io_helper(is_output: bool) -> cubedaw_plugin::FunctionStitch {
+    let v128s = || [ValType::V128, ValType::V128, ValType::V128, ValType::V128];
+    let (mut func, idx, base, num, fallback) = if is_output {
+        (
+            cubedaw_plugin::FunctionStitch::new(cubedaw_wasm::FuncType::new(
+                v128s().into_iter().chain([ValType::I32]),
+                [],
+            )),
+            4,
+            OUTPUTS_GLOBAL,
+            NUM_OUTPUTS_GLOBAL,
+            CHUNK_BYTES as i32,
+        )
+    } else {
+        (
+            cubedaw_plugin::FunctionStitch::new(cubedaw_wasm::FuncType::new(
+                [ValType::I32],
+                v128s(),
+            )),
+            0,
+            INPUTS_GLOBAL,
+            NUM_INPUTS_GLOBAL,
+            0,
+        )
+    };
+    let addr = idx + 1;
+    for inst in [
+        // base + idx * stride + chunk
+        Instruction::LocalGet(idx),
+        Instruction::GlobalGet(STRIDE_GLOBAL),
+        Instruction::I32Mul,
+        Instruction::GlobalGet(base),
+        Instruction::I32Add,
+        Instruction::GlobalGet(CHUNK_GLOBAL),
+        Instruction::I32Add,
+        // or the scratch chunk if idx is out of range
+        Instruction::GlobalGet(SCRATCH_GLOBAL),
+        Instruction::I32Const(fallback),
+        Instruction::I32Add,
+        Instruction::LocalGet(idx),
+        Instruction::GlobalGet(num),
+        Instruction::I32LtU,
+        Instruction::Select,
+        Instruction::LocalSet(addr),
+    ] {
+        func.add_instruction_raw(&inst);
+    }
+    for lane in 0..4 {
+        let memarg = cubedaw_plugin::MemArg {
+            offset: lane as u64 * 16,
+            align: 4,
+            memory_index: 0,
+        };
+        func.add_instruction_raw(&Instruction::LocalGet(addr));
+        if is_output {
+            func.add_instruction_raw(&Instruction::LocalGet(lane));
+            func.add_instruction_raw(&Instruction::V128Store(memarg));
+        } else {
+            func.add_instruction_raw(&Instruction::V128Load(memarg));
         }
     }
+    func.extend_locals([ValType::I32]);
+    func
+}
+
+/// The function exported for a node. Sets up the io globals and calls `chunk_func` for every chunk in the block.
+///
+/// The parameters are `(num_samples, num_outputs, num_inputs, io_ptr, state_ptr, args_ptr)`, which is reversed because
+/// `cubedaw_wasm::Func::call` reverses its arguments.
+fn block_function(chunk_func: u32) -> cubedaw_plugin::FunctionStitch {
+    let mut func = cubedaw_plugin::FunctionStitch::new(cubedaw_wasm::FuncType::new(
+        [
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+            ValType::I32,
+        ],
+        [],
+    ));
+    let (num_samples, num_outputs, num_inputs, io_ptr, state_ptr, args_ptr) = (0, 1, 2, 3, 4, 5);
+    for inst in [
+        Instruction::LocalGet(io_ptr),
+        Instruction::GlobalSet(SCRATCH_GLOBAL),
+        Instruction::LocalGet(num_samples),
+        Instruction::I32Const(size_of::<f32>().ilog2() as i32),
+        Instruction::I32Shl,
+        Instruction::GlobalSet(STRIDE_GLOBAL),
+        Instruction::LocalGet(io_ptr),
+        Instruction::I32Const(SCRATCH_BYTES as i32),
+        Instruction::I32Add,
+        Instruction::GlobalSet(INPUTS_GLOBAL),
+        Instruction::LocalGet(num_inputs),
+        Instruction::GlobalSet(NUM_INPUTS_GLOBAL),
+        Instruction::GlobalGet(INPUTS_GLOBAL),
+        Instruction::LocalGet(num_inputs),
+        Instruction::GlobalGet(STRIDE_GLOBAL),
+        Instruction::I32Mul,
+        Instruction::I32Add,
+        Instruction::GlobalSet(OUTPUTS_GLOBAL),
+        Instruction::LocalGet(num_outputs),
+        Instruction::GlobalSet(NUM_OUTPUTS_GLOBAL),
+        Instruction::I32Const(0),
+        Instruction::GlobalSet(CHUNK_GLOBAL),
+        Instruction::Block(cubedaw_plugin::BlockType::Empty),
+        Instruction::GlobalGet(STRIDE_GLOBAL),
+        Instruction::I32Eqz,
+        Instruction::BrIf(0),
+        Instruction::Loop(cubedaw_plugin::BlockType::Empty),
+        // chunk functions take their parameters in reverse, so state goes first (see `StandalonePlugin::run`)
+        Instruction::LocalGet(state_ptr),
+        Instruction::LocalGet(args_ptr),
+        Instruction::Call(chunk_func),
+        Instruction::GlobalGet(CHUNK_GLOBAL),
+        Instruction::I32Const(CHUNK_BYTES as i32),
+        Instruction::I32Add,
+        Instruction::GlobalSet(CHUNK_GLOBAL),
+        Instruction::GlobalGet(CHUNK_GLOBAL),
+        Instruction::GlobalGet(STRIDE_GLOBAL),
+        Instruction::I32LtU,
+        Instruction::BrIf(0),
+        Instruction::End,
+        Instruction::End,
+    ] {
+        func.add_instruction_raw(&inst);
+    }
+    func
 }
 
 #[derive(Debug)]
@@ -135,83 +312,107 @@ pub struct StandalonePlugin {
     byte_start: u32,
 }
 
-const fn ceil_to_multiple_of_power_of_2(n: u32, logm: u32) -> u32 {
-    let mask = (1 << logm) - 1;
-    (n + mask) & !mask
-}
-
 impl StandalonePlugin {
-    /// Runs the plugin. Make sure the input data is set before running this!
-    pub fn run(
+    /// Runs a node for a whole block of `num_samples` samples, which has to be a multiple of `InternalBufferType::N`.
+    /// Every input and output buffer has to be `num_samples` long.
+    #[allow(clippy::too_many_arguments)]
+    pub fn run<'a>(
         &mut self,
         key: &ResourceKey,
         args: &[u8],
         state: &mut [u8],
+        inputs: impl ExactSizeIterator<Item = &'a Buffer>,
+        outputs: impl ExactSizeIterator<Item = &'a mut Buffer>,
+        num_samples: u32,
+        samples: &IdMap<Sample>,
         attribute_map: &mut dyn AttributeMap,
     ) -> anyhow::Result<()> {
         assert!(
-            args.len() <= u32::MAX as usize,
-            "args won't fit in 32-bit assembly"
-        );
-        assert!(
-            state.len() <= u32::MAX as usize,
-            "state won't fit in 32-bit assembly"
-        );
-        assert!(
-            (args.len() as u64).saturating_add(state.len() as u64) <= u32::MAX as u64,
-            "args & state won't fit in 32-bit assembly"
+            num_samples % InternalBufferType::N as u32 == 0,
+            "block size isn't a multiple of the chunk size"
         );
         let Some(func) = self.exported_nodes.get(key) else {
             panic!("key {key:?} doesn't exist in plugin {self:?}");
         };
 
-        let args_size = ceil_to_multiple_of_power_of_2(args.len() as u32, PLUGIN_ALIGN.ilog2());
-        let args_start = self.byte_start;
-        let state_size = ceil_to_multiple_of_power_of_2(state.len() as u32, PLUGIN_ALIGN.ilog2());
-        let state_start = self.byte_start + args_size;
+        let (num_inputs, num_outputs) = (inputs.len() as u64, outputs.len() as u64);
+        let stride = num_samples as u64 * size_of::<f32>() as u64;
+
+        // memory layout: args, state, scratch, inputs, outputs
+        let align = |n: u64| n.next_multiple_of(PLUGIN_ALIGN as u64);
+        let args_start = self.byte_start as u64;
+        let state_start = args_start + align(args.len() as u64);
+        let io_start = state_start + align(state.len() as u64);
+        let inputs_start = io_start + SCRATCH_BYTES as u64;
+        let outputs_start = inputs_start + num_inputs * stride;
+        let required_size = outputs_start + num_outputs * stride;
+        let required_size: u32 = required_size
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("args, state and buffers won't fit in 32-bit memory"))?;
+        let (args_start, state_start, io_start, inputs_start, outputs_start) = (
+            args_start as u32,
+            state_start as u32,
+            io_start as u32,
+            inputs_start as u32,
+            outputs_start as u32,
+        );
 
         let page_size = self.memory.page_size_log2(&self.store);
-        let required_size = self.byte_start + args_size + state_size;
-        let current_size = self.memory.size(&self.store);
+        let current_size = self.memory.size(&self.store) << page_size;
         if current_size < required_size {
             self.memory
                 .grow(
                     &mut self.store,
-                    ceil_to_multiple_of_power_of_2(required_size - current_size, page_size)
-                        >> page_size,
+                    (required_size - current_size).div_ceil(1 << page_size),
                 )
                 .map_err(|_| anyhow::anyhow!("plugin memory limit exceeded"))?;
         }
         // write() only returns Err when the indices are out of range so these expect()s are unreachable
-        if !args.is_empty() {
-            self.memory
-                .write(&mut self.store, args_start, args)
-                .expect("unreachable");
-        }
-        if !state.is_empty() {
+        self.memory
+            .write(&mut self.store, args_start, args)
+            .expect("unreachable");
+        self.memory
+            .write(&mut self.store, state_start, state)
+            .expect("unreachable");
+        self.memory
+            .write(&mut self.store, io_start, &[0; CHUNK_BYTES as usize])
+            .expect("unreachable");
+        for (input_idx, input) in inputs.enumerate() {
             self.memory
-                .write(&mut self.store, state_start, state)
+                .write(
+                    &mut self.store,
+                    inputs_start + input_idx as u32 * stride as u32,
+                    &input.as_bytes()[..stride as usize],
+                )
                 .expect("unreachable");
         }
 
-        let params = self.store.data_mut();
-        // SAFETY: we're just extending the lifetime here
-        params.attribute_map = unsafe {
-            mem::transmute::<NonNull<_>, NonNull<_>>(NonNull::from_mut(&mut *attribute_map))
-        };
+        self.store.data_mut().set_call_data(samples, attribute_map);
 
+        // `Func::call` reverses the arguments, so the function's parameters are declared in reverse (see `block_function`)
         func.call(
             &mut self.store,
             &[
                 Value::I32(args_start as i32),
                 Value::I32(state_start as i32),
+                Value::I32(io_start as i32),
+                Value::I32(num_inputs as i32),
+                Value::I32(num_outputs as i32),
+                Value::I32(num_samples as i32),
             ],
             &mut [],
         )?;
 
-        if !state.is_empty() {
+        self.memory
+            .read(&self.store, state_start, state)
+            .expect("unreachable");
+        for (output_idx, output) in outputs.enumerate() {
             self.memory
-                .read(&self.store, state_start, state)
+                .read(
+                    &self.store,
+                    outputs_start + output_idx as u32 * stride as u32,
+                    &mut output.as_bytes_mut()[..stride as usize],
+                )
                 .expect("unreachable");
         }
 
@@ -232,27 +433,43 @@ pub type StandalonePluginStore = cubedaw_wasm::Store<StandalonePluginParameters>
 /// Parameters for `StandalonePluginStore`.
 #[derive(Debug)]
 pub struct StandalonePluginParameters {
-    pub inputs: Vec<InternalBufferType>,
-    pub outputs: Vec<InternalBufferType>,
-
     attribute_map: NonNull<dyn AttributeMap>,
+    samples: NonNull<IdMap<Sample>>,
+    sample_rate: u32,
+    resample_quality: ResampleQuality,
 }
-impl Default for StandalonePluginParameters {
-    fn default() -> Self {
+impl StandalonePluginParameters {
+    pub fn new(options: &WorkerOptions) -> Self {
         Self {
-            inputs: Default::default(),
-            outputs: Default::default(),
-
             attribute_map: NonNull::<super::NoopAttributeMap>::dangling(),
+            samples: NonNull::dangling(),
+            sample_rate: options.sample_rate,
+            resample_quality: options.resample_quality,
         }
     }
-}
-impl StandalonePluginParameters {
+    /// Sets the samples and attribute map used by the `sample` and `attribute` imports. This must be called before
+    /// every call into the plugin.
+    pub(super) fn set_call_data(
+        &mut self,
+        samples: &IdMap<Sample>,
+        attribute_map: &mut dyn AttributeMap,
+    ) {
+        self.samples = NonNull::from(samples);
+        // SAFETY: we're just extending the lifetime here
+        self.attribute_map = unsafe {
+            mem::transmute::<NonNull<_>, NonNull<_>>(NonNull::from_mut(&mut *attribute_map))
+        };
+    }
     /// # Safety
     /// The caller must be accessing this from a linker function call, as the attribute map is only valid during the call. Also, like, the returned `AttributeMap` isn't actually `AttributeMap + 'static`; don't share the reference anywhere.
     unsafe fn attribute_map(&mut self) -> &mut dyn AttributeMap {
         unsafe { self.attribute_map.as_mut() }
     }
+    /// # Safety
+    /// Same as [`Self::attribute_map`].
+    unsafe fn samples(&self) -> &IdMap<Sample> {
+        unsafe { self.samples.as_ref() }
+    }
 }
 
 pub(crate) fn make_linker(
@@ -264,61 +481,54 @@ pub(crate) fn make_linker(
     linker
         .func_wrap(
             "host",
-            "input",
-            |caller: cubedaw_wasm::wasmtime::Caller<'_, StandalonePluginParameters>,
-             input_idx: u32|
+            "attribute",
+            |mut caller: cubedaw_wasm::wasmtime::Caller<'_, StandalonePluginParameters>,
+             attribute_int: u32|
              -> (OtherV128, OtherV128, OtherV128, OtherV128) {
-                let data = caller.data();
-                let [a, b, c, d]: [V128; 4] = bytemuck::must_cast(
-                    data.inputs
-                        .get(input_idx as usize)
-                        .copied()
-                        .unwrap_or_else(|| {
-                            tracing::warn!(
-                                "plugin tried to fetch out of range input index {input_idx}"
-                            );
-                            bytemuck::zeroed()
-                        }),
-                );
+                let data = caller.data_mut();
+
+                // SAFETY: we are inside a linker-wrapped function
+                let attribute_map = unsafe { data.attribute_map() };
+                let val = match Attribute::from_int(attribute_int) {
+                    Some(attribute) => attribute_map.attribute(attribute),
+                    None => {
+                        tracing::warn!("plugin tried to fetch unknown attribute {attribute_int}");
+                        bytemuck::zeroed()
+                    }
+                };
+
+                let [a, b, c, d]: [V128; 4] = bytemuck::must_cast(val);
                 (a.into(), b.into(), c.into(), d.into())
             },
         )
-        .expect("failed to link");
-    linker
+        .expect("failed to link")
         .func_wrap(
             "host",
-            "output",
-            |mut caller: cubedaw_wasm::wasmtime::Caller<'_, StandalonePluginParameters>,
+            "sample",
+            |caller: cubedaw_wasm::wasmtime::Caller<'_, StandalonePluginParameters>,
+             id: u64,
+             base: i32,
              a: OtherV128,
              b: OtherV128,
              c: OtherV128,
-             d: OtherV128,
-             output_idx: u32| {
-                let data = caller.data_mut();
-                let Some(output) = data.outputs.get_mut(output_idx as usize) else {
-                    return;
-                };
-                let arr: [V128; 4] = [a.into(), b.into(), c.into(), d.into()];
-
-                *output = bytemuck::must_cast(arr);
-            },
-        )
-        .expect("failed to link");
-    linker
-        .func_wrap(
-            "host",
-            "attribute",
-            |mut caller: cubedaw_wasm::wasmtime::Caller<'_, StandalonePluginParameters>,
-             attribute_int: u32|
+             d: OtherV128|
              -> (OtherV128, OtherV128, OtherV128, OtherV128) {
-                let data = caller.data_mut();
+                let data = caller.data();
+                let offsets: [f32; InternalBufferType::N] =
+                    bytemuck::must_cast([a, b, c, d].map(V128::from));
 
                 // SAFETY: we are inside a linker-wrapped function
-                let attribute_map = unsafe { data.attribute_map() };
-                let val = match Attribute::from_int(attribute_int) {
-                    Some(attribute) => attribute_map.attribute(attribute),
+                let samples = unsafe { data.samples() };
+                let val = match NonZero::new(id).and_then(|id| samples.get(Id::from_raw(id))) {
+                    Some(sample) => super::read_sample(
+                        sample,
+                        data.sample_rate,
+                        data.resample_quality.kernel(),
+                        base,
+                        &offsets,
+                    ),
                     None => {
-                        tracing::warn!("plugin tried to fetch unknown attribute {attribute_int}");
+                        // probably a sampler with no sample selected, so don't spam the logs
                         bytemuck::zeroed()
                     }
                 };
@@ -0,0 +1,877 @@
+//! Node graphs compiled into a single WebAssembly module.
+//!
+//! Instead of calling into a plugin once per node per chunk and passing every input and output through the host,
+//! every node of a graph gets stitched into one module with a `process` function that runs the whole graph for a buffer.
+//! Cables become buffers in a separate "io" memory, so the only host calls left are for attributes and samples.
+
+use std::{
+    hash::{Hash, Hasher},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use ahash::{HashMap, HashMapExt};
+use anyhow::{Context, Result};
+use cubedaw_lib::{Buffer, IdMap, InternalBufferType, Sample};
+use cubedaw_plugin::{BlockType, CubedawPluginImport, Instruction, MemArg};
+use cubedaw_wasm::{FuncType, ValType};
+use resourcekey::ResourceKey;
+
+use crate::{NodeRegistry, PluginData, WorkerOptions};
+
+use super::{
+    AttributeMap, PLUGIN_ALIGN,
+    standalone::{StandalonePluginParameters, StandalonePluginStore},
+};
+
+/// A node, as seen by [`StitchedNodeGraphFactory::new`]. Nodes have to be topologically sorted.
+#[derive(Debug)]
+pub struct StitchedNode {
+    pub key: ResourceKey,
+    /// `None` for builtin nodes, which pass their inputs through to their outputs.
+    pub plugin: Option<Arc<PluginData>>,
+    /// Input nodes aren't processed; their outputs are written by the host instead.
+    pub is_input: bool,
+    /// For every input, the `(node index, output index)` of every cable connected to it.
+    pub inputs: Vec<Vec<(u32, u32)>>,
+    pub num_outputs: u32,
+    pub args_len: u32,
+    pub state_len: u32,
+}
+
+impl PartialEq for StitchedNode {
+    fn eq(&self, other: &Self) -> bool {
+        // plugins are compared by identity, since a plugin that's reloaded is a different plugin
+        self.key == other.key
+            && match (&self.plugin, &other.plugin) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.is_input == other.is_input
+            && self.inputs == other.inputs
+            && self.num_outputs == other.num_outputs
+            && self.args_len == other.args_len
+            && self.state_len == other.state_len
+    }
+}
+impl Eq for StitchedNode {}
+impl Hash for StitchedNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.plugin.as_ref().map(Arc::as_ptr).hash(state);
+        self.is_input.hash(state);
+        self.inputs.hash(state);
+        self.num_outputs.hash(state);
+        self.args_len.hash(state);
+        self.state_len.hash(state);
+    }
+}
+
+/// Everything that ends up in a stitched module, so graphs with the same structure can share one. Keys are compared
+/// in full, so two graphs only share a module if they really have the same structure.
+#[derive(Debug)]
+pub struct StitchKey {
+    /// The hash of the rest of the key. Keys are looked up every buffer, so it's only computed once.
+    hash: u64,
+    nodes: Vec<StitchedNode>,
+    sample_rate: u32,
+    buffer_size: u32,
+}
+
+impl StitchKey {
+    pub fn new(nodes: Vec<StitchedNode>, sample_rate: u32, buffer_size: u32) -> Self {
+        let mut hasher = std::hash::DefaultHasher::new();
+        (&nodes, sample_rate, buffer_size).hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            nodes,
+            sample_rate,
+            buffer_size,
+        }
+    }
+}
+impl PartialEq for StitchKey {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+            || (self.hash == other.hash
+                && self.sample_rate == other.sample_rate
+                && self.buffer_size == other.buffer_size
+                && self.nodes == other.nodes)
+    }
+}
+impl Eq for StitchKey {}
+impl Hash for StitchKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+// function indices. the helpers are added before any plugins so their indices are known while stitching
+const ATTRIBUTE_FUNC: u32 = 0;
+const SAMPLE_FUNC: u32 = 1;
+const INPUT_FUNC: u32 = 2;
+const OUTPUT_FUNC: u32 = 3;
+
+// global indices, used to tell the helpers where the current node's inputs and outputs are
+const INPUT_BASE_GLOBAL: u32 = 0;
+const NUM_INPUTS_GLOBAL: u32 = 1;
+const OUTPUT_BASE_GLOBAL: u32 = 2;
+const NUM_OUTPUTS_GLOBAL: u32 = 3;
+
+const IO_MEMORY: u32 = 0;
+
+const CHUNK_BYTES: u32 = InternalBufferType::BYTES as u32;
+
+// fixed addresses in the io memory
+/// Always zero. Out of range inputs read from here.
+const ZERO_SLOT: u32 = 0;
+/// Out of range outputs write to here.
+const TRASH_SLOT: u32 = CHUNK_BYTES;
+/// Index of the node currently being processed, so errors can be attributed to it.
+const CURRENT_NODE: u32 = 2 * CHUNK_BYTES;
+const LAYOUT_START: u32 = 3 * CHUNK_BYTES;
+
+const fn ceil_to_multiple_of(n: u32, m: u32) -> u32 {
+    n.div_ceil(m) * m
+}
+
+fn io_v128(offset: u32) -> MemArg {
+    MemArg {
+        offset: offset as u64,
+        align: 4,
+        memory_index: IO_MEMORY,
+    }
+}
+fn io_i32(offset: u32) -> MemArg {
+    MemArg {
+        offset: offset as u64,
+        align: 2,
+        memory_index: IO_MEMORY,
+    }
+}
+
+/// Where a node's data lives in the io memory.
+#[derive(Debug, Clone)]
+struct NodeLayout {
+    /// Address of the node's header: whether the node is enabled, its args pointer, and its state pointer, as `i32`s.
+    header: u32,
+    /// Address of the chunk-sized slots that the node's inputs are mixed into.
+    input_slots: u32,
+    /// Address of the bias buffer of every input.
+    biases: Box<[u32]>,
+    /// Address of the multiplier buffer of every cable of every input.
+    multipliers: Box<[Box<[u32]>]>,
+    /// Address of the first output buffer. Output buffers are laid out one after another.
+    outputs: u32,
+    /// Index of the plugin memory that the args and state are in, along with their lengths.
+    memory: Option<(usize, u32, u32)>,
+}
+
+#[derive(Debug)]
+pub struct StitchedNodeGraphFactory {
+    module: cubedaw_wasm::Module,
+    io_location: cubedaw_wasm::ExportLocation,
+    process_location: cubedaw_wasm::ExportLocation,
+    memory_locations: Box<[cubedaw_wasm::ExportLocation]>,
+    nodes: Arc<[NodeLayout]>,
+    buffer_bytes: u32,
+}
+
+impl StitchedNodeGraphFactory {
+    pub fn new(
+        nodes: &[StitchedNode],
+        registry: &NodeRegistry,
+        sample_rate: u32,
+        buffer_size: u32,
+    ) -> Result<Self> {
+        let buffer_bytes = buffer_size * size_of::<f32>() as u32;
+
+        // lay out the io memory
+        let mut io_size = LAYOUT_START;
+        let mut alloc = |size: u32| {
+            let addr = io_size;
+            io_size += ceil_to_multiple_of(size, CHUNK_BYTES);
+            addr
+        };
+        let mut layouts: Vec<NodeLayout> = nodes
+            .iter()
+            .map(|node| NodeLayout {
+                header: alloc(3 * size_of::<u32>() as u32),
+                input_slots: alloc(node.inputs.len() as u32 * CHUNK_BYTES),
+                biases: node.inputs.iter().map(|_| alloc(buffer_bytes)).collect(),
+                multipliers: node
+                    .inputs
+                    .iter()
+                    .map(|conns| conns.iter().map(|_| alloc(buffer_bytes)).collect())
+                    .collect(),
+                outputs: alloc(node.num_outputs * buffer_bytes),
+                memory: None,
+            })
+            .collect();
+
+        let mut module = cubedaw_plugin::ModuleStitch::with_imports(
+            cubedaw_plugin::ShimInfo::new(move |mut ctx| match ctx.import() {
+                CubedawPluginImport::SampleRate => {
+                    ctx.replace_only_current([Instruction::I32Const(sample_rate as i32)]);
+                }
+                CubedawPluginImport::Input => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(INPUT_FUNC));
+                }
+                CubedawPluginImport::Output => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(OUTPUT_FUNC));
+                }
+                CubedawPluginImport::Attribute => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(ATTRIBUTE_FUNC));
+                }
+                CubedawPluginImport::Sample => {
+                    ctx.replace_only_current([]);
+                    ctx.add_instruction_raw(Instruction::Call(SAMPLE_FUNC));
+                }
+            }),
+            [
+                ("attribute", CubedawPluginImport::Attribute.ty()),
+                ("sample", CubedawPluginImport::Sample.ty()),
+            ],
+        );
+
+        let io_memory = module.add_memory(io_size.div_ceil(1 << 16) as u64);
+        debug_assert_eq!(io_memory, IO_MEMORY);
+        for global in [
+            INPUT_BASE_GLOBAL,
+            NUM_INPUTS_GLOBAL,
+            OUTPUT_BASE_GLOBAL,
+            NUM_OUTPUTS_GLOBAL,
+        ] {
+            let global_idx = module.add_global_i32(0);
+            debug_assert_eq!(global_idx, global);
+        }
+
+        let input_func = module.add_function(Self::input_helper());
+        debug_assert_eq!(input_func, INPUT_FUNC);
+        let output_func = module.add_function(Self::output_helper(buffer_bytes));
+        debug_assert_eq!(output_func, OUTPUT_FUNC);
+
+        // stitch every node type into its own function. these get called from `process`
+        let mut node_funcs: HashMap<&ResourceKey, u32> = HashMap::new();
+        let mut plugin_memories: HashMap<*const PluginData, usize> = HashMap::new();
+        let mut memory_indices: Vec<u32> = Vec::new();
+        for (node, layout) in nodes.iter().zip(&mut layouts) {
+            let Some(plugin_data) = &node.plugin else {
+                continue;
+            };
+            if !node_funcs.contains_key(&node.key) {
+                let memories_before = module.memories.len();
+
+                let mut func = cubedaw_plugin::FunctionStitch::new(FuncType::new(
+                    [ValType::I32, ValType::I32],
+                    [],
+                ));
+                func.add_instruction_raw(&Instruction::LocalGet(0));
+                func.add_instruction_raw(&Instruction::LocalGet(1));
+                plugin_data
+                    .plugin
+                    .stitch_node(&node.key, &mut func, &mut module)
+                    .with_context(|| format!("failed to stitch node {}", node.key))?;
+                node_funcs.insert(&node.key, module.add_function(func));
+
+                if module.memories.len() != memories_before {
+                    // first time this plugin got stitched in
+                    plugin_memories.insert(Arc::as_ptr(plugin_data), memory_indices.len());
+                    memory_indices.push(memories_before);
+                }
+            }
+            let memory = *plugin_memories
+                .get(&Arc::as_ptr(plugin_data))
+                .expect("plugin was stitched but its memory wasn't recorded");
+            layout.memory = Some((memory, node.args_len, node.state_len));
+        }
+
+        let process_func = module.add_function(Self::process_function(
+            nodes,
+            &layouts,
+            &node_funcs,
+            buffer_bytes,
+        ));
+
+        module.export_function("process", process_func);
+        module.export_memory("io", IO_MEMORY);
+        for (i, &memory_idx) in memory_indices.iter().enumerate() {
+            module.export_memory(&format!("mem{i}"), memory_idx);
+        }
+
+        let module = cubedaw_wasm::Module::new(registry.engine(), &module.finish())
+            .context("failed to compile stitched node graph")?;
+
+        Ok(Self {
+            io_location: module
+                .get_export("io")
+                .context("io memory isn't exported")?,
+            process_location: module
+                .get_export("process")
+                .context("process function isn't exported")?,
+            memory_locations: (0..memory_indices.len())
+                .map(|i| {
+                    module
+                        .get_export(&format!("mem{i}"))
+                        .with_context(|| format!("memory {i} isn't exported"))
+                })
+                .collect::<Result<_>>()?,
+            module,
+            nodes: layouts.into(),
+            buffer_bytes,
+        })
+    }
+
+    /// `input(idx) -> (v128, v128, v128, v128)`. Reads from the input slots of the current node.
+    fn input_helper() -> cubedaw_plugin::FunctionStitch {
+        let mut func = cubedaw_plugin::FunctionStitch::new(FuncType::new(
+            [ValType::I32],
+            [ValType::V128, ValType::V128, ValType::V128, ValType::V128],
+        ));
+        let idx = 0;
+        let addr = 1;
+        for inst in [
+            Instruction::LocalGet(idx),
+            Instruction::I32Const(CHUNK_BYTES.ilog2() as i32),
+            Instruction::I32Shl,
+            Instruction::GlobalGet(INPUT_BASE_GLOBAL),
+            Instruction::I32Add,
+            Instruction::I32Const(ZERO_SLOT as i32),
+            Instruction::LocalGet(idx),
+            Instruction::GlobalGet(NUM_INPUTS_GLOBAL),
+            Instruction::I32LtU,
+            Instruction::Select,
+            Instruction::LocalSet(addr),
+        ] {
+            func.add_instruction_raw(&inst);
+        }
+        for lane in 0..4 {
+            func.add_instruction_raw(&Instruction::LocalGet(addr));
+            func.add_instruction_raw(&Instruction::V128Load(io_v128(lane * 16)));
+        }
+        func.extend_locals([ValType::I32]);
+        func
+    }
+    /// `output(v128, v128, v128, v128, idx)`. Writes to the current chunk of the current node's outputs.
+    fn output_helper(buffer_bytes: u32) -> cubedaw_plugin::FunctionStitch {
+        let mut func = cubedaw_plugin::FunctionStitch::new(FuncType::new(
+            [
+                ValType::V128,
+                ValType::V128,
+                ValType::V128,
+                ValType::V128,
+                ValType::I32,
+            ],
+            [],
+        ));
+        let idx = 4;
+        let addr = 5;
+        for inst in [
+            Instruction::LocalGet(idx),
+            Instruction::I32Const(buffer_bytes as i32),
+            Instruction::I32Mul,
+            Instruction::GlobalGet(OUTPUT_BASE_GLOBAL),
+            Instruction::I32Add,
+            Instruction::I32Const(TRASH_SLOT as i32),
+            Instruction::LocalGet(idx),
+            Instruction::GlobalGet(NUM_OUTPUTS_GLOBAL),
+            Instruction::I32LtU,
+            Instruction::Select,
+            Instruction::LocalSet(addr),
+        ] {
+            func.add_instruction_raw(&inst);
+        }
+        for lane in 0..4 {
+            func.add_instruction_raw(&Instruction::LocalGet(addr));
+            func.add_instruction_raw(&Instruction::LocalGet(lane));
+            func.add_instruction_raw(&Instruction::V128Store(io_v128(lane * 16)));
+        }
+        func.extend_locals([ValType::I32]);
+        func
+    }
+
+    /// `process()`. Runs the whole node graph for one buffer, one chunk at a time.
+    fn process_function(
+        nodes: &[StitchedNode],
+        layouts: &[NodeLayout],
+        node_funcs: &HashMap<&ResourceKey, u32>,
+        buffer_bytes: u32,
+    ) -> cubedaw_plugin::FunctionStitch {
+        let mut func = cubedaw_plugin::FunctionStitch::new(FuncType::new([], []));
+        // byte offset of the current chunk into every buffer
+        let chunk = 0;
+        let mut code = vec![Instruction::Loop(BlockType::Empty)];
+
+        for (node_idx, (node, layout)) in nodes.iter().zip(layouts).enumerate() {
+            if node.is_input {
+                continue;
+            }
+
+            code.extend([
+                Instruction::I32Const(0),
+                Instruction::I32Load(io_i32(layout.header)),
+                Instruction::If(BlockType::Empty),
+            ]);
+
+            // mix the cables going into each input
+            for (input_idx, conns) in node.inputs.iter().enumerate() {
+                let slot = layout.input_slots + input_idx as u32 * CHUNK_BYTES;
+                for lane in 0..4 {
+                    let lane_offset = lane * 16;
+                    code.extend([
+                        Instruction::I32Const(0),
+                        Instruction::LocalGet(chunk),
+                        Instruction::V128Load(io_v128(layout.biases[input_idx] + lane_offset)),
+                    ]);
+                    for (conn_idx, &(src_node, src_output)) in conns.iter().enumerate() {
+                        let src = layouts[src_node as usize].outputs + src_output * buffer_bytes;
+                        code.extend([
+                            Instruction::LocalGet(chunk),
+                            Instruction::V128Load(io_v128(src + lane_offset)),
+                            Instruction::LocalGet(chunk),
+                            Instruction::V128Load(io_v128(
+                                layout.multipliers[input_idx][conn_idx] + lane_offset,
+                            )),
+                            Instruction::F32x4Mul,
+                            Instruction::F32x4Add,
+                        ]);
+                    }
+                    code.push(Instruction::V128Store(io_v128(slot + lane_offset)));
+                }
+            }
+
+            match node.plugin {
+                Some(_) => {
+                    code.extend([
+                        Instruction::I32Const(layout.input_slots as i32),
+                        Instruction::GlobalSet(INPUT_BASE_GLOBAL),
+                        Instruction::I32Const(node.inputs.len() as i32),
+                        Instruction::GlobalSet(NUM_INPUTS_GLOBAL),
+                        Instruction::LocalGet(chunk),
+                        Instruction::I32Const(layout.outputs as i32),
+                        Instruction::I32Add,
+                        Instruction::GlobalSet(OUTPUT_BASE_GLOBAL),
+                        Instruction::I32Const(node.num_outputs as i32),
+                        Instruction::GlobalSet(NUM_OUTPUTS_GLOBAL),
+                        Instruction::I32Const(0),
+                        Instruction::I32Const(node_idx as i32),
+                        Instruction::I32Store(io_i32(CURRENT_NODE)),
+                        // stitched functions take their parameters in reverse (see `StandalonePlugin::run`), so state goes first
+                        Instruction::I32Const(0),
+                        Instruction::I32Load(io_i32(layout.header + 8)),
+                        Instruction::I32Const(0),
+                        Instruction::I32Load(io_i32(layout.header + 4)),
+                        Instruction::Call(node_funcs[&node.key]),
+                    ]);
+                }
+                None => {
+                    // special passthrough logic
+                    for io_idx in 0..(node.inputs.len() as u32).min(node.num_outputs) {
+                        for lane in 0..4 {
+                            let lane_offset = lane * 16;
+                            code.extend([
+                                Instruction::LocalGet(chunk),
+                                Instruction::I32Const(0),
+                                Instruction::V128Load(io_v128(
+                                    layout.input_slots + io_idx * CHUNK_BYTES + lane_offset,
+                                )),
+                                Instruction::V128Store(io_v128(
+                                    layout.outputs + io_idx * buffer_bytes + lane_offset,
+                                )),
+                            ]);
+                        }
+                    }
+                }
+            }
+
+            code.push(Instruction::End);
+        }
+
+        code.extend([
+            Instruction::LocalGet(chunk),
+            Instruction::I32Const(CHUNK_BYTES as i32),
+            Instruction::I32Add,
+            Instruction::LocalTee(chunk),
+            Instruction::I32Const(buffer_bytes as i32),
+            Instruction::I32LtU,
+            Instruction::BrIf(0),
+            Instruction::End,
+        ]);
+
+        for inst in &code {
+            func.add_instruction_raw(inst);
+        }
+        func.extend_locals([ValType::I32]);
+        func
+    }
+
+    pub fn create(&self, options: &WorkerOptions) -> Result<StitchedNodeGraph> {
+        let mut store = StandalonePluginStore::new(
+            options.registry.engine(),
+            StandalonePluginParameters::new(options),
+        );
+        let instance = options
+            .registry
+            .standalone_linker()
+            .instantiate(&mut store, &self.module)
+            .context("failed to instantiate stitched node graph")?;
+        let io = instance
+            .get_memory(&mut store, &self.io_location)
+            .context("no io memory in stitched node graph")?;
+        let process = instance
+            .get_func(&mut store, &self.process_location)
+            .context("no process function in stitched node graph")?;
+        let memories = self
+            .memory_locations
+            .iter()
+            .map(|location| {
+                instance
+                    .get_memory(&mut store, location)
+                    .context("plugin memory isn't exported")
+            })
+            .collect::<Result<Box<[_]>>>()?;
+
+        // put every node's args and state after the end of its plugin's memory
+        let mut memory_ends: Vec<u32> = memories
+            .iter()
+            .map(|memory| {
+                let size = memory.size(&store) << memory.page_size_log2(&store);
+                ceil_to_multiple_of(size, PLUGIN_ALIGN)
+            })
+            .collect();
+        let mut pointers = Vec::with_capacity(self.nodes.len());
+        for layout in self.nodes.iter() {
+            let Some((memory, args_len, state_len)) = layout.memory else {
+                pointers.push(None);
+                continue;
+            };
+            let args_ptr = memory_ends[memory];
+            let state_ptr = args_ptr + ceil_to_multiple_of(args_len, PLUGIN_ALIGN);
+            memory_ends[memory] = state_ptr + ceil_to_multiple_of(state_len, PLUGIN_ALIGN);
+
+            for (offset, value) in [(4, args_ptr), (8, state_ptr)] {
+                io.write(&mut store, layout.header + offset, &value.to_le_bytes())
+                    .expect("unreachable");
+            }
+            pointers.push(Some((memory, args_ptr, state_ptr)));
+        }
+        for (memory, end) in memories.iter().zip(memory_ends) {
+            let page_size = memory.page_size_log2(&store);
+            let current_size = memory.size(&store) << page_size;
+            if current_size < end {
+                memory
+                    .grow(&mut store, (end - current_size).div_ceil(1 << page_size))
+                    .context("failed to make room for node args and state")?;
+            }
+        }
+
+        Ok(StitchedNodeGraph {
+            store,
+            _instance: instance,
+            process,
+            io,
+            memories,
+            nodes: self.nodes.clone(),
+            pointers: pointers.into(),
+            buffer_bytes: self.buffer_bytes,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct StitchedNodeGraph {
+    store: StandalonePluginStore,
+    _instance: cubedaw_wasm::Instance,
+    process: cubedaw_wasm::Func,
+    io: cubedaw_wasm::Memory,
+    memories: Box<[cubedaw_wasm::Memory]>,
+
+    nodes: Arc<[NodeLayout]>,
+    /// The plugin memory index, args pointer and state pointer of every node.
+    pointers: Box<[Option<(usize, u32, u32)>]>,
+    buffer_bytes: u32,
+}
+
+// memory writes only fail when out of range, and everything here is in range by construction
+impl StitchedNodeGraph {
+    fn write_io(&mut self, addr: u32, bytes: &[u8]) {
+        self.io
+            .write(&mut self.store, addr, bytes)
+            .expect("unreachable");
+    }
+
+    /// Sets whether a node is processed. Disabled nodes leave their outputs untouched.
+    pub fn set_enabled(&mut self, node_idx: usize, enabled: bool) {
+        let addr = self.nodes[node_idx].header;
+        self.write_io(addr, &(enabled as u32).to_le_bytes());
+    }
+    pub fn write_bias(&mut self, node_idx: usize, input_idx: usize, buffer: &Buffer) {
+        let addr = self.nodes[node_idx].biases[input_idx];
+        self.write_io(addr, buffer.as_bytes());
+    }
+    pub fn write_multiplier(
+        &mut self,
+        node_idx: usize,
+        input_idx: usize,
+        conn_idx: usize,
+        buffer: &Buffer,
+    ) {
+        let addr = self.nodes[node_idx].multipliers[input_idx][conn_idx];
+        self.write_io(addr, buffer.as_bytes());
+    }
+    pub fn write_output(&mut self, node_idx: usize, output_idx: usize, buffer: &Buffer) {
+        let addr = self.nodes[node_idx].outputs + output_idx as u32 * self.buffer_bytes;
+        self.write_io(addr, buffer.as_bytes());
+    }
+    pub fn read_output(&self, node_idx: usize, output_idx: usize, buffer: &mut Buffer) {
+        let addr = self.nodes[node_idx].outputs + output_idx as u32 * self.buffer_bytes;
+        self.io
+            .read(&self.store, addr, buffer.as_bytes_mut())
+            .expect("unreachable");
+    }
+
+    /// Writes a node's args and state into its plugin's memory. Does nothing for builtin nodes.
+    pub fn write_args_and_state(&mut self, node_idx: usize, args: &[u8], state: &[u8]) {
+        let Some((memory, args_ptr, state_ptr)) = self.pointers[node_idx] else {
+            return;
+        };
+        let memory = &self.memories[memory];
+        memory
+            .write(&mut self.store, args_ptr, args)
+            .expect("unreachable");
+        memory
+            .write(&mut self.store, state_ptr, state)
+            .expect("unreachable");
+    }
+    /// Reads a node's state back from its plugin's memory. Does nothing for builtin nodes.
+    pub fn read_state(&self, node_idx: usize, state: &mut [u8]) {
+        let Some((memory, _, state_ptr)) = self.pointers[node_idx] else {
+            return;
+        };
+        self.memories[memory]
+            .read(&self.store, state_ptr, state)
+            .expect("unreachable");
+    }
+
+    /// Runs the node graph for one buffer. On error, returns the index of the node that caused it, if there is one.
+    pub fn process(
+        &mut self,
+        samples: &IdMap<Sample>,
+        attribute_map: &mut dyn AttributeMap,
+    ) -> Result<(), (Option<usize>, anyhow::Error)> {
+        self.write_io(CURRENT_NODE, &u32::MAX.to_le_bytes());
+        self.store.data_mut().set_call_data(samples, attribute_map);
+
+        self.process
+            .call(&mut self.store, &[], &mut [])
+            .map_err(|err| {
+                let mut current_node = [0; 4];
+                self.io
+                    .read(&self.store, CURRENT_NODE, &mut current_node)
+                    .expect("unreachable");
+                let current_node = u32::from_le_bytes(current_node);
+                (
+                    (current_node != u32::MAX).then_some(current_node as usize),
+                    err,
+                )
+            })
+    }
+}
+
+/// Stitched node graphs shared by all workers, keyed by the graph's structure.
+///
+/// Stitching and compiling a graph is slow, so it happens on a background thread. Until a graph is ready, graphs with
+/// its structure are processed node by node.
+#[derive(Debug)]
+pub struct StitchCache {
+    graphs: Arc<RwLock<HashMap<Arc<StitchKey>, StitchStatus>>>,
+    /// How many graphs are waiting to be stitched.
+    pending: Arc<AtomicUsize>,
+    /// Bumped whenever graphs are evicted or finish stitching, so workers know to update their instances of them.
+    generation: Arc<AtomicU64>,
+    tx: crossbeam_channel::Sender<StitchJob>,
+}
+
+#[derive(Debug)]
+enum StitchStatus {
+    Pending,
+    /// The graph failed to stitch. It's processed node by node instead.
+    Failed,
+    Ready(Arc<StitchedNodeGraphFactory>),
+}
+
+#[derive(Debug)]
+struct StitchJob {
+    key: Arc<StitchKey>,
+    registry: Arc<NodeRegistry>,
+}
+
+impl StitchCache {
+    pub fn new() -> Self {
+        let graphs: Arc<RwLock<HashMap<Arc<StitchKey>, StitchStatus>>> = Default::default();
+        let pending: Arc<AtomicUsize> = Default::default();
+        let generation: Arc<AtomicU64> = Default::default();
+        let (tx, rx) = crossbeam_channel::unbounded::<StitchJob>();
+        thread::Builder::new()
+            .name("cubedaw stitcher".into())
+            .spawn({
+                let graphs = graphs.clone();
+                let pending = pending.clone();
+                let generation = generation.clone();
+                // the thread stops once the cache is dropped
+                move || {
+                    for job in rx {
+                        let status = match StitchedNodeGraphFactory::new(
+                            &job.key.nodes,
+                            &job.registry,
+                            job.key.sample_rate,
+                            job.key.buffer_size,
+                        ) {
+                            Ok(factory) => StitchStatus::Ready(Arc::new(factory)),
+                            Err(err) => {
+                                tracing::error!(
+                                    "failed to stitch node graph, processing it node by node instead: {err:#}"
+                                );
+                                StitchStatus::Failed
+                            }
+                        };
+                        // the graph might've been evicted while it was being stitched
+                        if let Some(entry) = graphs.write().expect("poisoned").get_mut(&job.key) {
+                            if matches!(status, StitchStatus::Ready(_)) {
+                                generation.fetch_add(1, Ordering::Release);
+                            }
+                            *entry = status;
+                        }
+                        pending.fetch_sub(1, Ordering::Release);
+                    }
+                }
+            })
+            .expect("failed to spawn stitcher thread");
+
+        Self {
+            graphs,
+            pending,
+            generation,
+            tx,
+        }
+    }
+
+    /// Starts stitching the graph with the given structure in the background, unless it's already stitched or being
+    /// stitched. Returns the cache's copy of the key, which is quicker to look up than an equal key.
+    pub fn request(&self, key: StitchKey, options: &WorkerOptions) -> Arc<StitchKey> {
+        if let Some((key, _)) = self.graphs.read().expect("poisoned").get_key_value(&key) {
+            return key.clone();
+        }
+        let mut graphs = self.graphs.write().expect("poisoned");
+        if let Some((key, _)) = graphs.get_key_value(&key) {
+            return key.clone();
+        }
+        let key = Arc::new(key);
+        graphs.insert(key.clone(), StitchStatus::Pending);
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.tx
+            .send(StitchJob {
+                key: key.clone(),
+                registry: options.registry.clone(),
+            })
+            .expect("stitcher thread died");
+        key
+    }
+
+    /// Gets every graph that's done stitching.
+    pub fn ready_graphs(&self) -> Vec<(Arc<StitchKey>, Arc<StitchedNodeGraphFactory>)> {
+        self.graphs
+            .read()
+            .expect("poisoned")
+            .iter()
+            .filter_map(|(key, status)| match status {
+                StitchStatus::Ready(factory) => Some((key.clone(), factory.clone())),
+                StitchStatus::Pending | StitchStatus::Failed => None,
+            })
+            .collect()
+    }
+    pub fn contains(&self, key: &StitchKey) -> bool {
+        self.graphs.read().expect("poisoned").contains_key(key)
+    }
+
+    /// Evicts every graph that `keep` returns `false` for.
+    pub fn retain(&self, mut keep: impl FnMut(&StitchKey) -> bool) {
+        let mut graphs = self.graphs.write().expect("poisoned");
+        let len = graphs.len();
+        graphs.retain(|key, _| keep(key));
+        if graphs.len() != len {
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+    /// See [`WorkerState::update_stitched_graphs`](crate::WorkerState::update_stitched_graphs).
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Blocks until every requested graph is done stitching. Only for when processing doesn't have to be realtime,
+    /// like offline rendering.
+    pub fn wait(&self) {
+        while self.pending.load(Ordering::Acquire) > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl Default for StitchCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{StitchKey, StitchedNode};
+    use crate::WorkerOptions;
+
+    fn key(num_outputs: u32) -> StitchKey {
+        StitchKey::new(
+            vec![StitchedNode {
+                key: resourcekey::literal!("builtin:output"),
+                plugin: None,
+                is_input: false,
+                inputs: vec![Vec::new()],
+                num_outputs,
+                args_len: 0,
+                state_len: 0,
+            }],
+            44100,
+            512,
+        )
+    }
+
+    #[test]
+    fn colliding_keys_are_kept_apart() {
+        let options = WorkerOptions::new(Default::default());
+        let a = key(1);
+        let mut b = key(2);
+        b.hash = a.hash;
+        assert_ne!(a, b);
+
+        let a = options.stitch_cache.request(a, &options);
+        let b = options.stitch_cache.request(b, &options);
+        assert!(!Arc::ptr_eq(&a, &b));
+        assert!(options.stitch_cache.contains(&a) && options.stitch_cache.contains(&b));
+
+        // equal keys share the cache's copy
+        assert!(Arc::ptr_eq(
+            &a,
+            &options.stitch_cache.request(key(1), &options)
+        ));
+        options.stitch_cache.wait();
+    }
+}
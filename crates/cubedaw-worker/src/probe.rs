@@ -0,0 +1,164 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use cubedaw_lib::{Id, IdMap, Node, Note};
+
+/// A ring buffer of samples that one thread writes to and any number of threads read from, without locking.
+///
+/// Old samples are overwritten once the ring is full. Reads that happen during a write might see a mix of old and new samples,
+/// which is fine for displaying them.
+pub struct ProbeRing {
+    /// The samples as bits, since there's no `AtomicF32`.
+    samples: Box<[AtomicU32]>,
+    /// How many samples have been written in total.
+    written: AtomicU64,
+}
+
+impl ProbeRing {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "probe ring must have a nonzero capacity");
+        Self {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+    /// How many samples have been written in total. This can be used to check whether anything new has been written.
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Acquire)
+    }
+
+    /// Appends samples to the ring. Only one thread should write to a ring at a time, otherwise the samples get jumbled.
+    pub fn write(&self, samples: &[f32]) {
+        let written = self.written.load(Ordering::Relaxed);
+        let capacity = self.capacity() as u64;
+        // only the last `capacity` samples would survive anyways
+        let skipped = (samples.len() as u64).saturating_sub(capacity);
+        for (i, &sample) in samples[skipped as usize..].iter().enumerate() {
+            let index = (written + skipped + i as u64) % capacity;
+            self.samples[index as usize].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written
+            .store(written + samples.len() as u64, Ordering::Release);
+    }
+
+    /// Reads the most recent samples into the end of `out`, oldest first. If fewer samples than `out.len()` have been written
+    /// (or `out` is bigger than the ring), the start of `out` is filled with zeroes.
+    /// Returns the total number of samples written, like [`Self::written`].
+    pub fn read_latest(&self, out: &mut [f32]) -> u64 {
+        let written = self.written();
+        let capacity = self.capacity() as u64;
+        let available = written.min(capacity).min(out.len() as u64) as usize;
+
+        let (empty, filled) = out.split_at_mut(out.len() - available);
+        empty.fill(0.0);
+        let start = written - available as u64;
+        for (i, sample) in filled.iter_mut().enumerate() {
+            let index = (start + i as u64) % capacity;
+            *sample = f32::from_bits(self.samples[index as usize].load(Ordering::Relaxed));
+        }
+        written
+    }
+}
+
+impl std::fmt::Debug for ProbeRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProbeRing")
+            .field("capacity", &self.capacity())
+            .field("written", &self.written())
+            .finish()
+    }
+}
+
+/// Which node graph of a track a probe listens to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeSource {
+    /// The track's node graph, which processes the sum of all its notes.
+    Track,
+    /// The node graph of a single note. If this is `None`, the probe picks any playing note and sticks with it until it ends.
+    Voice(Option<Id<Note>>),
+}
+
+/// Copies the output of a node into a [`ProbeRing`] every buffer, so the ui can show what the node outputs.
+#[derive(Clone, Debug)]
+pub struct Probe {
+    pub node_id: Id<Node>,
+    pub output_index: u32,
+    pub source: ProbeSource,
+    pub ring: Arc<ProbeRing>,
+
+    /// The note being listened to, if the source is a voice.
+    voice: Option<Id<Note>>,
+}
+
+impl Probe {
+    pub fn new(
+        node_id: Id<Node>,
+        output_index: u32,
+        source: ProbeSource,
+        ring: Arc<ProbeRing>,
+    ) -> Self {
+        Self {
+            node_id,
+            output_index,
+            source,
+            ring,
+            voice: None,
+        }
+    }
+
+    /// Picks the note to listen to, if the current one stopped playing.
+    pub(crate) fn choose_voice<A, B>(
+        &mut self,
+        notes: &IdMap<Note, A>,
+        live_notes: &IdMap<Note, B>,
+    ) {
+        let is_playing = |note_id| notes.has(note_id) || live_notes.has(note_id);
+        self.voice = match self.source {
+            ProbeSource::Track => None,
+            ProbeSource::Voice(Some(note_id)) => Some(note_id),
+            ProbeSource::Voice(None) => match self.voice {
+                Some(note_id) if is_playing(note_id) => Some(note_id),
+                _ => live_notes.keys().chain(notes.keys()).next(),
+            },
+        };
+    }
+
+    /// Whether this probe listens to the note `note_id`.
+    pub(crate) fn listens_to_voice(&self, note_id: Id<Note>) -> bool {
+        self.voice == Some(note_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProbeRing;
+
+    #[test]
+    fn reads_latest_samples() {
+        let ring = ProbeRing::new(4);
+
+        let mut out = [1.0; 3];
+        assert_eq!(ring.read_latest(&mut out), 0);
+        assert_eq!(out, [0.0; 3]);
+
+        ring.write(&[1.0, 2.0]);
+        assert_eq!(ring.read_latest(&mut out), 2);
+        assert_eq!(out, [0.0, 1.0, 2.0]);
+
+        ring.write(&[3.0, 4.0, 5.0]);
+        assert_eq!(ring.read_latest(&mut out), 5);
+        assert_eq!(out, [3.0, 4.0, 5.0]);
+
+        // writing more than the capacity only keeps the end
+        ring.write(&[6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+        let mut out = [0.0; 6];
+        assert_eq!(ring.read_latest(&mut out), 11);
+        assert_eq!(out, [0.0, 0.0, 8.0, 9.0, 10.0, 11.0]);
+    }
+}
@@ -60,6 +60,8 @@ impl NodeRegistry {
         this.register_dummy_node(ResourceKey::new("builtin:output").unwrap());
         this.register_dummy_node(ResourceKey::new("builtin:track_output").unwrap());
         this.register_dummy_node(ResourceKey::new("builtin:output").unwrap());
+        this.register_dummy_node(ResourceKey::new("builtin:audio_input").unwrap());
+        this.register_dummy_node(ResourceKey::new("builtin:sidechain").unwrap());
         this
     }
 
@@ -146,6 +148,15 @@ pub struct NodeRegistryEntry {
     pub plugin_data: Option<Arc<PluginData>>,
 }
 
+impl NodeRegistryEntry {
+    /// How many samples this node delays its outputs by.
+    pub fn latency(&self) -> u32 {
+        self.plugin_data
+            .as_ref()
+            .map_or(0, |plugin_data| plugin_data.plugin.node_latency(&self.key))
+    }
+}
+
 #[derive(Debug)]
 pub struct PluginData {
     pub plugin: cubedaw_plugin::Plugin,
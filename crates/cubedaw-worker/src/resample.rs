@@ -0,0 +1,196 @@
+//! Band-limited resampling with a windowed sinc filter, for playing back samples at a different rate than they were
+//! recorded at.
+
+use std::{f64::consts::PI, sync::OnceLock};
+
+/// How many points of the kernel are precomputed per zero crossing. Points in between are linearly interpolated.
+const PHASES: usize = 256;
+/// Downsampling by more than this is treated as downsampling by this much, so the kernel doesn't get too wide.
+const MAX_RATIO: f64 = 8.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResampleQuality {
+    /// Linear interpolation. Cheap, but aliases.
+    Linear,
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl ResampleQuality {
+    pub const ALL: [Self; 4] = [Self::Linear, Self::Low, Self::Medium, Self::High];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Low => "Low",
+            Self::Medium => "Medium",
+            Self::High => "High",
+        }
+    }
+
+    /// How many zero crossings the kernel has on each side.
+    fn zero_crossings(self) -> usize {
+        match self {
+            Self::Linear => 1,
+            Self::Low => 4,
+            Self::Medium => 16,
+            Self::High => 64,
+        }
+    }
+
+    /// The kernel for this quality. Kernels are only computed once.
+    pub fn kernel(self) -> &'static SincKernel {
+        static KERNELS: [OnceLock<SincKernel>; 4] = [const { OnceLock::new() }; 4];
+        KERNELS[self as usize].get_or_init(|| SincKernel::new(self))
+    }
+}
+
+/// A Blackman-windowed sinc kernel.
+#[derive(Debug)]
+pub struct SincKernel {
+    quality: ResampleQuality,
+    /// One side of the kernel, from 0 to `zero_crossings` zero crossings, with [`PHASES`] points per zero crossing.
+    table: Box<[f32]>,
+}
+
+impl SincKernel {
+    fn new(quality: ResampleQuality) -> Self {
+        let zero_crossings = quality.zero_crossings();
+        let len = zero_crossings * PHASES;
+        let table = (0..=len + 1)
+            .map(|i| {
+                if i >= len {
+                    return 0.0;
+                }
+                if i % PHASES == 0 {
+                    // exactly zero at the zero crossings so integer positions pass frames through untouched
+                    return if i == 0 { 1.0 } else { 0.0 };
+                }
+                let x = i as f64 / PHASES as f64;
+                let sinc = (PI * x).sin() / (PI * x);
+                let t = i as f64 / len as f64;
+                let window = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+        Self { quality, table }
+    }
+
+    pub fn quality(&self) -> ResampleQuality {
+        self.quality
+    }
+
+    /// The kernel at `x` zero crossings from the center.
+    fn at(&self, x: f64) -> f32 {
+        let pos = x.abs() * PHASES as f64;
+        let index = pos as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let t = (pos - index as f64) as f32;
+        crate::util::lerp(self.table[index], self.table[index + 1], t)
+    }
+
+    /// Reads `data` at the fractional frame `pos`. `ratio` is how many frames the position moves per output sample;
+    /// when it's above 1 the kernel is widened so frequencies above the output's Nyquist frequency are filtered out.
+    /// Frames outside of `data` are silent.
+    pub fn interpolate(&self, data: &[f32], pos: f64, ratio: f64) -> f32 {
+        let frame = |index: i64| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| data.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        let index = pos.floor();
+        if self.quality == ResampleQuality::Linear {
+            let fraction = (pos - index) as f32;
+            let (a, b) = (frame(index as i64), frame(index as i64 + 1));
+            return a + (b - a) * fraction;
+        }
+
+        let scale = ratio.abs().clamp(1.0, MAX_RATIO);
+        let half_width = (self.quality.zero_crossings() as f64 * scale).ceil() as i64;
+        let index = index as i64;
+        let mut sum = 0.0;
+        let mut weight_sum = 0.0;
+        for i in index - half_width + 1..=index + half_width {
+            let weight = self.at((pos - i as f64) / scale);
+            sum += frame(i) * weight;
+            weight_sum += weight;
+        }
+        // normalizing keeps the gain at exactly 1 despite the window and the table's interpolation
+        if weight_sum != 0.0 {
+            sum / weight_sum
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Resamples all of `data` from `from_rate` to `to_rate`.
+pub fn resample(data: &[f32], from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Vec<f32> {
+    if from_rate == to_rate {
+        return data.to_vec();
+    }
+    let ratio = from_rate as f64 / to_rate as f64;
+    let len = (data.len() as f64 / ratio).ceil() as usize;
+    let kernel = quality.kernel();
+    (0..len)
+        .map(|i| kernel.interpolate(data, i as f64 * ratio, ratio))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+    fn rms(data: &[f32]) -> f32 {
+        (data.iter().map(|x| x * x).sum::<f32>() / data.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn integer_positions_are_exact() {
+        let data = [0.5, -0.25, 1.0, 0.0];
+        for quality in ResampleQuality::ALL {
+            let kernel = quality.kernel();
+            for (i, &x) in data.iter().enumerate() {
+                assert_eq!(kernel.interpolate(&data, i as f64, 1.0), x, "{quality:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn upsampling_keeps_tones() {
+        let resampled = resample(
+            &sine(1000.0, 8000, 800),
+            8000,
+            44100,
+            ResampleQuality::Medium,
+        );
+        let expected = sine(1000.0, 44100, resampled.len());
+        // skip the edges, where the kernel runs off the end of the data
+        let error: Vec<f32> = (500..resampled.len() - 500)
+            .map(|i| resampled[i] - expected[i])
+            .collect();
+        assert!(rms(&error) < 1e-3, "{}", rms(&error));
+    }
+
+    #[test]
+    fn downsampling_filters_out_high_frequencies() {
+        // 3 kHz is above the Nyquist frequency of 4 kHz audio
+        let data = sine(3000.0, 16000, 1600);
+        let sinc = resample(&data, 16000, 4000, ResampleQuality::High);
+        let linear = resample(&data, 16000, 4000, ResampleQuality::Linear);
+        let middle = |data: &[f32]| rms(&data[100..300]);
+        assert!(middle(&sinc) < 0.01, "{}", middle(&sinc));
+        assert!(middle(&linear) > 0.5);
+    }
+}
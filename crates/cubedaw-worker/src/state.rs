@@ -3,11 +3,23 @@ use std::{cell::RefCell, rc::Rc, sync::Arc};
 use ahash::{HashMap, HashMapExt};
 use resourcekey::ResourceKey;
 
-use crate::{plugin::standalone::StandalonePlugin, WorkerOptions};
+use crate::{
+    WorkerOptions,
+    plugin::{
+        standalone::StandalonePlugin,
+        stitched::{StitchKey, StitchedNodeGraph},
+    },
+};
 
 /// Per-worker state.
 pub struct WorkerState {
     pub standalone_instances: HashMap<ResourceKey, Rc<RefCell<StandalonePlugin>>>,
+    /// This worker's instances of the graphs in [`WorkerOptions::stitch_cache`], keyed by the graph's structure. `None`
+    /// means the graph failed to instantiate.
+    stitched_graphs: HashMap<Arc<StitchKey>, Option<StitchedNodeGraph>>,
+    /// The [`StitchCache::generation`](crate::plugin::stitched::StitchCache::generation) that `stitched_graphs` was
+    /// last updated at.
+    stitch_generation: Option<u64>,
 }
 
 impl WorkerState {
@@ -17,12 +29,56 @@ impl WorkerState {
         for (key, factory) in options.standalone_plugin_factories.iter() {
             let instance = arc_ptr_to_standalone_plugin_instance
                 .entry(Arc::as_ptr(factory))
-                .or_insert_with(|| Rc::new(RefCell::new(factory.create(options))));
-            standalone_instances.insert(key.clone(), instance.clone());
+                .or_insert_with(|| {
+                    factory
+                        .create(options)
+                        .map(|instance| Rc::new(RefCell::new(instance)))
+                        .inspect_err(|err| tracing::error!("{err:#}"))
+                        .ok()
+                });
+            if let Some(instance) = instance {
+                standalone_instances.insert(key.clone(), instance.clone());
+            }
         }
 
-        Self {
+        let mut this = Self {
             standalone_instances,
+            stitched_graphs: HashMap::new(),
+            stitch_generation: None,
+        };
+        this.update_stitched_graphs(options);
+        this
+    }
+
+    /// Gets this worker's instance of the stitched node graph with the given structure, or `None` if the graph
+    /// isn't instantiated yet or can't be stitched. Graphs are only instantiated by [`Self::update_stitched_graphs`],
+    /// so this is safe to call while processing.
+    pub fn stitched_graph(&mut self, key: &StitchKey) -> Option<&mut StitchedNodeGraph> {
+        self.stitched_graphs.get_mut(key)?.as_mut()
+    }
+
+    /// Drops the instances of graphs that were evicted from the shared cache and instantiates the ones that finished
+    /// stitching. This allocates, so it shouldn't be called while processing.
+    pub fn update_stitched_graphs(&mut self, options: &WorkerOptions) {
+        let generation = options.stitch_cache.generation();
+        if self.stitch_generation == Some(generation) {
+            return;
+        }
+        self.stitch_generation = Some(generation);
+
+        self.stitched_graphs
+            .retain(|key, _| options.stitch_cache.contains(key));
+        for (key, factory) in options.stitch_cache.ready_graphs() {
+            self.stitched_graphs.entry(key).or_insert_with(|| {
+                factory
+                    .create(options)
+                    .inspect_err(|err| {
+                        tracing::error!(
+                            "failed to instantiate stitched node graph, processing it node by node instead: {err:#}"
+                        )
+                    })
+                    .ok()
+            });
         }
     }
 }
@@ -0,0 +1,256 @@
+//! Time-stretching and pitch-shifting with WSOLA (waveform similarity overlap-add).
+//!
+//! The output is built out of overlapping grains of the input. Each grain is nudged a little so that it lines up with
+//! the grain before it, which avoids the phasing and doubling that plain overlap-add has.
+
+use crate::resample::SincKernel;
+
+/// How far apart grains start, in seconds. Each grain is twice this long.
+const HOP_SECONDS: f64 = 0.02;
+/// How far a grain can be nudged to line up with the grain before it, in seconds of input.
+const TOLERANCE_SECONDS: f64 = 0.006;
+/// How many grains back the alignment of a grain starts from. Where a grain ends up depends on the grains before it,
+/// and following that all the way back to the start would get slower the further into the audio it is.
+const HISTORY: i64 = 4;
+/// The most points the similarity between two grains is measured at.
+const CORRELATION_POINTS: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeStretch {
+    /// How fast the input plays, without changing its pitch. 2 is twice as fast.
+    pub speed: f64,
+    /// How much the pitch changes, without changing the speed. 2 is an octave up.
+    pub pitch: f64,
+}
+
+/// Where the last call to [`TimeStretch::render`] left off, so the next one can carry on from there instead of lining
+/// up the grains before it again. Keep one of these for each stream of audio that's rendered bit by bit.
+#[derive(Debug, Default)]
+pub struct GrainCache {
+    /// What the grains were rendered from, and (grain, how far it and the grain before it are nudged).
+    last: Option<(GrainSource, (i64, i64, i64))>,
+}
+
+/// Everything the grain alignment depends on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GrainSource {
+    data: *const f32,
+    len: usize,
+    start_frame: f64,
+    rate: f64,
+    output_rate: u32,
+    stretch: TimeStretch,
+}
+
+// SAFETY: the pointer is only compared, never read through
+unsafe impl Send for GrainSource {}
+unsafe impl Sync for GrainSource {}
+
+impl TimeStretch {
+    pub fn is_identity(self) -> bool {
+        self.speed == 1.0 && self.pitch == 1.0
+    }
+
+    /// Renders `out` from `data`, where `out[0]` is `first` samples after frame `start_frame` of `data` starts playing.
+    /// `rate` is how many frames of `data` play per output sample at normal speed. Output before the start is silent.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        self,
+        kernel: &SincKernel,
+        data: &[f32],
+        start_frame: f64,
+        rate: f64,
+        output_rate: u32,
+        first: i64,
+        cache: &mut GrainCache,
+        out: &mut [f32],
+    ) {
+        let source = GrainSource {
+            data: data.as_ptr(),
+            len: data.len(),
+            start_frame,
+            rate,
+            output_rate,
+            stretch: self,
+        };
+        let hop = (HOP_SECONDS * output_rate as f64).round().max(1.0);
+        let tolerance = (TOLERANCE_SECONDS * output_rate as f64 * rate).round() as i64;
+        let grain_rate = rate * self.pitch;
+        let grain_start =
+            |k: i64, delta: i64| start_frame + k as f64 * hop * rate * self.speed + delta as f64;
+        let frame = |index: i64| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| data.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        // how far to nudge grain `k` so it lines up with where grain `k - 1` (nudged by `prev_delta`) would have continued
+        let overlap = hop * grain_rate;
+        let stride = (overlap / CORRELATION_POINTS as f64).ceil().max(1.0) as usize;
+        let align = |k: i64, prev_delta: i64| {
+            let target = (grain_start(k - 1, prev_delta) + overlap).round() as i64;
+            let nominal = grain_start(k, 0).round() as i64;
+            let correlation = |delta: i64| {
+                (0..overlap as i64)
+                    .step_by(stride)
+                    .map(|j| frame(nominal + delta + j) * frame(target + j))
+                    .sum::<f32>()
+            };
+            // not moving wins ties, so silence isn't pushed around
+            let mut best = (0, correlation(0));
+            for delta in -tolerance..=tolerance {
+                let correlation = correlation(delta);
+                if correlation > best.1 {
+                    best = (delta, correlation);
+                }
+            }
+            best.0
+        };
+        let weight = |k: i64, u: f64| {
+            if k == 0 && u < hop {
+                // nothing comes before the first grain to fade in from
+                1.0
+            } else {
+                (core::f64::consts::FRAC_PI_2 * u / hop).sin().powi(2) as f32
+            }
+        };
+        let read = |k: i64, delta: i64, u: f64| {
+            kernel.interpolate(data, grain_start(k, delta) + u * grain_rate, grain_rate)
+        };
+
+        // how far grain `k` is nudged. this only depends on `k`, so buffers line up no matter where they start
+        let delta_of = |k: i64| {
+            let mut delta = 0;
+            for j in (k - HISTORY).max(0) + 1..=k {
+                delta = align(j, delta);
+            }
+            delta
+        };
+
+        // (grain, how far it and the grain before it are nudged)
+        let mut current = cache
+            .last
+            .filter(|&(last_source, _)| last_source == source)
+            .map(|(_, current)| current);
+        for (i, sample) in out.iter_mut().enumerate() {
+            let t = (first + i as i64) as f64;
+            if t < 0.0 {
+                *sample = 0.0;
+                continue;
+            }
+            let k = (t / hop).floor() as i64;
+            let (prev_delta, delta) = match current {
+                Some((current_k, prev_delta, delta)) if current_k == k => (prev_delta, delta),
+                Some((current_k, _, delta)) if current_k == k - 1 => (delta, delta_of(k)),
+                _ => (delta_of(k - 1), delta_of(k)),
+            };
+            current = Some((k, prev_delta, delta));
+
+            let u = t - k as f64 * hop;
+            *sample = read(k, delta, u) * weight(k, u);
+            if k > 0 {
+                *sample += read(k - 1, prev_delta, u + hop) * weight(k - 1, u + hop);
+            }
+        }
+        cache.last = current.map(|current| (source, current));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resample::ResampleQuality;
+    use std::f64::consts::PI;
+
+    const RATE: u32 = 8000;
+
+    fn render(stretch: TimeStretch, data: &[f32], len: usize) -> Vec<f32> {
+        let mut out = vec![0.0; len];
+        stretch.render(
+            ResampleQuality::Medium.kernel(),
+            data,
+            0.0,
+            1.0,
+            RATE,
+            0,
+            &mut GrainCache::default(),
+            &mut out,
+        );
+        out
+    }
+    fn sine(freq: f64, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f64 / RATE as f64).sin() as f32)
+            .collect()
+    }
+    /// Estimates the frequency of a tone from how often it crosses zero.
+    fn frequency(data: &[f32]) -> f64 {
+        let crossings = data
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        crossings as f64 / 2.0 * RATE as f64 / data.len() as f64
+    }
+
+    #[test]
+    fn stretching_keeps_pitch() {
+        let data = sine(200.0, RATE as usize);
+        for speed in [0.5, 1.5] {
+            let stretch = TimeStretch { speed, pitch: 1.0 };
+            let out = render(stretch, &data, (RATE as f64 * 0.6 / speed) as usize);
+            let freq = frequency(&out[400..]);
+            assert!((freq - 200.0).abs() < 5.0, "{speed}: {freq}");
+        }
+    }
+
+    #[test]
+    fn pitch_shifting_keeps_speed() {
+        let data = sine(200.0, RATE as usize);
+        let stretch = TimeStretch {
+            speed: 1.0,
+            pitch: 2.0f64.powf(7.0 / 12.0),
+        };
+        let out = render(stretch, &data, 6000);
+        let freq = frequency(&out[400..]);
+        assert!((freq - 200.0 * stretch.pitch).abs() < 8.0, "{freq}");
+    }
+
+    #[test]
+    fn buffers_match_whole_render() {
+        let data = sine(330.0, RATE as usize);
+        let stretch = TimeStretch {
+            speed: 0.8,
+            pitch: 1.25,
+        };
+        let whole = render(stretch, &data, 4000);
+        let kernel = ResampleQuality::Medium.kernel();
+        // carrying on from the last buffer or starting over doesn't change anything
+        let mut cache = GrainCache::default();
+        let mut carried_on = vec![0.0; 4000];
+        let mut started_over = vec![0.0; 4000];
+        for (i, (a, b)) in carried_on
+            .chunks_mut(300)
+            .zip(started_over.chunks_mut(300))
+            .enumerate()
+        {
+            let first = i as i64 * 300;
+            stretch.render(kernel, &data, 0.0, 1.0, RATE, first, &mut cache, a);
+            let mut fresh = GrainCache::default();
+            stretch.render(kernel, &data, 0.0, 1.0, RATE, first, &mut fresh, b);
+        }
+        assert_eq!(whole, carried_on);
+        assert_eq!(whole, started_over);
+
+        // a cache left over from rendering something else is ignored
+        let other = TimeStretch {
+            speed: 1.5,
+            pitch: 1.0,
+        };
+        let mut out = vec![0.0; 300];
+        other.render(kernel, &data, 0.0, 1.0, RATE, 1200, &mut cache, &mut out);
+        stretch.render(kernel, &data, 0.0, 1.0, RATE, 1500, &mut cache, &mut out);
+        assert_eq!(whole[1500..1800], out);
+    }
+}
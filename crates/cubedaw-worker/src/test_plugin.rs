@@ -0,0 +1,209 @@
+//! A tiny plugin written straight in wasm, so plugins can be tested without building the plugin crates.
+//!
+//! Nodes:
+//! - `test:scale`: outputs its input times an `f32` arg, and on its second output, how many chunks it's processed so
+//!   far (kept in its state).
+//! - `test:sampler`: plays the sample whose id is in its args, starting at the position in its state. Its input is
+//!   added to the position, like a sampler's pitch would be.
+
+use std::{borrow::Cow, sync::Arc};
+
+use ahash::{HashMap, HashMapExt};
+use cubedaw_lib::{Buffer, Id, InternalBufferType, Sample};
+use cubedaw_plugin::CubedawPluginImport;
+use resourcekey::ResourceKey;
+use wasm_encoder::{
+    CodeSection, CustomSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+    ImportSection, Instruction, MemArg, MemorySection, MemoryType, Module, TypeSection, ValType,
+};
+
+use crate::{DynNodeFactory, NodeRegistry};
+
+pub const SCALE: ResourceKey = resourcekey::literal!("test:scale");
+pub const SAMPLER: ResourceKey = resourcekey::literal!("test:sampler");
+
+// function indices of the imports
+const INPUT: u32 = 0;
+const OUTPUT: u32 = 1;
+const SAMPLE: u32 = 2;
+
+pub fn scale_args(scale: f32) -> Box<Buffer> {
+    scale.to_ne_bytes().as_slice().into()
+}
+pub fn sampler_args(sample_id: Id<Sample>) -> Box<Buffer> {
+    sample_id.raw().get().to_ne_bytes().as_slice().into()
+}
+
+/// A registry with the builtin nodes and the nodes of the test plugin. Every node's state starts out zeroed.
+pub fn registry() -> Arc<NodeRegistry> {
+    let mut registry = NodeRegistry::default();
+    let mut dyn_node_factories = HashMap::new();
+    for key in [SCALE, SAMPLER] {
+        dyn_node_factories.insert(key, DynNodeFactory::new(|_| Box::new([0; 4])));
+    }
+    registry.register_plugin(
+        cubedaw_plugin::Plugin::new(&module()).expect("test plugin is invalid"),
+        &mut dyn_node_factories,
+    );
+    Arc::new(registry)
+}
+
+fn mem(align: u32) -> MemArg {
+    MemArg {
+        offset: 0,
+        align,
+        memory_index: 0,
+    }
+}
+/// A `v128` with the lanes `first..first + 4`.
+fn lanes(first: f32) -> i128 {
+    let lanes = [first, first + 1.0, first + 2.0, first + 3.0];
+    i128::from_le_bytes(bytemuck::must_cast(lanes))
+}
+
+fn postcard_str(bytes: &mut Vec<u8>, s: &str) {
+    assert!(s.len() < 0x80, "string too long for a one-byte varint");
+    bytes.push(s.len() as u8);
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn module() -> Vec<u8> {
+    let mut types = TypeSection::new();
+    let mut imports = ImportSection::new();
+    for (i, import) in [
+        CubedawPluginImport::Input,
+        CubedawPluginImport::Output,
+        CubedawPluginImport::Sample,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        types.ty().func_type(&import.ty());
+        imports.import("host", import.name(), EntityType::Function(i as u32));
+    }
+    let node_type = 3;
+    types.ty().function([ValType::I32, ValType::I32], []);
+
+    let args = 0;
+    let state = 1;
+
+    // (args: *const f32, state: *mut f32)
+    let mut scale = Function::new([(5, ValType::V128)]);
+    let factor = 2;
+    let chunk = [3, 4, 5, 6];
+    scale
+        .instruction(&Instruction::LocalGet(args))
+        .instruction(&Instruction::F32Load(mem(2)))
+        .instruction(&Instruction::F32x4Splat)
+        .instruction(&Instruction::LocalSet(factor))
+        .instruction(&Instruction::I32Const(0))
+        .instruction(&Instruction::Call(INPUT));
+    for &local in chunk.iter().rev() {
+        scale.instruction(&Instruction::LocalSet(local));
+    }
+    for local in chunk {
+        scale
+            .instruction(&Instruction::LocalGet(local))
+            .instruction(&Instruction::LocalGet(factor))
+            .instruction(&Instruction::F32x4Mul);
+    }
+    scale
+        .instruction(&Instruction::I32Const(0))
+        .instruction(&Instruction::Call(OUTPUT))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::F32Load(mem(2)))
+        .instruction(&Instruction::F32Const(1.0.into()))
+        .instruction(&Instruction::F32Add)
+        .instruction(&Instruction::F32Store(mem(2)))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::F32Load(mem(2)))
+        .instruction(&Instruction::F32x4Splat)
+        .instruction(&Instruction::LocalTee(factor))
+        .instruction(&Instruction::LocalGet(factor))
+        .instruction(&Instruction::LocalGet(factor))
+        .instruction(&Instruction::LocalGet(factor))
+        .instruction(&Instruction::I32Const(1))
+        .instruction(&Instruction::Call(OUTPUT))
+        .instruction(&Instruction::End);
+
+    // (args: *const u64, state: *mut i32)
+    let mut sampler = Function::new([(4, ValType::V128)]);
+    let chunk = [2, 3, 4, 5];
+    sampler
+        .instruction(&Instruction::LocalGet(args))
+        .instruction(&Instruction::I64Load(mem(3)))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::I32Load(mem(2)))
+        .instruction(&Instruction::I32Const(0))
+        .instruction(&Instruction::Call(INPUT));
+    for &local in chunk.iter().rev() {
+        sampler.instruction(&Instruction::LocalSet(local));
+    }
+    for (i, local) in chunk.into_iter().enumerate() {
+        sampler
+            .instruction(&Instruction::LocalGet(local))
+            .instruction(&Instruction::V128Const(lanes(i as f32 * 4.0)))
+            .instruction(&Instruction::F32x4Add);
+    }
+    sampler
+        .instruction(&Instruction::Call(SAMPLE))
+        .instruction(&Instruction::I32Const(0))
+        .instruction(&Instruction::Call(OUTPUT))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::LocalGet(state))
+        .instruction(&Instruction::I32Load(mem(2)))
+        .instruction(&Instruction::I32Const(InternalBufferType::N as i32))
+        .instruction(&Instruction::I32Add)
+        .instruction(&Instruction::I32Store(mem(2)))
+        .instruction(&Instruction::End);
+
+    let mut functions = FunctionSection::new();
+    let mut exports = ExportSection::new();
+    let mut code = CodeSection::new();
+    let mut node_list = Vec::new();
+    for (i, (key, name, func)) in [(SCALE, "scale", scale), (SAMPLER, "sampler", sampler)]
+        .into_iter()
+        .enumerate()
+    {
+        functions.function(node_type);
+        exports.export(name, ExportKind::Func, 3 + i as u32);
+        code.function(&func);
+        postcard_str(&mut node_list, key.as_str());
+        postcard_str(&mut node_list, name);
+    }
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+
+    let mut meta = Vec::new();
+    for s in ["id", "test", "name", "Test"] {
+        postcard_str(&mut meta, s);
+    }
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&functions)
+        .section(&memories)
+        .section(&exports)
+        .section(&code);
+    for (name, data) in [
+        ("cubedaw:plugin_version", b"0.1.0".to_vec()),
+        ("cubedaw:plugin_meta", meta),
+        ("cubedaw:node_list", node_list),
+    ] {
+        module.section(&CustomSection {
+            name: name.into(),
+            data: Cow::Owned(data),
+        });
+    }
+    module.finish()
+}
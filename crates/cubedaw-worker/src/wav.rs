@@ -0,0 +1,206 @@
+//! Decoding WAV files into [`Sample`]s, and writing mono 32-bit float WAV files.
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, bail};
+use cubedaw_lib::Sample;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Decodes a WAV file. Integer PCM of 8 to 32 bits and 32/64-bit float are supported. Channels are mixed down to mono.
+pub fn decode(name: String, bytes: &[u8]) -> Result<Sample> {
+    if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+        bail!("not a WAV file");
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let size = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        // some files have a wrong size for the last chunk
+        let chunk = &rest[8..(8 + size).min(rest.len())];
+        match id {
+            b"fmt " => format = Some(Format::parse(chunk)?),
+            b"data" => data = Some(chunk),
+            _ => (),
+        }
+        // chunks are padded to an even size
+        rest = rest.get(8 + size + size % 2..).unwrap_or(&[]);
+    }
+    let format = format.context("WAV file has no format chunk")?;
+    let data = data.context("WAV file has no data chunk")?;
+
+    let bytes_per_sample = format.bits_per_sample.div_ceil(8) as usize;
+    let frame_size = bytes_per_sample * format.channels as usize;
+    let read_sample: fn(&[u8]) -> f32 = match (format.format, bytes_per_sample) {
+        // 8-bit WAV is unsigned
+        (FORMAT_PCM, 1) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 2) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (FORMAT_PCM, 3) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+        (FORMAT_PCM, 4) => |b| i32::from_le_bytes(b.try_into().unwrap()) as f32 / 2147483648.0,
+        (FORMAT_FLOAT, 4) => |b| f32::from_le_bytes(b.try_into().unwrap()),
+        (FORMAT_FLOAT, 8) => |b| f64::from_le_bytes(b.try_into().unwrap()) as f32,
+        (format_tag, _) => bail!(
+            "unsupported WAV format: format tag {format_tag}, {} bits per sample",
+            format.bits_per_sample
+        ),
+    };
+
+    let gain = 1.0 / format.channels as f32;
+    let samples: Arc<[f32]> = data
+        .chunks_exact(frame_size)
+        .map(|frame| {
+            frame
+                .chunks_exact(bytes_per_sample)
+                .map(read_sample)
+                .sum::<f32>()
+                * gain
+        })
+        .collect();
+
+    Ok(Sample::new(name, format.sample_rate, samples))
+}
+
+/// The length of the header written by [`write_header`]. The samples come right after it.
+pub const HEADER_LEN: u32 = 44;
+
+/// Writes the header of a mono 32-bit float WAV file with `num_samples` samples.
+pub fn write_header(writer: &mut impl Write, sample_rate: u32, num_samples: u32) -> io::Result<()> {
+    let data_len = num_samples * 4;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&FORMAT_FLOAT.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // channels
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 4).to_le_bytes())?; // bytes per second
+    writer.write_all(&4u16.to_le_bytes())?; // bytes per frame
+    writer.write_all(&32u16.to_le_bytes())?; // bits per sample
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    Ok(())
+}
+
+/// Encodes `samples` as a mono 32-bit float WAV file.
+pub fn encode(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN as usize + samples.len() * 4);
+    write_header(&mut bytes, sample_rate, samples.len() as u32)
+        .expect("writing to a Vec can't fail");
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
+
+struct Format {
+    /// [`FORMAT_PCM`] or [`FORMAT_FLOAT`]
+    format: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+impl Format {
+    fn parse(chunk: &[u8]) -> Result<Self> {
+        let u16_at = |i: usize| {
+            chunk
+                .get(i..i + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        };
+        let u32_at = |i: usize| {
+            chunk
+                .get(i..i + 4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        };
+        let (Some(mut format), Some(channels), Some(sample_rate), Some(bits_per_sample)) =
+            (u16_at(0), u16_at(2), u32_at(4), u16_at(14))
+        else {
+            bail!("WAV format chunk is too short");
+        };
+        if format == FORMAT_EXTENSIBLE {
+            // the actual format is the first two bytes of the subformat GUID
+            format = u16_at(24).context("WAV format chunk is too short")?;
+        }
+        if channels == 0 {
+            bail!("WAV file has no channels");
+        }
+        if sample_rate == 0 {
+            bail!("WAV file has a sample rate of 0");
+        }
+        Ok(Self {
+            format,
+            channels,
+            sample_rate,
+            bits_per_sample,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(format: u16, channels: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(4 + 24 + 8 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&format.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        let block_align = channels * bits_per_sample / 8;
+        bytes.extend_from_slice(&(44100 * block_align as u32).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&bits_per_sample.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn decode_formats() {
+        let stereo_16: Vec<u8> = [16384i16, 0, -32768, -32768]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let sample = decode("a".into(), &wav(FORMAT_PCM, 2, 16, &stereo_16)).unwrap();
+        assert_eq!(sample.sample_rate(), 44100);
+        assert_eq!(&**sample.data(), &[0.25, -1.0]);
+
+        let mono_24 = [0x00, 0x00, 0x40, 0x00, 0x00, 0xc0];
+        let sample = decode("b".into(), &wav(FORMAT_PCM, 1, 24, &mono_24)).unwrap();
+        assert_eq!(&**sample.data(), &[0.5, -0.5]);
+
+        let mono_float: Vec<u8> = [0.75f32, -0.125]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let sample = decode("c".into(), &wav(FORMAT_FLOAT, 1, 32, &mono_float)).unwrap();
+        assert_eq!(&**sample.data(), &[0.75, -0.125]);
+
+        assert!(decode("d".into(), &wav(FORMAT_FLOAT, 1, 16, &[0, 0])).is_err());
+        assert!(decode("e".into(), b"RIFF\0\0\0\0AVI ").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let samples = [0.0, 0.5, -1.0, 0.25];
+        let sample = decode("a".into(), &encode(&samples, 1234)).unwrap();
+        assert_eq!(sample.sample_rate(), 1234);
+        assert_eq!(&**sample.data(), &samples);
+    }
+}
@@ -6,18 +6,17 @@ use resourcekey::ResourceKey;
 
 use crate::{
     WorkerJob, WorkerState,
+    alloc_guard::AllocGuard,
     common::{HostToWorkerEvent, WorkerToHostEvent},
-    plugin::standalone::StandalonePluginFactory,
+    plugin::{standalone::StandalonePluginFactory, stitched::StitchCache},
     registry::NodeRegistry,
+    resample::ResampleQuality,
 };
 
 pub fn run_forever(
     tx: crossbeam_channel::Sender<WorkerToHostEvent>,
     rx: crossbeam_channel::Receiver<HostToWorkerEvent>,
 
-    work_tx: crossbeam_channel::Sender<WorkerJob>,
-    work_rx: crossbeam_channel::Receiver<WorkerJob>,
-
     options: &WorkerOptions,
 ) {
     let mut scratch = WorkerScratch::new(options);
@@ -25,24 +24,35 @@ pub fn run_forever(
 
     while let Ok(event) = rx.recv() {
         match event {
-            HostToWorkerEvent::StartProcessing { state, start_pos } => {
+            HostToWorkerEvent::RebuildState => {
+                worker_state = WorkerState::new(options);
+            }
+            HostToWorkerEvent::StartProcessing {
+                state,
+                start_pos,
+                work_tx,
+                work_rx,
+            } => {
+                worker_state.update_stitched_graphs(options);
+
+                let guard = AllocGuard::new("worker job processing");
                 loop {
                     match work_rx.recv() {
                         Ok(WorkerJob::Finalize) => break,
                         Ok(job) => {
-                            let result = match job.process(
+                            let result = job.process(
                                 state,
                                 start_pos,
                                 options,
                                 &mut worker_state,
                                 &mut scratch,
-                            ) {
-                                Ok(res) => res,
-                                Err(err) => {
-                                    let _ = tx.send(WorkerToHostEvent::Error(err));
-                                    return;
-                                }
-                            };
+                                &work_tx,
+                            );
+
+                            if let Some((track_id, error)) = result.error {
+                                tx.send(WorkerToHostEvent::Error { track_id, error })
+                                    .expect("channel closed during processing");
+                            }
 
                             if let Some(job_descriptor) = result.finished_job_descriptor {
                                 tx.send(WorkerToHostEvent::FinishJob(job_descriptor))
@@ -67,6 +77,8 @@ pub fn run_forever(
                         }
                     }
                 }
+                drop(guard);
+                drop((work_tx, work_rx));
                 // Note: as per the documentation of `WorkerToHostEvent::Idle`, workers must send exactly 1 of this event
                 // and must have dropped all references given from work_rx. Not doing this will cause UB.
                 // Just, like, don't be stupid with how the worker is implemented.
@@ -88,12 +100,37 @@ pub struct WorkerOptions {
 
     pub num_workers: u32,
 
+    /// The plugin factories are built for this sample rate, so changing it means creating new options with
+    /// [`Self::with_audio`].
     pub sample_rate: u32,
     pub buffer_size: u32,
+
+    /// Default time constant for parameter smoothing, in milliseconds. Node inputs can override this.
+    pub smoothing_ms: f32,
+
+    /// Whether to compile each node graph into a single wasm module instead of running its nodes one by one.
+    pub stitch_node_graphs: bool,
+    /// The compiled modules, shared by all workers.
+    pub(crate) stitch_cache: StitchCache,
+
+    /// How audio clips and samplers resample audio that was recorded at a different rate or is played back at a
+    /// different speed.
+    pub resample_quality: ResampleQuality,
 }
 
 impl WorkerOptions {
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+    pub const DEFAULT_BUFFER_SIZE: u32 = 512;
+
     pub fn new(registry: Arc<NodeRegistry>) -> Self {
+        Self::with_audio(
+            registry,
+            Self::DEFAULT_SAMPLE_RATE,
+            Self::DEFAULT_BUFFER_SIZE,
+        )
+    }
+    /// Creates options with a custom sample rate and buffer size.
+    pub fn with_audio(registry: Arc<NodeRegistry>, sample_rate: u32, buffer_size: u32) -> Self {
         let mut this = Self {
             standalone_plugin_factories: Default::default(),
 
@@ -104,8 +141,15 @@ impl WorkerOptions {
             //     .unwrap_or(u32::MAX), // just to be safe
             num_workers: 1, // TODO remove
 
-            sample_rate: 44100,
-            buffer_size: 512,
+            sample_rate,
+            buffer_size,
+
+            smoothing_ms: 5.0,
+
+            stitch_node_graphs: true,
+            stitch_cache: StitchCache::new(),
+
+            resample_quality: ResampleQuality::default(),
 
             registry,
         };
@@ -119,11 +163,20 @@ impl WorkerOptions {
             let factory = arc_ptr_to_standalone_plugin_factory
                 .entry(Arc::as_ptr(plugin_data))
                 .or_insert_with(|| {
-                    Arc::new(
-                        StandalonePluginFactory::new(&plugin_data.plugin, &this).expect("todo!()"),
-                    )
+                    StandalonePluginFactory::new(&plugin_data.plugin, &this)
+                        .map(Arc::new)
+                        .inspect_err(|err| {
+                            // nodes from this plugin won't have a factory and get silenced when processed
+                            tracing::error!(
+                                "failed to load plugin {:?}: {err:#}",
+                                plugin_data.plugin.manifest().id
+                            );
+                        })
+                        .ok()
                 });
-            standalone_plugin_factories.insert(key.clone(), factory.clone());
+            if let Some(factory) = factory {
+                standalone_plugin_factories.insert(key.clone(), factory.clone());
+            }
         }
 
         this.standalone_plugin_factories = standalone_plugin_factories;
@@ -1,17 +1,21 @@
 use std::sync::Arc;
 
 use crate::registry::NodeRegistry;
-use cpal::traits::HostTrait;
 use cubedaw_lib::Id;
-use cubedaw_worker::command::ActionDirection;
+use cubedaw_worker::{FrozenTrack, command::ActionDirection};
 use egui_dock::{DockArea, DockState};
 use util::Select;
 
-use crate::{Context, Screen, command::UiStateCommandWrapper, node};
+use crate::{
+    Context, Screen, command::UiStateCommandWrapper, node, state::ephemeral::Freeze,
+    tab::track::ImportedAudio,
+};
 
+pub mod audio_settings;
 pub mod config;
 pub mod context;
 pub mod state;
+pub mod transport;
 pub mod util;
 
 /// `eframe`-compatible app for cubedaw.
@@ -41,6 +45,19 @@ pub struct CubedawApp {
     undo_index: usize,
 
     worker_host: crate::workerhost::WorkerHostHandle,
+    audio_settings: audio_settings::AudioSettings,
+    /// The audio settings dialog, if it's open.
+    audio_settings_window: Option<audio_settings::AudioSettingsWindow>,
+    metronome: cubedaw_worker::Metronome,
+    /// Whether the live input gets recorded while playing. See [`crate::workerhost::WorkerHostHandle::set_recording`].
+    recording: bool,
+
+    /// Finished background renders of frozen tracks. See [`Self::start_freeze`].
+    freeze_rx: std::sync::mpsc::Receiver<(Id<cubedaw_lib::Track>, anyhow::Result<FrozenTrack>)>,
+    freeze_tx: std::sync::mpsc::Sender<(Id<cubedaw_lib::Track>, anyhow::Result<FrozenTrack>)>,
+    /// Audio files decoded in the background. See [`Self::start_import`].
+    import_rx: std::sync::mpsc::Receiver<ImportedAudio>,
+    import_tx: std::sync::mpsc::Sender<ImportedAudio>,
 }
 
 impl CubedawApp {
@@ -101,8 +118,19 @@ impl CubedawApp {
                 &mut ephemeral_state,
             );
 
+            let (freeze_tx, freeze_rx) = std::sync::mpsc::channel();
+            let (import_tx, import_rx) = std::sync::mpsc::channel();
+
             Self {
                 worker_host: crate::workerhost::WorkerHostHandle::new(),
+                audio_settings: Default::default(),
+                audio_settings_window: None,
+                metronome: Default::default(),
+                recording: false,
+                freeze_rx,
+                freeze_tx,
+                import_rx,
+                import_tx,
 
                 state,
                 ui_state,
@@ -144,8 +172,137 @@ impl CubedawApp {
         app
     }
 
+    fn init_worker_host_if_needed(&mut self) {
+        if !self.worker_host.is_init() {
+            self.start_worker_host();
+        }
+    }
+    fn start_worker_host(&mut self) {
+        self.worker_host.init(
+            self.state.clone(),
+            self.audio_settings
+                .worker_options(self.node_registry.inner().clone()),
+        );
+        self.worker_host.set_output(self.audio_settings.output());
+        self.worker_host.set_input(self.audio_settings.input());
+        self.worker_host.set_metronome(self.metronome);
+        self.worker_host.set_recording(self.recording);
+    }
+    fn apply_audio_settings(&mut self, settings: audio_settings::AudioSettings) {
+        let needs_restart = self.worker_host.is_init()
+            && (settings.sample_rate != self.audio_settings.sample_rate
+                || settings.buffer_size != self.audio_settings.buffer_size
+                || settings.resample_quality != self.audio_settings.resample_quality);
+        self.audio_settings = settings;
+        if needs_restart {
+            // the new host doesn't know about any of the old one's probes or frozen tracks
+            // (and frozen tracks would have to be rendered again at the new sample rate anyway)
+            self.start_worker_host();
+            self.ephemeral_state.freezes.clear();
+            for (track_id, track) in &self.ephemeral_state.tracks {
+                for (node_id, node) in &track.patch.nodes {
+                    if let Some(ref probe) = node.probe {
+                        self.worker_host
+                            .set_probe(track_id, node_id, Some(probe.clone()));
+                    }
+                }
+            }
+        } else if self.worker_host.is_init() {
+            self.worker_host.set_output(self.audio_settings.output());
+            self.worker_host.set_input(self.audio_settings.input());
+        }
+    }
+
+    fn toggle_playback(&mut self, now: std::time::Instant) {
+        self.init_worker_host_if_needed();
+        if !self.worker_host.is_playing() {
+            self.worker_host.reset();
+            self.worker_host
+                .start_processing(self.ui_state.playhead_pos);
+        } else {
+            if let Some(last_playhead_update) = self.worker_host.last_playhead_update() {
+                self.ui_state.playhead_pos = self
+                    .state
+                    .add_time_to_position(last_playhead_update.0, now - last_playhead_update.1)
+                    .round_to_song_pos();
+            }
+            self.worker_host.stop_processing();
+        }
+    }
+
+    /// Renders a track in the background. It's frozen once [`Self::finish_freeze`] gets the render.
+    fn start_freeze(&mut self, track_id: Id<cubedaw_lib::Track>) {
+        if self.ephemeral_state.freezes.get(track_id) == Some(&Freeze::Rendering) {
+            return;
+        }
+        self.ephemeral_state
+            .freezes
+            .insert(track_id, Freeze::Rendering);
+
+        let state = self.state.clone();
+        let audio_settings = self.audio_settings.clone();
+        let registry = self.node_registry.inner().clone();
+        let tx = self.freeze_tx.clone();
+        std::thread::Builder::new()
+            .name(format!("cubedaw freeze {track_id:?}"))
+            .spawn(move || {
+                let options = audio_settings.worker_options(registry);
+                let result = cubedaw_worker::offline::freeze(&state, options, track_id);
+                // the app might've closed in the meantime
+                let _ = tx.send((track_id, result));
+            })
+            .expect("failed to spawn freeze thread");
+    }
+    fn finish_freeze(
+        &mut self,
+        track_id: Id<cubedaw_lib::Track>,
+        result: anyhow::Result<FrozenTrack>,
+    ) {
+        // the track was unfrozen (or the audio settings changed) while it was rendering
+        if self.ephemeral_state.freezes.get(track_id) != Some(&Freeze::Rendering) {
+            return;
+        }
+        match result {
+            Ok(frozen) => {
+                self.ephemeral_state.freezes.insert(
+                    track_id,
+                    Freeze::Frozen {
+                        fingerprint: frozen.fingerprint,
+                    },
+                );
+                self.init_worker_host_if_needed();
+                self.worker_host.set_frozen(track_id, Some(frozen));
+            }
+            Err(error) => {
+                self.ephemeral_state.freezes.remove(track_id);
+                self.worker_host
+                    .push_error(crate::workerhost::WorkerHostError {
+                        track_id: Some(track_id),
+                        message: format!("failed to freeze track: {error:#}"),
+                    });
+            }
+        }
+    }
+
+    /// Decodes dropped audio files in the background. They're added to the track once they're decoded.
+    fn start_import(&mut self, event: context::ImportEvent) {
+        let tx = self.import_tx.clone();
+        std::thread::Builder::new()
+            .name("cubedaw import".into())
+            .spawn(move || {
+                let samples = crate::tab::track::decode_audio_files(&event.files);
+                // the app might've closed in the meantime
+                let _ = tx.send(ImportedAudio {
+                    track_id: event.track_id,
+                    start_pos: event.start_pos,
+                    samples,
+                });
+            })
+            .expect("failed to spawn import thread");
+    }
+
     fn ctx_finished(&mut self, result: crate::context::ContextResult, egui_ctx: &egui::Context) {
-        use context::DockEvent;
+        use context::{DockEvent, LiveNoteEvent};
 
         for event in result.dock_events {
             match event {
@@ -172,6 +329,46 @@ impl CubedawApp {
             }
         }
 
+        if !result.live_note_events.is_empty() || !result.probe_events.is_empty() {
+            self.init_worker_host_if_needed();
+        }
+        for event in result.live_note_events {
+            match event {
+                LiveNoteEvent::Start {
+                    track_id,
+                    note_id,
+                    pitch,
+                } => self.worker_host.start_live_note(
+                    track_id,
+                    note_id,
+                    cubedaw_lib::Note::new(0, pitch),
+                ),
+                LiveNoteEvent::Stop { track_id, note_id } => {
+                    self.worker_host.stop_live_note(track_id, note_id)
+                }
+            }
+        }
+        for event in result.probe_events {
+            self.worker_host
+                .set_probe(event.track_id, event.node_id, event.probe);
+        }
+        for error in result.errors {
+            self.worker_host.push_error(error);
+        }
+        for event in result.import_events {
+            self.start_import(event);
+        }
+        for event in result.freeze_events {
+            if event.frozen {
+                self.start_freeze(event.track_id);
+            } else {
+                self.ephemeral_state.freezes.remove(event.track_id);
+                if self.worker_host.is_init() {
+                    self.worker_host.set_frozen(event.track_id, None);
+                }
+            }
+        }
+
         'handle_tracker: {
             let crate::context::UiStateTrackerResult {
                 mut commands,
@@ -231,8 +428,16 @@ impl eframe::App for CubedawApp {
         self.last_frame_time = time;
 
         self.worker_host.handle_events();
+        if let Some(levels) = self.worker_host.levels() {
+            self.ephemeral_state.meters.update(levels, time);
+        }
+        while let Ok((track_id, result)) = self.freeze_rx.try_recv() {
+            self.finish_freeze(track_id, result);
+        }
+        let recordings = self.worker_host.take_recordings();
+        let imports: Vec<_> = self.import_rx.try_iter().collect();
 
-        let ctx =
+        let mut ctx =
             Context::new(
                 &self.state,
                 &self.ui_state,
@@ -250,7 +455,14 @@ impl eframe::App for CubedawApp {
                     None
                 },
             );
+        for recording in recordings {
+            crate::tab::track::add_recording(&mut ctx, recording);
+        }
+        for imported in imports {
+            crate::tab::track::add_imported_audio(&mut ctx, imported);
+        }
 
+        let mut transport_action = None;
         egui::TopBottomPanel::top("top_panel").show(egui_ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
@@ -258,6 +470,12 @@ impl eframe::App for CubedawApp {
                     if ui.button("Quit").clicked() {
                         egui_ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
+                    if ui.button("Audio Settings...").clicked() {
+                        self.audio_settings_window = Some(
+                            audio_settings::AudioSettingsWindow::new(self.audio_settings.clone()),
+                        );
+                        ui.close_menu();
+                    }
                     let _ = ui.button("Do nothing");
                     if ui.button("Panic! (this will crash the app)").clicked() {
                         panic!("PANIC!!!!!");
@@ -282,8 +500,48 @@ impl eframe::App for CubedawApp {
                         ui.close_menu();
                     }
                 });
-                #[cfg(debug_assertions)]
+                ui.separator();
+                let old_metronome = self.metronome;
+                let old_recording = self.recording;
+                transport_action =
+                    transport::transport_ui(ui, &mut ctx, &mut self.metronome, &mut self.recording);
+                if self.metronome != old_metronome && self.worker_host.is_init() {
+                    self.worker_host.set_metronome(self.metronome);
+                }
+                if self.recording != old_recording && self.worker_host.is_init() {
+                    self.worker_host.set_recording(self.recording);
+                }
+                ui.separator();
+                if !self.worker_host.errors().is_empty() {
+                    let text = egui::RichText::new(format!(
+                        "⚠ {} errors",
+                        self.worker_host.errors().len()
+                    ))
+                    .color(ui.visuals().error_fg_color);
+                    ui.menu_button(text, |ui| {
+                        for error in self.worker_host.errors() {
+                            let track_name = error
+                                .track_id
+                                .and_then(|track_id| ctx.ui_state.tracks.get(track_id))
+                                .map_or("<unknown track>", |track_ui| &track_ui.name);
+                            ui.label(format!("{track_name}: {}", error.message));
+                        }
+                        ui.separator();
+                        if ui.button("Clear").clicked() {
+                            self.worker_host.clear_errors();
+                            ui.close_menu();
+                        }
+                    });
+                }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.add_sized(
+                        [120.0, 6.0],
+                        crate::widget::LevelMeter::new(ctx.ephemeral_state.meters.master),
+                    );
+                    ui.label("Master");
+                    ui.separator();
+                    performance_ui(ui, &mut self.worker_host);
+                    #[cfg(debug_assertions)]
                     egui::warn_if_debug_build(ui);
                 });
             });
@@ -309,37 +567,36 @@ impl eframe::App for CubedawApp {
         }
         self.ctx_finished(result, egui_ctx);
 
+        if let Some(ref mut window) = self.audio_settings_window {
+            let mut open = true;
+            let applied = window.show(egui_ctx, &mut open);
+            if !open {
+                self.audio_settings_window = None;
+            }
+            if let Some(settings) = applied {
+                self.apply_audio_settings(settings);
+            }
+        }
+
         let now = std::time::Instant::now();
 
         // global key commands
 
         // TODO implement configurable keymaps
         if egui_ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Space)) {
-            if !self.worker_host.is_init() {
-                // TODO change/make configurable/whatever
-                self.worker_host.init(
-                    self.state.clone(),
-                    cubedaw_worker::WorkerOptions::new(self.node_registry.inner().clone()),
-                );
-                self.worker_host.set_device(Some(
-                    cpal::default_host()
-                        .default_output_device()
-                        .expect("no default output device. sorry!"),
-                ));
-            }
-            if !self.worker_host.is_playing() {
-                self.worker_host.reset();
-                self.worker_host
-                    .start_processing(self.ui_state.playhead_pos);
-            } else {
-                if let Some(last_playhead_update) = self.worker_host.last_playhead_update() {
-                    self.ui_state.playhead_pos = self
-                        .state
-                        .add_time_to_position(last_playhead_update.0, now - last_playhead_update.1)
-                        .round_to_song_pos();
+            transport_action = Some(transport::TransportAction::TogglePlayback);
+        }
+        match transport_action {
+            Some(transport::TransportAction::TogglePlayback) => self.toggle_playback(now),
+            Some(transport::TransportAction::ReturnToStart) => {
+                self.ui_state.playhead_pos = self.state.song_boundary.start;
+                if self.worker_host.is_playing() {
+                    self.worker_host.reset();
+                    self.worker_host
+                        .start_processing(self.ui_state.playhead_pos);
                 }
-                self.worker_host.stop_processing();
             }
+            None => (),
         }
 
         // undo system
@@ -384,12 +641,50 @@ impl eframe::App for CubedawApp {
         }
 
         // final stuff
-        if self.worker_host.is_playing() {
+        if self.worker_host.is_playing() || self.ephemeral_state.meters.is_active(now) {
             egui_ctx.request_repaint();
         }
     }
 }
 
+/// The DSP load/xrun indicator in the top bar.
+fn performance_ui(ui: &mut egui::Ui, worker_host: &mut crate::workerhost::WorkerHostHandle) {
+    /// How long the indicator stays red after an xrun.
+    const XRUN_HOLD: std::time::Duration = std::time::Duration::from_secs(2);
+    /// The load above which the indicator turns red.
+    const HIGH_LOAD: f32 = 0.8;
+
+    if !worker_host.is_init() {
+        return;
+    }
+    let performance = worker_host.performance();
+    let recent_xrun = performance
+        .last_xrun
+        .is_some_and(|last_xrun| last_xrun.elapsed() < XRUN_HOLD);
+
+    let mut text = format!("CPU {:.0}%", performance.load * 100.0);
+    if performance.xruns > 0 {
+        text += &format!(" | {} xruns", performance.xruns);
+    }
+    let mut text = egui::RichText::new(text).monospace();
+    if recent_xrun || performance.load > HIGH_LOAD {
+        text = text.color(ui.visuals().error_fg_color);
+    }
+    ui.menu_button(text, |ui| {
+        ui.label(format!("Peak load: {:.0}%", performance.peak_load * 100.0));
+        ui.label(format!("Xruns: {}", performance.xruns));
+        ui.separator();
+        if ui.button("Reset").clicked() {
+            worker_host.reset_performance();
+            ui.close_menu();
+        }
+    });
+
+    // keep the load readout (and the xrun highlight) up to date even when nothing else is happening
+    ui.ctx()
+        .request_repaint_after(std::time::Duration::from_millis(500));
+}
+
 pub type Tab = Box<dyn Screen>;
 
 pub struct CubedawTabViewer<'a> {
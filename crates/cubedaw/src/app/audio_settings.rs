@@ -0,0 +1,412 @@
+use std::{path::PathBuf, sync::Arc};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cubedaw_worker::{NodeRegistry, WorkerOptions, resample::ResampleQuality};
+
+use crate::workerhost::{AudioInput, AudioOutput};
+
+/// Sample rates offered in the settings, if the device supports them.
+const COMMON_SAMPLE_RATES: [u32; 6] = [22050, 44100, 48000, 88200, 96000, 192000];
+/// Buffer sizes offered in the settings, if the device supports them.
+const COMMON_BUFFER_SIZES: [u32; 8] = [32, 64, 128, 256, 512, 1024, 2048, 4096];
+
+/// Where the audio goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceChoice {
+    /// The host's default output device.
+    Default,
+    /// The output device with this name.
+    Named(String),
+    /// A WAV file. See [`AudioOutput::File`].
+    File(PathBuf),
+    /// Nowhere.
+    Silent,
+}
+
+/// Where the live input comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputChoice {
+    /// Nothing.
+    None,
+    /// The host's default input device.
+    Default,
+    /// The input device with this name.
+    Named(String),
+    /// A WAV file. See [`AudioInput::File`].
+    File(PathBuf),
+}
+
+/// The audio settings the worker host runs with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioSettings {
+    /// The audio host (ALSA, JACK, WASAPI, etc.), or `None` for the platform default.
+    pub host: Option<cpal::HostId>,
+    pub device: DeviceChoice,
+    pub input: InputChoice,
+    pub sample_rate: u32,
+    pub buffer_size: u32,
+    /// See [`AudioOutput::Device`].
+    pub queued_buffers: u32,
+    /// See [`WorkerOptions::resample_quality`].
+    pub resample_quality: ResampleQuality,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            // CUBEDAW_OUTPUT_FILE records playback to a file instead, e.g. on machines without an audio device
+            device: match std::env::var_os("CUBEDAW_OUTPUT_FILE") {
+                Some(path) => DeviceChoice::File(path.into()),
+                None => DeviceChoice::Default,
+            },
+            // likewise, CUBEDAW_INPUT_FILE plays a file on a loop as the input
+            input: match std::env::var_os("CUBEDAW_INPUT_FILE") {
+                Some(path) => InputChoice::File(path.into()),
+                None => InputChoice::None,
+            },
+            sample_rate: WorkerOptions::DEFAULT_SAMPLE_RATE,
+            buffer_size: WorkerOptions::DEFAULT_BUFFER_SIZE,
+            queued_buffers: 16,
+            resample_quality: ResampleQuality::default(),
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn worker_options(&self, registry: Arc<NodeRegistry>) -> WorkerOptions {
+        let mut options = WorkerOptions::with_audio(registry, self.sample_rate, self.buffer_size);
+        options.resample_quality = self.resample_quality;
+        options
+    }
+
+    /// Finds the chosen device. If it doesn't exist (anymore), this plays back silently.
+    pub fn output(&self) -> AudioOutput {
+        let host = self.cpal_host();
+        let device = match self.device {
+            DeviceChoice::Default => host.default_output_device(),
+            DeviceChoice::Named(ref name) => host.output_devices().ok().and_then(|mut devices| {
+                devices.find(|device| device.name().is_ok_and(|other| other == *name))
+            }),
+            DeviceChoice::File(ref path) => return AudioOutput::File(path.clone()),
+            DeviceChoice::Silent => return AudioOutput::Null,
+        };
+        match device {
+            Some(device) => AudioOutput::Device {
+                device,
+                queued_buffers: self.queued_buffers,
+            },
+            None => {
+                tracing::warn!("audio output device not found; playing back silently");
+                AudioOutput::Null
+            }
+        }
+    }
+
+    /// Finds the chosen input device. If it doesn't exist (anymore), the input is silent.
+    pub fn input(&self) -> AudioInput {
+        let host = self.cpal_host();
+        let device = match self.input {
+            InputChoice::None => return AudioInput::Null,
+            InputChoice::Default => host.default_input_device(),
+            InputChoice::Named(ref name) => host.input_devices().ok().and_then(|mut devices| {
+                devices.find(|device| device.name().is_ok_and(|other| other == *name))
+            }),
+            InputChoice::File(ref path) => return AudioInput::File(path.clone()),
+        };
+        match device {
+            Some(device) => AudioInput::Device(device),
+            None => {
+                tracing::warn!("audio input device not found; the input will be silent");
+                AudioInput::Null
+            }
+        }
+    }
+
+    fn cpal_host(&self) -> cpal::Host {
+        self.host.map_or_else(cpal::default_host, |host_id| {
+            cpal::host_from_id(host_id).unwrap_or_else(|_| {
+                tracing::warn!("audio host {} is unavailable", host_id.name());
+                cpal::default_host()
+            })
+        })
+    }
+
+    /// How long it takes for audio to get from the worker host to the speakers, at most.
+    pub fn latency_ms(&self) -> f32 {
+        let buffers = match self.device {
+            DeviceChoice::Default | DeviceChoice::Named(_) => self.queued_buffers + 1,
+            DeviceChoice::File(_) | DeviceChoice::Silent => 1,
+        };
+        (buffers * self.buffer_size) as f32 / self.sample_rate as f32 * 1000.0
+    }
+}
+
+/// What a device says it supports.
+struct DeviceInfo {
+    name: String,
+    configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl DeviceInfo {
+    fn supports_sample_rate(&self, sample_rate: u32) -> bool {
+        self.configs.iter().any(|config| {
+            (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&sample_rate)
+        })
+    }
+    fn supports_buffer_size(&self, buffer_size: u32) -> bool {
+        self.configs
+            .iter()
+            .any(|config| match *config.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } => (min..=max).contains(&buffer_size),
+                cpal::SupportedBufferSize::Unknown => true,
+            })
+    }
+}
+
+/// The audio settings dialog. Changes only take effect once they're applied.
+pub struct AudioSettingsWindow {
+    settings: AudioSettings,
+
+    hosts: Vec<cpal::HostId>,
+    /// The devices of `settings.host`. Enumerating devices is slow, so this is only done when the host changes.
+    devices: Vec<DeviceInfo>,
+    default_device_name: Option<String>,
+    /// The names of the input devices of `settings.host`.
+    input_devices: Vec<String>,
+    default_input_name: Option<String>,
+}
+
+impl AudioSettingsWindow {
+    pub fn new(settings: AudioSettings) -> Self {
+        let mut this = Self {
+            settings,
+            hosts: cpal::available_hosts(),
+            devices: Vec::new(),
+            default_device_name: None,
+            input_devices: Vec::new(),
+            default_input_name: None,
+        };
+        this.refresh_devices();
+        this
+    }
+
+    fn refresh_devices(&mut self) {
+        let host = match self.settings.host {
+            Some(host_id) => cpal::host_from_id(host_id).ok(),
+            None => Some(cpal::default_host()),
+        };
+        let Some(host) = host else {
+            self.devices.clear();
+            self.default_device_name = None;
+            self.input_devices.clear();
+            self.default_input_name = None;
+            return;
+        };
+
+        self.default_device_name = host
+            .default_output_device()
+            .and_then(|device| device.name().ok());
+        self.devices = host
+            .output_devices()
+            .map(|devices| {
+                devices
+                    .filter_map(|device| {
+                        Some(DeviceInfo {
+                            name: device.name().ok()?,
+                            configs: device
+                                .supported_output_configs()
+                                .map(Iterator::collect)
+                                .unwrap_or_default(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.default_input_name = host
+            .default_input_device()
+            .and_then(|device| device.name().ok());
+        self.input_devices = host
+            .input_devices()
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_default();
+    }
+
+    /// The info of the chosen device, if it's a device and it exists.
+    fn device_info(&self) -> Option<&DeviceInfo> {
+        let name = match self.settings.device {
+            DeviceChoice::Default => self.default_device_name.as_ref()?,
+            DeviceChoice::Named(ref name) => name,
+            DeviceChoice::File(_) | DeviceChoice::Silent => return None,
+        };
+        self.devices.iter().find(|device| device.name == *name)
+    }
+
+    /// Shows the window. Returns the new settings when they're applied.
+    pub fn show(&mut self, egui_ctx: &egui::Context, open: &mut bool) -> Option<AudioSettings> {
+        let mut applied = None;
+        egui::Window::new("Audio Settings")
+            .open(open)
+            .resizable(false)
+            .collapsible(false)
+            .show(egui_ctx, |ui| {
+                egui::Grid::new("audio_settings")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        self.grid_ui(ui);
+                    });
+
+                ui.label(format!("Latency: {:.1} ms", self.settings.latency_ms()));
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        applied = Some(self.settings.clone());
+                    }
+                    if ui.button("Refresh devices").clicked() {
+                        self.refresh_devices();
+                    }
+                });
+            });
+        applied
+    }
+
+    fn grid_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Host");
+        let host_name = |host: Option<cpal::HostId>| match host {
+            Some(host_id) => host_id.name().to_owned(),
+            None => format!("Default ({})", cpal::default_host().id().name()),
+        };
+        let old_host = self.settings.host;
+        egui::ComboBox::from_id_salt("host")
+            .selected_text(host_name(self.settings.host))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.settings.host, None, host_name(None));
+                for &host_id in &self.hosts {
+                    ui.selectable_value(&mut self.settings.host, Some(host_id), host_id.name());
+                }
+            });
+        if self.settings.host != old_host {
+            self.refresh_devices();
+        }
+        ui.end_row();
+
+        ui.label("Output");
+        let device_name = |device: &DeviceChoice| match device {
+            DeviceChoice::Default => match self.default_device_name {
+                Some(ref name) => format!("Default ({name})"),
+                None => "Default".into(),
+            },
+            DeviceChoice::Named(name) => name.clone(),
+            DeviceChoice::File(path) => format!("File ({})", path.display()),
+            DeviceChoice::Silent => "None (silent)".into(),
+        };
+        let mut choices = vec![DeviceChoice::Default];
+        choices.extend(
+            self.devices
+                .iter()
+                .map(|device| DeviceChoice::Named(device.name.clone())),
+        );
+        if let DeviceChoice::File(_) = self.settings.device {
+            choices.push(self.settings.device.clone());
+        }
+        choices.push(DeviceChoice::Silent);
+        let mut device = self.settings.device.clone();
+        egui::ComboBox::from_id_salt("device")
+            .selected_text(device_name(&device))
+            .width(240.0)
+            .show_ui(ui, |ui| {
+                for choice in choices {
+                    let text = device_name(&choice);
+                    ui.selectable_value(&mut device, choice, text);
+                }
+            });
+        self.settings.device = device;
+        ui.end_row();
+
+        ui.label("Input")
+            .on_hover_text("What the Audio Input node outputs, and what gets recorded.");
+        let input_name = |input: &InputChoice| match input {
+            InputChoice::None => "None".into(),
+            InputChoice::Default => match self.default_input_name {
+                Some(ref name) => format!("Default ({name})"),
+                None => "Default".into(),
+            },
+            InputChoice::Named(name) => name.clone(),
+            InputChoice::File(path) => format!("File ({})", path.display()),
+        };
+        let mut choices = vec![InputChoice::None, InputChoice::Default];
+        choices.extend(
+            self.input_devices
+                .iter()
+                .map(|name| InputChoice::Named(name.clone())),
+        );
+        if let InputChoice::File(_) = self.settings.input {
+            choices.push(self.settings.input.clone());
+        }
+        let mut input = self.settings.input.clone();
+        egui::ComboBox::from_id_salt("input")
+            .selected_text(input_name(&input))
+            .width(240.0)
+            .show_ui(ui, |ui| {
+                for choice in choices {
+                    let text = input_name(&choice);
+                    ui.selectable_value(&mut input, choice, text);
+                }
+            });
+        self.settings.input = input;
+        ui.end_row();
+
+        let device_info = self.device_info();
+
+        ui.label("Sample rate");
+        let mut sample_rate = self.settings.sample_rate;
+        egui::ComboBox::from_id_salt("sample_rate")
+            .selected_text(format!("{sample_rate} Hz"))
+            .show_ui(ui, |ui| {
+                for rate in COMMON_SAMPLE_RATES {
+                    if device_info.is_none_or(|info| info.supports_sample_rate(rate)) {
+                        ui.selectable_value(&mut sample_rate, rate, format!("{rate} Hz"));
+                    }
+                }
+            });
+        ui.end_row();
+
+        ui.label("Buffer size");
+        let mut buffer_size = self.settings.buffer_size;
+        egui::ComboBox::from_id_salt("buffer_size")
+            .selected_text(format!("{buffer_size} samples"))
+            .show_ui(ui, |ui| {
+                for size in COMMON_BUFFER_SIZES {
+                    if device_info.is_none_or(|info| info.supports_buffer_size(size)) {
+                        ui.selectable_value(&mut buffer_size, size, format!("{size} samples"));
+                    }
+                }
+            });
+        ui.end_row();
+
+        ui.label("Queued buffers")
+            .on_hover_text("More buffers means fewer dropouts, but more latency.");
+        ui.add(egui::DragValue::new(&mut self.settings.queued_buffers).range(1..=64));
+        ui.end_row();
+
+        ui.label("Resampling").on_hover_text(
+            "How audio clips and samplers play audio recorded at other sample rates.",
+        );
+        egui::ComboBox::from_id_salt("resample_quality")
+            .selected_text(self.settings.resample_quality.name())
+            .show_ui(ui, |ui| {
+                for quality in ResampleQuality::ALL {
+                    ui.selectable_value(
+                        &mut self.settings.resample_quality,
+                        quality,
+                        quality.name(),
+                    );
+                }
+            });
+        ui.end_row();
+
+        self.settings.sample_rate = sample_rate;
+        self.settings.buffer_size = buffer_size;
+    }
+}
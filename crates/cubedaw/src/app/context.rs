@@ -1,6 +1,6 @@
 use std::any::Any;
 
-use cubedaw_lib::{Id, IdMap, PreciseSongPos, State};
+use cubedaw_lib::{Id, IdMap, Node, Note, PreciseSongPos, State, Track};
 
 use crate::{
     EphemeralState, Screen, UiState,
@@ -43,6 +43,12 @@ pub struct Context<'a> {
     time_since_last_frame: f32,
 
     currently_playing_playhead_pos: Option<PreciseSongPos>,
+
+    live_note_events: Vec<LiveNoteEvent>,
+    probe_events: Vec<ProbeEvent>,
+    freeze_events: Vec<FreezeEvent>,
+    import_events: Vec<ImportEvent>,
+    errors: Vec<crate::workerhost::WorkerHostError>,
 }
 
 impl<'a> Context<'a> {
@@ -71,6 +77,12 @@ impl<'a> Context<'a> {
             time_since_last_frame,
 
             currently_playing_playhead_pos,
+
+            live_note_events: Vec::new(),
+            probe_events: Vec::new(),
+            freeze_events: Vec::new(),
+            import_events: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -95,12 +107,73 @@ impl<'a> Context<'a> {
         }
     }
 
+    /// Starts playing a note on a track immediately, without adding it to the state. Returns the id to pass to [`Self::stop_live_note`].
+    pub fn start_live_note(&mut self, track_id: Id<Track>, pitch: i32) -> Id<Note> {
+        let note_id = Id::arbitrary();
+        self.live_note_events.push(LiveNoteEvent::Start {
+            track_id,
+            note_id,
+            pitch,
+        });
+        note_id
+    }
+    pub fn stop_live_note(&mut self, track_id: Id<Track>, note_id: Id<Note>) {
+        self.live_note_events
+            .push(LiveNoteEvent::Stop { track_id, note_id });
+    }
+
+    /// Sets or removes the probe on a node, replacing whatever probe the node had before.
+    pub fn set_probe(
+        &mut self,
+        track_id: Id<Track>,
+        node_id: Id<Node>,
+        probe: Option<cubedaw_worker::Probe>,
+    ) {
+        self.probe_events.push(ProbeEvent {
+            track_id,
+            node_id,
+            probe,
+        });
+    }
+
+    /// Freezes a track (rendering it in the background) or unfreezes it. See [`crate::EphemeralState::freeze_state`].
+    pub fn set_frozen(&mut self, track_id: Id<Track>, frozen: bool) {
+        self.freeze_events.push(FreezeEvent { track_id, frozen });
+    }
+
+    /// Imports audio files as audio clips on a track, starting at `start_pos`. They're decoded in the background, so the
+    /// clips show up a bit later.
+    pub fn import_audio_files(
+        &mut self,
+        track_id: Id<Track>,
+        start_pos: i64,
+        files: Vec<egui::DroppedFile>,
+    ) {
+        self.import_events.push(ImportEvent {
+            track_id,
+            start_pos,
+            files,
+        });
+    }
+
+    /// Shows an error to the user, alongside the errors from processing.
+    pub fn report_error(&mut self, track_id: Option<Id<Track>>, message: String) {
+        tracing::error!("{message}");
+        self.errors
+            .push(crate::workerhost::WorkerHostError { track_id, message });
+    }
+
     pub fn finish(mut self) -> ContextResult {
         self.ephemeral_state
             .on_frame_end(self.state, self.ui_state, &mut self.tracker);
         ContextResult {
             dock_events: core::mem::take(&mut self.tabs.dock_events),
             tracker: self.tracker.finish(),
+            live_note_events: self.live_note_events,
+            probe_events: self.probe_events,
+            freeze_events: self.freeze_events,
+            import_events: self.import_events,
+            errors: self.errors,
         }
     }
 }
@@ -170,9 +243,47 @@ pub enum DockEvent {
     RemoveTabFromMap(Id<Tab>),
 }
 
+#[derive(Debug)]
+pub enum LiveNoteEvent {
+    Start {
+        track_id: Id<Track>,
+        note_id: Id<Note>,
+        pitch: i32,
+    },
+    Stop {
+        track_id: Id<Track>,
+        note_id: Id<Note>,
+    },
+}
+
+#[derive(Debug)]
+pub struct ProbeEvent {
+    pub track_id: Id<Track>,
+    pub node_id: Id<Node>,
+    pub probe: Option<cubedaw_worker::Probe>,
+}
+
+#[derive(Debug)]
+pub struct FreezeEvent {
+    pub track_id: Id<Track>,
+    pub frozen: bool,
+}
+
+#[derive(Debug)]
+pub struct ImportEvent {
+    pub track_id: Id<Track>,
+    pub start_pos: i64,
+    pub files: Vec<egui::DroppedFile>,
+}
+
 pub struct ContextResult {
     pub dock_events: Vec<DockEvent>,
     pub tracker: UiStateTrackerResult,
+    pub live_note_events: Vec<LiveNoteEvent>,
+    pub probe_events: Vec<ProbeEvent>,
+    pub freeze_events: Vec<FreezeEvent>,
+    pub import_events: Vec<ImportEvent>,
+    pub errors: Vec<crate::workerhost::WorkerHostError>,
 }
 
 #[derive(Default)]
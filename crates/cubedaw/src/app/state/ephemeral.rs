@@ -11,10 +11,11 @@ use crate::{
         node::{NodeAddOrRemove, NodeSelect, UiNodeMove},
         note::{NoteAddOrRemove, NoteMove},
         patch::CableAddOrRemove,
-        track::TrackAddOrRemove,
+        track::{TrackAddOrRemove, TrackMove},
     },
     context::UiStateTracker,
     util::{DragHandler, NodeSearch, SelectionRect},
+    widget::Meters,
 };
 
 #[derive(Debug)]
@@ -22,6 +23,8 @@ pub struct EphemeralState {
     pub note_drag: DragHandler<(Id<Track>, Id<Clip>, Id<Note>)>,
     pub clip_drag: DragHandler<(Id<Track>, Id<Clip>)>,
     pub track_drag: DragHandler<Id<Track>>,
+    /// Where the tracks being dragged would go if they were dropped now. Set by the track tab.
+    pub track_drop_target: Option<TrackDropTarget>,
 
     pub tracks: IdMap<Track, TrackEphemeralState>,
 
@@ -29,8 +32,61 @@ pub struct EphemeralState {
 
     pub node_search: NodeSearch,
 
+    /// Level meters, updated from the worker host every frame.
+    pub meters: Meters,
+
+    /// Tracks that are frozen or being frozen. See [`Self::freeze_state`].
+    pub freezes: IdMap<Track, Freeze>,
+    /// Tracks that recordings are put on.
+    pub record_armed: IdSet<Track>,
+
     _private: private::Private,
 }
+/// A place in the track tree that tracks can be moved to. See [`TrackMove`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackDropTarget {
+    pub parent: Id<Track>,
+    /// The sibling the tracks go after, or `None` to go first.
+    pub after: Option<Id<Track>>,
+}
+
+impl TrackDropTarget {
+    /// Whether moving `track_id` here would leave it where it already is.
+    fn is_where(self, ui_state: &UiState, track_id: Id<Track>) -> bool {
+        let Some(siblings) = ui_state
+            .tracks
+            .get(self.parent)
+            .map(|track_ui| &track_ui.track_list)
+        else {
+            return false;
+        };
+        let Some(pos) = siblings.iter().position(|&id| id == track_id) else {
+            return false;
+        };
+        match self.after {
+            None => pos == 0,
+            Some(after) => pos > 0 && siblings[pos - 1] == after,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freeze {
+    /// The track is being rendered in the background.
+    Rendering,
+    /// The worker host has the track's rendered audio. `fingerprint` is the [`State::track_fingerprint`] it was rendered at.
+    Frozen { fingerprint: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreezeState {
+    Unfrozen,
+    /// The track is unfrozen and can't be frozen as it's routed now.
+    Blocked(cubedaw_worker::offline::FreezeBlocker),
+    Rendering,
+    Frozen,
+}
+
 mod private {
     #[derive(Clone, Copy, Debug)]
     pub struct Private;
@@ -42,14 +98,39 @@ impl EphemeralState {
             note_drag: Default::default(),
             clip_drag: Default::default(),
             track_drag: Default::default(),
+            track_drop_target: None,
             tracks: Default::default(),
             selection_rect: Default::default(),
             node_search: Default::default(),
+            meters: Default::default(),
+            freezes: Default::default(),
+            record_armed: Default::default(),
 
             _private: private::Private,
         }
     }
 
+    pub fn freeze_state(&self, state: &State, track_id: Id<Track>) -> FreezeState {
+        if self.freezes.get(track_id) == Some(&Freeze::Rendering) {
+            return FreezeState::Rendering;
+        }
+        if !state.tracks.has(track_id) {
+            return FreezeState::Unfrozen;
+        }
+        // the worker host unfreezes tracks by itself when they're edited or rerouted
+        if let Some(blocker) = cubedaw_worker::offline::freeze_blocker(state, track_id) {
+            return FreezeState::Blocked(blocker);
+        }
+        match self.freezes.get(track_id) {
+            Some(&Freeze::Frozen { fingerprint })
+                if state.track_fingerprint(track_id) == fingerprint =>
+            {
+                FreezeState::Frozen
+            }
+            _ => FreezeState::Unfrozen,
+        }
+    }
+
     pub fn on_frame_end(
         &mut self,
         state: &State,
@@ -71,7 +152,8 @@ impl EphemeralState {
                 }
                 let track_ui = ui_state.tracks.force_get(track_id);
                 if !track_ui.closed {
-                    for &track_id in &track_ui.track_list {
+                    // reversed so they come off the stack in order
+                    for &track_id in track_ui.track_list.iter().rev() {
                         track_stack.push(track_id);
                     }
                 }
@@ -109,19 +191,43 @@ impl EphemeralState {
                     tracker.add(TrackSelect::new(track_id, selected));
                 }
             }
-            if let Some(finished_drag_offset) = result.movement {
-                // for (&node_id, node_ui) in &track_ui.patch.nodes {
-                //     if node_ui.selected {
-                //         ctx.tracker.add(UiNodeMove::new(
-                //             node_id,
-                //             track_id,
-                //             finished_drag_offset,
-                //         ));
-                //     }
-                // }
-
-                // TODO
-                let _ = finished_drag_offset;
+            if result.movement.is_some()
+                && let Some(TrackDropTarget { parent, mut after }) = self.track_drop_target.take()
+            {
+                // descendants of selected tracks move along with them, so only the topmost selected tracks are moved.
+                // they're kept in the order they're shown in
+                let is_selected = |track_id| {
+                    ui_state
+                        .tracks
+                        .get(track_id)
+                        .is_some_and(|track_ui| track_ui.select.is())
+                };
+                for &track_id in track_list.iter() {
+                    if !is_selected(track_id) {
+                        continue;
+                    }
+                    let mut ancestor = state.parent_of(track_id);
+                    let mut ancestor_selected = false;
+                    while let Some(ancestor_id) = ancestor {
+                        ancestor_selected |= is_selected(ancestor_id);
+                        ancestor = state.parent_of(ancestor_id);
+                    }
+                    if ancestor_selected {
+                        continue;
+                    }
+                    // dropping a track back where it was isn't worth an undo entry
+                    if (TrackDropTarget { parent, after }).is_where(ui_state, track_id) {
+                        after = Some(track_id);
+                        continue;
+                    }
+                    match TrackMove::new(state, track_id, parent, after) {
+                        Ok(command) => {
+                            tracker.add(command);
+                            after = Some(track_id);
+                        }
+                        Err(err) => tracing::warn!("couldn't move track: {err}"),
+                    }
+                }
             }
             if result.delete_selected {
                 // usually you'd have a link from a child back to the parent but doubly linked structures would be awful to debug
@@ -285,7 +391,7 @@ impl EphemeralState {
         {
             // nodes
             for (track_id, track_ephem) in &mut self.tracks {
-                let patch = &state.tracks.force_get(track_id).patch;
+                let patch = state.tracks.force_get(track_id).patch();
                 let patch_ui = &ui_state.tracks.force_get(track_id).patch;
                 let result = track_ephem.patch.node_drag.on_frame_end();
 
@@ -351,8 +457,108 @@ pub struct PatchEphemeralState {
 pub struct NodeEphemeralState {
     pub size: Vec2,
     pub input_state: Vec<InputEphemeralState>,
+    /// The probe on one of the node's outputs, if any. The worker host has a copy of this that writes to the same ring.
+    pub probe: Option<cubedaw_worker::Probe>,
 }
 #[derive(Debug)]
 pub struct InputEphemeralState {
     pub num_connected: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use cubedaw_lib::Patch;
+    use cubedaw_worker::command::ActionDirection;
+
+    use super::*;
+    use crate::{command::UiStateCommand, state::ui::TrackUiState};
+
+    /// A root track with `num_children` children, shown in the order they're returned in.
+    fn tracks(num_children: usize) -> (State, UiState, Id<Track>, Vec<Id<Track>>) {
+        let mut state = State::default();
+        let mut ui_state = UiState::new();
+        let root = Id::arbitrary();
+        let children: Vec<Id<Track>> = (0..num_children).map(|_| Id::arbitrary()).collect();
+        for &track_id in children.iter().chain([&root]) {
+            state.tracks.insert(track_id, Track::new(Patch::default()));
+            ui_state.tracks.insert(track_id, TrackUiState::default());
+        }
+        state
+            .tracks
+            .force_get_mut(root)
+            .children
+            .extend(children.iter().copied());
+        ui_state.tracks.force_get_mut(root).track_list = children.clone();
+        state.root_track = root;
+        (state, ui_state, root, children)
+    }
+
+    #[test]
+    fn track_moves_undo_in_order() {
+        let (mut state, mut ui_state, root, children) = tracks(5);
+        let mut ephemeral_state = EphemeralState::new();
+        let [a, b, c, d, e] = children[..] else {
+            unreachable!()
+        };
+        let group = Id::arbitrary();
+        state.tracks.insert(group, Track::new(Patch::default()));
+        state.tracks.force_get_mut(root).children.insert(group);
+        ui_state.tracks.insert(group, TrackUiState::default());
+        ui_state.tracks.force_get_mut(root).track_list.push(group);
+        let before = ui_state.tracks.force_get(root).track_list.clone();
+
+        // like dropping `b`, `d` and `e` right after `a`, or into `group`, all in one frame
+        for (parent, first_after) in [(root, Some(a)), (group, None)] {
+            let mut moves = Vec::new();
+            let mut after = first_after;
+            for track_id in [b, d, e] {
+                moves.push(TrackMove::new(&state, track_id, parent, after).unwrap());
+                after = Some(track_id);
+            }
+            for command in &mut moves {
+                command.run_ui(
+                    &mut ui_state,
+                    &mut ephemeral_state,
+                    ActionDirection::Forward,
+                );
+                command.inner().unwrap().execute(&mut state);
+            }
+            let moved = if parent == root {
+                vec![a, b, d, e, c, group]
+            } else {
+                vec![a, c, group]
+            };
+            assert_eq!(ui_state.tracks.force_get(root).track_list, moved);
+
+            for command in moves.iter_mut().rev() {
+                command.run_ui(
+                    &mut ui_state,
+                    &mut ephemeral_state,
+                    ActionDirection::Reverse,
+                );
+                command.inner().unwrap().rollback(&mut state);
+            }
+            assert_eq!(ui_state.tracks.force_get(root).track_list, before);
+            assert!(ui_state.tracks.force_get(group).track_list.is_empty());
+            assert_eq!(state.tracks.force_get(root).children.len(), 6);
+        }
+    }
+
+    #[test]
+    fn drop_targets_in_place() {
+        let (_, ui_state, root, children) = tracks(3);
+        let [a, b, _] = children[..] else {
+            unreachable!()
+        };
+        let target = |after| TrackDropTarget {
+            parent: root,
+            after,
+        };
+        assert!(target(None).is_where(&ui_state, a));
+        assert!(target(Some(a)).is_where(&ui_state, b));
+        assert!(!target(None).is_where(&ui_state, b));
+        assert!(!target(Some(b)).is_where(&ui_state, a));
+        // the root track isn't one of its own children
+        assert!(!target(None).is_where(&ui_state, root));
+    }
+}
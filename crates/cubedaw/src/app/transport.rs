@@ -0,0 +1,145 @@
+use cubedaw_lib::Range;
+
+use cubedaw_worker::Metronome;
+
+use crate::{Context, command::misc::LoopChange};
+
+const UNITS_PER_BEAT: i64 = Range::UNITS_PER_BEAT as i64;
+
+/// Something the transport bar wants the app to do with playback.
+pub enum TransportAction {
+    TogglePlayback,
+    ReturnToStart,
+}
+
+/// Formats a song position as `bar:beat:unit`. Bars and beats count from 1.
+pub fn format_position(pos: i64, beats_per_bar: u32) -> String {
+    let beats_per_bar = beats_per_bar.max(1) as i64;
+    let beat = pos.div_euclid(UNITS_PER_BEAT);
+    format!(
+        "{}:{}:{:03}",
+        beat.div_euclid(beats_per_bar) + 1,
+        beat.rem_euclid(beats_per_bar) + 1,
+        pos.rem_euclid(UNITS_PER_BEAT)
+    )
+}
+/// The inverse of [`format_position`]. The beat and unit can be left out.
+pub fn parse_position(text: &str, beats_per_bar: u32) -> Option<i64> {
+    let beats_per_bar = beats_per_bar.max(1) as i64;
+    let mut parts = text
+        .trim()
+        .split(':')
+        .map(|part| part.trim().parse::<i64>());
+    let bar = parts.next()?.ok()?;
+    let beat = parts.next().unwrap_or(Ok(1)).ok()?;
+    let unit = parts.next().unwrap_or(Ok(0)).ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(((bar - 1) * beats_per_bar + beat - 1) * UNITS_PER_BEAT + unit)
+}
+
+/// Shows the playback controls, the playhead position and the loop range. Also handles their keyboard shortcuts.
+pub fn transport_ui(
+    ui: &mut egui::Ui,
+    ctx: &mut Context,
+    metronome: &mut Metronome,
+    recording: &mut bool,
+) -> Option<TransportAction> {
+    let mut action = None;
+
+    let no_focus = ui.memory(|mem| mem.focused().is_none());
+    let mut toggle_loop = false;
+    if no_focus {
+        ui.input_mut(|i| {
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::Home) {
+                action = Some(TransportAction::ReturnToStart);
+            }
+            toggle_loop = i.consume_key(egui::Modifiers::NONE, egui::Key::L);
+        });
+    }
+
+    if ui
+        .button("⏮")
+        .on_hover_text("Return to start (Home)")
+        .clicked()
+    {
+        action = Some(TransportAction::ReturnToStart);
+    }
+    let (play_text, play_hover) = if ctx.is_playing() {
+        ("⏹", "Stop (Space)")
+    } else {
+        ("▶", "Play (Space)")
+    };
+    if ui.button(play_text).on_hover_text(play_hover).clicked() {
+        action = Some(TransportAction::TogglePlayback);
+    }
+    if ui
+        .selectable_label(*recording, "⏺")
+        .on_hover_text("Record the input onto armed tracks while playing")
+        .clicked()
+    {
+        *recording = !*recording;
+    }
+    if ui
+        .selectable_label(ctx.state.looping, "🔁")
+        .on_hover_text("Loop (L)")
+        .clicked()
+    {
+        toggle_loop = true;
+    }
+    let metronome_response = ui
+        .selectable_label(metronome.enabled, "🔔")
+        .on_hover_text("Metronome (right click for its volume)");
+    if metronome_response.clicked() {
+        metronome.enabled = !metronome.enabled;
+    }
+    metronome_response.context_menu(|ui| {
+        ui.add(egui::Slider::new(&mut metronome.level, 0.0..=1.0).text("Volume"));
+    });
+    if toggle_loop {
+        ctx.tracker.add(LoopChange::new(
+            ctx.state,
+            ctx.state.loop_range,
+            !ctx.state.looping,
+        ));
+    }
+
+    ui.label(
+        egui::RichText::new(format_position(
+            ctx.playhead_pos().song_pos,
+            ctx.state.beats_per_bar,
+        ))
+        .monospace(),
+    );
+
+    ui.separator();
+
+    let loop_range = ctx.state.loop_range;
+    let beats_per_bar = ctx.state.beats_per_bar;
+    let (mut start, mut end) = (loop_range.start, loop_range.end);
+    ui.label("Loop");
+    let start_response = ui.add(loop_drag_value(&mut start, beats_per_bar).range(0..=end - 1));
+    ui.label("to");
+    let end_response = ui.add(loop_drag_value(&mut end, beats_per_bar).range(start + 1..=i64::MAX));
+    let drag_started = start_response.drag_started() || end_response.drag_started();
+    if drag_started || start_response.changed() || end_response.changed() {
+        let command = LoopChange::new(ctx.state, Range::new(start, end), ctx.state.looping);
+        // a drag is one undo entry, even if it didn't change anything on its first frame
+        if drag_started || !(start_response.dragged() || end_response.dragged()) {
+            ctx.tracker.add(command);
+        } else {
+            ctx.tracker.add_weak(command);
+        }
+    }
+
+    action
+}
+
+/// A drag value for a song position, shown as `bar:beat:unit`.
+fn loop_drag_value(value: &mut i64, beats_per_bar: u32) -> egui::DragValue<'_> {
+    egui::DragValue::new(value)
+        .speed(4.0)
+        .custom_formatter(move |pos, _| format_position(pos as i64, beats_per_bar))
+        .custom_parser(move |text| parse_position(text, beats_per_bar).map(|pos| pos as f64))
+}
@@ -1,4 +1,4 @@
-use cubedaw_lib::{Clip, Id, Range, Track};
+use cubedaw_lib::{AudioClip, Clip, Id, Range, Track};
 use cubedaw_worker::command::{ActionDirection, StateCommand, StateCommandWrapper};
 
 use crate::{state::ui::ClipUiState, util::Select};
@@ -199,6 +199,48 @@ impl UiStateCommand for ClipAddOrRemove {
     }
 }
 
+/// Changes the sample offset, gain or fades of an audio clip.
+#[derive(Clone)]
+pub struct AudioClipChange {
+    track_id: Id<Track>,
+    id: Id<Clip>,
+    old: AudioClip,
+    new: AudioClip,
+}
+
+impl AudioClipChange {
+    pub fn new(track_id: Id<Track>, id: Id<Clip>, old: AudioClip, new: AudioClip) -> Self {
+        Self {
+            track_id,
+            id,
+            old,
+            new,
+        }
+    }
+}
+
+impl StateCommand for AudioClipChange {
+    fn run(&mut self, state: &mut cubedaw_lib::State, action: ActionDirection) {
+        let clip = state
+            .tracks
+            .force_get_mut(self.track_id)
+            .clip_mut(self.id)
+            .expect("tried to change nonexistent clip");
+        clip.audio = Some(match action {
+            ActionDirection::Forward => self.new.clone(),
+            ActionDirection::Reverse => self.old.clone(),
+        });
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        if self.track_id != other.track_id || self.id != other.id {
+            return false;
+        }
+        self.new = other.new.clone();
+        true
+    }
+}
+
 pub struct UiClipSelect {
     track_id: Id<Track>,
     id: Id<Clip>,
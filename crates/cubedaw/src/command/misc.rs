@@ -1,4 +1,5 @@
-use cubedaw_worker::command::ActionDirection;
+use cubedaw_lib::{Range, State};
+use cubedaw_worker::command::{ActionDirection, StateCommand};
 
 use super::UiStateCommand;
 
@@ -39,3 +40,37 @@ impl UiStateCommand for UiSetPlayhead {
         true
     }
 }
+
+#[derive(Clone)]
+pub struct LoopChange {
+    old_range: Range,
+    old_looping: bool,
+    new_range: Range,
+    new_looping: bool,
+}
+
+impl LoopChange {
+    pub fn new(state: &State, new_range: Range, new_looping: bool) -> Self {
+        Self {
+            old_range: state.loop_range,
+            old_looping: state.looping,
+            new_range,
+            new_looping,
+        }
+    }
+}
+
+impl StateCommand for LoopChange {
+    fn run(&mut self, state: &mut State, action: ActionDirection) {
+        (state.loop_range, state.looping) = match action {
+            ActionDirection::Forward => (self.new_range, self.new_looping),
+            ActionDirection::Reverse => (self.old_range, self.old_looping),
+        };
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        self.new_range = other.new_range;
+        self.new_looping = other.new_looping;
+        true
+    }
+}
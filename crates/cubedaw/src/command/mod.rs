@@ -11,6 +11,7 @@ pub mod misc;
 pub mod node;
 pub mod note;
 pub mod patch;
+pub mod sample;
 pub mod track;
 
 pub trait UiStateCommand: 'static + Send {
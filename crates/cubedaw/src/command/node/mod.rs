@@ -1,4 +1,4 @@
-use cubedaw_lib::{Buffer, Id, Node, NodeData, Track};
+use cubedaw_lib::{Buffer, Id, Node, NodeData, NodeInput, Track};
 use cubedaw_worker::command::{ActionDirection, StateCommand, StateCommandWrapper};
 use egui::Vec2;
 
@@ -45,7 +45,7 @@ impl NodeStateUpdate {
             state
                 .tracks
                 .get_mut(self.track_id)?
-                .patch
+                .patch_mut()
                 .node_entry_mut(self.id)?,
         )
     }
@@ -96,7 +96,7 @@ struct NoUiNodeAddOrRemove {
     id: Id<Node>,
     track_id: Id<Track>,
     data: Option<NodeData>,
-    inputs: Vec<f32>,
+    inputs: Vec<NodeInput>,
     num_outputs: u32,
     is_removal: bool,
 }
@@ -105,7 +105,7 @@ impl NoUiNodeAddOrRemove {
     pub fn addition(
         id: Id<Node>,
         data: NodeData,
-        inputs: Vec<f32>,
+        inputs: Vec<NodeInput>,
         num_outputs: u32,
         track_id: Id<Track>,
     ) -> Self {
@@ -130,11 +130,11 @@ impl NoUiNodeAddOrRemove {
     }
 
     fn get_patch<'a>(&self, state: &'a mut cubedaw_lib::State) -> &'a mut cubedaw_lib::Patch {
-        &mut state
+        state
             .tracks
             .get_mut(self.track_id)
             .expect("tried to add node to nonexistent patch")
-            .patch
+            .patch_mut()
     }
 }
 
@@ -147,8 +147,8 @@ impl StateCommand for NoUiNodeAddOrRemove {
                 .expect("tried to remove nonexistent node");
 
             assert!(self.inputs.is_empty());
-            self.inputs
-                .extend(node_data.inputs().iter().map(|input| input.bias));
+            // the node can't have had any cables, so the inputs have no connections
+            self.inputs.extend_from_slice(node_data.inputs());
             self.num_outputs = node_data.outputs().len() as u32;
 
             if self.data.replace(node_data.data).is_some() {
@@ -160,12 +160,18 @@ impl StateCommand for NoUiNodeAddOrRemove {
                 .take()
                 .expect("called execute_add on empty NodeAddOrRemove");
 
-            self.get_patch(state).insert_node(
+            let inputs = core::mem::take(&mut self.inputs);
+            let patch = self.get_patch(state);
+            patch.insert_node(
                 self.id,
                 node_data,
-                core::mem::replace(&mut self.inputs, Vec::new()),
+                inputs.iter().map(|input| input.bias).collect(),
                 self.num_outputs,
             );
+            let node = patch.node_entry_mut(self.id).expect("unreachable");
+            for (node_input, input) in node.inputs_mut().iter_mut().zip(inputs) {
+                node_input.smoothing_ms = input.smoothing_ms;
+            }
         }
     }
 }
@@ -204,7 +210,7 @@ impl NodeBiasChange {
             state
                 .tracks
                 .get_mut(self.track_id)?
-                .patch
+                .patch_mut()
                 .node_entry_mut(self.id)?
                 .inputs_mut()
                 .get_mut(self.input_index as usize)?,
@@ -267,7 +273,7 @@ impl NodeMultiplierChange {
             &mut state
                 .tracks
                 .get_mut(self.track_id)?
-                .patch
+                .patch_mut()
                 .node_entry_mut(self.id)?
                 .inputs_mut()
                 .get_mut(self.input_index as usize)?
@@ -309,7 +315,7 @@ impl NodeAddOrRemove {
     pub fn addition(
         id: Id<Node>,
         data: NodeData,
-        inputs: Vec<f32>,
+        inputs: Vec<NodeInput>,
         num_outputs: u32,
         track_id: Id<Track>,
         ui_state: NodeUiState,
@@ -34,13 +34,17 @@ impl CableAddOrRemove {
 }
 
 impl StateCommand for CableAddOrRemove {
-    fn run(&mut self, state: &mut cubedaw_lib::State, action: cubedaw_worker::command::ActionDirection) {
+    fn run(
+        &mut self,
+        state: &mut cubedaw_lib::State,
+        action: cubedaw_worker::command::ActionDirection,
+    ) {
         let track = state
             .tracks
             .get_mut(self.track_id)
             .expect("tried to add node to nonexistent clip");
         if self.is_removal ^ action.is_rollback() {
-            let cable_data = track.patch.take_cable(self.id);
+            let cable_data = track.patch_mut().take_cable(self.id);
 
             if self.data.replace(cable_data).is_some() {
                 panic!("called execute_remove on nonempty NodeAddOrRemove");
@@ -50,7 +54,7 @@ impl StateCommand for CableAddOrRemove {
                 .data
                 .take()
                 .expect("called execute_add on empty NodeAddOrRemove");
-            track.patch.insert_cable(self.id, cable, conn);
+            track.patch_mut().insert_cable(self.id, cable, conn);
         }
     }
 }
@@ -0,0 +1,39 @@
+use cubedaw_lib::{Id, Sample};
+use cubedaw_worker::command::{ActionDirection, StateCommand};
+
+/// Adds a sample to the project. Samples are only ever removed by undoing this.
+#[derive(Clone)]
+pub struct SampleAdd {
+    id: Id<Sample>,
+    data: Option<Sample>,
+}
+
+impl SampleAdd {
+    pub fn new(id: Id<Sample>, data: Sample) -> Self {
+        Self {
+            id,
+            data: Some(data),
+        }
+    }
+}
+
+impl StateCommand for SampleAdd {
+    fn run(&mut self, state: &mut cubedaw_lib::State, action: ActionDirection) {
+        match action {
+            ActionDirection::Forward => state.samples.insert(
+                self.id,
+                self.data
+                    .take()
+                    .expect("execute() called on empty SampleAdd"),
+            ),
+            ActionDirection::Reverse => {
+                self.data = Some(
+                    state
+                        .samples
+                        .remove(self.id)
+                        .expect("tried to remove nonexistent sample"),
+                );
+            }
+        }
+    }
+}
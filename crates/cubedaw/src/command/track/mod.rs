@@ -1,4 +1,4 @@
-use cubedaw_lib::{Id, NodeData, Track};
+use cubedaw_lib::{Id, NodeData, State, Track, TrackSend};
 use cubedaw_worker::command::{ActionDirection, StateCommand, StateCommandWrapper};
 
 use crate::{registry::NodeRegistry, state::ui::TrackUiState, util::Select};
@@ -217,6 +217,107 @@ impl UiStateCommand for TrackAddOrRemove {
     }
 }
 
+#[derive(Clone)]
+struct NoUiTrackMove {
+    id: Id<Track>,
+    old_parent: Id<Track>,
+    new_parent: Id<Track>,
+}
+
+impl StateCommand for NoUiTrackMove {
+    fn run(&mut self, state: &mut State, action: ActionDirection) {
+        let (from, to) = match action {
+            ActionDirection::Forward => (self.old_parent, self.new_parent),
+            ActionDirection::Reverse => (self.new_parent, self.old_parent),
+        };
+        let did_remove = state.tracks.force_get_mut(from).children.remove(&self.id);
+        assert!(
+            did_remove,
+            "tried to move track from a track it isn't a child of"
+        );
+        state.tracks.force_get_mut(to).children.insert(self.id);
+    }
+}
+
+/// Moves a track (and its descendants) to a different place in the track tree, possibly under a different parent.
+pub struct TrackMove {
+    inner: NoUiTrackMove,
+    /// The sibling the track goes after in its new parent's track list, or `None` to go first.
+    after: Option<Id<Track>>,
+    // where the track was in its old parent's track list
+    old_pos: usize,
+}
+
+impl TrackMove {
+    /// Fails if the track is the root track or the move would create a cycle, like moving a track into one of its
+    /// own descendants.
+    pub fn new(
+        state: &State,
+        id: Id<Track>,
+        new_parent: Id<Track>,
+        after: Option<Id<Track>>,
+    ) -> anyhow::Result<Self> {
+        let old_parent = state
+            .parent_of(id)
+            .ok_or_else(|| anyhow::anyhow!("the root track can't be moved"))?;
+        anyhow::ensure!(
+            !state.track_depends_on(id, new_parent),
+            "moving a track into a track that depends on it would create a cycle"
+        );
+        Ok(Self {
+            inner: NoUiTrackMove {
+                id,
+                old_parent,
+                new_parent,
+            },
+            after,
+            old_pos: 0,
+        })
+    }
+}
+
+impl UiStateCommand for TrackMove {
+    fn run_ui(
+        &mut self,
+        ui_state: &mut crate::UiState,
+        _ephemeral_state: &mut crate::EphemeralState,
+        action: ActionDirection,
+    ) {
+        let NoUiTrackMove {
+            id,
+            old_parent,
+            new_parent,
+        } = self.inner;
+        let (from, to) = match action {
+            ActionDirection::Forward => (old_parent, new_parent),
+            ActionDirection::Reverse => (new_parent, old_parent),
+        };
+
+        let from_list = &mut ui_state.tracks.force_get_mut(from).track_list;
+        let pos = from_list
+            .iter()
+            .position(|&track_id| track_id == id)
+            .expect("track list desynced with state");
+        from_list.remove(pos);
+
+        let to_list = &mut ui_state.tracks.force_get_mut(to).track_list;
+        let pos = match action {
+            ActionDirection::Forward => {
+                self.old_pos = pos;
+                self.after
+                    .and_then(|after| to_list.iter().position(|&track_id| track_id == after))
+                    .map_or(0, |after_pos| after_pos + 1)
+            }
+            ActionDirection::Reverse => self.old_pos,
+        };
+        to_list.insert(pos.min(to_list.len()), id);
+    }
+
+    fn inner(&mut self) -> Option<&mut dyn StateCommandWrapper> {
+        Some(&mut self.inner)
+    }
+}
+
 pub struct TrackSelect {
     id: Id<Track>,
     select: Select,
@@ -240,3 +341,83 @@ impl UiStateCommand for TrackSelect {
         }
     }
 }
+
+#[derive(Clone)]
+pub struct TrackGainChange {
+    id: Id<Track>,
+    old: f32,
+    new: f32,
+}
+
+impl TrackGainChange {
+    pub fn new(id: Id<Track>, old: f32, new: f32) -> Self {
+        Self { id, old, new }
+    }
+}
+
+impl StateCommand for TrackGainChange {
+    fn run(&mut self, state: &mut State, action: ActionDirection) {
+        state.tracks.force_get_mut(self.id).set_gain(match action {
+            ActionDirection::Forward => self.new,
+            ActionDirection::Reverse => self.old,
+        });
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        if self.id != other.id {
+            return false;
+        }
+        self.new = other.new;
+        true
+    }
+}
+
+/// Adds, changes or removes (if the new send is `None`) the send from one track to another.
+#[derive(Clone)]
+pub struct TrackSendChange {
+    id: Id<Track>,
+    target_id: Id<Track>,
+    old: Option<TrackSend>,
+    new: Option<TrackSend>,
+}
+
+impl TrackSendChange {
+    /// Fails if the send would create a routing cycle.
+    pub fn new(
+        state: &State,
+        id: Id<Track>,
+        target_id: Id<Track>,
+        new: Option<TrackSend>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            new.is_none() || !state.track_depends_on(id, target_id),
+            "sending to a track that depends on this one would create a cycle"
+        );
+        Ok(Self {
+            id,
+            target_id,
+            old: state.tracks.force_get(id).sends().get(target_id).copied(),
+            new,
+        })
+    }
+}
+
+impl StateCommand for TrackSendChange {
+    fn run(&mut self, state: &mut State, action: ActionDirection) {
+        state.tracks.force_get_mut(self.id).set_send(
+            self.target_id,
+            match action {
+                ActionDirection::Forward => self.new,
+                ActionDirection::Reverse => self.old,
+            },
+        );
+    }
+
+    fn try_merge(&mut self, other: &Self) -> bool {
+        if self.id != other.id || self.target_id != other.target_id {
+            return false;
+        }
+        self.new = other.new;
+        true
+    }
+}
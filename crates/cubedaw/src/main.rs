@@ -28,6 +28,12 @@ pub use registry::NodeRegistry;
 mod widget;
 mod workerhost;
 
+/// Counts heap allocations on the audio threads so they can be reported. See [`cubedaw_worker::alloc_guard`].
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: cubedaw_worker::alloc_guard::GuardedAllocator =
+    cubedaw_worker::alloc_guard::GuardedAllocator;
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result<()> {
     use app::CubedawApp;
@@ -70,3 +70,68 @@ impl NodeUi for DownmixNodeUi {
         unreachable!("builtin nodes don't have node factories");
     }
 }
+
+pub struct AudioInputNodeUi;
+impl NodeUi for AudioInputNodeUi {
+    fn create(&self, _creation_context: &NodeCreationContext) -> Box<Buffer> {
+        Default::default()
+    }
+    fn title(&self, _: &Buffer, _ctx: &Context) -> Result<std::borrow::Cow<'_, str>> {
+        Ok("Audio Input".into())
+    }
+    fn ui(&self, _: &mut Buffer, ui: &mut egui::Ui, node_ui: &mut dyn NodeUiContext) -> Result<()> {
+        node_ui.output_ui(ui, "Input");
+        Ok(())
+    }
+
+    fn make_node_factory(&self) -> cubedaw_worker::DynNodeFactory {
+        unreachable!("builtin nodes don't have node factories");
+    }
+}
+
+pub struct SidechainNodeUi;
+impl NodeUi for SidechainNodeUi {
+    fn create(&self, _creation_context: &NodeCreationContext) -> Box<Buffer> {
+        Track::sidechain_args(None)
+    }
+    fn title(&self, _: &Buffer, _ctx: &Context) -> Result<std::borrow::Cow<'_, str>> {
+        Ok("Sidechain".into())
+    }
+    fn ui(
+        &self,
+        buf: &mut Buffer,
+        ui: &mut egui::Ui,
+        node_ui: &mut dyn NodeUiContext,
+    ) -> Result<()> {
+        let mut source_id = Track::sidechain_source(buf);
+        let sources = node_ui.sidechain_sources();
+        let current = source_id.and_then(|source_id| {
+            sources
+                .iter()
+                .find(|&&(id, _)| id == source_id)
+                .map(|&(_, name)| name)
+        });
+        egui::ComboBox::from_id_salt(0)
+            .selected_text(current.unwrap_or("No track"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut source_id, None, "No track");
+                for &(id, name) in &sources {
+                    ui.selectable_value(&mut source_id, Some(id), name);
+                }
+            });
+        if source_id != Track::sidechain_source(buf) {
+            let args = Track::sidechain_args(source_id);
+            buf.as_bytes_mut()
+                .get_mut(..args.as_bytes().len())
+                .context("sidechain args are too short")?
+                .copy_from_slice(args.as_bytes());
+        }
+
+        node_ui.output_ui(ui, "Sidechain");
+        Ok(())
+    }
+
+    fn make_node_factory(&self) -> cubedaw_worker::DynNodeFactory {
+        unreachable!("builtin nodes don't have node factories");
+    }
+}
@@ -4,6 +4,8 @@ mod math;
 pub use math::MathNode;
 mod oscillator;
 pub use oscillator::OscillatorNode;
+mod sampler;
+pub use sampler::SamplerNode;
 
 trait ZerocopyTryFromExt {
     type Output;
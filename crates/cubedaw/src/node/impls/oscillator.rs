@@ -4,7 +4,10 @@ use zerocopy::{IntoBytes, TryFromBytes};
 
 use crate::{
     Context,
-    node::{NodeInputUiOptions, ui::PitchState},
+    node::{
+        NodeInputUiOptions,
+        ui::{PITCH_SMOOTHING_MS, PitchState},
+    },
     registry::NodeUi,
 };
 
@@ -91,4 +94,9 @@ impl NodeUi for OscillatorNode {
     fn make_node_factory(&self) -> cubedaw_worker::DynNodeFactory {
         cubedaw_worker::DynNodeFactory::new_castable(|_| 0.0f32)
     }
+
+    fn input_smoothing_ms(&self, input_index: usize) -> Option<f32> {
+        // the only input is the pitch
+        (input_index == 0).then_some(PITCH_SMOOTHING_MS)
+    }
 }
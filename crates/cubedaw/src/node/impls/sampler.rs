@@ -0,0 +1,116 @@
+use std::num::NonZero;
+
+use anyhow::Result;
+use cubedaw_lib::{Buffer, Id, Sample};
+use zerocopy::{IntoBytes, TryFromBytes};
+
+use crate::{
+    Context,
+    node::{
+        NodeInputUiOptions,
+        ui::{PITCH_SMOOTHING_MS, PitchState},
+    },
+    registry::NodeUi,
+    widget::DragValue,
+};
+
+mod schema;
+pub use schema::*;
+
+use super::ZerocopyTryFromExt;
+
+impl SamplerNodeArgs {
+    fn sample_id(&self) -> Option<Id<Sample>> {
+        NonZero::new(self.sample_id).map(Id::from_raw)
+    }
+}
+
+pub struct SamplerNode;
+
+impl NodeUi for SamplerNode {
+    fn create(&self, _ctx: &crate::node::NodeCreationContext) -> Box<Buffer> {
+        SamplerNodeArgs {
+            sample_id: 0,
+            root_pitch: 0.0,
+            loop_start: 0.0,
+            loop_end: 0.0,
+            pitch_state: PitchState::Relative,
+            _pad1: Default::default(),
+        }
+        .as_bytes()
+        .into()
+    }
+    fn title(&self, state_buf: &Buffer, ctx: &Context) -> Result<std::borrow::Cow<'_, str>> {
+        let (state, _) = SamplerNodeArgs::try_ref_from_prefix(state_buf.as_bytes()).anyhow()?;
+        Ok(
+            match state.sample_id().and_then(|id| ctx.state.samples.get(id)) {
+                Some(sample) => format!("Sampler ({})", sample.name).into(),
+                None => "Sampler".into(),
+            },
+        )
+    }
+    fn ui(
+        &self,
+        state_buf: &mut Buffer,
+        ui: &mut egui::Ui,
+        ctx: &mut dyn crate::node::NodeUiContext,
+    ) -> Result<()> {
+        let (state, _) = SamplerNodeArgs::try_mut_from_prefix(state_buf.as_bytes_mut()).anyhow()?;
+
+        let samples = ctx.samples();
+        let current = state.sample_id().and_then(|id| samples.get(id));
+        let mut sorted_samples: Vec<_> = samples.iter().collect();
+        sorted_samples.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+        egui::ComboBox::from_id_salt(0)
+            .selected_text(current.map_or("No sample", |sample| &sample.name))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.sample_id, 0, "No sample");
+                for (id, sample) in sorted_samples {
+                    ui.selectable_value(&mut state.sample_id, id.raw().get(), &sample.name);
+                }
+            });
+
+        let root = NodeInputUiOptions::pitch();
+        ui.add(
+            DragValue::new(&mut state.root_pitch)
+                .name(Some("Root"))
+                .display(root.display)
+                .display_range(root.display_range),
+        );
+
+        // loop points can't go past the end of the sample
+        let duration = current.map_or(0.0, |sample| sample.duration_seconds() as f32);
+        let loop_range = egui::Rangef::new(0.0, duration);
+        ui.add(
+            DragValue::new(&mut state.loop_start)
+                .name(Some("Loop start"))
+                .range(loop_range)
+                .display_range(loop_range),
+        );
+        ui.add(
+            DragValue::new(&mut state.loop_end)
+                .name(Some("Loop end"))
+                .range(loop_range)
+                .display_range(loop_range),
+        );
+
+        ctx.input_ui(
+            ui,
+            "Pitch",
+            NodeInputUiOptions::pitch_choice(&mut state.pitch_state),
+        );
+        ctx.output_ui(ui, "Out");
+
+        Ok(())
+    }
+
+    fn make_node_factory(&self) -> cubedaw_worker::DynNodeFactory {
+        // the playback position
+        cubedaw_worker::DynNodeFactory::new_castable(|_| 0.0f64)
+    }
+
+    fn input_smoothing_ms(&self, input_index: usize) -> Option<f32> {
+        // the only input is the pitch
+        (input_index == 0).then_some(PITCH_SMOOTHING_MS)
+    }
+}
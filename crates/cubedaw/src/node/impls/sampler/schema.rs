@@ -0,0 +1,15 @@
+#[repr(C)]
+#[derive(
+    zerocopy::TryFromBytes, zerocopy::IntoBytes, zerocopy::Immutable, zerocopy::KnownLayout,
+)]
+pub struct SamplerNodeArgs {
+    /// The raw id of the sample to play, or 0 if there isn't one.
+    pub sample_id: u64,
+    /// The pitch that plays the sample back at its original speed.
+    pub root_pitch: f32,
+    /// Loop points, in seconds into the sample. The sample doesn't loop if `loop_end <= loop_start`.
+    pub loop_start: f32,
+    pub loop_end: f32,
+    pub pitch_state: super::PitchState,
+    pub _pad1: [u8; 3],
+}
@@ -24,6 +24,16 @@ pub fn register_builtin_nodes(registry: &mut NodeRegistry) {
         "Note Output",
         Box::new(impls::builtin::DownmixNodeUi),
     );
+    registry.register_node_without_factory(
+        resourcekey::literal!("builtin:audio_input"),
+        "Audio Input",
+        Box::new(impls::builtin::AudioInputNodeUi),
+    );
+    registry.register_node_without_factory(
+        resourcekey::literal!("builtin:sidechain"),
+        "Sidechain",
+        Box::new(impls::builtin::SidechainNodeUi),
+    );
 }
 
 pub fn register_cubedaw_nodes(registry: &mut NodeRegistry) {
@@ -37,6 +47,11 @@ pub fn register_cubedaw_nodes(registry: &mut NodeRegistry) {
         "Math",
         impls::OscillatorNode,
     );
+    registry.register_node(
+        resourcekey::literal!("cubedaw:sampler"),
+        "Sampler",
+        impls::SamplerNode,
+    );
     let inner = std::sync::Arc::get_mut(&mut registry.inner)
         .expect("shared registry passed to register_cubedaw_nodes");
 
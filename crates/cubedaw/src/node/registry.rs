@@ -20,6 +20,12 @@ pub trait NodeUi: 'static + Send + Sync {
     fn make_node_factory(&self) -> DynNodeFactory {
         DynNodeFactory(Box::new(|_| Box::new([])))
     }
+
+    /// How long changes to an input's value take to glide to the new value, in milliseconds. This is set on the
+    /// node's inputs when it's created. If `None`, the default smoothing time is used.
+    fn input_smoothing_ms(&self, _input_index: usize) -> Option<f32> {
+        None
+    }
 }
 impl std::fmt::Debug for dyn NodeUi {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,4 +1,4 @@
-
+use cubedaw_lib::{Id, IdMap, Sample, Track};
 use egui::Rangef;
 
 use crate::widget::{ValueHandler, ValueHandlerContext};
@@ -9,6 +9,11 @@ pub trait NodeUiContext {
     // Instead, each input should have an Id<Input> or something that identifies it.
     fn input_ui(&mut self, ui: &mut egui::Ui, name: &str, options: NodeInputUiOptions);
     fn output_ui(&mut self, ui: &mut egui::Ui, name: &str);
+    /// The project's samples, for nodes that play them.
+    fn samples(&self) -> &IdMap<Sample>;
+    /// The tracks that a sidechain node on this track can read from (with their names), sorted by name.
+    /// Tracks that depend on this track are left out, since reading them would make a cycle.
+    fn sidechain_sources(&self) -> Vec<(Id<Track>, &str)>;
 }
 
 pub struct NodeInputUiOptions<'a> {
@@ -80,6 +85,10 @@ impl<'a> NodeInputUiOptions<'a> {
     }
 }
 
+/// How long changes to pitch inputs take to glide, in milliseconds. Gliding between pitches sounds wrong, so pitch
+/// changes are immediate. See [`NodeUi::input_smoothing_ms`](crate::registry::NodeUi::input_smoothing_ms).
+pub const PITCH_SMOOTHING_MS: f32 = 0.0;
+
 fn pitch_internal<'a>(pitch_state: PitchState) -> NodeInputUiOptions<'a> {
     struct PitchDisplay<const IS_RELATIVE: bool>;
     fn get_parts(val: f32) -> (String, String) {
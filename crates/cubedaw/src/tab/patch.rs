@@ -1,4 +1,4 @@
-use std::iter;
+use std::{iter, sync::Arc};
 
 use anyhow::Result;
 
@@ -6,7 +6,10 @@ use crate::{
     command::{node::NodeStateUpdate, patch::CableAddOrRemove},
     math,
 };
-use cubedaw_lib::{Buffer, Cable, CableConnection, CableTag, Id, IdMap, Node, NodeData, Track};
+use cubedaw_lib::{
+    Buffer, Cable, CableConnection, CableTag, Id, IdMap, Node, NodeData, NodeInput, Sample, Track,
+};
+use cubedaw_worker::{Probe, ProbeRing, ProbeSource};
 use egui::{
     Align, Area, CentralPanel, Color32, CornerRadius, CursorIcon, Direction, Frame, Layout, Pos2,
     Rangef, Rect, Response, Sense, Shape, Stroke, Ui, UiBuilder, Vec2, WidgetText,
@@ -22,9 +25,12 @@ use crate::{
     context::UiStateTracker,
     state::{ephemeral::NodeEphemeralState, ui::NodeUiState},
     util::Select,
-    widget::DragValue,
+    widget::{DragValue, ProbeScope},
 };
 
+/// How many samples a probe keeps around. This is a bit more than a scope shows, so it doesn't show partially written samples.
+const PROBE_CAPACITY: usize = 4096;
+
 pub struct PatchTab {
     id: Id<crate::app::Tab>,
 
@@ -226,7 +232,7 @@ impl<'tab, 'ctx> Prepared<'tab, 'ctx> {
         Self {
             tab,
             track_id,
-            patch: &ctx.state.tracks.force_get(track_id).patch,
+            patch: ctx.state.tracks.force_get(track_id).patch(),
             patch_ui: &ctx.ui_state.tracks.force_get(track_id).patch,
 
             viewport,
@@ -293,6 +299,12 @@ impl<'tab, 'ctx> Prepared<'tab, 'ctx> {
                 if ui.button("Oscillator").clicked() {
                     node_added = Some(resourcekey::literal!("cubedaw:oscillator"));
                 }
+                if ui.button("Audio Input").clicked() {
+                    node_added = Some(resourcekey::literal!("builtin:audio_input"));
+                }
+                if ui.button("Sidechain").clicked() {
+                    node_added = Some(resourcekey::literal!("builtin:sidechain"));
+                }
                 if ui.button("Note Output").clicked() {
                     node_added = Some(resourcekey::literal!("builtin:output"));
                 }
@@ -399,6 +411,8 @@ impl<'tab, 'ctx> Prepared<'tab, 'ctx> {
             node_id,
             track_id,
             node_data,
+            ctx.state,
+            ctx.ui_state,
             match real_node_data {
                 Some((_, ref mut node_ephemeral)) => node_ephemeral,
                 None => &mut default_node_ephemeral,
@@ -449,6 +463,16 @@ impl<'tab, 'ctx> Prepared<'tab, 'ctx> {
                 &mut ui_ctx,
             )?;
 
+            if let Some(ref probe) = ui_ctx.node_ephemeral.probe {
+                frame_prepared.content_ui.separator();
+                frame_prepared.content_ui.add(ProbeScope::new(&probe.ring));
+            }
+            if ui_ctx.probe_changed
+                && let Some(node_id) = node_id
+            {
+                ctx.set_probe(track_id, node_id, ui_ctx.node_ephemeral.probe.clone());
+            }
+
             if *node_state_copy != *node_state
                 && let Some(node_id) = node_id
             {
@@ -657,10 +681,27 @@ impl<'tab, 'ctx> Prepared<'tab, 'ctx> {
                     let node_data = fake_entry.data;
                     if self.viewport_interaction.clicked() {
                         // place the node
+                        let node_thingy = &ctx
+                            .node_registry
+                            .get(&node_data.key)
+                            .unwrap_or_else(|| {
+                                panic!("unknown node encountered: {:?}", node_data.key)
+                            })
+                            .ui;
+                        let inputs = result
+                            .inputs
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, input)| NodeInput {
+                                bias: input.value,
+                                smoothing_ms: node_thingy.input_smoothing_ms(index),
+                                connections: Vec::new(),
+                            })
+                            .collect();
                         ctx.tracker.add(NodeAddOrRemove::addition(
                             Id::arbitrary(),
                             node_data,
-                            result.inputs.into_iter().map(|input| input.value).collect(),
+                            inputs,
                             result.outputs.len() as u32,
                             track_id,
                             NodeUiState {
@@ -1041,6 +1082,8 @@ struct CubedawNodeUiContext<'a> {
     node_id: Option<Id<Node>>,
     track_id: Id<Track>,
     node_data: &'a Node,
+    state: &'a cubedaw_lib::State,
+    ui_state: &'a crate::UiState,
 
     node_ephemeral: &'a mut NodeEphemeralState,
     inputs: Vec<CubedawNodeUiContextInputData>,
@@ -1048,12 +1091,16 @@ struct CubedawNodeUiContext<'a> {
 
     tracker: UiStateTracker,
     currently_drawn_cable: Option<CurrentlyDrawnCable>,
+    /// Whether the probe in `node_ephemeral` was changed and the worker host needs to know.
+    probe_changed: bool,
 }
 impl<'a> CubedawNodeUiContext<'a> {
     pub fn new(
         id: Option<Id<Node>>,
         track_id: Id<Track>,
         node_data: &'a Node,
+        state: &'a cubedaw_lib::State,
+        ui_state: &'a crate::UiState,
         ephemeral: &'a mut NodeEphemeralState,
         currently_drawn_cable: Option<CurrentlyDrawnCable>,
     ) -> Self {
@@ -1061,6 +1108,8 @@ impl<'a> CubedawNodeUiContext<'a> {
             node_id: id,
             track_id,
             node_data,
+            state,
+            ui_state,
 
             node_ephemeral: ephemeral,
             inputs: Vec::new(),
@@ -1068,6 +1117,7 @@ impl<'a> CubedawNodeUiContext<'a> {
 
             tracker: UiStateTracker::new(),
             currently_drawn_cable,
+            probe_changed: false,
         }
     }
 
@@ -1270,10 +1320,55 @@ impl crate::node::NodeUiContext for CubedawNodeUiContext<'_> {
             .with_layout(Layout::right_to_left(Align::Min), |ui| ui.label(name))
             .inner;
 
+        let output_index = self.outputs.len() as u32;
+        if let Some(node_id) = self.node_id {
+            response.interact(Sense::click()).context_menu(|ui| {
+                let current_source = self
+                    .node_ephemeral
+                    .probe
+                    .as_ref()
+                    .filter(|probe| probe.output_index == output_index)
+                    .map(|probe| probe.source);
+                let mut source = current_source;
+
+                ui.label("Probe");
+                ui.radio_value(&mut source, None, "Off");
+                ui.radio_value(&mut source, Some(ProbeSource::Track), "Track");
+                ui.radio_value(&mut source, Some(ProbeSource::Voice(None)), "Any voice");
+
+                if source != current_source {
+                    self.node_ephemeral.probe = source.map(|source| {
+                        Probe::new(
+                            node_id,
+                            output_index,
+                            source,
+                            Arc::new(ProbeRing::new(PROBE_CAPACITY)),
+                        )
+                    });
+                    self.probe_changed = true;
+                    ui.close_menu();
+                }
+            });
+        }
+
         self.outputs.push(CubedawNodeUiContextOutputData {
             y_pos: response.rect.center().y,
         });
     }
+    fn samples(&self) -> &IdMap<Sample> {
+        &self.state.samples
+    }
+    fn sidechain_sources(&self) -> Vec<(Id<Track>, &str)> {
+        let mut sources: Vec<_> = self
+            .ui_state
+            .tracks
+            .iter()
+            .filter(|&(source_id, _)| !self.state.track_depends_on(source_id, self.track_id))
+            .map(|(source_id, track_ui)| (source_id, track_ui.name.as_str()))
+            .collect();
+        sources.sort_by_key(|&(_, name)| name);
+        sources
+    }
 }
 
 #[derive(Debug)]
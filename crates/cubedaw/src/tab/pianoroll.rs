@@ -29,6 +29,13 @@ pub struct PianoRollTab {
     units_per_pitch: f32,
 
     currently_drawn_note: Option<(i64, Note)>,
+
+    /// The piano key currently being held down with the mouse, if any.
+    held_piano_key: Option<(Id<Track>, i32, Id<Note>)>,
+
+    /// Whether the computer keyboard can be used to play notes. See [`KEYBOARD_PIANO_KEYS`].
+    keyboard_piano: bool,
+    keyboard_piano_notes: Vec<(egui::Key, Id<Track>, Id<Note>)>,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -55,6 +62,50 @@ impl ops::Sub for Note2DPos {
 const MIN_NOTE_SHOWN: i32 = -39;
 const MAX_NOTE_SHOWN: i32 = 47;
 
+const PIANO_KEY_WIDTH: f32 = 40.0;
+
+/// Tracker-style keyboard layout: the bottom two rows are one octave starting at middle C, the top two rows are the octave above that.
+// TODO make configurable (and not qwerty-specific)
+const KEYBOARD_PIANO_KEYS: [(egui::Key, i32); 30] = {
+    use egui::Key as K;
+    [
+        (K::Z, 0),
+        (K::S, 1),
+        (K::X, 2),
+        (K::D, 3),
+        (K::C, 4),
+        (K::V, 5),
+        (K::G, 6),
+        (K::B, 7),
+        (K::H, 8),
+        (K::N, 9),
+        (K::J, 10),
+        (K::M, 11),
+        (K::Comma, 12),
+        (K::L, 13),
+        (K::Period, 14),
+        (K::Q, 12),
+        (K::Num2, 13),
+        (K::W, 14),
+        (K::Num3, 15),
+        (K::E, 16),
+        (K::R, 17),
+        (K::Num5, 18),
+        (K::T, 19),
+        (K::Num6, 20),
+        (K::Y, 21),
+        (K::Num7, 22),
+        (K::U, 23),
+        (K::I, 24),
+        (K::Num9, 25),
+        (K::O, 26),
+    ]
+};
+
+fn is_black_key(pitch: i32) -> bool {
+    matches!(pitch.rem_euclid(12), 1 | 3 | 6 | 8 | 10)
+}
+
 impl crate::Screen for PianoRollTab {
     fn create(_state: &cubedaw_lib::State, ui_state: &crate::UiState) -> Self {
         Self {
@@ -65,6 +116,11 @@ impl crate::Screen for PianoRollTab {
             units_per_pitch: 16.0,
 
             currently_drawn_note: None,
+
+            held_piano_key: None,
+
+            keyboard_piano: false,
+            keyboard_piano_notes: Vec::new(),
         }
     }
 
@@ -77,6 +133,16 @@ impl crate::Screen for PianoRollTab {
     }
 
     fn update(&mut self, ctx: &mut crate::Context, ui: &mut egui::Ui) -> Result<()> {
+        egui::TopBottomPanel::top(egui::Id::new(self.id)).show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut self.keyboard_piano, "Keyboard piano")
+                    .on_hover_text("Play notes on the selected track with the computer keyboard");
+            });
+        });
+
+        // this has to happen before the notes are handled so that keys like X don't delete notes
+        self.handle_keyboard_piano(ctx, ui);
+
         egui::CentralPanel::default().show_inside(ui, |ui| {
             SongViewer::new().ui(ctx, ui, |ctx, ui, view| {
                 match Prepared::start(ui, ctx, view, self) {
@@ -84,6 +150,7 @@ impl crate::Screen for PianoRollTab {
                         let rendered_clips = prepared.handle_clips(ui, ctx);
                         prepared.handle_notes(ui, ctx, self, &rendered_clips);
                         prepared.handle_drawn_note(ui, ctx, self);
+                        prepared.handle_piano_keys(ui, ctx, self);
                     }
                     None => {
                         ui.with_layout(
@@ -105,6 +172,40 @@ impl PianoRollTab {
         self.track_id = track_id;
         self.currently_drawn_note = None;
     }
+
+    fn handle_keyboard_piano(&mut self, ctx: &mut crate::Context, ui: &egui::Ui) {
+        let is_active = self.keyboard_piano
+            && ctx.focused_tab() == Some(self.id)
+            && ui.memory(|mem| mem.focused().is_none())
+            && self
+                .track_id
+                .is_some_and(|track_id| ctx.state.tracks.has(track_id));
+
+        // stop notes whose keys were released (or all of them if the keyboard piano isn't active)
+        self.keyboard_piano_notes.retain(|&(key, track_id, note_id)| {
+            if is_active && ui.input(|i| i.key_down(key)) {
+                true
+            } else {
+                ctx.stop_live_note(track_id, note_id);
+                false
+            }
+        });
+
+        if !is_active {
+            return;
+        }
+        let track_id = self.track_id.expect("checked above");
+
+        for (key, pitch) in KEYBOARD_PIANO_KEYS {
+            // consume the key even if it's held so other things don't react to the key repeat
+            if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key))
+                && !self.keyboard_piano_notes.iter().any(|&(k, ..)| k == key)
+            {
+                let note_id = ctx.start_live_note(track_id, pitch);
+                self.keyboard_piano_notes.push((key, track_id, note_id));
+            }
+        }
+    }
 }
 
 // Clips
@@ -500,6 +601,10 @@ impl<'ctx, 'arg> Prepared<'ctx, 'arg> {
             if ui.input(|i| i.pointer.button_released(egui::PointerButton::Primary)) {
                 if let Some((start_pos, note)) = tab.currently_drawn_note.take() {
                     let (clip_range, clip_id) = match track.clip_at(start_pos) {
+                        // audio clips can't have notes
+                        Some((_, clip_id)) if track.clip(clip_id).unwrap().audio.is_some() => {
+                            return;
+                        }
                         Some(data) => data,
                         None => {
                             let clip_id = Id::arbitrary();
@@ -554,4 +659,100 @@ impl<'ctx, 'arg> Prepared<'ctx, 'arg> {
             tab.currently_drawn_note = None;
         }
     }
+
+    fn handle_piano_keys(
+        &mut self,
+        ui: &mut egui::Ui,
+        ctx: &mut crate::Context,
+        tab: &mut PianoRollTab,
+    ) {
+        let Self {
+            track_id,
+            view,
+            ntspc,
+            ..
+        } = *self;
+
+        let visible_rect = view.screen_rect.with_min_y(view.top_bar_rect.bottom());
+        let keys_rect = Rect::from_x_y_ranges(
+            Rangef::new(
+                view.screen_rect.left(),
+                view.screen_rect.left() + PIANO_KEY_WIDTH,
+            ),
+            Rangef::new(
+                ntspc.note_y_to_screen_y(MAX_NOTE_SHOWN),
+                ntspc.note_y_to_screen_y(MIN_NOTE_SHOWN - 1),
+            ),
+        )
+        .intersect(visible_rect);
+
+        let keys_resp = ui.allocate_rect(keys_rect, egui::Sense::click_and_drag());
+
+        let pressed_pitch = if keys_resp.is_pointer_button_down_on() {
+            ui.input(|i| i.pointer.interact_pos()).map(|pos| {
+                ntspc
+                    .screen_y_to_note_y(pos.y)
+                    .clamp(MIN_NOTE_SHOWN, MAX_NOTE_SHOWN)
+            })
+        } else {
+            None
+        };
+
+        // glide between keys when the mouse is dragged over them
+        if let Some((held_track_id, held_pitch, held_note_id)) = tab.held_piano_key
+            && (pressed_pitch != Some(held_pitch) || held_track_id != track_id)
+        {
+            ctx.stop_live_note(held_track_id, held_note_id);
+            tab.held_piano_key = None;
+        }
+        if let Some(pitch) = pressed_pitch
+            && tab.held_piano_key.is_none()
+        {
+            tab.held_piano_key = Some((track_id, pitch, ctx.start_live_note(track_id, pitch)));
+        }
+
+        let painter = ui.painter().with_clip_rect(visible_rect);
+        let stroke = ui.visuals().window_stroke;
+        for pitch in MIN_NOTE_SHOWN..=MAX_NOTE_SHOWN {
+            let key_y = ntspc.note_y_to_screen_y(pitch);
+            let key_rect = Rect::from_x_y_ranges(
+                keys_rect.x_range(),
+                Rangef::new(key_y, key_y + self.units_per_pitch()),
+            );
+
+            let is_held = tab
+                .held_piano_key
+                .is_some_and(|(_, held_pitch, _)| held_pitch == pitch)
+                || tab.keyboard_piano_notes.iter().any(|&(key, ..)| {
+                    KEYBOARD_PIANO_KEYS
+                        .iter()
+                        .any(|&(k, key_pitch)| k == key && key_pitch == pitch)
+                });
+
+            let color = if is_held {
+                ui.visuals().selection.bg_fill
+            } else if is_black_key(pitch) {
+                Color32::from_gray(40)
+            } else {
+                Color32::from_gray(220)
+            };
+            painter.rect(
+                key_rect,
+                CornerRadius::ZERO,
+                color,
+                stroke,
+                StrokeKind::Inside,
+            );
+            if pitch.rem_euclid(12) == 0 {
+                painter.text(
+                    pos2(key_rect.right() - 4.0, key_rect.center().y),
+                    egui::Align2::RIGHT_CENTER,
+                    // middle C is C4
+                    format!("C{}", pitch.div_euclid(12) + 4),
+                    egui::FontId::proportional(self.units_per_pitch() * 0.75),
+                    Color32::from_gray(40),
+                );
+            }
+        }
+    }
 }
@@ -1,16 +1,23 @@
 use core::f32;
-use std::collections::VecDeque;
 
-use anyhow::Result;
-use cubedaw_lib::{Id, IdMap, Range, Track};
+use anyhow::{Context as _, Result};
+use cubedaw_lib::{AudioClip, Clip, Id, IdMap, Range, Sample, State, Track};
 use cubedaw_worker::command::ActionDirection;
 use egui::{Color32, CursorIcon, Pos2, Rect, Sense, Stroke, StrokeKind, UiBuilder};
 
 use crate::{
     app::Tab,
-    state::ui::TrackUiState,
+    command::{
+        clip::{AudioClipChange, ClipAddOrRemove},
+        sample::SampleAdd,
+        track::{TrackGainChange, TrackSendChange},
+    },
+    state::{
+        ephemeral::{FreezeState, TrackDropTarget},
+        ui::TrackUiState,
+    },
     util::Select,
-    widget::{EditableLabel, SongViewer, SongViewerPrepared},
+    widget::{EditableLabel, LevelMeter, Meter, SongViewer, SongViewerPrepared},
 };
 
 #[derive(Debug)]
@@ -130,8 +137,12 @@ struct TrackListEntry<'a> {
     track_id: Id<Track>,
     track: &'a Track,
     track_ui: &'a TrackUiState,
+    freeze: FreezeState,
+    armed: bool,
     position: f32,
     height: f32,
+    /// How deeply the track is nested. Children of the root track are at depth 0 if the root track is hidden.
+    depth: i32,
     indentation: f32,
 }
 impl<'a> TrackListEntry<'a> {
@@ -167,7 +178,8 @@ impl TrackListEntry<'_> {
         rect: egui::Rect,
         should_highlight: bool,
         id_source: u32,
-    ) {
+        meter: Meter,
+    ) -> HeaderClicks {
         let visuals = if should_highlight {
             &ui.visuals().widgets.hovered
         } else {
@@ -186,15 +198,57 @@ impl TrackListEntry<'_> {
                     .max_rect(rect.shrink(4.0))
                     .id_salt(id_source),
             ),
-        );
+            meter,
+        )
     }
-    fn track_header_inner(&self, tracker: &mut crate::context::UiStateTracker, ui: &mut egui::Ui) {
+    fn track_header_inner(
+        &self,
+        tracker: &mut crate::context::UiStateTracker,
+        ui: &mut egui::Ui,
+        meter: Meter,
+    ) -> HeaderClicks {
         let Self {
-            track_id, track_ui, ..
+            track_id,
+            track_ui,
+            freeze,
+            armed,
+            ..
         } = *self;
 
         let mut new_track_name = track_ui.name.clone();
-        ui.add(EditableLabel::new(&mut new_track_name).id_salt(track_id));
+        let clicks = ui
+            .horizontal(|ui| {
+                let arm_response = ui.selectable_label(armed, "⏺").on_hover_text(if armed {
+                    "Disarm"
+                } else {
+                    "Arm for recording"
+                });
+                let freeze_response = ui.add_enabled(
+                    !matches!(freeze, FreezeState::Blocked(_)),
+                    egui::SelectableLabel::new(
+                        matches!(freeze, FreezeState::Rendering | FreezeState::Frozen),
+                        "❄",
+                    ),
+                );
+                let freeze_response = match freeze {
+                    FreezeState::Unfrozen => freeze_response.on_hover_text("Freeze"),
+                    FreezeState::Blocked(blocker) => {
+                        freeze_response.on_disabled_hover_text(format!("Can't freeze: {blocker}"))
+                    }
+                    FreezeState::Rendering => freeze_response.on_hover_text("Freezing..."),
+                    FreezeState::Frozen => freeze_response.on_hover_text("Unfreeze"),
+                };
+                if freeze == FreezeState::Rendering {
+                    ui.spinner();
+                }
+                ui.add(EditableLabel::new(&mut new_track_name).id_salt(track_id));
+                HeaderClicks {
+                    freeze: freeze_response.clicked(),
+                    arm: arm_response.clicked(),
+                }
+            })
+            .inner;
+        ui.add(LevelMeter::new(meter));
 
         if new_track_name != track_ui.name {
             tracker.add(
@@ -208,10 +262,21 @@ impl TrackListEntry<'_> {
                 },
             );
         }
+
+        clicks
     }
 }
 
+/// Which buttons in a track header were clicked.
+#[derive(Debug, Default, Clone, Copy)]
+struct HeaderClicks {
+    freeze: bool,
+    arm: bool,
+}
+
 const DEFAULT_TRACK_HEIGHT: f32 = 48.0;
+/// How far each level of nesting indents a track header.
+const TRACK_INDENTATION: f32 = 16.0;
 
 impl<'ctx> Prepared<'ctx> {
     fn new(ctx: &mut crate::Context<'ctx>, ui: &mut egui::Ui, tab: &mut TrackTab) -> Self {
@@ -247,14 +312,25 @@ impl<'ctx> Prepared<'ctx> {
                         track_id,
                         track,
                         track_ui,
+                        freeze: ctx.ephemeral_state.freeze_state(ctx.state, track_id),
+                        armed: ctx.ephemeral_state.record_armed.contains(&track_id),
                         position: current_y,
                         height,
-                        indentation: depth as f32 * 16.0,
+                        depth,
+                        indentation: depth as f32 * TRACK_INDENTATION,
                     });
                     current_y += height;
                 }
 
-                for &child_id in &ctx.ui_state.tracks.force_get(track_id).track_list {
+                // reversed so they come off the stack in order
+                for &child_id in ctx
+                    .ui_state
+                    .tracks
+                    .force_get(track_id)
+                    .track_list
+                    .iter()
+                    .rev()
+                {
                     track_stack.push((
                         child_id,
                         depth + 1,
@@ -266,6 +342,7 @@ impl<'ctx> Prepared<'ctx> {
         let mut track_list = TrackList::new(track_entries, current_y);
 
         let mut dragging_would_succeed = false;
+        ctx.ephemeral_state.track_drop_target = None;
 
         let width_of_track_header_panel = ui.max_rect().width();
         if let Some(egui::Vec2 {
@@ -282,45 +359,86 @@ impl<'ctx> Prepared<'ctx> {
                 "there are more than u32::MAX tracks. wat"
             );
 
-            let mut dragging_track_list = Vec::new();
-            let mut dragging_tracks = VecDeque::new();
-            let mut not_dragging_tracks = Vec::new();
-
-            for track_entry in track_list.list.into_iter() {
-                if track_entry.would_be_dragged {
-                    dragging_tracks.push_back(track_entry);
+            let (dragging_tracks, mut not_dragging_tracks): (Vec<_>, Vec<_>) = track_list
+                .list
+                .into_iter()
+                .partition(|track_entry| track_entry.would_be_dragged);
+            let root_dragged = dragging_tracks
+                .iter()
+                .any(|track_entry| track_entry.track_id == ctx.state.root_track);
+
+            // the dragged tracks all go in the gap closest to where the topmost one was dragged to
+            let mut gap = not_dragging_tracks.len();
+            let mut depth_offset = 0;
+            if let Some(first) = dragging_tracks.first() {
+                let dragged_top = first.position + raw_movement_y;
+                let mut current_y = ui.max_rect().top();
+                for (i, track_entry) in not_dragging_tracks.iter().enumerate() {
+                    if dragged_top < current_y + track_entry.height * 0.5 {
+                        gap = i;
+                        break;
+                    }
+                    current_y += track_entry.height;
+                }
+                // nothing goes above the root track
+                let min_depth = if ctx.ui_state.show_root_track {
+                    if !root_dragged {
+                        gap = gap.max(1);
+                    }
+                    1
                 } else {
-                    not_dragging_tracks.push(track_entry);
+                    0
+                };
+
+                // a track can be nested under the one above it, or be a sibling of it or any of its ancestors (as
+                // long as the one below doesn't end up under it). dragging sideways chooses which
+                let above = gap.checked_sub(1).map(|i| &not_dragging_tracks[i]);
+                let below = not_dragging_tracks.get(gap);
+                let min = below.map_or(min_depth, |below| below.depth.max(min_depth));
+                let max = above.map_or(min_depth, |above| above.depth + 1);
+                let depth = (first.depth + (raw_movement_x / TRACK_INDENTATION).round() as i32)
+                    .clamp(min, max.max(min));
+                depth_offset = depth - first.depth;
+
+                // the parent is the closest track above that's one level up, and the dragged tracks go after its
+                // closest child above
+                let mut parent = ctx.state.root_track;
+                let mut after = None;
+                for track_entry in not_dragging_tracks[..gap].iter().rev() {
+                    if track_entry.depth == depth - 1 {
+                        parent = track_entry.track_id;
+                        break;
+                    }
+                    if track_entry.depth == depth && after.is_none() {
+                        after = Some(track_entry.track_id);
+                    }
+                }
+                if !root_dragged {
+                    ctx.ephemeral_state.track_drop_target = Some(TrackDropTarget { parent, after });
                 }
             }
 
+            let mut dragging_track_list = Vec::new();
             let mut current_y = ui.max_rect().top();
-            for track_entry in not_dragging_tracks.into_iter().map(Some).chain([None]) {
-                while dragging_tracks.front().is_some_and(|front| {
-                    track_entry.as_ref().is_none_or(|track_entry| {
-                        front.position + raw_movement_y < current_y + track_entry.height * 0.5
-                    })
-                }) {
-                    let front = dragging_tracks.pop_front().unwrap();
-                    let position = {
-                        let y = current_y;
-                        current_y += front.height;
-                        y
-                    };
-                    dragging_track_list.push(TrackListEntry { position, ..front });
-                }
-
-                if let Some(track_entry) = track_entry {
-                    let position = {
-                        let y = current_y;
-                        current_y += track_entry.height;
-                        y
-                    };
-                    dragging_track_list.push(TrackListEntry {
-                        position,
+            let below_gap = not_dragging_tracks.split_off(gap);
+            let entries = not_dragging_tracks
+                .into_iter()
+                .chain(dragging_tracks.into_iter().map(|track_entry| {
+                    let depth = track_entry.depth + depth_offset;
+                    TrackListEntry {
+                        depth,
+                        indentation: depth as f32 * TRACK_INDENTATION,
                         ..track_entry
-                    });
-                }
+                    }
+                }))
+                .chain(below_gap);
+            for track_entry in entries {
+                let position = current_y;
+                current_y += track_entry.height;
+                dragging_track_list.push(TrackListEntry {
+                    position,
+                    ..track_entry
+                });
             }
 
             track_list = TrackList::new(dragging_track_list, current_y);
@@ -334,6 +452,8 @@ impl<'ctx> Prepared<'ctx> {
     }
 
     fn ui_left_sidebar(&mut self, ctx: &mut crate::Context, ui: &mut egui::Ui) {
+        let mut freeze_clicked = None;
+        let mut arm_clicked = None;
         ctx.ephemeral_state.track_drag.handle(
             |pos| pos,
             |prepared: &mut crate::util::Prepared<_, _>| {
@@ -362,6 +482,16 @@ impl<'ctx> Prepared<'ctx> {
                         track_entry.track_ui.select,
                     );
 
+                    response.context_menu(|ui| {
+                        track_menu(
+                            ui,
+                            &mut ctx.tracker,
+                            ctx.state,
+                            ctx.ui_state,
+                            track_entry.track_id,
+                        );
+                    });
+
                     if response.double_clicked() {
                         ctx.tabs
                             .get_or_create_tab::<super::pianoroll::PianoRollTab>(
@@ -385,13 +515,20 @@ impl<'ctx> Prepared<'ctx> {
                         !(prepared.would_be_dragged(track_entry.track_ui.select)
                             && self.dragging_would_succeed),
                         |ui| {
-                            track_entry.track_header(
+                            let clicks = track_entry.track_header(
                                 &mut ctx.tracker,
                                 ui,
                                 rect,
                                 track_entry.is_highlighted,
                                 0,
+                                ctx.ephemeral_state.meters.track(track_entry.track_id),
                             );
+                            if clicks.freeze {
+                                freeze_clicked = Some((track_entry.track_id, track_entry.freeze));
+                            }
+                            if clicks.arm {
+                                arm_clicked = Some(track_entry.track_id);
+                            }
                         },
                     );
                 }
@@ -429,12 +566,22 @@ impl<'ctx> Prepared<'ctx> {
                                 transformed_rect,
                                 true,
                                 1,
+                                ctx.ephemeral_state.meters.track(track_entry.track_id),
                             );
                         });
                     }
                 }
             },
         );
+        if let Some((track_id, freeze)) = freeze_clicked {
+            // clicking while it's still rendering cancels the freeze
+            ctx.set_frozen(track_id, freeze == FreezeState::Unfrozen);
+        }
+        if let Some(track_id) = arm_clicked
+            && !ctx.ephemeral_state.record_armed.remove(&track_id)
+        {
+            ctx.ephemeral_state.record_armed.insert(track_id);
+        }
         let viewport_interaction = ui.response();
         viewport_interaction.context_menu(|ui| {
             let mut b = ctx.ui_state.show_root_track;
@@ -548,7 +695,7 @@ impl<'ctx> Prepared<'ctx> {
                     let track_id = track_entry.track_id;
                     let track = track_entry.track;
 
-                    for (clip_range, clip_id, _clip) in track.clips() {
+                    for (clip_range, clip_id, clip) in track.clips() {
                         let clip_ui = track_entry.track_ui.clips.force_get(clip_id);
 
                         let mut clip_range = clip_range;
@@ -571,17 +718,41 @@ impl<'ctx> Prepared<'ctx> {
                             .on_hover_cursor(CursorIcon::Grab);
 
                         const SECTION_COLOR: Color32 = Color32::from_rgb(145, 0, 235);
+                        const AUDIO_SECTION_COLOR: Color32 = Color32::from_rgb(0, 120, 200);
+                        let color = if clip.audio.is_some() {
+                            AUDIO_SECTION_COLOR
+                        } else {
+                            SECTION_COLOR
+                        };
                         ui.painter().rect(
                             clip_rect,
                             4.0,
                             match clip_ui.select {
-                                Select::Select => SECTION_COLOR.gamma_multiply(0.7),
-                                Select::Deselect => SECTION_COLOR.gamma_multiply(0.5),
+                                Select::Select => color.gamma_multiply(0.7),
+                                Select::Deselect => color.gamma_multiply(0.5),
                             },
-                            Stroke::new(2.0, SECTION_COLOR),
+                            Stroke::new(2.0, color),
                             StrokeKind::Inside,
                         );
 
+                        if let Some(ref audio) = clip.audio
+                            && let Some(sample) = ctx.state.samples.get(audio.sample_id)
+                        {
+                            paint_waveform(ui, view, ctx.state, clip_rect, clip, audio, sample);
+                            clip_response.context_menu(|ui| {
+                                audio_clip_menu(
+                                    ui,
+                                    &mut ctx.tracker,
+                                    track_id,
+                                    clip_id,
+                                    clip,
+                                    audio,
+                                    sample,
+                                    ctx.state.bpm,
+                                );
+                            });
+                        }
+
                         drag.process_interaction(
                             clip_id.cast(),
                             &clip_response,
@@ -593,8 +764,422 @@ impl<'ctx> Prepared<'ctx> {
             );
         }
 
+        // audio files can be dropped onto a track to import them
+        let (hovered_files, dropped_files, pointer_pos) = ui.input(|i| {
+            (
+                !i.raw.hovered_files.is_empty(),
+                i.raw.dropped_files.clone(),
+                i.pointer.latest_pos(),
+            )
+        });
+        // (other tabs ignore files that weren't dropped on them)
+        let pointer_pos = pointer_pos.filter(|&pos| screen_rect.contains(pos));
+        let drop_target = pointer_pos.and_then(|pos| {
+            let (_, entry) = track_list.entry_at_y(pos.y)?;
+            Some((entry, view.input_screen_x_to_song_x(pos.x)))
+        });
+        if hovered_files && let Some((entry, pos)) = drop_target {
+            ui.painter().vline(
+                view.song_x_to_screen_x(pos),
+                entry.actual_pos..=entry.actual_pos + entry.height,
+                ui.visuals().widgets.active.fg_stroke,
+            );
+        }
+        if !dropped_files.is_empty() && pointer_pos.is_some() {
+            match drop_target {
+                Some((entry, pos)) => ctx.import_audio_files(entry.track_id, pos, dropped_files),
+                None => {
+                    ctx.report_error(None, "drop audio files onto a track to import them".into())
+                }
+            }
+        }
+
         view.ui_top_bar(ctx, ui);
 
         view.ui_playhead(ctx, ui);
     }
 }
+
+/// Draws the waveform of an audio clip, with its gain and fades applied.
+fn paint_waveform(
+    ui: &egui::Ui,
+    view: &SongViewerPrepared,
+    state: &State,
+    clip_rect: Rect,
+    clip: &Clip,
+    audio: &AudioClip,
+    sample: &Sample,
+) {
+    let visible_rect = clip_rect.intersect(view.screen_rect);
+    if !visible_rect.is_positive() {
+        return;
+    }
+    let frames_per_unit = sample.sample_rate() as f64 * audio.speed(state.bpm) * 60.0
+        / (state.bpm as f64 * Range::UNITS_PER_BEAT as f64);
+    // `units_per_tick` is actually how wide each unit is on the screen
+    let pos_at = |x: f32| ((x - clip_rect.left()) / view.units_per_tick) as f64;
+    let frame_at = |x: f32| audio.start_offset as f64 + pos_at(x) * frames_per_unit;
+
+    let center = clip_rect.center().y;
+    let half_height = (clip_rect.height() * 0.5 - 4.0).max(0.0);
+    let stroke = Stroke::new(1.0, ui.visuals().strong_text_color().gamma_multiply(0.7));
+
+    let mut x = visible_rect.left().floor();
+    while x < visible_rect.right() {
+        let start = frame_at(x).max(0.0) as usize;
+        let end = (frame_at(x + 1.0).ceil() as usize).max(start + 1);
+        if let Some((min, max)) = sample.peak(start..end) {
+            let gain = audio.gain_at(pos_at(x + 0.5), clip.length) * half_height;
+            ui.painter().vline(
+                x + 0.5,
+                egui::Rangef::new(
+                    center - max.clamp(-1.0, 1.0) * gain,
+                    center - min.clamp(-1.0, 1.0) * gain,
+                ),
+                stroke,
+            );
+        }
+        x += 1.0;
+    }
+}
+
+/// The context menu of a track header: the track's fader and sends.
+fn track_menu(
+    ui: &mut egui::Ui,
+    tracker: &mut crate::context::UiStateTracker,
+    state: &State,
+    ui_state: &crate::UiState,
+    track_id: Id<Track>,
+) {
+    let track = state.tracks.force_get(track_id);
+    let mut gain = track.gain();
+    let response = ui.add(egui::Slider::new(&mut gain, 0.0..=2.0).text("Volume"));
+    if response.drag_started() || (gain != track.gain() && !response.dragged()) {
+        tracker.add(TrackGainChange::new(track_id, track.gain(), gain));
+    } else if gain != track.gain() {
+        tracker.add_weak(TrackGainChange::new(track_id, track.gain(), gain));
+    }
+
+    ui.separator();
+    ui.label("Sends");
+    let mut sends: Vec<_> = track
+        .sends()
+        .iter()
+        .filter_map(|(target_id, &send)| {
+            // sends to deleted tracks come back if the deletion is undone
+            let target_ui = ui_state.tracks.get(target_id)?;
+            Some((target_id, target_ui.name.as_str(), send))
+        })
+        .collect();
+    sends.sort_by_key(|&(_, name, _)| name);
+    for (target_id, name, send) in sends {
+        ui.horizontal(|ui| {
+            let mut new_send = send;
+            ui.label(name);
+            let response = ui.add(egui::Slider::new(&mut new_send.amount, 0.0..=2.0));
+            ui.checkbox(&mut new_send.pre_fader, "Pre-fader")
+                .on_hover_text("Send the track's output before its volume is applied");
+            let new_send = (!ui.button("🗑").on_hover_text("Remove").clicked()).then_some(new_send);
+
+            let Ok(command) = TrackSendChange::new(state, track_id, target_id, new_send) else {
+                return;
+            };
+            if response.drag_started() || (new_send != Some(send) && !response.dragged()) {
+                tracker.add(command);
+            } else if new_send != Some(send) {
+                tracker.add_weak(command);
+            }
+        });
+    }
+
+    ui.menu_button("Add send", |ui| {
+        let mut targets: Vec<_> = ui_state
+            .tracks
+            .iter()
+            .filter(|&(target_id, _)| {
+                !track.sends().has(target_id) && !state.track_depends_on(track_id, target_id)
+            })
+            .map(|(target_id, target_ui)| (target_id, target_ui.name.as_str()))
+            .collect();
+        targets.sort_by_key(|&(_, name)| name);
+        if targets.is_empty() {
+            ui.label("No tracks to send to");
+        }
+        for (target_id, name) in targets {
+            if ui.button(name).clicked() {
+                match TrackSendChange::new(state, track_id, target_id, Some(Default::default())) {
+                    Ok(command) => tracker.add(command),
+                    Err(err) => tracing::warn!("couldn't add send: {err}"),
+                }
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+/// The context menu of an audio clip.
+#[allow(clippy::too_many_arguments)]
+fn audio_clip_menu(
+    ui: &mut egui::Ui,
+    tracker: &mut crate::context::UiStateTracker,
+    track_id: Id<Track>,
+    clip_id: Id<Clip>,
+    clip: &Clip,
+    audio: &AudioClip,
+    sample: &Sample,
+    bpm: f32,
+) {
+    const UNITS_PER_BEAT: f64 = Range::UNITS_PER_BEAT as f64;
+
+    ui.label(&sample.name);
+    let mut new_audio = audio.clone();
+    let sample_rate = sample.sample_rate() as f64;
+    let responses = [
+        ui.add(egui::Slider::new(&mut new_audio.gain, 0.0..=2.0).text("Gain")),
+        ui.add(
+            egui::DragValue::new(&mut new_audio.start_offset)
+                .range(0..=sample.len())
+                .speed(sample_rate / 100.0)
+                .custom_formatter(move |frames, _| format!("{:.3} s", frames / sample_rate))
+                .custom_parser(move |text| {
+                    let text = text.trim().trim_end_matches('s');
+                    text.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .map(|secs| secs * sample_rate)
+                })
+                .prefix("Offset: "),
+        ),
+        ui.add(beats_drag_value(&mut new_audio.fade_in, clip.length).prefix("Fade in: ")),
+        ui.add(beats_drag_value(&mut new_audio.fade_out, clip.length).prefix("Fade out: ")),
+        ui.add(
+            egui::DragValue::new(&mut new_audio.pitch)
+                .range(-24.0..=24.0)
+                .speed(0.05)
+                .fixed_decimals(2)
+                .prefix("Pitch: ")
+                .suffix(" st"),
+        ),
+    ];
+
+    ui.separator();
+    let mut follow_tempo = new_audio.source_bpm.is_some();
+    if ui
+        .checkbox(&mut follow_tempo, "Follow tempo")
+        .on_hover_text("Time-stretch the audio so it stays in time when the tempo changes")
+        .changed()
+    {
+        new_audio.source_bpm = follow_tempo.then_some(bpm);
+    }
+    let mut tempo_responses = None;
+    if let Some(ref mut source_bpm) = new_audio.source_bpm {
+        tempo_responses = Some(
+            ui.add(
+                egui::DragValue::new(source_bpm)
+                    .range(10.0..=999.0)
+                    .speed(0.1)
+                    .prefix("Recorded at: ")
+                    .suffix(" BPM"),
+            )
+            .on_hover_text("The tempo the audio was recorded at"),
+        );
+    }
+
+    let responses = || responses.iter().chain(&tempo_responses);
+    let drag_started = responses().any(|r| r.drag_started());
+    let dragged = responses().any(|r| r.dragged());
+    if drag_started || (new_audio != *audio && !dragged) {
+        tracker.add(AudioClipChange::new(
+            track_id,
+            clip_id,
+            audio.clone(),
+            new_audio,
+        ));
+    } else if new_audio != *audio {
+        tracker.add_weak(AudioClipChange::new(
+            track_id,
+            clip_id,
+            audio.clone(),
+            new_audio,
+        ));
+    }
+
+    fn beats_drag_value(value: &mut u64, max: u64) -> egui::DragValue<'_> {
+        egui::DragValue::new(value)
+            .range(0..=max)
+            .speed(4.0)
+            .custom_formatter(|units, _| format!("{:.2} beats", units / UNITS_PER_BEAT))
+            .custom_parser(|text| {
+                let text = text.trim().trim_end_matches("beats");
+                text.trim()
+                    .parse::<f64>()
+                    .ok()
+                    .map(|beats| beats * UNITS_PER_BEAT)
+            })
+    }
+}
+
+/// Audio files that were decoded in the background, ready to be put on a track with [`add_imported_audio`].
+#[derive(Debug)]
+pub struct ImportedAudio {
+    pub track_id: Id<Track>,
+    pub start_pos: i64,
+    /// The name of each file and what decoding it gave.
+    pub samples: Vec<(String, Result<Sample>)>,
+}
+
+/// Reads and decodes dropped audio files. This can take a while, so it's done off the UI thread.
+pub fn decode_audio_files(files: &[egui::DroppedFile]) -> Vec<(String, Result<Sample>)> {
+    files
+        .iter()
+        .map(|file| {
+            let name = match file.path {
+                Some(ref path) => path.file_stem().map_or_else(
+                    || file.name.clone(),
+                    |stem| stem.to_string_lossy().into_owned(),
+                ),
+                None => file.name.clone(),
+            };
+            let sample = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => Ok(bytes.to_vec()),
+                (None, Some(path)) => std::fs::read(path)
+                    .with_context(|| format!("failed to read {}", path.display())),
+                (None, None) => Err(anyhow::anyhow!("the file has no data")),
+            }
+            .and_then(|bytes| cubedaw_worker::wav::decode(name.clone(), &bytes));
+            (name, sample)
+        })
+        .collect()
+}
+
+/// Puts imported audio files on their track as audio clips, one after the other starting at their start position.
+pub fn add_imported_audio(ctx: &mut crate::Context, imported: ImportedAudio) {
+    let ImportedAudio {
+        track_id,
+        mut start_pos,
+        samples,
+    } = imported;
+    if !ctx.state.tracks.has(track_id) {
+        ctx.report_error(None, "failed to import audio: the track was deleted".into());
+        return;
+    }
+
+    // importing several files is one undo entry
+    let mut first = true;
+    for (name, sample) in samples {
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(error) => {
+                ctx.report_error(
+                    Some(track_id),
+                    format!("failed to import {name}: {error:#}"),
+                );
+                continue;
+            }
+        };
+
+        let length = (sample.duration_seconds() * ctx.state.bpm as f64 / 60.0
+            * Range::UNITS_PER_BEAT as f64)
+            .ceil()
+            .max(1.0) as i64;
+        // clips can't overlap, so the clip is cut short if there's another clip in the way
+        let track = ctx.state.tracks.force_get(track_id);
+        if track.clip_at(start_pos).is_some() {
+            ctx.report_error(
+                Some(track_id),
+                format!("failed to import {name}: there's already a clip there"),
+            );
+            continue;
+        }
+        let end = track
+            .clips_intersecting(Range::new(start_pos, start_pos + length))
+            .map(|(range, _)| range.start)
+            .min()
+            .unwrap_or(start_pos + length);
+
+        let sample_id = Id::arbitrary();
+        let clip = Clip::audio(name, (end - start_pos) as u64, AudioClip::new(sample_id));
+        if first {
+            ctx.tracker.add(SampleAdd::new(sample_id, sample));
+            first = false;
+        } else {
+            ctx.tracker.add_weak(SampleAdd::new(sample_id, sample));
+        }
+        ctx.tracker.add_weak(ClipAddOrRemove::addition(
+            Id::arbitrary(),
+            start_pos,
+            clip,
+            track_id,
+        ));
+        start_pos = end;
+    }
+}
+
+/// Puts a recording from the worker host onto every record-armed track, as an audio clip.
+pub fn add_recording(ctx: &mut crate::Context, recording: crate::workerhost::Recording) {
+    if recording.audio.is_empty() {
+        return;
+    }
+    let armed: Vec<Id<Track>> = ctx
+        .ephemeral_state
+        .record_armed
+        .iter()
+        .copied()
+        .filter(|&track_id| ctx.state.tracks.has(track_id))
+        .collect();
+    if armed.is_empty() {
+        ctx.report_error(None, "recording discarded: no tracks are armed".into());
+        return;
+    }
+
+    // latency compensation can put the start before the song does; that part can't be placed anywhere
+    let mut audio = recording.audio;
+    let mut start_pos = recording.start;
+    if start_pos < 0 {
+        let samples_before_start = (-start_pos as f64 / ctx.state.bpm as f64 * 60.0
+            / Range::UNITS_PER_BEAT as f64
+            * recording.sample_rate as f64)
+            .ceil() as usize;
+        if samples_before_start >= audio.len() {
+            return;
+        }
+        audio.drain(..samples_before_start);
+        start_pos = 0;
+    }
+    let sample = Sample::new("Recording".into(), recording.sample_rate, audio.into());
+    let length = (sample.duration_seconds() * ctx.state.bpm as f64 / 60.0
+        * Range::UNITS_PER_BEAT as f64)
+        .ceil()
+        .max(1.0) as i64;
+
+    // all the tracks share the sample, and the whole recording is one undo entry
+    let sample_id = Id::arbitrary();
+    ctx.tracker.add(SampleAdd::new(sample_id, sample));
+    for track_id in armed {
+        let track = ctx.state.tracks.force_get(track_id);
+        if track.clip_at(start_pos).is_some() {
+            ctx.report_error(
+                Some(track_id),
+                "failed to add the recording: there's already a clip there".into(),
+            );
+            continue;
+        }
+        // like with imports, the clip is cut short if there's another clip in the way
+        let end = track
+            .clips_intersecting(Range::new(start_pos, start_pos + length))
+            .map(|(range, _)| range.start)
+            .min()
+            .unwrap_or(start_pos + length);
+        let clip = Clip::audio(
+            "Recording".into(),
+            (end - start_pos) as u64,
+            AudioClip::new(sample_id),
+        );
+        ctx.tracker.add_weak(ClipAddOrRemove::addition(
+            Id::arbitrary(),
+            start_pos,
+            clip,
+            track_id,
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use cubedaw_lib::{Id, IdMap, Track};
+use cubedaw_worker::Level;
+use egui::{Color32, Rect, Sense, Vec2};
+
+use crate::workerhost::Levels;
+
+/// How fast the meters fall back down after a peak.
+const FALLOFF_DB_PER_SECOND: f32 = 24.0;
+/// How long the clip indicator stays lit after the signal clips.
+const CLIP_HOLD: Duration = Duration::from_secs(2);
+/// The quietest level shown on a meter.
+const MIN_DB: f32 = -60.0;
+
+fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-9).log10()
+}
+fn db_to_amplitude(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Display state of a level meter. This falls off smoothly instead of jumping around every buffer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Meter {
+    peak: f32,
+    rms: f32,
+    last_update: Option<Instant>,
+    last_clip: Option<Instant>,
+}
+
+impl Meter {
+    pub fn update(&mut self, level: Level, now: Instant) {
+        let elapsed = self
+            .last_update
+            .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+        let falloff = db_to_amplitude(-FALLOFF_DB_PER_SECOND * elapsed);
+
+        self.peak = level.peak.max(self.peak * falloff);
+        self.rms = level.rms.max(self.rms * falloff);
+        self.last_update = Some(now);
+        if level.clipped {
+            self.last_clip = Some(now);
+        }
+    }
+
+    pub fn is_clipping(&self, now: Instant) -> bool {
+        self.last_clip
+            .is_some_and(|last_clip| now - last_clip < CLIP_HOLD)
+    }
+    /// Whether the meter shows anything; if it does, it'll probably change next frame.
+    pub fn is_active(&self, now: Instant) -> bool {
+        amplitude_to_db(self.peak) > MIN_DB || self.is_clipping(now)
+    }
+}
+
+/// The meters of the master output and every track.
+#[derive(Debug, Default)]
+pub struct Meters {
+    pub master: Meter,
+    pub tracks: IdMap<Track, Meter>,
+}
+
+impl Meters {
+    pub fn update(&mut self, levels: &Levels, now: Instant) {
+        self.master.update(levels.master, now);
+        self.tracks.retain(|track_id, _| {
+            levels
+                .tracks
+                .iter()
+                .any(|&(other_id, _)| other_id == track_id)
+        });
+        for &(track_id, level) in &levels.tracks {
+            self.tracks
+                .get_mut_or_insert_default(track_id)
+                .update(level, now);
+        }
+    }
+    pub fn track(&self, track_id: Id<Track>) -> Meter {
+        self.tracks.get(track_id).copied().unwrap_or_default()
+    }
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.master.is_active(now) || self.tracks.values().any(|meter| meter.is_active(now))
+    }
+}
+
+/// A horizontal level meter showing RMS as a bar, peak as a line and a clip indicator at the end.
+pub struct LevelMeter {
+    meter: Meter,
+}
+
+impl LevelMeter {
+    pub fn new(meter: Meter) -> Self {
+        Self { meter }
+    }
+}
+
+impl egui::Widget for LevelMeter {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let height = 6.0;
+        let (rect, response) = ui.allocate_exact_size(
+            Vec2::new(ui.available_width().max(height * 4.0), height),
+            Sense::hover(),
+        );
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let painter = ui.painter();
+        let clip_width = height;
+        let bar_rect = rect.with_max_x(rect.right() - clip_width - 2.0);
+        let clip_rect = rect.with_min_x(rect.right() - clip_width);
+
+        let x_of = |amplitude: f32| {
+            let fraction = (1.0 - amplitude_to_db(amplitude) / MIN_DB).clamp(0.0, 1.0);
+            bar_rect.left() + fraction * bar_rect.width()
+        };
+        let color_of = |amplitude: f32| match amplitude_to_db(amplitude) {
+            db if db > -1.0 => Color32::from_rgb(230, 60, 40),
+            db if db > -12.0 => Color32::from_rgb(230, 200, 40),
+            _ => Color32::from_rgb(60, 200, 80),
+        };
+
+        painter.rect_filled(rect, 1.0, ui.visuals().extreme_bg_color);
+
+        let rms_x = x_of(self.meter.rms);
+        if rms_x > bar_rect.left() {
+            painter.rect_filled(bar_rect.with_max_x(rms_x), 1.0, color_of(self.meter.rms));
+        }
+        let peak_x = x_of(self.meter.peak);
+        if peak_x > bar_rect.left() {
+            painter.rect_filled(
+                Rect::from_x_y_ranges(peak_x - 1.0..=peak_x, bar_rect.y_range()),
+                0.0,
+                color_of(self.meter.peak),
+            );
+        }
+
+        if self.meter.is_clipping(Instant::now()) {
+            painter.rect_filled(clip_rect, 1.0, Color32::from_rgb(230, 60, 40));
+        }
+
+        response.on_hover_ui(|ui| {
+            ui.label(format!(
+                "peak {:.1} dB, rms {:.1} dB",
+                amplitude_to_db(self.meter.peak),
+                amplitude_to_db(self.meter.rms)
+            ));
+        })
+    }
+}
@@ -2,6 +2,10 @@ mod drag_value;
 pub use drag_value::{DefaultValueDisplay, DragValue, ValueHandler, ValueHandlerContext};
 mod editable_label;
 pub use editable_label::EditableLabel;
+mod meter;
+pub use meter::{LevelMeter, Meter, Meters};
+mod probe_scope;
+pub use probe_scope::ProbeScope;
 mod song_viewer;
 pub use song_viewer::{SongViewer, SongViewerPrepared};
 
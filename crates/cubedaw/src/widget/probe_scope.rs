@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use cubedaw_worker::ProbeRing;
+use egui::{Color32, Pos2, Sense, Stroke, Vec2};
+
+/// How many of the most recent samples the scope shows.
+const SCOPE_SAMPLES: usize = 2048;
+
+/// An oscilloscope showing the most recent samples of a probe, along with a readout of the latest value.
+pub struct ProbeScope<'a> {
+    ring: &'a ProbeRing,
+}
+
+impl<'a> ProbeScope<'a> {
+    pub fn new(ring: &'a ProbeRing) -> Self {
+        Self { ring }
+    }
+}
+
+impl egui::Widget for ProbeScope<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let mut samples = vec![0.0; SCOPE_SAMPLES.min(self.ring.capacity())];
+        let written = self.ring.read_latest(&mut samples);
+
+        let response = ui
+            .vertical(|ui| {
+                let (rect, response) =
+                    ui.allocate_exact_size(Vec2::new(ui.available_width(), 40.0), Sense::hover());
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+                if written == 0 {
+                    painter.text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        "No signal",
+                        egui::FontId::proportional(10.0),
+                        ui.visuals().weak_text_color(),
+                    );
+                } else {
+                    // scale so everything fits, but don't blow up quiet signals too much
+                    let peak = samples.iter().copied().map(f32::abs).fold(1.0, f32::max);
+                    let y_of = |val: f32| rect.center().y - val / peak * rect.height() * 0.5;
+
+                    painter.hline(
+                        rect.x_range(),
+                        rect.center().y,
+                        Stroke::new(1.0, ui.visuals().faint_bg_color),
+                    );
+
+                    // draw the min and max of the samples in each column, so nothing gets aliased away
+                    let num_columns = (rect.width().floor() as usize).max(1);
+                    let stroke = Stroke::new(1.0, Color32::from_rgb(80, 200, 240));
+                    for (column, chunk) in samples
+                        .chunks(samples.len().div_ceil(num_columns))
+                        .enumerate()
+                    {
+                        let (min, max) = chunk
+                            .iter()
+                            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &val| {
+                                (min.min(val), max.max(val))
+                            });
+                        let x = rect.left() + column as f32 + 0.5;
+                        painter.line_segment(
+                            [Pos2::new(x, y_of(max) - 0.5), Pos2::new(x, y_of(min) + 0.5)],
+                            stroke,
+                        );
+                    }
+                }
+
+                let latest = samples.last().copied().unwrap_or(0.0);
+                let peak = samples.iter().copied().map(f32::abs).fold(0.0, f32::max);
+                ui.small(format!("{latest:.3} (peak {peak:.3})"));
+
+                response
+            })
+            .inner;
+
+        // probes are updated from the audio thread, so keep redrawing
+        ui.ctx().request_repaint_after(Duration::from_millis(30));
+
+        response
+    }
+}
@@ -1,8 +1,7 @@
 use cubedaw_lib::{PreciseSongPos, Range};
 use egui::{CornerRadius, NumExt, Pos2, Rangef, Rect, Response, Sense, UiBuilder, Vec2};
 
-// TODO make these not hardcoded
-const BEATS_PER_BAR: i64 = 4;
+// TODO make this not hardcoded
 const TOP_BAR_HEIGHT: f32 = 18.0;
 
 // Number of empty ticks to display on either side of the song
@@ -54,6 +53,10 @@ impl Default for SongViewer {
     }
 }
 
+fn units_per_bar(ctx: &crate::Context) -> i64 {
+    ctx.state.beats_per_bar.max(1) as i64 * Range::UNITS_PER_BEAT as i64
+}
+
 pub struct SongViewerPrepared<'a> {
     pub max_rect: Rect,
     pub screen_rect: Rect,
@@ -180,7 +183,7 @@ impl<'a> SongViewerPrepared<'a> {
 
         for pos in self.song_view_range.iter_snap_to(self.vbar_step) {
             let stroke =
-                if pos % (BEATS_PER_BAR * Range::UNITS_PER_BEAT as i64) == 0 {
+                if pos % units_per_bar(ctx) == 0 {
                     ui.visuals().widgets.hovered.bg_stroke
                 } else {
                     const NUM_DIVISIONS_THING: u32 = 4;
@@ -221,10 +224,28 @@ impl<'a> SongViewerPrepared<'a> {
             );
         }
 
+        // loop range
+        {
+            let loop_screen_range = self.song_range_to_screen_range(ctx.state.loop_range);
+            let color = if ctx.state.looping {
+                ui.visuals().selection.bg_fill
+            } else {
+                ui.visuals().widgets.inactive.bg_fill
+            };
+            ui.painter().rect_filled(
+                Rect::from_x_y_ranges(
+                    loop_screen_range,
+                    Rangef::new(top_bar_rect.top(), top_bar_rect.top() + 4.0),
+                ),
+                CornerRadius::ZERO,
+                color,
+            );
+        }
+
         // Bar indicators
-        const UNITS_PER_BAR: i64 = BEATS_PER_BAR * Range::UNITS_PER_BEAT as i64;
-        for bar in self.song_view_range.multiples_within_range(UNITS_PER_BAR) {
-            let pos = bar * UNITS_PER_BAR;
+        let units_per_bar = units_per_bar(ctx);
+        for bar in self.song_view_range.multiples_within_range(units_per_bar) {
+            let pos = bar * units_per_bar;
 
             ui.painter().text(
                 Pos2::new(
@@ -254,6 +275,34 @@ impl<'a> SongViewerPrepared<'a> {
                 ));
         }
 
+        // dragging on the top bar sets the loop range
+        if top_bar_interaction.dragged_by(egui::PointerButton::Primary)
+            && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+            && let Some(pointer_pos) = top_bar_interaction.interact_pointer_pos()
+        {
+            let a = self.input_screen_x_to_song_x(origin.x);
+            let b = self.input_screen_x_to_song_x(pointer_pos.x);
+            // until the drag covers some distance the loop stays as it was, but the undo entry is still started here
+            let command = if a != b {
+                crate::command::misc::LoopChange::new(
+                    ctx.state,
+                    Range::new(a.min(b), a.max(b)),
+                    true,
+                )
+            } else {
+                crate::command::misc::LoopChange::new(
+                    ctx.state,
+                    ctx.state.loop_range,
+                    ctx.state.looping,
+                )
+            };
+            if top_bar_interaction.drag_started() {
+                ctx.tracker.add(command);
+            } else if a != b {
+                ctx.tracker.add_weak(command);
+            }
+        }
+
         top_bar_interaction
     }
     pub fn ui_playhead(&self, ctx: &mut crate::Context, ui: &mut egui::Ui) {
@@ -0,0 +1,164 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use anyhow::Context as _;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cubedaw_lib::{Buffer, InternalBufferType};
+use cubedaw_worker::WorkerOptions;
+
+use super::{Clock, OutputSink};
+
+/// Plays audio through an audio device.
+pub struct CpalSink {
+    device: cpal::Device,
+    queued_buffers: u32,
+    stream: Option<OpenStream>,
+    clock: Clock,
+    /// How many times audio was dropped because the ring buffer was full, i.e. because we got ahead of the device.
+    overflows: u32,
+}
+
+struct OpenStream {
+    // the stream stops when this is dropped
+    _stream: cpal::Stream,
+    output_ring_buffer: crossbeam_channel::Sender<InternalBufferType>,
+    /// How many times the stream ran out of audio. Written by the audio callback.
+    underflows: Arc<AtomicU32>,
+    /// How many samples it takes the device to play audio it's been given. Written by the audio callback.
+    device_latency: Arc<AtomicU32>,
+
+    sample_rate: u32,
+    buffer_size: u32,
+}
+
+impl CpalSink {
+    /// Creates a sink for `device`. The stream is only opened once audio is written.
+    pub fn new(device: cpal::Device, queued_buffers: u32) -> Self {
+        Self {
+            device,
+            queued_buffers,
+            stream: None,
+            clock: Clock::default(),
+            overflows: 0,
+        }
+    }
+
+    /// Opens the stream if it isn't open yet or if the options changed since it was opened.
+    fn open(&mut self, options: &WorkerOptions) -> anyhow::Result<&mut OpenStream> {
+        if self.stream.as_ref().is_some_and(|stream| {
+            stream.sample_rate != options.sample_rate || stream.buffer_size != options.buffer_size
+        }) {
+            self.stream = None;
+        }
+        if let Some(ref mut stream) = self.stream {
+            return Ok(stream);
+        }
+
+        let (tx, rx) = crossbeam_channel::bounded::<InternalBufferType>(
+            (options.buffer_size as usize / InternalBufferType::N * self.queued_buffers as usize)
+                .max(1),
+        );
+
+        let underflows = Arc::new(AtomicU32::new(0));
+        let callback_underflows = underflows.clone();
+        let device_latency = Arc::new(AtomicU32::new(0));
+        let callback_device_latency = device_latency.clone();
+        let sample_rate = options.sample_rate;
+        let mut ring_buffer: VecDeque<f32> =
+            VecDeque::with_capacity(options.buffer_size as usize * 2);
+        let stream = self
+            .device
+            .build_output_stream(
+                &cpal::StreamConfig {
+                    channels: 1,
+                    sample_rate: cpal::SampleRate(options.sample_rate),
+                    buffer_size: cpal::BufferSize::Fixed(options.buffer_size),
+                },
+                move |buffer: &mut [f32], info| {
+                    let timestamp = info.timestamp();
+                    if let Some(delay) = timestamp.playback.duration_since(&timestamp.callback) {
+                        callback_device_latency.store(
+                            (delay.as_secs_f64() * sample_rate as f64) as u32,
+                            Ordering::Relaxed,
+                        );
+                    }
+                    let mut underflowed = false;
+                    for val in buffer.iter_mut() {
+                        if ring_buffer.is_empty() {
+                            ring_buffer.extend(
+                                rx.try_recv()
+                                    .unwrap_or_else(|_| {
+                                        underflowed = true;
+                                        bytemuck::zeroed()
+                                    })
+                                    .as_array(),
+                            );
+                        }
+                        let unclamped_val = ring_buffer.pop_front().expect("unreachable");
+                        *val = unclamped_val.clamp(-1.0, 1.0);
+                    }
+                    // one dropout per callback, no matter how much of it was silence
+                    if underflowed {
+                        callback_underflows.fetch_add(1, Ordering::Relaxed);
+                    }
+                },
+                |err| tracing::error!("audio stream error: {err}"),
+                None,
+            )
+            .context("failed to build output stream")?;
+        stream.play().context("failed to start output stream")?;
+
+        Ok(self.stream.insert(OpenStream {
+            _stream: stream,
+            output_ring_buffer: tx,
+            underflows,
+            device_latency,
+
+            sample_rate: options.sample_rate,
+            buffer_size: options.buffer_size,
+        }))
+    }
+}
+
+impl OutputSink for CpalSink {
+    fn write(&mut self, buffer: &Buffer, options: &WorkerOptions) -> anyhow::Result<()> {
+        let stream = self.open(options)?;
+        let mut overflowed = false;
+        for &data in buffer.as_internal() {
+            match stream.output_ring_buffer.try_send(data) {
+                Ok(()) => (),
+                Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
+                    anyhow::bail!("audio stream closed");
+                }
+                Err(crossbeam_channel::TrySendError::Full(_)) => overflowed = true,
+            }
+        }
+        // one dropout per buffer, no matter how much of it was dropped
+        if overflowed {
+            self.overflows += 1;
+        }
+
+        // the ring buffer is deep enough that the device can't pace us; TODO let the device do the pacing
+        self.clock.wait(options);
+        Ok(())
+    }
+    fn take_xruns(&mut self) -> u32 {
+        let underflows = self
+            .stream
+            .as_ref()
+            .map_or(0, |stream| stream.underflows.swap(0, Ordering::Relaxed));
+        self.clock.take_xruns() + underflows + std::mem::take(&mut self.overflows)
+    }
+    fn latency(&self) -> u32 {
+        self.stream.as_ref().map_or(0, |stream| {
+            // what's still waiting to be sent to the device, plus the device's own latency
+            (stream.output_ring_buffer.len() * InternalBufferType::N) as u32
+                + stream.device_latency.load(Ordering::Relaxed)
+        })
+    }
+}
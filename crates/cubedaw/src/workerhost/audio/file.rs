@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context as _;
+use cubedaw_lib::Buffer;
+use cubedaw_worker::{WorkerOptions, wav};
+
+use super::{Clock, OutputSink};
+
+/// Writes audio to a mono 32-bit float WAV file, in realtime.
+pub struct WavFileSink {
+    path: PathBuf,
+    writer: Option<WavWriter>,
+    clock: Clock,
+}
+
+impl WavFileSink {
+    /// Creates a sink for the file at `path`. The file is only created once audio is written.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            writer: None,
+            clock: Clock::default(),
+        }
+    }
+}
+
+impl OutputSink for WavFileSink {
+    fn write(&mut self, buffer: &Buffer, options: &WorkerOptions) -> anyhow::Result<()> {
+        if self
+            .writer
+            .as_ref()
+            .is_some_and(|writer| writer.sample_rate != options.sample_rate)
+        {
+            // a WAV file can't change sample rates partway through; start over
+            if let Some(writer) = self.writer.take() {
+                writer.finish()?;
+            }
+        }
+        let writer = match self.writer {
+            Some(ref mut writer) => writer,
+            None => self.writer.insert(
+                WavWriter::create(&self.path, options.sample_rate)
+                    .with_context(|| format!("failed to create {}", self.path.display()))?,
+            ),
+        };
+        writer
+            .write(buffer)
+            .with_context(|| format!("failed to write to {}", self.path.display()))?;
+
+        self.clock.wait(options);
+        Ok(())
+    }
+    fn take_xruns(&mut self) -> u32 {
+        self.clock.take_xruns()
+    }
+    fn latency(&self) -> u32 {
+        0
+    }
+}
+
+impl Drop for WavFileSink {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take()
+            && let Err(error) = writer.finish()
+        {
+            tracing::error!("failed to finish {}: {error:#}", self.path.display());
+        }
+    }
+}
+
+struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    num_samples: u32,
+}
+
+impl WavWriter {
+    fn create(path: &PathBuf, sample_rate: u32) -> io::Result<Self> {
+        let mut this = Self {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate,
+            num_samples: 0,
+        };
+        // the lengths are filled in by `finish`
+        this.write_header()?;
+        Ok(this)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        wav::write_header(&mut self.file, self.sample_rate, self.num_samples)
+    }
+
+    fn write(&mut self, buffer: &Buffer) -> io::Result<()> {
+        for sample in buffer.iter() {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.num_samples += buffer.len() as u32;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
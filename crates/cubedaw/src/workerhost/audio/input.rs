@@ -0,0 +1,371 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use anyhow::Context as _;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cubedaw_lib::Buffer;
+use cubedaw_worker::WorkerOptions;
+
+/// Where the worker host's live input comes from. See the `builtin:audio_input` node.
+pub enum AudioInput {
+    /// An audio device, like a microphone.
+    Device(cpal::Device),
+    /// A WAV file, played on a loop. Mostly useful for testing without an audio device.
+    File(PathBuf),
+    /// Nothing. The input is silent.
+    Null,
+}
+
+impl AudioInput {
+    /// Creates the source. Like [`super::AudioOutput::into_sink`], this has to happen on the thread that uses it.
+    pub fn into_source(self) -> Box<dyn InputSource> {
+        match self {
+            Self::Device(device) => Box::new(CpalSource::new(device)),
+            Self::File(path) => Box::new(WavFileSource::new(path)),
+            Self::Null => Box::new(NullSource),
+        }
+    }
+}
+
+/// Something the worker host reads live audio from.
+pub trait InputSource {
+    /// Fills `buffer` with the next buffer of audio. This shouldn't block; if there isn't enough audio yet, the rest is silent.
+    fn read(&mut self, buffer: &mut Buffer, options: &WorkerOptions) -> anyhow::Result<()>;
+    /// How many samples pass between audio coming in and it being read.
+    fn latency(&self) -> u32;
+}
+
+/// Reads silence.
+pub struct NullSource;
+
+impl InputSource for NullSource {
+    fn read(&mut self, buffer: &mut Buffer, _options: &WorkerOptions) -> anyhow::Result<()> {
+        buffer.fill(0.0);
+        Ok(())
+    }
+    fn latency(&self) -> u32 {
+        0
+    }
+}
+
+/// Records audio from an audio device.
+pub struct CpalSource {
+    device: cpal::Device,
+    stream: Option<OpenStream>,
+}
+
+struct OpenStream {
+    // the stream stops when this is dropped
+    _stream: cpal::Stream,
+    input_ring_buffer: crossbeam_channel::Receiver<f32>,
+    /// How many samples it takes the device to deliver audio it's captured. Written by the audio callback.
+    device_latency: Arc<AtomicU32>,
+
+    sample_rate: u32,
+    buffer_size: u32,
+}
+
+impl CpalSource {
+    /// How many buffers of audio can pile up before the oldest is thrown away. More means fewer dropouts but more latency.
+    const MAX_QUEUED_BUFFERS: usize = 2;
+    /// The sample formats [`Self::build_stream`] is used with.
+    const SAMPLE_FORMATS: [cpal::SampleFormat; 4] = [
+        cpal::SampleFormat::F32,
+        cpal::SampleFormat::I16,
+        cpal::SampleFormat::U16,
+        cpal::SampleFormat::I32,
+    ];
+
+    /// Creates a source for `device`. The stream is only opened once audio is read.
+    pub fn new(device: cpal::Device) -> Self {
+        Self {
+            device,
+            stream: None,
+        }
+    }
+
+    /// Opens the stream if it isn't open yet or if the options changed since it was opened.
+    fn open(&mut self, options: &WorkerOptions) -> anyhow::Result<&mut OpenStream> {
+        if self.stream.as_ref().is_some_and(|stream| {
+            stream.sample_rate != options.sample_rate || stream.buffer_size != options.buffer_size
+        }) {
+            self.stream = None;
+        }
+        if let Some(ref mut stream) = self.stream {
+            return Ok(stream);
+        }
+
+        let (config, sample_format) = self.choose_config(options)?;
+        // room for a few extra buffers so a late worker host doesn't lose audio
+        let (tx, rx) = crossbeam_channel::bounded::<f32>(
+            options.buffer_size as usize * (Self::MAX_QUEUED_BUFFERS + 2),
+        );
+        let device_latency = Arc::new(AtomicU32::new(0));
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => {
+                self.build_stream::<f32>(&config, tx, device_latency.clone())
+            }
+            cpal::SampleFormat::I16 => {
+                self.build_stream::<i16>(&config, tx, device_latency.clone())
+            }
+            cpal::SampleFormat::U16 => {
+                self.build_stream::<u16>(&config, tx, device_latency.clone())
+            }
+            cpal::SampleFormat::I32 => {
+                self.build_stream::<i32>(&config, tx, device_latency.clone())
+            }
+            format => anyhow::bail!("unsupported input sample format: {format}"),
+        }
+        .context("failed to build input stream")?;
+        stream.play().context("failed to start input stream")?;
+
+        Ok(self.stream.insert(OpenStream {
+            _stream: stream,
+            input_ring_buffer: rx,
+            device_latency,
+
+            sample_rate: options.sample_rate,
+            buffer_size: options.buffer_size,
+        }))
+    }
+
+    /// Picks the config that's closest to what the worker host wants out of the ones the device supports: the
+    /// project's sample rate, as few channels as possible (they get mixed down to mono anyway) and `f32` samples if
+    /// there's a choice.
+    fn choose_config(
+        &self,
+        options: &WorkerOptions,
+    ) -> anyhow::Result<(cpal::StreamConfig, cpal::SampleFormat)> {
+        let sample_rate = cpal::SampleRate(options.sample_rate);
+        let config = self
+            .device
+            .supported_input_configs()
+            .context("failed to get the input device's supported configs")?
+            .filter(|config| {
+                config.min_sample_rate() <= sample_rate
+                    && sample_rate <= config.max_sample_rate()
+                    && Self::SAMPLE_FORMATS.contains(&config.sample_format())
+            })
+            .min_by_key(|config| {
+                (
+                    config.channels(),
+                    config.sample_format() != cpal::SampleFormat::F32,
+                )
+            })
+            .with_context(|| {
+                format!(
+                    "the input device doesn't support recording at {} Hz",
+                    options.sample_rate
+                )
+            })?
+            .with_sample_rate(sample_rate);
+
+        let buffer_size = match *config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max }
+                if (min..=max).contains(&options.buffer_size) =>
+            {
+                cpal::BufferSize::Fixed(options.buffer_size)
+            }
+            _ => cpal::BufferSize::Default,
+        };
+        Ok((
+            cpal::StreamConfig {
+                channels: config.channels(),
+                sample_rate,
+                buffer_size,
+            },
+            config.sample_format(),
+        ))
+    }
+
+    /// Builds a stream that mixes every frame down to mono and sends it to `tx`.
+    fn build_stream<T>(
+        &self,
+        config: &cpal::StreamConfig,
+        tx: crossbeam_channel::Sender<f32>,
+        device_latency: Arc<AtomicU32>,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample,
+        f32: cpal::FromSample<T>,
+    {
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0;
+        self.device.build_input_stream(
+            config,
+            move |buffer: &[T], info| {
+                let timestamp = info.timestamp();
+                if let Some(delay) = timestamp.callback.duration_since(&timestamp.capture) {
+                    device_latency.store(
+                        (delay.as_secs_f64() * sample_rate as f64) as u32,
+                        Ordering::Relaxed,
+                    );
+                }
+                for frame in buffer.chunks_exact(channels) {
+                    let val = frame
+                        .iter()
+                        .map(|&sample| cpal::Sample::to_sample::<f32>(sample))
+                        .sum::<f32>()
+                        / channels as f32;
+                    // if the worker host isn't keeping up, drop the audio on the floor
+                    let _ = tx.try_send(val);
+                }
+            },
+            |err| tracing::error!("audio input stream error: {err}"),
+            None,
+        )
+    }
+}
+
+impl InputSource for CpalSource {
+    fn read(&mut self, buffer: &mut Buffer, options: &WorkerOptions) -> anyhow::Result<()> {
+        let stream = self.open(options)?;
+        let rx = &stream.input_ring_buffer;
+
+        // skip audio that's been waiting too long so the input doesn't fall further and further behind
+        let max_queued = buffer.len() * Self::MAX_QUEUED_BUFFERS;
+        for _ in max_queued..rx.len() {
+            let _ = rx.try_recv();
+        }
+
+        for val in buffer.iter_mut() {
+            *val = match rx.try_recv() {
+                Ok(val) => val,
+                Err(crossbeam_channel::TryRecvError::Empty) => 0.0,
+                Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                    anyhow::bail!("audio input stream closed");
+                }
+            };
+        }
+        Ok(())
+    }
+    fn latency(&self) -> u32 {
+        self.stream.as_ref().map_or(0, |stream| {
+            // what's waiting to be read, plus the device's own latency
+            stream.input_ring_buffer.len() as u32 + stream.device_latency.load(Ordering::Relaxed)
+        })
+    }
+}
+
+/// Plays a WAV file on a loop, as if it were coming in live.
+pub struct WavFileSource {
+    path: PathBuf,
+    /// The file resampled to the project's sample rate, and that sample rate.
+    data: Option<(Vec<f32>, u32)>,
+    /// Where in the file the next buffer starts.
+    pos: usize,
+}
+
+impl WavFileSource {
+    /// Creates a source for the file at `path`. The file is only read once audio is read.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            data: None,
+            pos: 0,
+        }
+    }
+
+    fn load(&self, options: &WorkerOptions) -> anyhow::Result<Vec<f32>> {
+        let bytes = std::fs::read(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        let sample = cubedaw_worker::wav::decode(self.path.display().to_string(), &bytes)
+            .with_context(|| format!("failed to decode {}", self.path.display()))?;
+        Ok(cubedaw_worker::resample::resample(
+            sample.data(),
+            sample.sample_rate(),
+            options.sample_rate,
+            options.resample_quality,
+        ))
+    }
+}
+
+impl InputSource for WavFileSource {
+    fn read(&mut self, buffer: &mut Buffer, options: &WorkerOptions) -> anyhow::Result<()> {
+        if self
+            .data
+            .as_ref()
+            .is_none_or(|&(_, sample_rate)| sample_rate != options.sample_rate)
+        {
+            let data = self.load(options)?;
+            self.pos = 0;
+            self.data = Some((data, options.sample_rate));
+        }
+        let Some((ref data, _)) = self.data else {
+            unreachable!()
+        };
+
+        if data.is_empty() {
+            buffer.fill(0.0);
+            return Ok(());
+        }
+        for val in buffer.iter_mut() {
+            *val = data[self.pos];
+            self.pos = (self.pos + 1) % data.len();
+        }
+        Ok(())
+    }
+    fn latency(&self) -> u32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `samples` to a WAV file in the temp directory, so it can be replayed.
+    fn wav_file(name: &str, samples: &[f32], sample_rate: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cubedaw-input-{name}.wav"));
+        std::fs::write(&path, cubedaw_worker::wav::encode(samples, sample_rate))
+            .expect("failed to write the test file");
+        path
+    }
+    fn options(sample_rate: u32) -> WorkerOptions {
+        WorkerOptions::with_audio(Default::default(), sample_rate, 16)
+    }
+
+    #[test]
+    fn wav_file_loops() {
+        let samples: Vec<f32> = (0..40).map(|i| i as f32 / 40.0).collect();
+        let mut source = WavFileSource::new(wav_file("loops", &samples, 44100));
+        let options = options(44100);
+
+        let mut buffer = Buffer::new_box_zeroed(16);
+        let mut read = Vec::new();
+        for _ in 0..6 {
+            source.read(&mut buffer, &options).unwrap();
+            read.extend_from_slice(&buffer);
+        }
+        // 96 samples: the file twice, then the start of it again
+        let expected: Vec<f32> = samples.iter().cycle().take(96).copied().collect();
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn wav_file_is_resampled() {
+        let samples: Vec<f32> = (0..24).map(|i| (i as f32 * 0.7).sin()).collect();
+        let mut source = WavFileSource::new(wav_file("resampled", &samples, 22050));
+
+        let mut buffer = Buffer::new_box_zeroed(16);
+        let mut read = Vec::new();
+        for _ in 0..6 {
+            source.read(&mut buffer, &options(44100)).unwrap();
+            read.extend_from_slice(&buffer);
+        }
+        // twice as many samples, so it loops every 48 samples, and every other one lands right on the original
+        assert_eq!(read[..48], read[48..]);
+        for (i, &val) in samples.iter().enumerate() {
+            assert_eq!(read[i * 2], val, "sample {i}");
+        }
+
+        // changing the sample rate starts over at the new rate
+        source.read(&mut buffer, &options(22050)).unwrap();
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), samples[..16]);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use cubedaw_lib::Buffer;
+use cubedaw_worker::WorkerOptions;
+
+mod device;
+pub use device::CpalSink;
+mod file;
+pub use file::WavFileSink;
+mod input;
+pub use input::AudioInput;
+
+/// Where the worker host's output goes.
+pub enum AudioOutput {
+    /// An audio device.
+    Device {
+        device: cpal::Device,
+        /// How many buffers can be queued up for the device. More means fewer dropouts but more latency.
+        queued_buffers: u32,
+    },
+    /// A WAV file, written in realtime.
+    File(PathBuf),
+    /// Nowhere. The song still plays, just silently.
+    Null,
+}
+
+impl AudioOutput {
+    /// Creates the sink. This has to happen on the thread that uses the sink, since audio streams can't be sent between threads.
+    pub fn into_sink(self) -> Box<dyn OutputSink> {
+        match self {
+            Self::Device {
+                device,
+                queued_buffers,
+            } => Box::new(CpalSink::new(device, queued_buffers)),
+            Self::File(path) => Box::new(WavFileSink::new(path)),
+            Self::Null => Box::new(NullSink::default()),
+        }
+    }
+}
+
+/// Something the worker host outputs audio to.
+pub trait OutputSink {
+    /// Outputs a buffer of audio, then blocks until the sink is ready for the next one. This is what paces the worker host.
+    fn write(&mut self, buffer: &Buffer, options: &WorkerOptions) -> anyhow::Result<()>;
+    /// Returns how many xruns (dropouts where the sink ran out of audio or had to drop some) happened since the last
+    /// call.
+    fn take_xruns(&mut self) -> u32;
+    /// How many samples pass between a buffer being written and it actually being heard.
+    fn latency(&self) -> u32;
+}
+
+/// Throws audio away, at the same rate an audio device would play it.
+#[derive(Default)]
+pub struct NullSink {
+    clock: Clock,
+}
+
+impl OutputSink for NullSink {
+    fn write(&mut self, _buffer: &Buffer, options: &WorkerOptions) -> anyhow::Result<()> {
+        self.clock.wait(options);
+        Ok(())
+    }
+    fn take_xruns(&mut self) -> u32 {
+        self.clock.take_xruns()
+    }
+    fn latency(&self) -> u32 {
+        0
+    }
+}
+
+/// Paces output so buffers are produced in realtime.
+#[derive(Default)]
+pub struct Clock {
+    /// When the last buffer was due.
+    last_deadline: Option<Instant>,
+    xruns: u32,
+}
+
+impl Clock {
+    /// Waits until the next buffer is due. If we're already late, this returns immediately and the clock starts over.
+    pub fn wait(&mut self, options: &WorkerOptions) {
+        let duration_per_buffer =
+            Duration::from_secs_f64(options.buffer_size as f64 / options.sample_rate as f64);
+
+        let now = Instant::now();
+        let deadline = self.last_deadline.unwrap_or(now) + duration_per_buffer;
+        if now < deadline {
+            std::thread::sleep(deadline - now);
+            self.last_deadline = Some(deadline);
+        } else {
+            tracing::debug!(
+                "audio workerhost underflow: behind by {:.02} ms",
+                (now - deadline).as_secs_f64() * 1000.0
+            );
+            self.last_deadline = Some(now);
+            self.xruns += 1;
+        }
+    }
+    /// Returns how many times [`Self::wait`] was late since the last call.
+    pub fn take_xruns(&mut self) -> u32 {
+        std::mem::take(&mut self.xruns)
+    }
+}
@@ -1,11 +1,14 @@
 use std::{sync::mpsc, thread};
 
 use anyhow::Result;
-use cubedaw_lib::Buffer;
-use cubedaw_worker::WorkerOptions;
+use cubedaw_lib::{Buffer, Id, Node, Note, Range, Track};
 use cubedaw_worker::command::StateCommandWrapper;
+use cubedaw_worker::{FrozenTrack, Level, Metronome, Probe, WorkerOptions};
 
 mod audio;
+pub use audio::{AudioInput, AudioOutput};
+mod recorder;
+use recorder::Recorder;
 
 pub struct WorkerHostHandle {
     tx: mpsc::Sender<AppToWorkerHostEvent>,
@@ -15,6 +18,75 @@ pub struct WorkerHostHandle {
     is_playing: bool,
     is_init: bool,
     last_playhead_update: Option<(cubedaw_lib::PreciseSongPos, std::time::Instant)>,
+    errors: Vec<WorkerHostError>,
+    performance: Performance,
+    /// The take that's being recorded, if there is one.
+    take: Option<Recording>,
+    recordings: Vec<Recording>,
+    levels: Option<Levels>,
+}
+
+/// The levels of the master output and every track.
+#[derive(Debug, Clone, Default)]
+pub struct Levels {
+    pub master: Level,
+    pub tracks: Vec<(Id<Track>, Level)>,
+}
+
+impl Levels {
+    /// Makes each level the louder of itself and the one in `other`.
+    fn merge(&mut self, other: &Self) {
+        self.master = self.master.max(other.master);
+        for &(track_id, level) in &other.tracks {
+            match self.tracks.iter_mut().find(|(id, _)| *id == track_id) {
+                Some((_, current)) => *current = current.max(level),
+                None => self.tracks.push((track_id, level)),
+            }
+        }
+    }
+}
+
+/// Audio recorded from the live input while the song was playing.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Where in the song the recording starts, with the input and output latency taken into account. This can be
+    /// negative if recording started right at the start of the song.
+    pub start: i64,
+    pub sample_rate: u32,
+    pub audio: Vec<f32>,
+}
+
+/// How well the worker host is keeping up with realtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Performance {
+    /// Time spent processing a buffer, as a fraction of how long the buffer lasts. Smoothed so it's readable.
+    pub load: f32,
+    /// The highest unsmoothed load since the last reset.
+    pub peak_load: f32,
+    /// The number of xruns (dropouts) since the last reset.
+    pub xruns: u64,
+    pub last_xrun: Option<std::time::Instant>,
+}
+
+impl Performance {
+    /// How much of each new load measurement goes into [`Self::load`].
+    const SMOOTHING: f32 = 0.05;
+
+    fn update(&mut self, load: f32, xruns: u32, now: std::time::Instant) {
+        self.load += (load - self.load) * Self::SMOOTHING;
+        self.peak_load = self.peak_load.max(load);
+        if xruns > 0 {
+            self.xruns += xruns as u64;
+            self.last_xrun = Some(now);
+        }
+    }
+}
+
+/// An error that happened in the worker host. The worker host keeps running after these.
+#[derive(Debug, Clone)]
+pub struct WorkerHostError {
+    pub track_id: Option<Id<Track>>,
+    pub message: String,
 }
 
 impl WorkerHostHandle {
@@ -32,22 +104,56 @@ impl WorkerHostHandle {
             is_playing: false,
             is_init: false,
             last_playhead_update: None,
+            errors: Vec::new(),
+            performance: Performance::default(),
+            take: None,
+            recordings: Vec::new(),
+            levels: None,
         }
     }
 
+    /// Starts a new worker host with `state` and `worker_options`, replacing the current one if there is one.
     pub fn init(&mut self, state: cubedaw_lib::State, worker_options: WorkerOptions) {
         self.tx
             .send(AppToWorkerHostEvent::Init {
-                state,
+                state: Box::new(state),
                 options: worker_options,
             })
             .expect("channel closed???");
 
         self.is_init = true;
     }
-    pub fn set_device(&mut self, device: Option<cpal::Device>) {
+    /// Switches where the audio goes. Until this is called, the worker host plays back silently.
+    pub fn set_output(&mut self, output: AudioOutput) {
+        self.tx
+            .send(AppToWorkerHostEvent::SetOutput(output))
+            .expect("channel closed???");
+    }
+
+    /// Switches where the live input comes from. Until this is called, the input is silent.
+    pub fn set_input(&mut self, input: AudioInput) {
+        self.tx
+            .send(AppToWorkerHostEvent::SetInput(input))
+            .expect("channel closed???");
+    }
+    /// Starts or stops recording the live input. While recording, everything that comes in while the song plays is
+    /// sent back as a [`Recording`] once playback stops, loops or recording is turned off.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.tx
+            .send(AppToWorkerHostEvent::SetRecording(recording))
+            .expect("channel closed???");
+    }
+
+    /// See [`cubedaw_worker::WorkerHost::set_frozen`].
+    pub fn set_frozen(&mut self, track_id: Id<Track>, frozen: Option<FrozenTrack>) {
+        self.tx
+            .send(AppToWorkerHostEvent::SetFrozen { track_id, frozen })
+            .expect("channel closed???");
+    }
+    /// See [`cubedaw_worker::WorkerHost::set_metronome`].
+    pub fn set_metronome(&mut self, metronome: Metronome) {
         self.tx
-            .send(AppToWorkerHostEvent::SwitchAudioDevice(device))
+            .send(AppToWorkerHostEvent::SetMetronome(metronome))
             .expect("channel closed???");
     }
 
@@ -72,6 +178,36 @@ impl WorkerHostHandle {
         self.is_playing = false;
         self.last_playhead_update = None;
     }
+    /// Starts a live note on a track. This plays regardless of whether the song is playing.
+    pub fn start_live_note(
+        &mut self,
+        track_id: Id<Track>,
+        note_id: Id<Note>,
+        note: cubedaw_lib::Note,
+    ) {
+        self.tx
+            .send(AppToWorkerHostEvent::StartLiveNote {
+                track_id,
+                note_id,
+                note,
+            })
+            .expect("channel closed???");
+    }
+    pub fn stop_live_note(&mut self, track_id: Id<Track>, note_id: Id<Note>) {
+        self.tx
+            .send(AppToWorkerHostEvent::StopLiveNote { track_id, note_id })
+            .expect("channel closed???");
+    }
+    /// Sets or removes the probe on a node. See [`cubedaw_worker::WorkerHost::set_probe`].
+    pub fn set_probe(&mut self, track_id: Id<Track>, node_id: Id<Node>, probe: Option<Probe>) {
+        self.tx
+            .send(AppToWorkerHostEvent::SetProbe {
+                track_id,
+                node_id,
+                probe,
+            })
+            .expect("channel closed???");
+    }
     pub fn send_commands(&mut self, commands: Box<[Box<dyn StateCommandWrapper>]>, is_undo: bool) {
         self.tx
             .send(AppToWorkerHostEvent::Commands { commands, is_undo })
@@ -96,7 +232,19 @@ impl WorkerHostHandle {
         self.rx.recv().expect("channel closed???")
     }
 
+    /// Sends the track levels back so the worker host can reuse them instead of allocating on the audio thread.
+    fn recycle_levels(&mut self, levels: Levels) {
+        self.tx
+            .send(AppToWorkerHostEvent::ReturnTrackLevels(levels.tracks))
+            .expect("channel closed???");
+    }
+
+    /// Handles everything the worker host sent since the last call.
     pub fn handle_events(&mut self) {
+        let now = std::time::Instant::now();
+        if let Some(levels) = self.levels.take() {
+            self.recycle_levels(levels);
+        }
         while let Some(event) = self.try_recv() {
             match event {
                 WorkerHostToAppEvent::PlayheadUpdate { pos, timestamp } => {
@@ -104,10 +252,77 @@ impl WorkerHostHandle {
                         self.last_playhead_update = Some((pos, timestamp));
                     }
                 }
+                WorkerHostToAppEvent::Error(error) => {
+                    self.errors.push(error);
+                }
+                WorkerHostToAppEvent::ReturnCommands(commands) => drop(commands),
+                WorkerHostToAppEvent::RecordingStarted { start, sample_rate } => {
+                    self.take = Some(Recording {
+                        start,
+                        sample_rate,
+                        audio: Vec::new(),
+                    });
+                }
+                WorkerHostToAppEvent::RecordedAudio(buffer) => {
+                    if let Some(ref mut take) = self.take {
+                        take.audio.extend_from_slice(&buffer);
+                    }
+                    self.tx
+                        .send(AppToWorkerHostEvent::ReturnRecordedAudio(buffer))
+                        .expect("channel closed???");
+                }
+                WorkerHostToAppEvent::RecordingStopped => {
+                    if let Some(take) = self.take.take() {
+                        self.recordings.push(take);
+                    }
+                }
+                WorkerHostToAppEvent::Performance { load, xruns } => {
+                    self.performance.update(load, xruns, now);
+                }
+                WorkerHostToAppEvent::Levels(levels) => match &mut self.levels {
+                    Some(current) => {
+                        current.merge(&levels);
+                        self.recycle_levels(levels);
+                    }
+                    None => self.levels = Some(levels),
+                },
             }
         }
     }
 
+    /// The levels the worker host sent during the last call to [`Self::handle_events`], or `None` if it didn't send
+    /// any. If it processed several buffers in that time, each level is the loudest of them.
+    pub fn levels(&self) -> Option<&Levels> {
+        self.levels.as_ref()
+    }
+
+    pub fn errors(&self) -> &[WorkerHostError] {
+        &self.errors
+    }
+    /// Adds an error that didn't come from the worker host itself, but should be shown along with its errors.
+    pub fn push_error(&mut self, error: WorkerHostError) {
+        self.errors.push(error);
+    }
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+    }
+
+    /// Returns the recordings that finished since the last call.
+    pub fn take_recordings(&mut self) -> Vec<Recording> {
+        std::mem::take(&mut self.recordings)
+    }
+
+    pub fn performance(&self) -> Performance {
+        self.performance
+    }
+    /// Resets the xrun count and peak load.
+    pub fn reset_performance(&mut self) {
+        self.performance = Performance {
+            load: self.performance.load,
+            ..Default::default()
+        };
+    }
+
     pub fn last_playhead_update(
         &self,
     ) -> Option<(cubedaw_lib::PreciseSongPos, std::time::Instant)> {
@@ -125,10 +340,17 @@ impl WorkerHostHandle {
 
 enum AppToWorkerHostEvent {
     Init {
-        state: cubedaw_lib::State,
+        state: Box<cubedaw_lib::State>,
         options: WorkerOptions,
     },
-    SwitchAudioDevice(Option<cpal::Device>),
+    SetOutput(AudioOutput),
+    SetInput(AudioInput),
+    SetRecording(bool),
+    SetMetronome(Metronome),
+    SetFrozen {
+        track_id: Id<Track>,
+        frozen: Option<FrozenTrack>,
+    },
     StartPlaying {
         from: i64,
     },
@@ -136,39 +358,76 @@ enum AppToWorkerHostEvent {
     // Stop all notes from playing
     Reset,
     UpdatePlayheadPos(i64),
+    StartLiveNote {
+        track_id: Id<Track>,
+        note_id: Id<Note>,
+        note: cubedaw_lib::Note,
+    },
+    StopLiveNote {
+        track_id: Id<Track>,
+        note_id: Id<Note>,
+    },
+    SetProbe {
+        track_id: Id<Track>,
+        node_id: Id<Node>,
+        probe: Option<Probe>,
+    },
     Commands {
         commands: Box<[Box<dyn StateCommandWrapper>]>,
         is_undo: bool,
     },
+    /// Track levels the app is done with, so the worker host can fill them in again.
+    ReturnTrackLevels(Vec<(Id<Track>, Level)>),
+    /// A buffer sent with [`WorkerHostToAppEvent::RecordedAudio`] that the app is done with.
+    ReturnRecordedAudio(Box<Buffer>),
 }
 
-#[derive(Debug)]
 enum WorkerHostToAppEvent {
     PlayheadUpdate {
         pos: cubedaw_lib::PreciseSongPos,
         timestamp: std::time::Instant,
     },
+    Error(WorkerHostError),
+    /// The levels of the master output and every track after processing a buffer.
+    Levels(Levels),
+    /// Stats about the last buffer.
+    Performance {
+        /// Processing time as a fraction of the buffer's duration.
+        load: f32,
+        /// Xruns since the last buffer.
+        xruns: u32,
+    },
+    /// Commands that have been applied. They're sent back so they get freed here instead of on the audio thread.
+    ReturnCommands(Box<[Box<dyn StateCommandWrapper>]>),
+    /// A new take started. It's followed by its audio, a buffer at a time, and then [`Self::RecordingStopped`].
+    RecordingStarted {
+        /// See [`Recording::start`].
+        start: i64,
+        sample_rate: u32,
+    },
+    RecordedAudio(Box<Buffer>),
+    RecordingStopped,
 }
 
 fn worker_host(rx: mpsc::Receiver<AppToWorkerHostEvent>, tx: mpsc::Sender<WorkerHostToAppEvent>) {
-    use std::time::{Duration, Instant};
-
     let Ok(first_event) = rx.recv() else { return };
     let AppToWorkerHostEvent::Init { state, options } = first_event else {
         panic!("other event sent to worker_host before Init");
     };
 
-    let mut time_to_wait_until = Instant::now();
-    let mut duration_per_frame =
-        Duration::from_secs_f64(options.buffer_size as f64 / options.sample_rate as f64);
-
-    let mut host = cubedaw_worker::WorkerHost::new(state, options);
+    let mut host = cubedaw_worker::WorkerHost::new(*state, options);
     let mut is_playing = false;
 
     let mut playhead_pos = Default::default();
 
     let mut output_buffer = Buffer::new_box_zeroed(host.options().buffer_size);
-    let mut audio_handler = audio::CpalAudioHandler::new();
+    let mut sink = AudioOutput::Null.into_sink();
+
+    let mut input_buffer = Buffer::new_box_zeroed(host.options().buffer_size);
+    let mut source = AudioInput::Null.into_source();
+    let mut recorder = Recorder::new(host.options().buffer_size);
+    // track levels the app has sent back, so sending levels doesn't allocate every buffer
+    let mut spare_track_levels: Vec<Vec<(Id<Track>, Level)>> = Vec::with_capacity(16);
 
     'outer: loop {
         // process events first
@@ -181,22 +440,37 @@ fn worker_host(rx: mpsc::Receiver<AppToWorkerHostEvent>, tx: mpsc::Sender<Worker
 
             match event {
                 AppToWorkerHostEvent::Init { state, options } => {
+                    if options.buffer_size != host.options().buffer_size {
+                        output_buffer = Buffer::new_box_zeroed(options.buffer_size);
+                        input_buffer = Buffer::new_box_zeroed(options.buffer_size);
+                    }
+                    // the sample rate might have changed partway through
+                    if recorder.stop(&tx).is_err() {
+                        return;
+                    }
+                    recorder.set_buffer_size(options.buffer_size);
                     host.join();
-
-                    time_to_wait_until = Instant::now();
-                    duration_per_frame = Duration::from_secs_f64(
-                        options.buffer_size as f64 / options.sample_rate as f64,
-                    );
-
-                    host = cubedaw_worker::WorkerHost::new(state, options);
+                    host = cubedaw_worker::WorkerHost::new(*state, options);
+                }
+                AppToWorkerHostEvent::SetOutput(output) => {
+                    sink = output.into_sink();
+                }
+                AppToWorkerHostEvent::SetInput(input) => {
+                    source = input.into_source();
+                }
+                AppToWorkerHostEvent::SetRecording(recording) => {
+                    recorder.set_enabled(recording);
+                }
+                AppToWorkerHostEvent::SetMetronome(metronome) => {
+                    host.set_metronome(metronome);
+                }
+                AppToWorkerHostEvent::SetFrozen { track_id, frozen } => {
+                    host.set_frozen(track_id, frozen);
                 }
-                AppToWorkerHostEvent::SwitchAudioDevice(device) => match device {
-                    Some(device) => audio_handler.set_device(device, host.options()),
-                    None => audio_handler.close(),
-                },
                 AppToWorkerHostEvent::StartPlaying { from } => {
                     playhead_pos = cubedaw_lib::PreciseSongPos::from_song_pos(from);
                     is_playing = true;
+                    recorder.start_playing();
                 }
                 AppToWorkerHostEvent::StopPlaying => {
                     is_playing = false;
@@ -207,20 +481,87 @@ fn worker_host(rx: mpsc::Receiver<AppToWorkerHostEvent>, tx: mpsc::Sender<Worker
                 AppToWorkerHostEvent::UpdatePlayheadPos(pos) => {
                     playhead_pos = cubedaw_lib::PreciseSongPos::from_song_pos(pos);
                 }
-                AppToWorkerHostEvent::Commands { commands, is_undo } => {
-                    for mut command in commands.into_vec() {
+                AppToWorkerHostEvent::StartLiveNote {
+                    track_id,
+                    note_id,
+                    note,
+                } => {
+                    host.start_live_note(track_id, note_id, note);
+                }
+                AppToWorkerHostEvent::StopLiveNote { track_id, note_id } => {
+                    host.stop_live_note(track_id, note_id);
+                }
+                AppToWorkerHostEvent::SetProbe {
+                    track_id,
+                    node_id,
+                    probe,
+                } => {
+                    host.set_probe(track_id, node_id, probe);
+                }
+                AppToWorkerHostEvent::Commands {
+                    mut commands,
+                    is_undo,
+                } => {
+                    for command in commands.iter_mut() {
                         if is_undo {
                             command.rollback(host.state_mut());
                         } else {
                             command.execute(host.state_mut());
                         }
                     }
+                    if tx
+                        .send(WorkerHostToAppEvent::ReturnCommands(commands))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                AppToWorkerHostEvent::ReturnTrackLevels(tracks) => {
+                    spare_track_levels.push(tracks);
+                }
+                AppToWorkerHostEvent::ReturnRecordedAudio(buffer) => {
+                    recorder.return_buffer(buffer);
                 }
             }
         }
         let live_playhead_pos = playhead_pos;
 
+        // read the input
+        if let Err(error) = source.read(&mut input_buffer, host.options()) {
+            source = AudioInput::Null.into_source();
+            input_buffer.fill(0.0);
+            let res = tx.send(WorkerHostToAppEvent::Error(WorkerHostError {
+                track_id: None,
+                message: format!("audio input failed: {error:#}"),
+            }));
+            if res.is_err() {
+                return;
+            }
+        }
+        host.live_input_mut().copy_from(&input_buffer);
+
+        // record it
+        let res = recorder.record(
+            &input_buffer,
+            is_playing,
+            || {
+                // what's coming in now was played along to what was heard a round trip ago
+                let latency = (sink.latency() + source.latency()) as f64;
+                let units_per_sample = host.state().bpm as f64 / 60.0
+                    * Range::UNITS_PER_BEAT as f64
+                    / host.options().sample_rate as f64;
+                playhead_pos.song_pos - (latency * units_per_sample).round() as i64
+            },
+            host.options().sample_rate,
+            &tx,
+        );
+        if res.is_err() {
+            return;
+        }
+        let song_pos_before = playhead_pos;
+
         // process the audio
+        let process_start = std::time::Instant::now();
         host = host.process(
             if is_playing {
                 Some(&mut playhead_pos)
@@ -230,30 +571,56 @@ fn worker_host(rx: mpsc::Receiver<AppToWorkerHostEvent>, tx: mpsc::Sender<Worker
             live_playhead_pos,
             &mut output_buffer,
         );
+        let process_time = process_start.elapsed();
+        if playhead_pos < song_pos_before && recorder.looped(&tx).is_err() {
+            return;
+        }
+        let mut tracks = spare_track_levels.pop().unwrap_or_default();
+        tracks.clear();
+        tracks.extend(host.track_levels());
+        let res = tx.send(WorkerHostToAppEvent::Levels(Levels {
+            master: host.master_level(),
+            tracks,
+        }));
+        if res.is_err() {
+            return;
+        }
+        for error in host.take_errors() {
+            let res = tx.send(WorkerHostToAppEvent::Error(WorkerHostError {
+                track_id: error.track_id,
+                message: format!("{:#}", error.error),
+            }));
+            if res.is_err() {
+                return;
+            }
+        }
 
         // play the audio!
-        audio_handler.open(host.options());
-        for data in output_buffer.as_internal() {
-            audio_handler.send(*data);
+        // this blocks until the sink wants more audio
+        if let Err(error) = sink.write(&output_buffer, host.options()) {
+            // keep the song going silently rather than stopping altogether
+            sink = AudioOutput::Null.into_sink();
+            let res = tx.send(WorkerHostToAppEvent::Error(WorkerHostError {
+                track_id: None,
+                message: format!("audio output failed: {error:#}"),
+            }));
+            if res.is_err() {
+                return;
+            }
         }
-
-        time_to_wait_until += duration_per_frame;
-
-        let now = Instant::now();
-        if now < time_to_wait_until {
-            // dbg!(time_to_wait_until - now);
-            std::thread::sleep(time_to_wait_until - now);
-        } else {
-            eprintln!(
-                "audio workerhost underflow: behind by {:.02} ms",
-                (now - time_to_wait_until).as_secs_f64() * 1000.0
-            );
-            time_to_wait_until = now;
+        let options = host.options();
+        let res = tx.send(WorkerHostToAppEvent::Performance {
+            load: process_time.as_secs_f32() * options.sample_rate as f32
+                / options.buffer_size as f32,
+            xruns: sink.take_xruns(),
+        });
+        if res.is_err() {
+            return;
         }
         if is_playing {
             let res = tx.send(WorkerHostToAppEvent::PlayheadUpdate {
                 pos: playhead_pos,
-                timestamp: time_to_wait_until,
+                timestamp: std::time::Instant::now(),
             });
             if res.is_err() {
                 return;
@@ -0,0 +1,256 @@
+use std::sync::mpsc;
+
+use cubedaw_lib::Buffer;
+
+use super::WorkerHostToAppEvent;
+
+type SendResult = Result<(), mpsc::SendError<WorkerHostToAppEvent>>;
+
+/// Records the live input on the worker host's side. The audio is streamed to the app a buffer at a time, so nothing
+/// grows on the audio thread; [`super::WorkerHostHandle::handle_events`] puts it back together.
+pub(super) struct Recorder {
+    /// Whether recording is turned on.
+    enabled: bool,
+    /// Whether a take is in progress.
+    recording: bool,
+    /// Set when the song loops while recording, so recording only resumes the next time the song is played.
+    take_done: bool,
+    /// Buffers the app has sent back, so recording doesn't allocate every buffer.
+    spare_buffers: Vec<Box<Buffer>>,
+    buffer_size: u32,
+}
+
+impl Recorder {
+    /// How many buffers are allocated up front when recording is turned on. The app sends them back every frame, so
+    /// this only has to cover a few frames' worth of buffers.
+    const SPARE_BUFFERS: usize = 16;
+
+    pub fn new(buffer_size: u32) -> Self {
+        Self {
+            enabled: false,
+            recording: false,
+            take_done: false,
+            spare_buffers: Vec::new(),
+            buffer_size,
+        }
+    }
+
+    /// Turns recording on or off. Turning it off stops the current take at the next buffer.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if enabled {
+            self.fill_spare_buffers();
+        }
+    }
+    /// Throws away the spare buffers if they're the wrong size now. Stop the take first with [`Self::stop`].
+    pub fn set_buffer_size(&mut self, buffer_size: u32) {
+        if buffer_size != self.buffer_size {
+            self.buffer_size = buffer_size;
+            self.spare_buffers.clear();
+            if self.enabled {
+                self.fill_spare_buffers();
+            }
+        }
+    }
+    fn fill_spare_buffers(&mut self) {
+        while self.spare_buffers.len() < Self::SPARE_BUFFERS {
+            self.spare_buffers
+                .push(Buffer::new_box_zeroed(self.buffer_size));
+        }
+    }
+    /// Takes back a buffer that was sent with [`WorkerHostToAppEvent::RecordedAudio`].
+    pub fn return_buffer(&mut self, buffer: Box<Buffer>) {
+        // buffers from before the buffer size changed are no use anymore
+        if buffer.len() == self.buffer_size as usize {
+            self.spare_buffers.push(buffer);
+        }
+    }
+
+    /// Lets recording start again after the song looped.
+    pub fn start_playing(&mut self) {
+        self.take_done = false;
+    }
+
+    /// Records a buffer of input if recording is on and the song is playing, and stops the take otherwise. `start` is
+    /// where in the song the input starts, and is only called when a new take starts.
+    pub fn record(
+        &mut self,
+        input: &Buffer,
+        is_playing: bool,
+        start: impl FnOnce() -> i64,
+        sample_rate: u32,
+        tx: &mpsc::Sender<WorkerHostToAppEvent>,
+    ) -> SendResult {
+        if !(self.enabled && is_playing && !self.take_done) {
+            return self.stop(tx);
+        }
+        if !self.recording {
+            self.recording = true;
+            tx.send(WorkerHostToAppEvent::RecordingStarted {
+                start: start(),
+                sample_rate,
+            })?;
+        }
+        // if the app hasn't sent enough buffers back, allocating is better than losing audio
+        let mut buffer = self
+            .spare_buffers
+            .pop()
+            .unwrap_or_else(|| Buffer::new_box_zeroed(self.buffer_size));
+        buffer.copy_from(input);
+        tx.send(WorkerHostToAppEvent::RecordedAudio(buffer))
+    }
+
+    /// Stops the take because the song looped. Recording over what was just recorded would be confusing, so it doesn't
+    /// start again until the song is played again.
+    pub fn looped(&mut self, tx: &mpsc::Sender<WorkerHostToAppEvent>) -> SendResult {
+        if self.recording {
+            self.take_done = true;
+        }
+        self.stop(tx)
+    }
+
+    /// Stops the take, if there is one.
+    pub fn stop(&mut self, tx: &mpsc::Sender<WorkerHostToAppEvent>) -> SendResult {
+        if std::mem::take(&mut self.recording) {
+            tx.send(WorkerHostToAppEvent::RecordingStopped)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the recorder sent, simplified.
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Started(i64),
+        Audio(f32),
+        Stopped,
+    }
+
+    fn events(rx: &mpsc::Receiver<WorkerHostToAppEvent>, recorder: &mut Recorder) -> Vec<Event> {
+        rx.try_iter()
+            .map(|event| match event {
+                WorkerHostToAppEvent::RecordingStarted { start, .. } => Event::Started(start),
+                WorkerHostToAppEvent::RecordedAudio(buffer) => {
+                    let first = buffer[0];
+                    recorder.return_buffer(buffer);
+                    Event::Audio(first)
+                }
+                WorkerHostToAppEvent::RecordingStopped => Event::Stopped,
+                _ => panic!("the recorder sent something that isn't about recording"),
+            })
+            .collect()
+    }
+
+    fn input(val: f32) -> Box<Buffer> {
+        let mut buffer = Buffer::new_box_zeroed(16);
+        buffer.fill(val);
+        buffer
+    }
+
+    #[test]
+    fn takes_start_and_stop() {
+        let (tx, rx) = mpsc::channel();
+        let mut recorder = Recorder::new(16);
+
+        // not recording yet
+        recorder
+            .record(&input(1.0), true, || 0, 44100, &tx)
+            .unwrap();
+        assert_eq!(events(&rx, &mut recorder), []);
+
+        recorder.set_enabled(true);
+        // the song isn't playing
+        recorder
+            .record(&input(2.0), false, || 0, 44100, &tx)
+            .unwrap();
+        assert_eq!(events(&rx, &mut recorder), []);
+
+        recorder
+            .record(&input(3.0), true, || 100, 44100, &tx)
+            .unwrap();
+        recorder
+            .record(&input(4.0), true, || 200, 44100, &tx)
+            .unwrap();
+        recorder
+            .record(&input(5.0), false, || 300, 44100, &tx)
+            .unwrap();
+        assert_eq!(
+            events(&rx, &mut recorder),
+            [
+                Event::Started(100),
+                Event::Audio(3.0),
+                Event::Audio(4.0),
+                Event::Stopped
+            ]
+        );
+
+        recorder
+            .record(&input(6.0), true, || 400, 44100, &tx)
+            .unwrap();
+        recorder.set_enabled(false);
+        recorder
+            .record(&input(7.0), true, || 500, 44100, &tx)
+            .unwrap();
+        assert_eq!(
+            events(&rx, &mut recorder),
+            [Event::Started(400), Event::Audio(6.0), Event::Stopped]
+        );
+    }
+
+    #[test]
+    fn looping_ends_the_take() {
+        let (tx, rx) = mpsc::channel();
+        let mut recorder = Recorder::new(16);
+        recorder.set_enabled(true);
+
+        recorder
+            .record(&input(1.0), true, || 0, 44100, &tx)
+            .unwrap();
+        recorder.looped(&tx).unwrap();
+        recorder
+            .record(&input(2.0), true, || 0, 44100, &tx)
+            .unwrap();
+        assert_eq!(
+            events(&rx, &mut recorder),
+            [Event::Started(0), Event::Audio(1.0), Event::Stopped]
+        );
+
+        // until the song is played again
+        recorder.start_playing();
+        recorder
+            .record(&input(3.0), true, || 50, 44100, &tx)
+            .unwrap();
+        assert_eq!(
+            events(&rx, &mut recorder),
+            [Event::Started(50), Event::Audio(3.0)]
+        );
+    }
+
+    #[test]
+    fn reuses_buffers() {
+        let (tx, rx) = mpsc::channel();
+        let mut recorder = Recorder::new(16);
+        recorder.set_enabled(true);
+        for _ in 0..Recorder::SPARE_BUFFERS * 4 {
+            recorder
+                .record(&input(1.0), true, || 0, 44100, &tx)
+                .unwrap();
+            events(&rx, &mut recorder);
+        }
+        assert_eq!(recorder.spare_buffers.len(), Recorder::SPARE_BUFFERS);
+
+        // the old buffers are the wrong size now
+        recorder.set_buffer_size(32);
+        recorder.return_buffer(input(1.0));
+        assert!(
+            recorder
+                .spare_buffers
+                .iter()
+                .all(|buffer| buffer.len() == 32)
+        );
+    }
+}
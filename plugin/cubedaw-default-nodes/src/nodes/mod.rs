@@ -1,6 +1,6 @@
 mod math;
-mod distortion
 mod oscillator;
+mod sampler;
 
 #[derive(
     Clone, Copy, PartialEq, Eq, zerocopy::TryFromBytes, zerocopy::IntoBytes, zerocopy::Immutable,
@@ -0,0 +1,51 @@
+use cubedaw_pluginlib::{f32x16, Attribute};
+
+use super::PitchState;
+
+mod schema;
+use schema::*;
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct SamplerNodeState {
+    /// Playback position, in samples at the project's sample rate.
+    position: f64,
+}
+
+#[no_mangle]
+fn do_sampler(args: &SamplerNodeArgs, state: &mut SamplerNodeState) {
+    let mut pitch = cubedaw_pluginlib::input::<0>();
+    if args.pitch_state.is_relative() {
+        pitch += cubedaw_pluginlib::attribute::<{ Attribute::Pitch }>()
+    }
+
+    // how far the position moves every sample
+    let speed = f32x16::splat(2.0).powf(pitch - f32x16::splat(args.root_pitch));
+    let ends = speed.prefix_sum_with(0.0);
+    let starts = ends - speed;
+
+    let sample_rate = cubedaw_pluginlib::sample_rate() as f64;
+    let loop_start = args.loop_start as f64 * sample_rate;
+    let loop_end = args.loop_end as f64 * sample_rate;
+    let wrap = |pos: f64| {
+        if loop_end > loop_start && pos >= loop_end {
+            loop_start + (pos - loop_start) % (loop_end - loop_start)
+        } else {
+            pos
+        }
+    };
+
+    // offsets are relative to the base so they stay small enough for f32s to be precise
+    let base = state.position as i32;
+    let mut offsets = starts.to_array();
+    for offset in &mut offsets {
+        *offset = (wrap(state.position + *offset as f64) - base as f64) as f32;
+    }
+    state.position = wrap(state.position + ends.extract(15) as f64);
+
+    let val = cubedaw_pluginlib::sample(args.sample_id, base, f32x16::from_array(offsets));
+
+    cubedaw_pluginlib::output::<0>(val);
+}
+
+cubedaw_pluginlib::export_node!("cubedaw:sampler", do_sampler);
@@ -41,6 +41,7 @@ mod ffi {
         pub fn input(index: u32) -> f32x16;
         pub fn output(val: f32x16, index: u32);
         pub fn attribute(attr: super::Attribute) -> f32x16;
+        pub fn sample(sample_id: u64, base: i32, offsets: f32x16) -> f32x16;
         pub fn kick();
     }
 }
@@ -60,6 +61,9 @@ mod ffi {
     pub unsafe extern "C" fn attribute(_attr: super::Attribute) -> f32x16 {
         f32x16::splat(42.0)
     }
+    pub unsafe extern "C" fn sample(_sample_id: u64, _base: i32, _offsets: f32x16) -> f32x16 {
+        f32x16::splat(0.0)
+    }
     pub unsafe extern "C" fn kick() {}
 }
 
@@ -68,6 +72,14 @@ pub fn sample_rate() -> u32 {
     unsafe { ffi::sample_rate() }
 }
 
+/// Reads frames from one of the project's samples at the positions `base + offsets`, measured in samples at the
+/// project's sample rate. Frames are interpolated, and reading outside of the sample or from a sample that doesn't
+/// exist gives silence.
+#[inline(always)]
+pub fn sample(sample_id: u64, base: i32, offsets: f32x16) -> f32x16 {
+    unsafe { ffi::sample(sample_id, base, offsets) }
+}
+
 macro_rules! conditional_inline {
     ($($args:tt)*) => {
         #[cfg_attr(not(feature = "inline"), inline(never))]